@@ -0,0 +1,61 @@
+//! Times evaluation alone: each script is scanned and parsed once up front,
+//! and printed output goes to `io::sink()` so writing it costs nothing.
+//! Run with `cargo bench --bench interpreter`.
+
+use std::io;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rox::{Interpreter, Parser, Scanner, Stmt};
+
+fn parse(source: String) -> Vec<Stmt> {
+    let tokens = Scanner::new(source)
+        .scan_tokens()
+        .expect("the benchmark script should scan")
+        .to_vec();
+    Parser::new(tokens)
+        .parse()
+        .expect("the benchmark script should parse")
+}
+
+fn bench_script(c: &mut Criterion, name: &str, source: String) {
+    let statements = parse(source);
+    c.bench_function(name, |b| {
+        b.iter(|| {
+            Interpreter::new(io::sink())
+                .interpret(&statements)
+                .expect("the benchmark script should run")
+        })
+    });
+}
+
+/// One statement whose expression is nested a hundred groupings deep.
+fn deep_arithmetic(c: &mut Criterion) {
+    let mut expr = String::from("1");
+    for i in 0..100 {
+        expr = format!("({} * 2 - {} + 1) / 2", expr, i);
+    }
+    bench_script(c, "deep arithmetic", format!("print {};", expr));
+}
+
+/// Globals defined and read in a flat list, then locals read through a few
+/// enclosing blocks.
+fn variables(c: &mut Criterion) {
+    let mut source = String::from("var v0 = 0;\n");
+    for i in 1..1_000 {
+        source += &format!("var v{} = v{} + {};\n", i, i - 1, i);
+    }
+    source += "{ var a = 1; { var b = a; { var c = b;\n";
+    for _ in 0..1_000 {
+        source += "c = a + b + c;\n";
+    }
+    source += "} } }\n";
+    bench_script(c, "variables", source);
+}
+
+fn print_loop(c: &mut Criterion) {
+    let source = "var i = 0; while (i < 10000) { print i; i = i + 1; }".to_owned();
+    bench_script(c, "print loop", source);
+}
+
+criterion_group!(benches, deep_arithmetic, variables, print_loop);
+criterion_main!(benches);
@@ -0,0 +1,83 @@
+//! Scans and parses a generated 12,000 line script, reporting how long it
+//! took and how many allocations were made, then how fast scanning alone
+//! gets through it. Run with `cargo bench --bench scan_parse`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use rox::{Parser, Scanner};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const LINES: usize = 12_000;
+const RUNS: u32 = 10;
+
+/// Lots of repeated identifiers and strings, like a real script has.
+fn generate_script() -> String {
+    let mut source = String::new();
+    for i in 0..LINES / 6 {
+        source += &format!("var total{} = 0;\n", i % 50);
+        source += "fun add(left, right) { return left + right; }\n";
+        source += &format!("total{} = add(total{}, {});\n", i % 50, i % 50, i);
+        source += "if (total0 > 10) print \"big\"; else print \"small\";\n";
+        source += "print \"héllo, wörld\"; // ünïcode in strings and comments\n";
+        source += "while (total0 < 5) total0 = total0 + 1;\n";
+    }
+    source
+}
+
+fn main() {
+    let source = generate_script();
+
+    let allocations_before = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        let tokens = Scanner::new(source.clone())
+            .scan_tokens()
+            .expect("the generated script should scan")
+            .to_vec();
+        Parser::new(tokens)
+            .parse()
+            .expect("the generated script should parse");
+    }
+    let elapsed = start.elapsed() / RUNS;
+    let allocations = (ALLOCATIONS.load(Ordering::Relaxed) - allocations_before) / RUNS as usize;
+
+    println!(
+        "scan + parse {} lines: {:?}, {} allocations",
+        LINES, elapsed, allocations
+    );
+
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        Scanner::new(source.clone())
+            .scan_tokens()
+            .expect("the generated script should scan");
+    }
+    let elapsed = start.elapsed() / RUNS;
+    let megabytes = source.len() as f64 / (1024.0 * 1024.0);
+
+    println!(
+        "scan {:.1}MB: {:?}, {:.1}MB/s",
+        megabytes,
+        elapsed,
+        megabytes / elapsed.as_secs_f64()
+    );
+}
@@ -0,0 +1,200 @@
+use std::fs;
+use std::io::{self, Write};
+
+use rox::Interpreter;
+
+const HELP: &str = "\
+:help          show this list
+:env           show every variable and its value
+:load <path>   run a file in this session
+:undef <name>  remove a global
+:quit          leave the REPL";
+
+/// What the REPL should do after a line has been handled.
+#[derive(Debug, PartialEq)]
+pub enum Flow {
+    Continue,
+    Quit,
+}
+
+/// Handles `line` if it is a meta-command, writing any output to `out`.
+/// Returns `None` for lines that should be run as Lox.
+///
+/// A meta-command is a `:` at the very start of the line, after any
+/// indentation. No Lox statement can start that way, so nothing that could
+/// be source is taken over.
+pub fn execute(
+    line: &str,
+    interpreter: &mut Interpreter,
+    out: &mut impl Write,
+) -> Option<io::Result<Flow>> {
+    let line = line.trim();
+    if !line.starts_with(':') {
+        return None;
+    }
+
+    let mut words = line[1..].splitn(2, char::is_whitespace);
+    let command = words.next().unwrap_or("");
+    let argument = words.next().map(str::trim).unwrap_or("");
+
+    let result = match (command, argument) {
+        ("quit", "") => return Some(Ok(Flow::Quit)),
+        ("help", "") => writeln!(out, "{}", HELP),
+        ("env", "") => env(interpreter, out),
+        ("load", path) if !path.is_empty() => load(path, interpreter, out),
+        ("undef", name) if !name.is_empty() => {
+            if interpreter.undefine(name) {
+                Ok(())
+            } else {
+                writeln!(out, "'{}' is not defined", name)
+            }
+        }
+        _ => writeln!(out, "unknown command '{}', try :help for a list", line),
+    };
+    Some(result.map(|_| Flow::Continue))
+}
+
+fn env(interpreter: &Interpreter, out: &mut impl Write) -> io::Result<()> {
+    write!(out, "{}", interpreter.dump_environment())
+}
+
+/// Errors in the file are reported like any other, and whatever it defined
+/// before failing stays defined. The file's imports are found relative to
+/// it, as when it's run as a script, and a file it imports is only run if
+/// nothing has imported it before.
+fn load(path: &str, interpreter: &mut Interpreter, out: &mut impl Write) -> io::Result<()> {
+    match fs::read_to_string(path) {
+        Ok(source) => {
+            let previous = interpreter.set_script_path(Some(path.into()));
+            let _ = crate::run(source, interpreter, false);
+            interpreter.set_script_path(previous);
+            Ok(())
+        }
+        Err(e) => writeln!(out, "could not load {}: {}", path, e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    fn execute_command(line: &str, interpreter: &mut Interpreter) -> (Option<Flow>, String) {
+        let mut out = Vec::new();
+        let flow = execute(line, interpreter, &mut out).map(Result::unwrap);
+        (flow, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_lox_source_is_not_a_command() {
+        let mut interpreter = Interpreter::new(io::sink());
+
+        for line in &["print \":env\";", "", "  var a = 1;"] {
+            assert_eq!(
+                execute_command(line, &mut interpreter),
+                (None, "".to_owned())
+            );
+        }
+    }
+
+    #[test]
+    fn test_quit_and_help() {
+        let mut interpreter = Interpreter::new(io::sink());
+
+        assert_eq!(
+            execute_command(" :quit ", &mut interpreter),
+            (Some(Flow::Quit), "".to_owned())
+        );
+        let (flow, out) = execute_command(":help", &mut interpreter);
+        assert_eq!(flow, Some(Flow::Continue));
+        assert!(out.contains(":load <path>"), "{}", out);
+    }
+
+    #[test]
+    fn test_unknown_command() {
+        let mut interpreter = Interpreter::new(io::sink());
+
+        for line in &[":nope", ":quit now", ":load", ":"] {
+            let (flow, out) = execute_command(line, &mut interpreter);
+            assert_eq!(flow, Some(Flow::Continue));
+            assert_eq!(
+                out,
+                format!("unknown command '{}', try :help for a list\n", line)
+            );
+        }
+    }
+
+    #[test]
+    fn test_load_env_and_undef() {
+        let path = env::temp_dir().join("rox_commands_load.lox");
+        fs::write(
+            &path,
+            "var greeting = \"hi\";\nvar count = 2;\nprint count;",
+        )
+        .unwrap();
+
+        let mut printed = Vec::new();
+        let mut interpreter = Interpreter::new(&mut printed);
+        let load = format!(":load {}", path.display());
+        assert_eq!(
+            execute_command(&load, &mut interpreter),
+            (Some(Flow::Continue), "".to_owned())
+        );
+        fs::remove_file(&path).unwrap();
+
+        let (_, out) = execute_command(":env", &mut interpreter);
+        assert!(out.contains("count = 2\n"), "{}", out);
+        assert!(out.contains("greeting = \"hi\"\n"), "{}", out);
+
+        execute_command(":undef count", &mut interpreter);
+        let (_, out) = execute_command(":env", &mut interpreter);
+        assert!(!out.contains("count"), "{}", out);
+        assert!(out.find("argc = ") < out.find("greeting = "), "{}", out);
+        assert_eq!(
+            execute_command(":undef count", &mut interpreter),
+            (Some(Flow::Continue), "'count' is not defined\n".to_owned())
+        );
+
+        drop(interpreter);
+        assert_eq!(String::from_utf8(printed).unwrap(), "2\n");
+    }
+
+    #[test]
+    fn test_load_imports_relative_to_the_file() {
+        let dir = env::temp_dir().join("rox_commands_load_imports");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("main.lox"), "import \"lib.lox\";\nprint lib;").unwrap();
+        fs::write(
+            dir.join("lib.lox"),
+            "print \"importing\";\nvar lib = \"loaded\";",
+        )
+        .unwrap();
+
+        let mut printed = Vec::new();
+        let mut interpreter = Interpreter::new(&mut printed);
+        let load = format!(":load {}", dir.join("main.lox").display());
+        execute_command(&load, &mut interpreter);
+        // loading again runs the file, but not what it imports
+        execute_command(&load, &mut interpreter);
+        fs::remove_dir_all(&dir).unwrap();
+
+        drop(interpreter);
+        assert_eq!(
+            String::from_utf8(printed).unwrap(),
+            "importing\nloaded\nloaded\n"
+        );
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let mut interpreter = Interpreter::new(io::sink());
+
+        let (flow, out) = execute_command(":load /no/such/file.lox", &mut interpreter);
+        assert_eq!(flow, Some(Flow::Continue));
+        assert!(
+            out.starts_with("could not load /no/such/file.lox: "),
+            "{}",
+            out
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! Line editing at the prompt: completing keywords and variable names,
+//! highlighting brackets, and going on to another line while a bracket,
+//! string or comment is still open, so a whole function can be typed in.
+
+use std::borrow::Cow;
+
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Helper};
+
+use rox::{Interpreter, Scanner, Token, TokenType, KEYWORDS};
+
+const MATCHED: &str = "\x1b[1;34m";
+const UNMATCHED: &str = "\x1b[1;31m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Default)]
+pub struct ReplHelper {
+    /// The globals defined when the prompt was last shown.
+    names: Vec<String>,
+}
+
+impl ReplHelper {
+    /// Picks up what the session has defined so far, for completion.
+    pub fn refresh(&mut self, interpreter: &Interpreter) {
+        self.names = interpreter
+            .globals()
+            .into_iter()
+            .map(|(name, _)| name.to_string())
+            .collect();
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete(line, pos, &self.names))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        highlight_brackets(line, pos)
+    }
+
+    // the brackets to highlight change as the cursor moves
+    fn highlight_char(&self, _: &str, _: usize) -> bool {
+        true
+    }
+}
+
+impl Validator for ReplHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        if is_incomplete(ctx.input()) {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+impl Helper for ReplHelper {}
+
+/// Where the word the cursor at `pos` is at the end of begins, and the
+/// keywords and `names` it could be the start of, sorted. Nothing is offered
+/// for a property after a `.`, as what the object has isn't known.
+pub fn complete(line: &str, pos: usize, names: &[String]) -> (usize, Vec<String>) {
+    let before = &line[..pos];
+    let start = before
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_alphanumeric() || *c == '_')
+        .last()
+        .map_or(pos, |(i, _)| i);
+    let word = &before[start..];
+    let after_dot = before[..start].trim_end().ends_with('.');
+    if word.is_empty() || after_dot || word.starts_with(|c: char| c.is_ascii_digit()) {
+        return (pos, Vec::new());
+    }
+
+    let mut candidates: Vec<String> = KEYWORDS
+        .iter()
+        .map(|keyword| keyword.to_string())
+        .chain(names.iter().cloned())
+        .filter(|candidate| candidate.starts_with(word))
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    (start, candidates)
+}
+
+/// Whether `input` stops inside a string or comment, or with more brackets
+/// opened than closed, so that Enter should start another line rather than
+/// run it. Meta-commands are always complete.
+pub fn is_incomplete(input: &str) -> bool {
+    if input.trim_start().starts_with(':') {
+        return false;
+    }
+
+    let mut scanner = Scanner::new(input.to_owned());
+    let _ = scanner.scan_tokens();
+    if scanner.is_unterminated() {
+        return true;
+    }
+    let depth = scanner.tokens().iter().fold(0, |depth, token| {
+        if is_opener(&token.tag) {
+            depth + 1
+        } else if closes(&token.tag).is_some() {
+            depth - 1
+        } else {
+            depth
+        }
+    });
+    depth > 0
+}
+
+fn is_opener(tag: &TokenType) -> bool {
+    matches!(
+        tag,
+        TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket
+    )
+}
+
+/// The opener `tag` closes, if it's a closer.
+fn closes(tag: &TokenType) -> Option<TokenType> {
+    match tag {
+        TokenType::RightParen => Some(TokenType::LeftParen),
+        TokenType::RightBrace => Some(TokenType::LeftBrace),
+        TokenType::RightBracket => Some(TokenType::LeftBracket),
+        _ => None,
+    }
+}
+
+/// `line` with the bracket next to the cursor and its partner in blue, and
+/// any closer that doesn't close anything in red.
+fn highlight_brackets(line: &str, pos: usize) -> Cow<'_, str> {
+    let mut scanner = Scanner::new(line.to_owned());
+    let _ = scanner.scan_tokens();
+    let tokens = scanner.tokens();
+
+    let mut open: Vec<&Token> = Vec::new();
+    let mut pairs = Vec::new();
+    let mut colored = Vec::new();
+    for token in tokens {
+        if is_opener(&token.tag) {
+            open.push(token);
+        } else if let Some(opener) = closes(&token.tag) {
+            match open.last() {
+                Some(last) if last.tag == opener => pairs.push((open.pop().unwrap(), token)),
+                _ => colored.push((token, UNMATCHED)),
+            }
+        }
+    }
+
+    // the bracket just before the cursor, or else the one under it
+    let at_cursor = |token: &Token| token.end == pos || token.start == pos;
+    let cursor_pair = pairs
+        .iter()
+        .filter(|(opener, closer)| at_cursor(opener) || at_cursor(closer))
+        .min_by_key(|(opener, closer)| (opener.end != pos && closer.end != pos) as u8);
+    if let Some((opener, closer)) = cursor_pair {
+        colored.push((opener, MATCHED));
+        colored.push((closer, MATCHED));
+    }
+    if colored.is_empty() {
+        return Cow::Borrowed(line);
+    }
+
+    colored.sort_by_key(|(token, _)| token.start);
+    let mut highlighted = String::with_capacity(line.len() + colored.len() * 12);
+    let mut copied = 0;
+    for (token, color) in colored {
+        highlighted.push_str(&line[copied..token.start]);
+        highlighted.push_str(color);
+        highlighted.push_str(&line[token.start..token.end]);
+        highlighted.push_str(RESET);
+        copied = token.end;
+    }
+    highlighted.push_str(&line[copied..]);
+    Cow::Owned(highlighted)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rox::Parser;
+    use std::io;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_completes_keywords_and_names() {
+        let defined = names(&["printer", "total"]);
+
+        assert_eq!(
+            complete("pri", 3, &defined),
+            (0, names(&["print", "printer"]))
+        );
+        assert_eq!(complete("var x = to", 10, &defined), (8, names(&["total"])));
+        assert_eq!(complete("if (tr", 6, &defined), (4, names(&["true"])));
+        // only what's before the cursor counts
+        assert_eq!(complete("to + 1", 2, &defined), (0, names(&["total"])));
+    }
+
+    #[test]
+    fn test_nothing_is_offered_without_a_word() {
+        let defined = names(&["total"]);
+
+        assert_eq!(complete("print ", 6, &defined), (6, vec![]));
+        assert_eq!(complete("point.to", 8, &defined), (8, vec![]));
+        assert_eq!(complete("print 1", 7, &defined), (7, vec![]));
+        assert_eq!(complete("print xyz", 9, &defined), (6, vec![]));
+    }
+
+    #[test]
+    fn test_completion_sees_what_the_session_defined() {
+        let mut interpreter = Interpreter::new(io::sink());
+        let mut helper = ReplHelper::default();
+        helper.refresh(&interpreter);
+        assert_eq!(complete("cou", 3, &helper.names), (0, vec![]));
+
+        let tokens = Scanner::new("var counter = 1;".to_owned())
+            .scan_tokens()
+            .unwrap()
+            .to_vec();
+        interpreter
+            .interpret(&Parser::new(tokens).parse().unwrap())
+            .unwrap();
+        helper.refresh(&interpreter);
+        assert_eq!(complete("cou", 3, &helper.names), (0, names(&["counter"])));
+    }
+
+    #[test]
+    fn test_open_brackets_continue_on_the_next_line() {
+        assert!(is_incomplete("fun f() {"));
+        assert!(is_incomplete("print (1 +"));
+        assert!(is_incomplete("var list = [1,"));
+        assert!(is_incomplete("fun f() {\n  if (x) {\n    print x;\n  }"));
+    }
+
+    #[test]
+    fn test_open_strings_and_comments_continue_on_the_next_line() {
+        assert!(is_incomplete("print \"two"));
+        assert!(is_incomplete("/* a comment"));
+        assert!(is_incomplete("print \"${1 +"));
+    }
+
+    #[test]
+    fn test_balanced_or_broken_input_runs() {
+        assert!(!is_incomplete(""));
+        assert!(!is_incomplete("print 1"));
+        assert!(!is_incomplete("fun f() {\n  print 1;\n}"));
+        // brackets in strings and comments don't count
+        assert!(!is_incomplete("print \"(\"; // {"));
+        // too many closers is a mistake to report, not wait out
+        assert!(!is_incomplete("print 1);"));
+        assert!(!is_incomplete("print @;"));
+        assert!(!is_incomplete(":load {"));
+    }
+
+    #[test]
+    fn test_highlights_the_bracket_at_the_cursor_and_its_partner() {
+        let line = "f(a[1])";
+
+        assert_eq!(
+            highlight_brackets(line, 7),
+            format!("f{0}({1}a[1]{0}){1}", MATCHED, RESET)
+        );
+        assert_eq!(
+            highlight_brackets(line, 3),
+            format!("f(a{0}[{1}1{0}]{1})", MATCHED, RESET)
+        );
+        assert_eq!(highlight_brackets(line, 0), line);
+    }
+
+    #[test]
+    fn test_highlights_unmatched_closers() {
+        assert_eq!(
+            highlight_brackets("(1]", 0),
+            format!("(1{}]{}", UNMATCHED, RESET)
+        );
+        assert_eq!(
+            highlight_brackets("1)", 0),
+            format!("1{}){}", UNMATCHED, RESET)
+        );
+    }
+}
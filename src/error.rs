@@ -1,3 +1,453 @@
-pub fn report(line: u32, message: &str) {
-    eprintln!("[line {}] Error: {}", line, message);
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use crate::token::{Name, Token};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoxError {
+    /// `column` counts characters from the start of the line, starting at 0
+    /// like `line` does, though both are shown counting from 1. `lexeme` is the text that could not be scanned.
+    Scan {
+        line: u32,
+        column: u32,
+        lexeme: String,
+        message: String,
+    },
+    /// `token` is the lexeme the parser stopped at, `None` at the end of input.
+    Parse {
+        line: u32,
+        column: u32,
+        token: Option<String>,
+        message: String,
+    },
+    /// Found by the resolver after parsing succeeded. `name` is the
+    /// variable the error is about.
+    Resolve {
+        line: u32,
+        column: u32,
+        name: String,
+        message: String,
+    },
+    /// `column` is `None` when the failing node only records its line.
+    /// `frames` are the calls the error unwound through, innermost first.
+    Runtime {
+        line: u32,
+        column: Option<u32>,
+        message: String,
+        frames: Vec<Frame>,
+    },
+    /// Execution ran past a limit set with `Interpreter::with_limits`.
+    /// `line` is `None` when the limit was hit between expressions.
+    BudgetExceeded { line: Option<u32>, message: String },
+}
+
+/// A call that was in progress when a runtime error happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub function: String,
+    /// The line the function was called from.
+    pub line: u32,
+}
+
+/// How many frames `render` shows before summing up the rest.
+const MAX_RENDERED_FRAMES: usize = 10;
+
+impl RoxError {
+    pub fn runtime(line: u32, message: impl Into<String>) -> Self {
+        RoxError::Runtime {
+            line,
+            column: None,
+            message: message.into(),
+            frames: Vec::new(),
+        }
+    }
+
+    /// A runtime error pointing at `token`.
+    pub fn runtime_at(token: &Token, message: impl Into<String>) -> Self {
+        RoxError::Runtime {
+            line: token.line,
+            column: Some(token.column),
+            message: message.into(),
+            frames: Vec::new(),
+        }
+    }
+
+    /// Records that the error unwound through the call `frame`. Only
+    /// runtime errors keep their frames.
+    pub fn called_from(mut self, frame: Frame) -> Self {
+        if let RoxError::Runtime { frames, .. } = &mut self {
+            frames.push(frame);
+        }
+        self
+    }
+
+    /// Where the error happened. The column is `None` for runtime errors
+    /// that only know their line, and the line is 0 for a budget error that
+    /// doesn't know that either.
+    pub fn position(&self) -> (u32, Option<u32>) {
+        match self {
+            RoxError::Scan { line, column, .. }
+            | RoxError::Parse { line, column, .. }
+            | RoxError::Resolve { line, column, .. } => (*line, Some(*column)),
+            RoxError::Runtime { line, column, .. } => (*line, *column),
+            RoxError::BudgetExceeded { line, .. } => (line.unwrap_or(0), None),
+        }
+    }
+
+    /// A runtime error pointing at the variable `name`.
+    pub fn runtime_at_name(name: &Name, message: impl Into<String>) -> Self {
+        RoxError::Runtime {
+            line: name.line,
+            column: Some(name.column),
+            message: message.into(),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            RoxError::Scan { message, .. }
+            | RoxError::Parse { message, .. }
+            | RoxError::Resolve { message, .. }
+            | RoxError::Runtime { message, .. }
+            | RoxError::BudgetExceeded { message, .. } => message,
+        }
+    }
+}
+
+/// Lines and columns are shown counting from 1, as editors count them.
+impl Display for RoxError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            RoxError::Scan {
+                line,
+                column,
+                message,
+                ..
+            } => write!(
+                f,
+                "[line {}, col {}] Error: {}",
+                line + 1,
+                column + 1,
+                message
+            ),
+            RoxError::Parse {
+                line,
+                column,
+                token: Some(token),
+                message,
+            } => write!(
+                f,
+                "[line {}, col {}] Error at '{}': {}",
+                line + 1,
+                column + 1,
+                token,
+                message
+            ),
+            RoxError::Parse {
+                line,
+                column,
+                token: None,
+                message,
+            } => write!(
+                f,
+                "[line {}, col {}] Error at end: {}",
+                line + 1,
+                column + 1,
+                message
+            ),
+            RoxError::Resolve {
+                line,
+                column,
+                name,
+                message,
+            } => write!(
+                f,
+                "[line {}, col {}] Error at '{}': {}",
+                line + 1,
+                column + 1,
+                name,
+                message
+            ),
+            RoxError::Runtime {
+                line,
+                column: Some(column),
+                message,
+                ..
+            } => write!(
+                f,
+                "[line {}, col {}] Error: {}",
+                line + 1,
+                column + 1,
+                message
+            ),
+            RoxError::Runtime {
+                line,
+                column: None,
+                message,
+                ..
+            } => write!(f, "[line {}] Error: {}", line + 1, message),
+            RoxError::BudgetExceeded {
+                line: Some(line),
+                message,
+            } => write!(f, "[line {}] Error: {}", line + 1, message),
+            RoxError::BudgetExceeded {
+                line: None,
+                message,
+            } => write!(f, "Error: {}", message),
+        }
+    }
+}
+
+impl Error for RoxError {}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "in function '{}' called at line {}",
+            self.function,
+            self.line + 1
+        )
+    }
+}
+
+/// Something suspicious found before running a program that doesn't stop it
+/// from running.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub line: u32,
+    pub column: u32,
+    pub message: String,
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[line {}, col {}] Warning: {}",
+            self.line + 1,
+            self.column + 1,
+            self.message
+        )
+    }
+}
+
+/// An error or a warning found in a program without running it, as
+/// `check_source` reports them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Diagnostic {
+    Error(RoxError),
+    Warning(Warning),
+}
+
+impl Diagnostic {
+    pub fn is_error(&self) -> bool {
+        matches!(self, Diagnostic::Error(_))
+    }
+
+    /// The line and column it was found at. An error without a column is
+    /// at the start of its line.
+    pub fn position(&self) -> (u32, u32) {
+        match self {
+            Diagnostic::Error(error) => {
+                let (line, column) = error.position();
+                (line, column.unwrap_or(0))
+            }
+            Diagnostic::Warning(warning) => (warning.line, warning.column),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Diagnostic::Error(error) => write!(f, "{}", error),
+            Diagnostic::Warning(warning) => write!(f, "{}", warning),
+        }
+    }
+}
+
+/// The error followed by the line of `source` it is on, with a caret under
+/// its column when it has one, then the calls a runtime error unwound
+/// through, a line each.
+pub fn render(error: &RoxError, source: &str) -> String {
+    if let RoxError::BudgetExceeded { line: None, .. } = error {
+        return error.to_string();
+    }
+    let (line, column) = error.position();
+    let mut rendered = format!("{}{}", error, snippet(source, line, column));
+    if let RoxError::Runtime { frames, .. } = error {
+        for frame in frames.iter().take(MAX_RENDERED_FRAMES) {
+            rendered += &format!("\n    {}", frame);
+        }
+        if frames.len() > MAX_RENDERED_FRAMES {
+            let more = frames.len() - MAX_RENDERED_FRAMES;
+            rendered += &format!("\n    ... and {} more frames", more);
+        }
+    }
+    rendered
+}
+
+/// Like `render`, for a warning.
+pub fn render_warning(warning: &Warning, source: &str) -> String {
+    format!(
+        "{}{}",
+        warning,
+        snippet(source, warning.line, Some(warning.column))
+    )
+}
+
+/// `render` or `render_warning`, whichever `diagnostic` is.
+pub fn render_diagnostic(diagnostic: &Diagnostic, source: &str) -> String {
+    match diagnostic {
+        Diagnostic::Error(error) => render(error, source),
+        Diagnostic::Warning(warning) => render_warning(warning, source),
+    }
+}
+
+/// Nothing when `line` is past the end of `source`. Tabs before the column
+/// are kept, so the caret lines up however wide they are shown.
+fn snippet(source: &str, line: u32, column: Option<u32>) -> String {
+    let text = match source.lines().nth(line as usize) {
+        Some(text) => text,
+        None => return String::new(),
+    };
+
+    let gutter = (line + 1).to_string();
+    let mut snippet = format!("\n{} | {}", gutter, text);
+    if let Some(column) = column {
+        let mut chars = text.chars();
+        let indent: String = (0..column)
+            .map(|_| match chars.next() {
+                Some('\t') => '\t',
+                _ => ' ',
+            })
+            .collect();
+        snippet += &format!("\n{} | {}^", " ".repeat(gutter.len()), indent);
+    }
+    snippet
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let parse_error = RoxError::Parse {
+            line: 3,
+            column: 14,
+            token: Some(";".to_owned()),
+            message: "expected an expression".to_owned(),
+        };
+        assert_eq!(
+            parse_error.to_string(),
+            "[line 4, col 15] Error at ';': expected an expression"
+        );
+
+        let end_error = RoxError::Parse {
+            line: 3,
+            column: 0,
+            token: None,
+            message: "unexpected end of input".to_owned(),
+        };
+        assert_eq!(
+            end_error.to_string(),
+            "[line 4, col 1] Error at end: unexpected end of input"
+        );
+
+        let resolve_error = RoxError::Resolve {
+            line: 2,
+            column: 12,
+            name: "a".to_owned(),
+            message: "Cannot read local variable in its own initializer".to_owned(),
+        };
+        assert_eq!(
+            resolve_error.to_string(),
+            "[line 3, col 13] Error at 'a': Cannot read local variable in its own initializer"
+        );
+
+        assert_eq!(
+            RoxError::runtime(1, "can't return from top-level code").to_string(),
+            "[line 2] Error: can't return from top-level code"
+        );
+
+        let plus = Token::with_column(crate::token::TokenType::Plus, 1, 6);
+        assert_eq!(
+            RoxError::runtime_at(&plus, "Divide by zero error").to_string(),
+            "[line 2, col 7] Error: Divide by zero error"
+        );
+
+        let warning = Warning {
+            line: 4,
+            column: 8,
+            message: "variable 'x' is never read".to_owned(),
+        };
+        assert_eq!(
+            warning.to_string(),
+            "[line 5, col 9] Warning: variable 'x' is never read"
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let source = "var a = 1;\n\tprint -true;\n";
+
+        let unary = Token::with_column(crate::token::TokenType::Minus, 1, 7);
+        assert_eq!(
+            render(&RoxError::runtime_at(&unary, "bad operand"), source),
+            "[line 2, col 8] Error: bad operand\n2 | \tprint -true;\n  | \t      ^"
+        );
+
+        // without a column only the line is shown
+        assert_eq!(
+            render(&RoxError::runtime(0, "oops"), source),
+            "[line 1] Error: oops\n1 | var a = 1;"
+        );
+
+        let end = RoxError::Parse {
+            line: 0,
+            column: 10,
+            token: None,
+            message: "expected ';'".to_owned(),
+        };
+        assert_eq!(
+            render(&end, source),
+            "[line 1, col 11] Error at end: expected ';'\n1 | var a = 1;\n  |           ^"
+        );
+
+        // the calls it unwound through follow, innermost first, and past
+        // the first ten are only counted
+        let mut deep = RoxError::runtime(0, "oops");
+        for line in 0..12 {
+            deep = deep.called_from(Frame {
+                function: format!("f{}", line),
+                line,
+            });
+        }
+        let rendered = render(&deep, source);
+        let lines: Vec<_> = rendered.lines().collect();
+        assert_eq!(lines.len(), 13);
+        assert_eq!(lines[2], "    in function 'f0' called at line 1");
+        assert_eq!(lines[11], "    in function 'f9' called at line 10");
+        assert_eq!(lines[12], "    ... and 2 more frames");
+        assert_eq!(deep.to_string(), "[line 1] Error: oops");
+
+        // past the end of the source there is nothing to show
+        assert_eq!(
+            render(&RoxError::runtime(9, "oops"), source),
+            "[line 10] Error: oops"
+        );
+
+        let warning = Warning {
+            line: 0,
+            column: 4,
+            message: "unused".to_owned(),
+        };
+        assert_eq!(
+            render_warning(&warning, source),
+            "[line 1, col 5] Warning: unused\n1 | var a = 1;\n  |     ^"
+        );
+    }
 }
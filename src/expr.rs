@@ -1,10 +1,24 @@
-use crate::token::Token;
+use crate::number;
+use crate::span::Span;
+use crate::statement::Stmt;
+use crate::symbol::Symbol;
+use crate::token::{Name, Token};
 use std::fmt::{self, Display, Formatter};
+use std::sync::Arc;
 
-#[derive(Debug, PartialEq, Clone)]
-pub enum Expr {
+/// An expression and the source it was parsed from.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ExprKind {
     Assign {
-        name: Token,
+        name: Name,
         value: Box<Expr>,
     },
     Binary {
@@ -16,6 +30,10 @@ pub enum Expr {
         expr: Box<Expr>,
     },
     Literal(LiteralValue),
+    /// `"a ${x} b"`: string literals for the text, with the expressions in
+    /// between, so `x` is `parts[1]`. Each value is joined on as `+` would
+    /// join it to a string.
+    Interpolation(Vec<Expr>),
     Unary {
         operator: Token,
         operand: Box<Expr>,
@@ -25,44 +43,336 @@ pub enum Expr {
         true_expr: Box<Expr>,
         false_expr: Box<Expr>,
     },
-    Variable(Token),
+    Variable(Name),
+    /// `++name` or `--name`, which evaluates to the updated value. There is
+    /// no postfix form.
+    Increment {
+        operator: Token,
+        name: Name,
+    },
+    /// `defined name`: whether `name` is bound, without failing when it isn't.
+    Defined(Name),
+    /// `fun (params) { body }`, a function without a name.
+    Lambda {
+        params: Vec<Name>,
+        body: Arc<Vec<Stmt>>,
+    },
+    Call {
+        callee: Box<Expr>,
+        paren: Token,
+        arguments: Vec<Expr>,
+    },
+    Get {
+        object: Box<Expr>,
+        name: Token,
+    },
+    /// `object.name = value`, which creates the property if it's missing.
+    Set {
+        object: Box<Expr>,
+        name: Token,
+        value: Box<Expr>,
+    },
+    /// `this` in a method, the instance it was called on. Looked up like a
+    /// variable named `this`.
+    This(Name),
+    /// `super.method`, the superclass's method bound to `this`. `keyword`
+    /// is looked up like a variable named `super`.
+    Super {
+        keyword: Name,
+        method: Token,
+    },
+    /// `[a, b, c]`
+    ArrayLiteral(Vec<Expr>),
+    /// `{"a": 1, "b": 2}`. Errors about the keys are reported at the brace.
+    MapLiteral {
+        brace: Token,
+        entries: Vec<(Expr, Expr)>,
+    },
+    /// `object[index]`. Errors are reported at the opening bracket.
+    Index {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+    },
+    /// `object[index] = value`
+    SetIndex {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+        value: Box<Expr>,
+    },
+    Error {
+        line: u32,
+        message: String,
+    },
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub enum LiteralValue {
-    True,
-    False,
-    Nil,
-    STRING(String),
-    Number(f32),
-}
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
 
-impl LiteralValue {
-    pub fn is_truthy(&self) -> bool {
-        match self {
-            LiteralValue::False | LiteralValue::Nil => false,
-            _ => true,
+    /// The line of the first error node in this expression, if it has any.
+    pub fn error_line(&self) -> Option<u32> {
+        match &self.kind {
+            ExprKind::Error { line, .. } => Some(*line),
+            ExprKind::Literal(_)
+            | ExprKind::Variable(_)
+            | ExprKind::Defined(_)
+            | ExprKind::This(_)
+            | ExprKind::Super { .. }
+            | ExprKind::Lambda { .. }
+            | ExprKind::Increment { .. } => None,
+            ExprKind::Assign { value, .. } => value.error_line(),
+            ExprKind::Binary { left, right, .. } => {
+                left.error_line().or_else(|| right.error_line())
+            }
+            ExprKind::Grouping { expr } => expr.error_line(),
+            ExprKind::Unary { operand, .. } => operand.error_line(),
+            ExprKind::Call {
+                callee, arguments, ..
+            } => callee
+                .error_line()
+                .or_else(|| arguments.iter().find_map(Expr::error_line)),
+            ExprKind::Get { object, .. } => object.error_line(),
+            ExprKind::Set { object, value, .. } => {
+                object.error_line().or_else(|| value.error_line())
+            }
+            ExprKind::ArrayLiteral(elements) | ExprKind::Interpolation(elements) => {
+                elements.iter().find_map(Expr::error_line)
+            }
+            ExprKind::MapLiteral { entries, .. } => entries
+                .iter()
+                .find_map(|(key, value)| key.error_line().or_else(|| value.error_line())),
+            ExprKind::Index { object, index, .. } => {
+                object.error_line().or_else(|| index.error_line())
+            }
+            ExprKind::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => object
+                .error_line()
+                .or_else(|| index.error_line())
+                .or_else(|| value.error_line()),
+            ExprKind::Ternary {
+                condition,
+                true_expr,
+                false_expr,
+            } => condition
+                .error_line()
+                .or_else(|| true_expr.error_line())
+                .or_else(|| false_expr.error_line()),
         }
     }
 
-    pub fn is_number(&self) -> bool {
-        match self {
-            LiteralValue::Number(_) => true,
+    /// Whether `self` and `other` are the same tree wherever they were
+    /// written. `==` ignores spans but not the lines and columns kept in
+    /// tokens and names, so the same program laid out differently doesn't
+    /// compare equal with it. Bindings are still compared.
+    pub fn structural_eq(&self, other: &Self) -> bool {
+        use ExprKind::*;
+        match (&self.kind, &other.kind) {
+            (Assign { name, value }, Assign { name: n, value: v }) => name.same(n) && value.same(v),
+            (
+                Binary {
+                    left,
+                    operator,
+                    right,
+                },
+                Binary {
+                    left: l,
+                    operator: o,
+                    right: r,
+                },
+            ) => left.same(l) && operator.same(o) && right.same(r),
+            (Grouping { expr }, Grouping { expr: e }) => expr.same(e),
+            (Literal(literal), Literal(l)) => literal == l,
+            (
+                Unary { operator, operand },
+                Unary {
+                    operator: o,
+                    operand: e,
+                },
+            ) => operator.same(o) && operand.same(e),
+            (
+                Ternary {
+                    condition,
+                    true_expr,
+                    false_expr,
+                },
+                Ternary {
+                    condition: c,
+                    true_expr: t,
+                    false_expr: f,
+                },
+            ) => condition.same(c) && true_expr.same(t) && false_expr.same(f),
+            (Variable(name), Variable(n)) | (Defined(name), Defined(n)) | (This(name), This(n)) => {
+                name.same(n)
+            }
+            (
+                Increment { operator, name },
+                Increment {
+                    operator: o,
+                    name: n,
+                },
+            ) => operator.same(o) && name.same(n),
+            (Lambda { params, body }, Lambda { params: p, body: b }) => {
+                params.same(p) && body.same(b)
+            }
+            (
+                Call {
+                    callee, arguments, ..
+                },
+                Call {
+                    callee: c,
+                    arguments: a,
+                    ..
+                },
+            ) => callee.same(c) && arguments.same(a),
+            (Get { object, name }, Get { object: o, name: n }) => object.same(o) && name.same(n),
+            (
+                Set {
+                    object,
+                    name,
+                    value,
+                },
+                Set {
+                    object: o,
+                    name: n,
+                    value: v,
+                },
+            ) => object.same(o) && name.same(n) && value.same(v),
+            (
+                Super { keyword, method },
+                Super {
+                    keyword: k,
+                    method: m,
+                },
+            ) => keyword.same(k) && method.same(m),
+            (ArrayLiteral(elements), ArrayLiteral(e)) => elements.same(e),
+            (Interpolation(parts), Interpolation(p)) => parts.same(p),
+            (MapLiteral { entries, .. }, MapLiteral { entries: e, .. }) => entries.same(e),
+            (
+                Index { object, index, .. },
+                Index {
+                    object: o,
+                    index: i,
+                    ..
+                },
+            ) => object.same(o) && index.same(i),
+            (
+                SetIndex {
+                    object,
+                    index,
+                    value,
+                    ..
+                },
+                SetIndex {
+                    object: o,
+                    index: i,
+                    value: v,
+                    ..
+                },
+            ) => object.same(o) && index.same(i) && value.same(v),
+            (Error { message, .. }, Error { message: m, .. }) => message == m,
             _ => false,
         }
     }
 }
 
-impl From<bool> for LiteralValue {
-    fn from(b: bool) -> Self {
-        if b {
-            LiteralValue::True
-        } else {
-            LiteralValue::False
+/// Expressions are compared by what they say, not where they were written,
+/// so a parsed tree equals the same tree built by hand.
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+/// Comparison that ignores where things were written, for the parts of a
+/// syntax tree. See `Expr::structural_eq`.
+pub(crate) trait Structural {
+    fn same(&self, other: &Self) -> bool;
+}
+
+impl Structural for Expr {
+    fn same(&self, other: &Self) -> bool {
+        self.structural_eq(other)
+    }
+}
+
+impl Structural for Stmt {
+    fn same(&self, other: &Self) -> bool {
+        self.structural_eq(other)
+    }
+}
+
+impl Structural for Token {
+    fn same(&self, other: &Self) -> bool {
+        self.tag == other.tag
+    }
+}
+
+impl Structural for Name {
+    fn same(&self, other: &Self) -> bool {
+        self.symbol == other.symbol && self.binding == other.binding
+    }
+}
+
+impl<T: Structural> Structural for Box<T> {
+    fn same(&self, other: &Self) -> bool {
+        (**self).same(other)
+    }
+}
+
+impl<T: Structural> Structural for Arc<T> {
+    fn same(&self, other: &Self) -> bool {
+        (**self).same(other)
+    }
+}
+
+impl<T: Structural> Structural for Option<T> {
+    fn same(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Some(a), Some(b)) => a.same(b),
+            (a, b) => a.is_none() && b.is_none(),
         }
     }
 }
 
+impl<T: Structural> Structural for Vec<T> {
+    fn same(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().zip(other).all(|(a, b)| a.same(b))
+    }
+}
+
+impl<A: Structural, B: Structural> Structural for (A, B) {
+    fn same(&self, other: &Self) -> bool {
+        self.0.same(&other.0) && self.1.same(&other.1)
+    }
+}
+
+/// An expression that wasn't parsed, so has no source to point at. Handy
+/// for building trees by hand.
+impl From<ExprKind> for Expr {
+    fn from(kind: ExprKind) -> Self {
+        Self::new(kind, Span::default())
+    }
+}
+
+/// A literal as written in the source. The interpreter turns these into
+/// runtime values.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LiteralValue {
+    True,
+    False,
+    Nil,
+    STRING(Symbol),
+    Number(f64),
+}
+
 impl Display for LiteralValue {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self {
@@ -70,36 +380,164 @@ impl Display for LiteralValue {
             LiteralValue::False => write!(f, "false"),
             LiteralValue::Nil => write!(f, "nil"),
             LiteralValue::STRING(s) => write!(f, "{}", s),
-            LiteralValue::Number(s) => write!(f, "{}", s.to_string()),
+            LiteralValue::Number(n) => write!(f, "{}", number::format(*n)),
         }
     }
 }
 
-#[cfg(test)]
-mod print {
-    use super::Expr;
+/// Renders syntax trees as parenthesized s-expressions, for debugging the
+/// parser.
+pub mod print {
+    use super::{Expr, ExprKind};
+    use crate::statement::{Stmt, StmtKind};
+
+    pub fn print_stmt(s: &Stmt) -> String {
+        match &s.kind {
+            StmtKind::Expr(e) => format!("(expr {})", print_ast(e)),
+            StmtKind::Print { values, .. } => {
+                let values: Vec<_> = values.iter().map(print_ast).collect();
+                format!("(print {})", values.join(" "))
+            }
+            StmtKind::Eprint { values, .. } => {
+                let values: Vec<_> = values.iter().map(print_ast).collect();
+                format!("(eprint {})", values.join(" "))
+            }
+            StmtKind::Assert { condition, .. } => format!("(assert {})", print_ast(condition)),
+            StmtKind::Import { path, .. } => format!("(import \"{}\")", path),
+            StmtKind::VarDec {
+                name,
+                initializer: Some(e),
+            } => format!("(var '{}' {})", name, print_ast(e)),
+            StmtKind::VarDec {
+                name,
+                initializer: None,
+            } => format!("(var '{}')", name),
+            StmtKind::Const { name, initializer } => {
+                format!("(const '{}' {})", name, print_ast(initializer))
+            }
+            StmtKind::Block(statements) => group("block", statements.iter()),
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let mut res = format!("(if {} {}", print_ast(condition), print_stmt(then_branch));
+                if let Some(else_branch) = else_branch {
+                    res += " ";
+                    res += &print_stmt(else_branch);
+                }
+                res.push(')');
+                res
+            }
+            StmtKind::While { condition, body } => {
+                format!("(while {} {})", print_ast(condition), print_stmt(body))
+            }
+            StmtKind::DoWhile { body, condition } => {
+                format!("(do {} while {})", print_stmt(body), print_ast(condition))
+            }
+            StmtKind::Function { name, params, body } => {
+                let params: Vec<_> = params.iter().map(|p| p.to_string()).collect();
+                group(
+                    &format!("fun '{}' ({})", name, params.join(" ")),
+                    body.iter(),
+                )
+            }
+            StmtKind::Class {
+                name,
+                superclass: Some(superclass),
+                methods,
+            } => group(
+                &format!("class '{}' < '{}'", name, superclass),
+                methods.iter(),
+            ),
+            StmtKind::Class {
+                name,
+                superclass: None,
+                methods,
+            } => group(&format!("class '{}'", name), methods.iter()),
+            StmtKind::Return { value: Some(e), .. } => format!("(return {})", print_ast(e)),
+            StmtKind::Return { value: None, .. } => "(return)".to_owned(),
+            StmtKind::Error { .. } => "(error)".to_owned(),
+        }
+    }
+
+    fn group<'a>(name: &str, statements: impl Iterator<Item = &'a Stmt>) -> String {
+        let mut res = format!("({}", name);
+        for s in statements {
+            res += " ";
+            res += &print_stmt(s);
+        }
+        res.push(')');
+        res
+    }
 
     pub fn print_ast(e: &Expr) -> String {
-        match e {
-            Expr::Assign { name, value } => {
-                parenthesize(&format!("assign '{}'", name.tag), &[value])
+        match &e.kind {
+            ExprKind::Assign { name, value } => {
+                parenthesize(&format!("assign '{}'", name), &[value])
             }
-            Expr::Binary {
+            ExprKind::Binary {
                 left,
                 operator,
                 right,
             } => parenthesize(&operator.tag.to_string(), &[left, right]),
-            Expr::Unary { operator, operand } => {
+            ExprKind::Unary { operator, operand } => {
                 parenthesize(&operator.tag.to_string(), &[operand])
             }
-            Expr::Literal(val) => val.to_string(),
-            Expr::Grouping { expr } => parenthesize("grouping", &[expr]),
-            Expr::Ternary {
+            ExprKind::Literal(val) => val.to_string(),
+            ExprKind::Interpolation(parts) => {
+                let parts: Vec<_> = parts.iter().collect();
+                parenthesize("interpolation", &parts)
+            }
+            ExprKind::Grouping { expr } => parenthesize("grouping", &[expr]),
+            ExprKind::Ternary {
                 condition,
                 true_expr,
                 false_expr,
             } => parenthesize("?", &[condition, true_expr, false_expr]),
-            Expr::Variable(val) => format!("variable: '{}'", val),
+            ExprKind::Variable(name) => format!("variable: '{}'", name),
+            ExprKind::Defined(name) => format!("(defined '{}')", name),
+            ExprKind::Lambda { params, body } => {
+                let params: Vec<_> = params.iter().map(|p| p.to_string()).collect();
+                group(&format!("fun ({})", params.join(" ")), body.iter())
+            }
+            ExprKind::Increment { operator, name } => format!("({} '{}')", operator.tag, name),
+            ExprKind::Call {
+                callee, arguments, ..
+            } => {
+                let mut exprs = vec![&**callee];
+                exprs.extend(arguments.iter());
+                parenthesize("call", &exprs)
+            }
+            ExprKind::Get { object, name } => {
+                parenthesize(&format!("get '{}'", name.tag), &[object])
+            }
+            ExprKind::Set {
+                object,
+                name,
+                value,
+            } => parenthesize(&format!("set '{}'", name.tag), &[object, value]),
+            ExprKind::This(_) => "this".to_owned(),
+            ExprKind::Super { method, .. } => format!("(super '{}')", method.tag),
+            ExprKind::ArrayLiteral(elements) => {
+                let elements: Vec<_> = elements.iter().collect();
+                parenthesize("list", &elements)
+            }
+            ExprKind::MapLiteral { entries, .. } => {
+                let entries: Vec<_> = entries
+                    .iter()
+                    .flat_map(|(key, value)| vec![key, value])
+                    .collect();
+                parenthesize("map", &entries)
+            }
+            ExprKind::Index { object, index, .. } => parenthesize("index", &[object, index]),
+            ExprKind::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => parenthesize("set-index", &[object, index, value]),
+            ExprKind::Error { .. } => "(error)".to_owned(),
         }
     }
 
@@ -113,24 +551,26 @@ mod print {
         res
     }
 
+    #[cfg(test)]
     fn rpn(e: &Expr) -> String {
-        match e {
-            Expr::Binary {
+        match &e.kind {
+            ExprKind::Binary {
                 left,
                 operator,
                 right,
-            } => format!("{} {} {}", rpn(left), rpn(right), operator.tag.to_string()),
-            Expr::Literal(val) => val.to_string(),
-            Expr::Grouping { expr } => rpn(expr),
-            Expr::Unary { operator, operand } => {
-                format!("{}{}", operator.tag.to_string(), rpn(operand))
+            } => format!("{} {} {}", rpn(left), rpn(right), operator.tag),
+            ExprKind::Literal(val) => val.to_string(),
+            ExprKind::Grouping { expr } => rpn(expr),
+            ExprKind::Unary { operator, operand } => {
+                format!("{}{}", operator.tag, rpn(operand))
             }
             _ => unreachable!(),
         }
     }
 
+    #[cfg(test)]
     mod test {
-        use super::{print_ast, rpn, Expr};
+        use super::{print_ast, print_stmt, rpn, Expr, ExprKind};
         use crate::expr::LiteralValue;
         use crate::token::{Token, TokenType};
 
@@ -140,16 +580,23 @@ mod print {
             let mul_operator = Token::new(TokenType::Star, 1);
             let lit_123 = LiteralValue::Number(123.0);
             let lit_4567 = LiteralValue::Number(45.67);
-            let expr = Expr::Binary {
-                left: Box::new(Expr::Unary {
-                    operator: minus_operator,
-                    operand: Box::new(Expr::Literal(lit_123)),
-                }),
+            let expr: Expr = ExprKind::Binary {
+                left: Box::new(
+                    ExprKind::Unary {
+                        operator: minus_operator,
+                        operand: Box::new(ExprKind::Literal(lit_123).into()),
+                    }
+                    .into(),
+                ),
                 operator: mul_operator,
-                right: Box::new(Expr::Grouping {
-                    expr: Box::new(Expr::Literal(lit_4567)),
-                }),
-            };
+                right: Box::new(
+                    ExprKind::Grouping {
+                        expr: Box::new(ExprKind::Literal(lit_4567).into()),
+                    }
+                    .into(),
+                ),
+            }
+            .into();
 
             assert_eq!(print_ast(&expr), "(* (- 123) (grouping 45.67))")
         }
@@ -159,21 +606,52 @@ mod print {
             let minus_operator = Token::new(TokenType::Minus, 1);
             let mul_operator = Token::new(TokenType::Star, 1);
             let plus_operator = Token::new(TokenType::Plus, 1);
-            let expr = Expr::Binary {
-                left: Box::new(Expr::Binary {
-                    left: Box::new(Expr::Literal(LiteralValue::Number(1.0))),
-                    operator: plus_operator,
-                    right: Box::new(Expr::Literal(LiteralValue::Number(2.0))),
-                }),
+            let number = |n| Box::new(Expr::from(ExprKind::Literal(LiteralValue::Number(n))));
+            let expr: Expr = ExprKind::Binary {
+                left: Box::new(
+                    ExprKind::Binary {
+                        left: number(1.0),
+                        operator: plus_operator,
+                        right: number(2.0),
+                    }
+                    .into(),
+                ),
                 operator: mul_operator,
-                right: Box::new(Expr::Binary {
-                    left: Box::new(Expr::Literal(LiteralValue::Number(4.0))),
-                    operator: minus_operator,
-                    right: Box::new(Expr::Literal(LiteralValue::Number(3.0))),
-                }),
-            };
+                right: Box::new(
+                    ExprKind::Binary {
+                        left: number(4.0),
+                        operator: minus_operator,
+                        right: number(3.0),
+                    }
+                    .into(),
+                ),
+            }
+            .into();
 
             assert_eq!(rpn(&expr), "1 2 + 4 3 - *")
         }
+
+        #[test]
+        fn test_print_stmt() {
+            use crate::parser::Parser;
+            use crate::scanner::Scanner;
+
+            let source = "var a = 1; if (a) { print a; } else a = 2; fun f(x, y) { return; }";
+            let tokens = Scanner::new(source.to_owned())
+                .scan_tokens()
+                .unwrap()
+                .to_vec();
+            let statements = Parser::new(tokens).parse().unwrap();
+            let printed: Vec<_> = statements.iter().map(print_stmt).collect();
+
+            assert_eq!(
+                printed,
+                vec![
+                    "(var 'a' 1)",
+                    "(if variable: 'a' (block (print variable: 'a')) (expr (assign 'a' 2)))",
+                    "(fun 'f' (x y) (return))",
+                ]
+            )
+        }
     }
 }
@@ -0,0 +1,903 @@
+//! Writes syntax trees back out as Lox source in one canonical layout: a
+//! statement per line, four spaces of indentation per block, and a space on
+//! either side of every binary operator.
+//!
+//! Parsing the output gives back the same tree, so the only parentheses
+//! written are the groupings the tree already has, plus any a tree built by
+//! hand needs to parse the same way. A few things don't survive the trip
+//! through the parser and so can't be written back:
+//!
+//! - comments, which the scanner throws away,
+//! - `for` loops, which come out as the `while` loops they desugar into,
+//! - statements that failed to parse, which are written as `/* error */`.
+//!
+//! Function expressions are the exception to the layout: their bodies are
+//! written on the one line, with a space wherever a newline would go.
+
+use crate::expr::{Expr, ExprKind, LiteralValue};
+use crate::number;
+use crate::parser::{self, Associativity};
+use crate::statement::{Stmt, StmtKind};
+use crate::token::{Name, TokenType};
+use std::slice;
+
+const INDENT: &str = "    ";
+
+// How tightly each kind of expression binds, loosest first. A child that
+// binds more loosely than its position allows is wrapped in parentheses.
+const COMMA: u8 = 0;
+const ASSIGNMENT: u8 = 1;
+const TERNARY: u8 = 2;
+/// Added to the parser's binary operator precedence, which starts at 1.
+const BINARY: u8 = 2;
+const LOWEST_BINARY: u8 = BINARY + 1;
+const UNARY: u8 = 7;
+const CALL: u8 = 8;
+const PRIMARY: u8 = 9;
+
+/// Formats a whole program, ending every statement with a newline.
+pub fn format_program(statements: &[Stmt]) -> String {
+    let mut formatter = Formatter::default();
+    for statement in statements {
+        formatter.statement(statement);
+        formatter.out.push('\n');
+    }
+    formatter.out
+}
+
+/// Formats a single expression on one line.
+pub fn format_expr(e: &Expr) -> String {
+    expr(e, COMMA)
+}
+
+#[derive(Default)]
+struct Formatter {
+    out: String,
+    indent: usize,
+    one_line: bool,
+}
+
+impl Formatter {
+    fn new_line(&mut self) {
+        if self.one_line {
+            self.out.push(' ');
+            return;
+        }
+        self.out.push('\n');
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    /// Writes `s` from the current position. Any lines after the first are
+    /// indented to the current depth, and no newline is written after it.
+    fn statement(&mut self, s: &Stmt) {
+        match &s.kind {
+            StmtKind::Expr(e) => {
+                let text = expr(e, COMMA);
+                // a brace at the start of a statement would be taken as a
+                // block rather than a map
+                if text.starts_with('{') {
+                    self.out += &format!("({});", text);
+                } else {
+                    self.out += &format!("{};", text);
+                }
+            }
+            StmtKind::Print { values, .. } => {
+                self.out += &format!("print {};", print_values(values))
+            }
+            StmtKind::Eprint { values, .. } => {
+                self.out += &format!("eprint {};", print_values(values))
+            }
+            StmtKind::VarDec {
+                name,
+                initializer: Some(e),
+            } => self.out += &format!("var {} = {};", name, expr(e, COMMA)),
+            StmtKind::VarDec {
+                name,
+                initializer: None,
+            } => self.out += &format!("var {};", name),
+            StmtKind::Const { name, initializer } => {
+                self.out += &format!("const {} = {};", name, expr(initializer, COMMA))
+            }
+            StmtKind::Block(statements) => self.block(statements),
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.out += &format!("if ({})", expr(condition, COMMA));
+                match else_branch {
+                    // an else always binds to the nearest if, so one that
+                    // belongs further out needs the inner if put in a block
+                    Some(_) if dangles(then_branch) => {
+                        self.out.push(' ');
+                        self.block(slice::from_ref(then_branch));
+                    }
+                    _ => self.branch(then_branch),
+                }
+
+                if let Some(else_branch) = else_branch {
+                    if is_block(then_branch) || dangles(then_branch) {
+                        self.out.push(' ');
+                    } else {
+                        self.new_line();
+                    }
+                    self.out += "else";
+                    if let StmtKind::If { .. } = else_branch.kind {
+                        self.out.push(' ');
+                        self.statement(else_branch);
+                    } else {
+                        self.branch(else_branch);
+                    }
+                }
+            }
+            StmtKind::While { condition, body } => {
+                self.out += &format!("while ({})", expr(condition, COMMA));
+                self.branch(body);
+            }
+            StmtKind::DoWhile { body, condition } => {
+                self.out += "do ";
+                match &body.kind {
+                    StmtKind::Block(statements) => self.block(statements),
+                    _ => self.block(slice::from_ref(body)),
+                }
+                self.out += &format!(" while ({});", expr(condition, COMMA));
+            }
+            StmtKind::Function { name, params, body } => {
+                self.out += &format!("fun {}({}) ", name, params_list(params));
+                self.block(body);
+            }
+            StmtKind::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                self.out += &format!("class {}", name);
+                if let Some(superclass) = superclass {
+                    self.out += &format!(" < {}", superclass);
+                }
+                self.out += " {";
+                if !methods.is_empty() {
+                    self.indent += 1;
+                    for method in methods {
+                        if let StmtKind::Function { name, params, body } = &method.kind {
+                            self.new_line();
+                            self.out += &format!("{}({}) ", name, params_list(params));
+                            self.block(body);
+                        }
+                    }
+                    self.indent -= 1;
+                    self.new_line();
+                }
+                self.out.push('}');
+            }
+            StmtKind::Return { value: Some(e), .. } => {
+                self.out += &format!("return {};", expr(e, COMMA))
+            }
+            StmtKind::Return { value: None, .. } => self.out += "return;",
+            StmtKind::Assert { condition, .. } => {
+                self.out += &format!("assert {};", expr(condition, COMMA))
+            }
+            StmtKind::Import { path, .. } => {
+                self.out += &format!("import \"{}\";", escape_dollars(path))
+            }
+            StmtKind::Error { .. } => self.out += "/* error */",
+        }
+    }
+
+    fn block(&mut self, statements: &[Stmt]) {
+        if statements.is_empty() {
+            self.out += "{}";
+            return;
+        }
+
+        self.out.push('{');
+        self.indent += 1;
+        for s in statements {
+            self.new_line();
+            self.statement(s);
+        }
+        self.indent -= 1;
+        self.new_line();
+        self.out.push('}');
+    }
+
+    /// The body of an `if`, `else` or `while`: a block on the same line,
+    /// anything else indented on the next.
+    fn branch(&mut self, s: &Stmt) {
+        if is_block(s) {
+            self.out.push(' ');
+            self.statement(s);
+        } else {
+            self.indent += 1;
+            self.new_line();
+            self.statement(s);
+            self.indent -= 1;
+        }
+    }
+}
+
+/// The commas separate the values, so any comma expression among them needs
+/// parentheses.
+fn print_values(values: &[Expr]) -> String {
+    let values: Vec<_> = values.iter().map(|e| expr(e, ASSIGNMENT)).collect();
+    values.join(", ")
+}
+
+/// Writes `\$` for a `$` that would otherwise start an interpolation, or
+/// would be read as part of a `\$` with the backslash before it.
+fn escape_dollars(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    let mut previous = None;
+    while let Some(c) = chars.next() {
+        if c == '$' && (chars.peek() == Some(&'{') || previous == Some('\\')) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+        previous = Some(c);
+    }
+    escaped
+}
+
+fn is_block(s: &Stmt) -> bool {
+    matches!(s.kind, StmtKind::Block(_))
+}
+
+/// Whether `s` ends in an `if` without an `else`, which would take an
+/// `else` written after it.
+fn dangles(s: &Stmt) -> bool {
+    match &s.kind {
+        StmtKind::If {
+            else_branch: None, ..
+        } => true,
+        StmtKind::If {
+            else_branch: Some(branch),
+            ..
+        }
+        | StmtKind::While { body: branch, .. } => dangles(branch),
+        _ => false,
+    }
+}
+
+/// Formats `e` where only an expression binding at least as tightly as
+/// `min` can go without parentheses.
+fn expr(e: &Expr, min: u8) -> String {
+    let text = unparenthesized(e);
+    if precedence(e) < min {
+        format!("({})", text)
+    } else {
+        text
+    }
+}
+
+fn precedence(e: &Expr) -> u8 {
+    match &e.kind {
+        ExprKind::Binary { operator, .. } if operator.tag == TokenType::Comma => COMMA,
+        ExprKind::Binary { operator, .. } => parser::binary_operator(&operator.tag)
+            .map_or(LOWEST_BINARY, |(precedence, _)| BINARY + precedence),
+        ExprKind::Assign { .. } | ExprKind::SetIndex { .. } | ExprKind::Set { .. } => ASSIGNMENT,
+        ExprKind::Ternary { .. } => TERNARY,
+        ExprKind::Unary { .. } | ExprKind::Increment { .. } | ExprKind::Defined(_) => UNARY,
+        ExprKind::Call { .. } | ExprKind::Get { .. } | ExprKind::Index { .. } => CALL,
+        ExprKind::Grouping { .. }
+        | ExprKind::Literal(_)
+        | ExprKind::Interpolation(_)
+        | ExprKind::Variable(_)
+        | ExprKind::This(_)
+        | ExprKind::Super { .. }
+        | ExprKind::ArrayLiteral(_)
+        | ExprKind::MapLiteral { .. }
+        | ExprKind::Lambda { .. }
+        | ExprKind::Error { .. } => PRIMARY,
+    }
+}
+
+fn unparenthesized(e: &Expr) -> String {
+    match &e.kind {
+        ExprKind::Binary {
+            left,
+            operator,
+            right,
+        } if operator.tag == TokenType::Comma => {
+            format!("{}, {}", expr(left, COMMA), expr(right, ASSIGNMENT))
+        }
+        ExprKind::Binary {
+            left,
+            operator,
+            right,
+        } => {
+            // the side an operator associates to can hold the same operator
+            // again, the other side needs something tighter
+            let precedence = precedence(e);
+            let (left_min, right_min) = match parser::binary_operator(&operator.tag) {
+                Some((_, Associativity::Right)) => (precedence + 1, precedence),
+                _ => (precedence, precedence + 1),
+            };
+            format!(
+                "{} {} {}",
+                expr(left, left_min),
+                operator.tag,
+                expr(right, right_min)
+            )
+        }
+        ExprKind::Assign { name, value } => format!("{} = {}", name, expr(value, ASSIGNMENT)),
+        ExprKind::SetIndex {
+            object,
+            index,
+            value,
+            ..
+        } => format!(
+            "{}[{}] = {}",
+            expr(object, CALL),
+            expr(index, COMMA),
+            expr(value, ASSIGNMENT)
+        ),
+        ExprKind::Ternary {
+            condition,
+            true_expr,
+            false_expr,
+        } => format!(
+            "{} ? {} : {}",
+            expr(condition, LOWEST_BINARY),
+            expr(true_expr, COMMA),
+            expr(false_expr, ASSIGNMENT)
+        ),
+        ExprKind::Unary { operator, operand } => {
+            let operand = expr(operand, UNARY);
+            // `- -a` would scan as `--a`
+            let space = if operator.tag == TokenType::Minus && operand.starts_with('-') {
+                " "
+            } else {
+                ""
+            };
+            format!("{}{}{}", operator.tag, space, operand)
+        }
+        ExprKind::Increment { operator, name } => format!("{}{}", operator.tag, name),
+        ExprKind::Defined(name) => format!("defined {}", name),
+        ExprKind::Call {
+            callee, arguments, ..
+        } => format!("{}({})", expr(callee, CALL), list(arguments)),
+        ExprKind::Get { object, name } => format!("{}.{}", expr(object, CALL), name.tag),
+        ExprKind::Set {
+            object,
+            name,
+            value,
+        } => format!(
+            "{}.{} = {}",
+            expr(object, CALL),
+            name.tag,
+            expr(value, ASSIGNMENT)
+        ),
+        ExprKind::This(_) => "this".to_owned(),
+        ExprKind::Super { method, .. } => format!("super.{}", method.tag),
+        ExprKind::Index { object, index, .. } => {
+            format!("{}[{}]", expr(object, CALL), expr(index, COMMA))
+        }
+        ExprKind::Grouping { expr: inner } => format!("({})", expr(inner, COMMA)),
+        ExprKind::Literal(value) => literal(value),
+        ExprKind::Interpolation(parts) => interpolation(parts),
+        ExprKind::Variable(name) => name.to_string(),
+        ExprKind::ArrayLiteral(elements) => format!("[{}]", list(elements)),
+        ExprKind::MapLiteral { entries, .. } => {
+            let entries: Vec<_> = entries
+                .iter()
+                .map(|(key, value)| {
+                    format!("{}: {}", expr(key, ASSIGNMENT), expr(value, ASSIGNMENT))
+                })
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+        ExprKind::Lambda { params, body } => {
+            let mut formatter = Formatter {
+                one_line: true,
+                ..Formatter::default()
+            };
+            formatter.out = format!("fun ({}) ", params_list(params));
+            formatter.block(body);
+            formatter.out
+        }
+        ExprKind::Error { .. } => "/* error */".to_owned(),
+    }
+}
+
+/// Writes the text of `parts` between the quotes, and the expressions
+/// between them back in their `${...}`.
+fn interpolation(parts: &[Expr]) -> String {
+    let mut text = String::from("\"");
+    for (i, part) in parts.iter().enumerate() {
+        match &part.kind {
+            ExprKind::Literal(LiteralValue::STRING(s)) if i % 2 == 0 => {
+                text += &escape_dollars(s);
+            }
+            _ => text += &format!("${{{}}}", expr(part, COMMA)),
+        }
+    }
+    text.push('"');
+    text
+}
+
+fn params_list(params: &[Name]) -> String {
+    let params: Vec<_> = params.iter().map(|p| p.to_string()).collect();
+    params.join(", ")
+}
+
+/// Comma separated, as in call arguments and list elements, where a comma
+/// expression needs parentheses to keep its commas to itself.
+fn list(exprs: &[Expr]) -> String {
+    let exprs: Vec<_> = exprs.iter().map(|e| expr(e, ASSIGNMENT)).collect();
+    exprs.join(", ")
+}
+
+/// Strings are written between quotes exactly as they are, since Lox has no
+/// escape sequences. Every string the scanner produces can be written back
+/// that way, as none can contain a `"`.
+fn literal(value: &LiteralValue) -> String {
+    match value {
+        LiteralValue::STRING(s) => format!("\"{}\"", escape_dollars(s)),
+        LiteralValue::Number(n) => number::format(*n),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::token::{Binding, Name, Token};
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use std::sync::Arc;
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        let tokens = Scanner::new(source.to_owned())
+            .scan_tokens()
+            .unwrap()
+            .to_vec();
+        Parser::new(tokens).parse().unwrap()
+    }
+
+    fn format_source(source: &str) -> String {
+        format_program(&parse(source))
+    }
+
+    fn name(text: &str) -> Name {
+        Name {
+            symbol: text.into(),
+            line: 0,
+            column: 0,
+            binding: Binding::Dynamic,
+        }
+    }
+
+    fn token(tag: TokenType) -> Token {
+        Token::new(tag, 0)
+    }
+
+    fn print(value: Expr) -> Stmt {
+        StmtKind::Print {
+            line: 0,
+            values: vec![value],
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(
+            format_source(
+                "var a=1;fun add(x,y){return x+y;}\n\nif(a>0)print add(a,2);else{print-a;}\
+                 while(a<3)a=a+1;do{}while(false);{}"
+            ),
+            "var a = 1;
+fun add(x, y) {
+    return x + y;
+}
+if (a > 0)
+    print add(a, 2);
+else {
+    print -a;
+}
+while (a < 3)
+    a = a + 1;
+do {} while (false);
+{}
+"
+        );
+    }
+
+    #[test]
+    fn test_class_layout() {
+        assert_eq!(
+            format_source("class Point{init(x,y){this.x=x;this.y=y;}sum(){return this.x+this.y;}}class Empty{}"),
+            "class Point {
+    init(x, y) {
+        this.x = x;
+        this.y = y;
+    }
+    sum() {
+        return this.x + this.y;
+    }
+}
+class Empty {}
+"
+        );
+        assert_eq!(
+            format_source("class B<A{m(){return super.m();}}"),
+            "class B < A {\n    m() {\n        return super.m();\n    }\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_expressions() {
+        for source in &[
+            "print (1 + 2) * 3, ((4));",
+            "print a, (b, c), d = 1;",
+            "eprint \"warning:\", a;",
+            "print [1, [2], {\"a b\": nil}][0];",
+            "print c ? a = 1 : b = 2;",
+            "print f(a, (b, c))(d).e[1, 2];",
+            "print - -1 - --a + !defined b;",
+            "print \"two\nlines\";",
+            "print \"s${x}\", \"${a}${b + 1} \\${c} ${{\"}\": [\"\\${\"]}[\"}\"]}\";",
+            "({})[\"a\"] = 1;",
+            "var twice = fun (x) { return x + x; };",
+            "fun (a, b) { if (a) print b; else { print a; } }(1, 2);",
+            "print map(fun () {}, [1]);",
+            "a.b.c = this.d = 1;",
+            "print super.m(1).n;",
+        ] {
+            assert_eq!(format_source(source), format!("{}\n", source));
+        }
+        assert_eq!(
+            format_source("if(a)if(b)x;else y;else if(c)z;"),
+            "if (a)\n    if (b)\n        x;\n    else\n        y;\nelse if (c)\n    z;\n"
+        );
+    }
+
+    #[test]
+    fn test_hand_built_trees_get_the_parentheses_they_need() {
+        let number = |n| Box::new(Expr::from(ExprKind::Literal(LiteralValue::Number(n))));
+        let sum: Expr = ExprKind::Binary {
+            left: number(1.0),
+            operator: token(TokenType::Plus),
+            right: number(2.0),
+        }
+        .into();
+        let product: Expr = ExprKind::Binary {
+            left: number(3.0),
+            operator: token(TokenType::Star),
+            right: Box::new(sum),
+        }
+        .into();
+        assert_eq!(format_expr(&product), "3 * (1 + 2)");
+
+        let map: Expr = ExprKind::MapLiteral {
+            brace: token(TokenType::LeftBrace),
+            entries: Vec::new(),
+        }
+        .into();
+        assert_eq!(format_program(&[StmtKind::Expr(map).into()]), "({});\n");
+
+        let variable = |text| Expr::from(ExprKind::Variable(name(text)));
+        // the else belongs to the outer if
+        let inner = StmtKind::If {
+            condition: variable("b"),
+            then_branch: Box::new(print(variable("x"))),
+            else_branch: None,
+        };
+        let outer: Stmt = StmtKind::If {
+            condition: variable("a"),
+            then_branch: Box::new(inner.into()),
+            else_branch: Some(Box::new(print(variable("y")))),
+        }
+        .into();
+        assert_eq!(
+            format_program(&[outer]),
+            "if (a) {\n    if (b)\n        print x;\n} else\n    print y;\n"
+        );
+    }
+
+    /// `e` in a position where only something binding at least as tightly
+    /// as `min` parses without parentheses, grouped if it needs them, the
+    /// way a parsed tree would be.
+    fn fit(e: Expr, min: u8) -> Box<Expr> {
+        if precedence(&e) < min {
+            Box::new(ExprKind::Grouping { expr: Box::new(e) }.into())
+        } else {
+            Box::new(e)
+        }
+    }
+
+    fn fit_all(exprs: Vec<Expr>, min: u8) -> Vec<Expr> {
+        exprs.into_iter().map(|e| *fit(e, min)).collect()
+    }
+
+    fn arb_name() -> impl Strategy<Value = Name> {
+        prop::sample::select(vec!["a", "b", "count", "_x1", "défini"]).prop_map(name)
+    }
+
+    fn arb_literal() -> impl Strategy<Value = LiteralValue> {
+        prop_oneof![
+            Just(LiteralValue::True),
+            Just(LiteralValue::False),
+            Just(LiteralValue::Nil),
+            (0u32..1000).prop_map(|n| LiteralValue::Number(n.into())),
+            (0.0..1e12f64).prop_map(LiteralValue::Number),
+            "[a-z é\n!?${}\\\\]{0,6}".prop_map(|s| LiteralValue::STRING(s.as_str().into())),
+        ]
+    }
+
+    fn arb_binary_operator() -> impl Strategy<Value = TokenType> {
+        prop::sample::select(vec![
+            TokenType::Comma,
+            TokenType::BangEqual,
+            TokenType::EqualEqual,
+            TokenType::Greater,
+            TokenType::GreaterEqual,
+            TokenType::Less,
+            TokenType::LessEqual,
+            TokenType::Minus,
+            TokenType::Plus,
+            TokenType::Slash,
+            TokenType::Star,
+        ])
+    }
+
+    fn binary(left: Expr, tag: TokenType, right: Expr) -> Expr {
+        let precedence = match parser::binary_operator(&tag) {
+            Some((precedence, _)) => BINARY + precedence,
+            None => COMMA,
+        };
+        ExprKind::Binary {
+            left: fit(left, precedence),
+            operator: token(tag),
+            right: fit(right, precedence + 1),
+        }
+        .into()
+    }
+
+    /// Expressions shaped the way the parser would build them.
+    fn arb_expr() -> impl Strategy<Value = Expr> {
+        let leaf = prop_oneof![
+            arb_literal().prop_map(ExprKind::Literal),
+            arb_name().prop_map(ExprKind::Variable),
+            arb_name().prop_map(ExprKind::Defined),
+            Just("this").prop_map(|this| ExprKind::This(name(this))),
+            arb_name().prop_map(|method| ExprKind::Super {
+                keyword: name("super"),
+                method: token(TokenType::Identifer(method.symbol)),
+            }),
+            (
+                prop::sample::select(vec![TokenType::PlusPlus, TokenType::MinusMinus]),
+                arb_name()
+            )
+                .prop_map(|(tag, name)| ExprKind::Increment {
+                    operator: token(tag),
+                    name
+                }),
+        ]
+        .prop_map(Expr::from);
+
+        leaf.prop_recursive(4, 32, 3, |inner| {
+            prop_oneof![
+                (inner.clone(), arb_binary_operator(), inner.clone())
+                    .prop_map(|(left, tag, right)| binary(left, tag, right)),
+                (
+                    prop::sample::select(vec![TokenType::Bang, TokenType::Minus]),
+                    inner.clone()
+                )
+                    .prop_map(|(tag, operand)| ExprKind::Unary {
+                        operator: token(tag),
+                        operand: fit(operand, UNARY),
+                    }
+                    .into()),
+                (arb_name(), inner.clone()).prop_map(|(name, value)| ExprKind::Assign {
+                    name,
+                    value: fit(value, ASSIGNMENT),
+                }
+                .into()),
+                (inner.clone(), inner.clone(), inner.clone()).prop_map(|(object, index, value)| {
+                    ExprKind::SetIndex {
+                        object: fit(object, CALL),
+                        bracket: token(TokenType::LeftBracket),
+                        index: Box::new(index),
+                        value: fit(value, ASSIGNMENT),
+                    }
+                    .into()
+                }),
+                (inner.clone(), inner.clone(), inner.clone()).prop_map(
+                    |(condition, true_expr, false_expr)| ExprKind::Ternary {
+                        condition: fit(condition, LOWEST_BINARY),
+                        true_expr: Box::new(true_expr),
+                        false_expr: fit(false_expr, ASSIGNMENT),
+                    }
+                    .into()
+                ),
+                (inner.clone(), vec(inner.clone(), 0..3)).prop_map(|(callee, arguments)| {
+                    ExprKind::Call {
+                        callee: fit(callee, CALL),
+                        paren: token(TokenType::LeftParen),
+                        arguments: fit_all(arguments, ASSIGNMENT),
+                    }
+                    .into()
+                }),
+                (inner.clone(), arb_name()).prop_map(|(object, name)| ExprKind::Get {
+                    object: fit(object, CALL),
+                    name: token(TokenType::Identifer(name.symbol)),
+                }
+                .into()),
+                (inner.clone(), arb_name(), inner.clone()).prop_map(|(object, name, value)| {
+                    ExprKind::Set {
+                        object: fit(object, CALL),
+                        name: token(TokenType::Identifer(name.symbol)),
+                        value: fit(value, ASSIGNMENT),
+                    }
+                    .into()
+                }),
+                (inner.clone(), inner.clone()).prop_map(|(object, index)| ExprKind::Index {
+                    object: fit(object, CALL),
+                    bracket: token(TokenType::LeftBracket),
+                    index: Box::new(index),
+                }
+                .into()),
+                inner
+                    .clone()
+                    .prop_map(|e| ExprKind::Grouping { expr: Box::new(e) }.into()),
+                vec(inner.clone(), 0..3)
+                    .prop_map(
+                        |elements| ExprKind::ArrayLiteral(fit_all(elements, ASSIGNMENT)).into()
+                    ),
+                vec((inner.clone(), inner), 0..3).prop_map(|entries| ExprKind::MapLiteral {
+                    brace: token(TokenType::LeftBrace),
+                    entries: entries
+                        .into_iter()
+                        .map(|(key, value)| (*fit(key, ASSIGNMENT), *fit(value, ASSIGNMENT)))
+                        .collect(),
+                }
+                .into()),
+            ]
+        })
+    }
+
+    fn block(statements: Vec<Stmt>) -> Stmt {
+        StmtKind::Block(statements).into()
+    }
+
+    /// Statements shaped the way the parser would build them. Declarations
+    /// can't be the body of an `if` or a loop, so they come from
+    /// `arb_declaration` instead.
+    fn arb_stmt() -> impl Strategy<Value = Stmt> {
+        let leaf = prop_oneof![
+            arb_expr().prop_map(|e| {
+                // a brace at the start would make it a block
+                if format_expr(&e).starts_with('{') {
+                    StmtKind::Expr(ExprKind::Grouping { expr: Box::new(e) }.into())
+                } else {
+                    StmtKind::Expr(e)
+                }
+            }),
+            (any::<bool>(), prop::collection::vec(arb_expr(), 1..4)).prop_map(|(error, values)| {
+                let values = fit_all(values, ASSIGNMENT);
+                if error {
+                    StmtKind::Eprint { line: 0, values }
+                } else {
+                    StmtKind::Print { line: 0, values }
+                }
+            }),
+            prop::option::of(arb_expr()).prop_map(|value| StmtKind::Return { line: 0, value }),
+            arb_expr().prop_map(|condition| StmtKind::Assert {
+                keyword: token(TokenType::Assert),
+                condition,
+            }),
+            "[a-z./${}]{0,8}".prop_map(|path| StmtKind::Import {
+                keyword: token(TokenType::Import),
+                path,
+            }),
+        ]
+        .prop_map(Stmt::from);
+
+        leaf.prop_recursive(3, 16, 3, |inner| {
+            let declarations = vec(arb_declaration(inner.clone()).boxed(), 0..3);
+            prop_oneof![
+                declarations.clone().prop_map(block),
+                (arb_expr(), inner.clone(), prop::option::of(inner.clone())).prop_map(
+                    |(condition, then_branch, else_branch)| {
+                        // without braces the else would go to the inner if
+                        let then_branch = if else_branch.is_some() && dangles(&then_branch) {
+                            block(vec![then_branch])
+                        } else {
+                            then_branch
+                        };
+                        StmtKind::If {
+                            condition,
+                            then_branch: Box::new(then_branch),
+                            else_branch: else_branch.map(Box::new),
+                        }
+                        .into()
+                    }
+                ),
+                (arb_expr(), inner).prop_map(|(condition, body)| StmtKind::While {
+                    condition,
+                    body: Box::new(body),
+                }
+                .into()),
+                (declarations, arb_expr()).prop_map(|(body, condition)| {
+                    StmtKind::DoWhile {
+                        body: Box::new(block(body)),
+                        condition,
+                    }
+                    .into()
+                }),
+            ]
+        })
+    }
+
+    fn arb_declaration(statement: BoxedStrategy<Stmt>) -> impl Strategy<Value = Stmt> {
+        prop_oneof![
+            statement.clone(),
+            (arb_name(), prop::option::of(arb_expr()))
+                .prop_map(|(name, initializer)| StmtKind::VarDec { name, initializer }.into()),
+            (arb_name(), arb_expr()).prop_map(|(name, initializer)| StmtKind::Const {
+                name,
+                initializer
+            }
+            .into()),
+            (
+                arb_name(),
+                vec(arb_name(), 0..3),
+                vec(statement.clone(), 0..3)
+            )
+                .prop_map(|(name, params, body)| StmtKind::Function {
+                    name,
+                    params,
+                    body: Arc::new(body),
+                }
+                .into()),
+            (
+                arb_name(),
+                prop::option::of(arb_name()),
+                vec(
+                    (arb_name(), vec(arb_name(), 0..3), vec(statement, 0..3)),
+                    0..3
+                )
+            )
+                .prop_map(|(name, superclass, methods)| StmtKind::Class {
+                    name,
+                    superclass,
+                    methods: methods
+                        .into_iter()
+                        .map(|(name, params, body)| {
+                            StmtKind::Function {
+                                name,
+                                params,
+                                body: Arc::new(body),
+                            }
+                            .into()
+                        })
+                        .collect(),
+                }
+                .into()),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_formatted_source_parses_back_to_the_same_tree(
+            statements in vec(arb_declaration(arb_stmt().boxed()), 0..4)
+        ) {
+            let source = format_program(&statements);
+            let tokens = Scanner::new(source.clone()).scan_tokens().map(|tokens| tokens.to_vec());
+            prop_assert!(tokens.is_ok(), "{:?}\n{}", tokens, source);
+            let parsed = Parser::new(tokens.unwrap()).parse();
+            prop_assert!(parsed.is_ok(), "{:?}\n{}", parsed, source);
+
+            let parsed = parsed.unwrap();
+            let same = parsed.len() == statements.len()
+                && parsed.iter().zip(&statements).all(|(a, b)| a.structural_eq(b));
+            prop_assert!(same, "{:?}\n{:?}\n{}", parsed, statements, source);
+        }
+    }
+}
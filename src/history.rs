@@ -0,0 +1,64 @@
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+/// Used when `ROX_HISTORY_SIZE` is unset or not a number.
+pub const DEFAULT_HISTORY_SIZE: usize = 1000;
+
+const HISTORY_FILE_NAME: &str = ".rox_history";
+
+/// Where REPL history is kept: `ROX_HISTORY` if set, otherwise
+/// `~/.rox_history`. `None` when neither can be worked out, in which case
+/// history only lasts for the session.
+pub fn history_path() -> Option<PathBuf> {
+    resolve_path(env::var_os("ROX_HISTORY"), env::var_os("HOME"))
+}
+
+/// The number of entries to keep, read from `ROX_HISTORY_SIZE`.
+pub fn history_size() -> usize {
+    resolve_size(env::var("ROX_HISTORY_SIZE").ok())
+}
+
+fn resolve_path(rox_history: Option<OsString>, home: Option<OsString>) -> Option<PathBuf> {
+    match (rox_history, home) {
+        (Some(path), _) if !path.is_empty() => Some(PathBuf::from(path)),
+        (_, Some(home)) if !home.is_empty() => Some(PathBuf::from(home).join(HISTORY_FILE_NAME)),
+        _ => None,
+    }
+}
+
+fn resolve_size(size: Option<String>) -> usize {
+    size.and_then(|size| size.trim().parse().ok())
+        .unwrap_or(DEFAULT_HISTORY_SIZE)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resolve_path() {
+        let home = Some(OsString::from("/home/rox"));
+
+        assert_eq!(
+            resolve_path(Some(OsString::from("/tmp/history")), home.clone()),
+            Some(PathBuf::from("/tmp/history"))
+        );
+        assert_eq!(
+            resolve_path(None, home.clone()),
+            Some(PathBuf::from("/home/rox/.rox_history"))
+        );
+        assert_eq!(
+            resolve_path(Some(OsString::new()), home),
+            Some(PathBuf::from("/home/rox/.rox_history"))
+        );
+        assert_eq!(resolve_path(None, None), None);
+    }
+
+    #[test]
+    fn test_resolve_size() {
+        assert_eq!(resolve_size(Some("50".to_owned())), 50);
+        assert_eq!(resolve_size(Some("lots".to_owned())), DEFAULT_HISTORY_SIZE);
+        assert_eq!(resolve_size(None), DEFAULT_HISTORY_SIZE);
+    }
+}
@@ -1,17 +1,121 @@
-use crate::expr::{Expr, LiteralValue};
-use crate::statement::Stmt;
-use crate::token::{Token, TokenType};
+use crate::error::{self, Frame, RoxError, Warning};
+use crate::expr::{print::print_ast, Expr, ExprKind};
+use crate::number;
+use crate::parser;
+use crate::statement::{Stmt, StmtKind};
+use crate::symbol::Symbol;
+use crate::token::{Binding, Name, Token, TokenType};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
 use std::io::{self, Write};
+use std::mem;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
+mod callable;
+mod class;
 mod environment;
+mod format_string;
+mod import;
+mod natives;
+mod trace;
+mod value;
+mod version;
+pub use callable::Callable;
+use callable::Function;
+use class::Class;
 use environment::Environment;
+pub use trace::TraceEvent;
+pub use value::Value;
 
-pub type EvalResult = Result<LiteralValue, String>;
-pub type ExecuteResult = Result<(), String>;
+pub type EvalResult = Result<Value, RoxError>;
+pub type ExecuteResult = Result<(), RoxError>;
+/// `Some` carries the value of a `return` that is unwinding to its call site.
+pub type StmtResult = Result<Option<Value>, RoxError>;
 
+/// How many steps pass between checks of the clock, when there's a time
+/// limit. Reading the clock costs far more than taking a step. A power of
+/// two, so checking whether it's time is a mask rather than a division.
+const STEPS_PER_CLOCK_CHECK: u64 = 1024;
+
+/// The longest string `"ab" * n` may produce, in characters.
+const MAX_REPEATED_STRING_LENGTH: usize = 1_000_000;
+
+/// How deeply `execute` and `evaluate` may recurse by default, between
+/// them, within one call. The same limit as the parser's, so a program
+/// written too deeply nested fails there, before it runs.
+pub const DEFAULT_MAX_DEPTH: usize = parser::DEFAULT_MAX_DEPTH;
+
+/// When less stack than this is left, `execute` and `evaluate` carry on
+/// on a new piece of `STACK_SEGMENT_SIZE` bytes, so deep recursion runs
+/// out of `DEFAULT_MAX_CALL_DEPTH` rather than of stack, however small the
+/// thread's stack is. A debug build needs about 25KB a call.
+const STACK_RED_ZONE: usize = 128 * 1024;
+const STACK_SEGMENT_SIZE: usize = 1024 * 1024;
+
+/// How many calls may be in progress at once by default. Nesting is counted
+/// per call, so this is what stops runaway recursion. A function that
+/// returns a call of itself reuses its call, so isn't bounded.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+type Tracer<'a> = Box<dyn FnMut(&TraceEvent) + Send + 'a>;
+
+/// A call in progress.
+struct Call {
+    frame: Frame,
+    /// The body of the Lox function called, or `None` for a native.
+    body: Option<Arc<Vec<Stmt>>>,
+    /// The caller's `depth_base`.
+    depth_base: usize,
+}
+
+/// `Send`, so a script can be run on another thread. Values are shared with
+/// `Arc` and `RwLock` rather than `Rc` and `RefCell` for this reason, and
+/// the writer printed output goes to must be `Send` too.
 pub struct Interpreter<'a> {
-    stdout: Box<dyn Write + 'a>,
-    environment: Environment,
+    stdout: Box<dyn Write + Send + 'a>,
+    /// Where `eprint` writes, and `report` renders errors to.
+    stderr: Box<dyn Write + Send + 'a>,
+    globals: Arc<RwLock<Environment>>,
+    environment: Arc<RwLock<Environment>>,
+    /// The calls in progress, innermost last. Past `max_call_depth` a call
+    /// fails.
+    call_stack: Vec<Call>,
+    max_call_depth: usize,
+    /// The function and arguments a `return` asked the running function to
+    /// be called again with, in place of a nested call.
+    tail_call: Option<(Arc<Function>, Vec<Value>)>,
+    /// How many calls of `execute` and `evaluate` are in progress. Past
+    /// `max_depth` more than `depth_base`, the depth the running call
+    /// started at, they fail.
+    depth: usize,
+    depth_base: usize,
+    max_depth: usize,
+    /// The line of the last `print` whose output may still be buffered.
+    unflushed_line: Option<u32>,
+    /// How many decimals the numbers `print` shows get, if not as many as
+    /// they need.
+    float_precision: Option<usize>,
+    /// Whether reading an undefined variable gives nil, and assigning to
+    /// one defines it, rather than failing.
+    lenient: bool,
+    /// The undefined variables read in lenient mode that have been warned
+    /// about, which aren't again.
+    warned_undefined: HashSet<Symbol>,
+    /// Statements executed and expressions evaluated since `interpret` was
+    /// last called. Past `max_steps`, or past `deadline`, it fails.
+    steps: u64,
+    max_steps: u64,
+    time_limit: Option<Duration>,
+    deadline: Option<Instant>,
+    /// Told about each statement and evaluation, when one is installed.
+    tracer: Option<Tracer<'a>>,
+    /// The file being run, which imports are found relative to.
+    script_path: Option<PathBuf>,
+    /// The canonical path of every file run so far, which importing again
+    /// does nothing.
+    imported: HashSet<PathBuf>,
 }
 
 impl<'a> Default for Interpreter<'a> {
@@ -22,165 +126,1576 @@ impl<'a> Default for Interpreter<'a> {
 }
 
 impl<'a> Interpreter<'a> {
-    pub fn new(stdout: impl Write + 'a) -> Self {
+    /// An interpreter that prints to `stdout`. What `eprint` writes goes to
+    /// the process's stderr with the `cli` feature and is dropped without
+    /// it; `with_streams` says where it goes instead.
+    pub fn new(stdout: impl Write + Send + 'a) -> Self {
+        Self::with_max_depth(stdout, DEFAULT_MAX_DEPTH)
+    }
+
+    /// An interpreter that fails when execution nests more than
+    /// `max_depth` statements and expressions deep.
+    pub fn with_max_depth(stdout: impl Write + Send + 'a, max_depth: usize) -> Self {
+        let mut globals = Environment::new();
+        natives::define_globals(&mut globals);
+        let globals = Arc::new(RwLock::new(globals));
+
         Self {
             stdout: Box::new(stdout),
-            environment: Environment::new(),
+            stderr: default_stderr(),
+            environment: Arc::clone(&globals),
+            globals,
+            call_stack: Vec::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            tail_call: None,
+            depth: 0,
+            depth_base: 0,
+            max_depth,
+            unflushed_line: None,
+            float_precision: None,
+            lenient: false,
+            warned_undefined: HashSet::new(),
+            steps: 0,
+            max_steps: u64::MAX,
+            time_limit: None,
+            deadline: None,
+            tracer: None,
+            script_path: None,
+            imported: HashSet::new(),
         }
     }
 
+    /// An interpreter that prints to `stdout`, and sends what `eprint`
+    /// writes and the errors it's asked to `report` to `stderr`, so an
+    /// embedder can capture the two apart.
+    pub fn with_streams(stdout: impl Write + Send + 'a, stderr: impl Write + Send + 'a) -> Self {
+        let mut interpreter = Self::new(stdout);
+        interpreter.stderr = Box::new(stderr);
+        interpreter
+    }
+
+    /// Limits each call of `interpret` or `interpret_continuing` to
+    /// `max_steps` statements and expressions, and to `max_millis`
+    /// milliseconds. Either limit can be 0 for no limit. Going past one
+    /// fails with `RoxError::BudgetExceeded`, so an untrusted script can't
+    /// hang its host.
+    pub fn with_limits(mut self, max_steps: u64, max_millis: u64) -> Self {
+        self.max_steps = if max_steps == 0 { u64::MAX } else { max_steps };
+        self.time_limit = if max_millis == 0 {
+            None
+        } else {
+            Some(Duration::from_millis(max_millis))
+        };
+        self
+    }
+
+    /// Fails any call made while `max_call_depth` calls are in progress.
+    pub fn with_max_call_depth(mut self, max_call_depth: usize) -> Self {
+        self.max_call_depth = max_call_depth;
+        self
+    }
+
+    /// Prints every number given to `print`, `eprint` or a `{}` of `format`
+    /// with `precision` decimals, such as 2 for `0.33` and `1.00`, or as
+    /// usual with `None`. Numbers in lists, maps and strings are left alone.
+    pub fn set_float_precision(&mut self, precision: Option<usize>) {
+        self.float_precision = precision;
+    }
+
+    /// In lenient mode, reading a variable that isn't defined gives nil,
+    /// with a warning to the error stream the first time each name is read,
+    /// and assigning to one defines it in the innermost scope rather than
+    /// failing. A name the resolver found to be global is defined there
+    /// instead, where reads of it will look. Off unless set.
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+
+    /// Sends everything printed from now on to `writer`, and returns the
+    /// previous writer without flushing it.
+    pub fn set_writer(&mut self, writer: impl Write + Send + 'a) -> Box<dyn Write + Send + 'a> {
+        self.unflushed_line = None;
+        mem::replace(&mut self.stdout, Box::new(writer))
+    }
+
+    /// Calls `tracer` with each statement before it runs, and each variable
+    /// read or written and expression worked out after. Without a tracer,
+    /// all this costs is checking there isn't one.
+    pub fn set_tracer(&mut self, tracer: impl FnMut(&TraceEvent) + Send + 'a) {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    /// Flushes anything printed so far. `interpret` does this after every
+    /// batch of statements.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.unflushed_line = None;
+        self.stdout.flush()
+    }
+
+    /// Writes `error` to the error stream, showing where in `source` it is,
+    /// as `error::render` lays it out.
+    pub fn report(&mut self, error: &RoxError, source: &str) -> io::Result<()> {
+        writeln!(self.stderr, "{}", error::render(error, source))?;
+        self.stderr.flush()
+    }
+
+    /// Defines the global `name` as `value`, replacing anything it held, so
+    /// a host can hand values to the code it runs.
+    pub fn define(&mut self, name: &str, value: Value) {
+        self.globals
+            .write()
+            .unwrap()
+            .define(name.into(), Some(value));
+    }
+
+    /// Makes the script's command line arguments available as the globals
+    /// `arg0`, `arg1`, ... with their count in `argc`.
+    pub fn set_args(&mut self, args: &[String]) {
+        let mut globals = self.globals.write().unwrap();
+        for (i, arg) in args.iter().enumerate() {
+            globals.define(format!("arg{}", i).into(), Some(Value::Str(arg.clone())));
+        }
+        globals.define("argc".into(), Some(Value::Number(args.len() as f64)));
+    }
+
+    /// Sets the file being run, which `import` finds files relative to, and
+    /// returns the one set before. The file counts as imported already, so
+    /// importing it back from a file it imports does nothing.
+    pub fn set_script_path(&mut self, path: Option<PathBuf>) -> Option<PathBuf> {
+        if let Some(canonical) = path.as_ref().and_then(|p| p.canonicalize().ok()) {
+            self.imported.insert(canonical);
+        }
+        mem::replace(&mut self.script_path, path)
+    }
+
+    /// Runs `statements` in order, stopping at the first runtime error.
     pub fn interpret(&mut self, statements: &[Stmt]) -> ExecuteResult {
-        for s in statements {
-            self.execute(s.clone())?;
+        check_for_parse_errors(statements)?;
+        self.reset_budget();
+
+        let result = statements
+            .iter()
+            .try_for_each(|s| self.execute(s).map(|_| ()));
+        let flushed = self.flush_printed();
+        result.and(flushed)
+    }
+
+    /// Every global and its value, sorted by name. Natives are included.
+    pub fn globals(&self) -> Vec<(Symbol, Value)> {
+        let mut globals: Vec<_> = self
+            .globals
+            .read()
+            .unwrap()
+            .bindings()
+            .map(|(name, value)| (Arc::clone(name), value.clone()))
+            .collect();
+        globals.sort_by(|(a, _), (b, _)| a.cmp(b));
+        globals
+    }
+
+    /// Every variable in scope, as `Environment::dump` lists them.
+    pub fn dump_environment(&self) -> String {
+        self.environment.read().unwrap().dump()
+    }
+
+    /// Removes the global `name`, returning whether it was defined.
+    pub fn undefine(&mut self, name: &str) -> bool {
+        self.globals.write().unwrap().undefine(name)
+    }
+
+    /// Runs every top-level statement in `statements`, carrying on with the
+    /// next one when one fails at runtime, as the REPL does. Returns the
+    /// errors in the order they happened.
+    pub fn interpret_continuing(&mut self, statements: &[Stmt]) -> Vec<RoxError> {
+        if let Err(e) = check_for_parse_errors(statements) {
+            return vec![e];
         }
+        self.reset_budget();
 
-        Ok(())
+        let mut errors: Vec<_> = statements
+            .iter()
+            .filter_map(|s| self.execute(s).err())
+            .collect();
+        errors.extend(self.flush_printed().err());
+        errors
+    }
+
+    fn reset_budget(&mut self) {
+        self.steps = 0;
+        self.deadline = self.time_limit.map(|limit| Instant::now() + limit);
+    }
+
+    /// Counts one step, returning why the budget is spent if it now is.
+    fn take_step(&mut self) -> Option<String> {
+        self.steps += 1;
+        if self.steps > self.max_steps {
+            return Some(format!(
+                "execution budget exceeded: more than {} steps",
+                self.max_steps
+            ));
+        }
+        match (self.deadline, self.time_limit) {
+            (Some(deadline), Some(limit))
+                if self.steps & (STEPS_PER_CLOCK_CHECK - 1) == 0 && Instant::now() >= deadline =>
+            {
+                Some(format!(
+                    "execution budget exceeded: ran for more than {}ms",
+                    limit.as_millis()
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    fn flush_printed(&mut self) -> ExecuteResult {
+        match self.unflushed_line {
+            Some(line) => self.flush().map_err(|e| output_error(line, e)),
+            None => Ok(()),
+        }
+    }
+
+    /// Kept out of `execute`, whose frame every level of recursion in a
+    /// script pays for.
+    fn eprint(&mut self, line: u32, values: &[Expr]) -> ExecuteResult {
+        let text = self.print_line(values)?;
+        // anything printed before goes out first, so the two streams
+        // interleave the way the script wrote them
+        self.flush_printed()?;
+        writeln!(self.stderr, "{}", text)
+            .and_then(|_| self.stderr.flush())
+            .map_err(|e| output_error(line, e))
+    }
+
+    /// The values of a `print` or `eprint`, separated by spaces. Every value
+    /// is evaluated before any is printed, so an error in one doesn't leave
+    /// half a line behind.
+    fn print_line(&mut self, values: &[Expr]) -> Result<String, RoxError> {
+        let values = values
+            .iter()
+            .map(|value| self.evaluate(value).map(|v| self.show(&v)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(values.join(" "))
+    }
+
+    /// `value` as `print` shows it.
+    fn show(&self, value: &Value) -> String {
+        match (value, self.float_precision) {
+            (Value::Number(n), Some(decimals)) => number::format_fixed(*n, decimals),
+            _ => value.to_string(),
+        }
+    }
+
+    /// Evaluates `expr` the way the REPL echoes it back.
+    pub fn evaluate_expr_to_string(&mut self, expr: &Expr) -> Result<String, RoxError> {
+        self.evaluate(expr).map(|value| value.inspect())
     }
 
-    pub fn execute(&mut self, s: Stmt) -> ExecuteResult {
-        match s {
-            Stmt::Expr(e) => self.evaluate(e).map(|_| ()),
-            Stmt::Print(e) => {
-                let val = self.evaluate(e)?;
-                writeln!(self.stdout, "{}", val).expect("failed to print");
-                Ok(())
+    pub fn execute(&mut self, s: &Stmt) -> StmtResult {
+        if let Some(message) = self.take_step() {
+            return Err(RoxError::BudgetExceeded {
+                line: None,
+                message,
+            });
+        }
+        if self.tracer.is_some() {
+            self.trace_stmt(s);
+        }
+
+        if self.depth - self.depth_base >= self.max_depth {
+            let message = format!("statement too deeply nested (limit {})", self.max_depth);
+            return Err(RoxError::Runtime {
+                line: s.span.line,
+                column: Some(s.span.column),
+                message,
+                frames: Vec::new(),
+            });
+        }
+
+        self.depth += 1;
+        let result = stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT_SIZE, || {
+            self.execute_nested(s)
+        });
+        self.depth -= 1;
+        result
+    }
+
+    fn execute_nested(&mut self, s: &Stmt) -> StmtResult {
+        match &s.kind {
+            StmtKind::Expr(e) => self.evaluate(e).map(|_| None),
+            StmtKind::Print { line, values } => {
+                let text = self.print_line(values)?;
+                writeln!(self.stdout, "{}", text).map_err(|e| output_error(*line, e))?;
+                self.unflushed_line = Some(*line);
+                Ok(None)
             }
-            Stmt::VarDec { name, initializer } => {
+            StmtKind::Eprint { line, values } => self.eprint(*line, values).map(|_| None),
+            StmtKind::VarDec { name, initializer } => {
                 let value = if let Some(expr) = initializer {
                     Some(self.evaluate(expr)?)
                 } else {
                     None
                 };
 
-                self.environment.define(name, value);
-                Ok(())
+                if self.tracer.is_some() {
+                    self.trace_declaration(&name.symbol, value.as_ref());
+                }
+                self.environment
+                    .write()
+                    .unwrap()
+                    .define(Arc::clone(&name.symbol), value);
+                Ok(None)
+            }
+            StmtKind::Const { name, initializer } => {
+                let value = self.evaluate(initializer)?;
+
+                if self.tracer.is_some() {
+                    self.trace_declaration(&name.symbol, Some(&value));
+                }
+                self.environment
+                    .write()
+                    .unwrap()
+                    .define_constant(Arc::clone(&name.symbol), value);
+                Ok(None)
+            }
+            StmtKind::Block(statements) => {
+                let environment = Environment::with_enclosing(Arc::clone(&self.environment));
+                self.execute_block(statements, Arc::new(RwLock::new(environment)))
+            }
+            StmtKind::Function { name, params, body } => {
+                let value = Value::Callable(Callable::Function(Arc::new(self.function(
+                    name.to_string(),
+                    params,
+                    body,
+                ))));
+                self.environment
+                    .write()
+                    .unwrap()
+                    .define(Arc::clone(&name.symbol), Some(value));
+                Ok(None)
+            }
+            StmtKind::Class {
+                name,
+                superclass,
+                methods,
+            } => self
+                .declare_class(name, superclass.as_ref(), methods)
+                .map(|_| None),
+            StmtKind::Return { line, value } => {
+                if self.call_stack.is_empty() {
+                    return Err(RoxError::runtime(*line, "can't return from top-level code"));
+                }
+
+                let value = match value {
+                    Some(expr) => {
+                        if let Some(result) = self.tail_call(expr) {
+                            return result.map(|_| Some(Value::Nil));
+                        }
+                        self.evaluate(expr)?
+                    }
+                    None => Value::Nil,
+                };
+                Ok(Some(value))
+            }
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                if self.evaluate(condition)?.is_truthy() {
+                    self.execute(then_branch)
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(else_branch)
+                } else {
+                    Ok(None)
+                }
+            }
+            StmtKind::While { condition, body } => {
+                while self.evaluate(condition)?.is_truthy() {
+                    if let Some(value) = self.execute(body)? {
+                        return Ok(Some(value));
+                    }
+                }
+                Ok(None)
+            }
+            StmtKind::DoWhile { body, condition } => loop {
+                if let Some(value) = self.execute(body)? {
+                    return Ok(Some(value));
+                }
+                if !self.evaluate(condition)?.is_truthy() {
+                    return Ok(None);
+                }
+            },
+            StmtKind::Assert { keyword, condition } => {
+                if self.evaluate(condition)?.is_truthy() {
+                    Ok(None)
+                } else {
+                    Err(RoxError::runtime_at(
+                        keyword,
+                        format!(
+                            "Assertion failed [line {}]: {}",
+                            keyword.line + 1,
+                            print_ast(condition)
+                        ),
+                    ))
+                }
+            }
+            StmtKind::Import { keyword, path } => self.import(keyword, path).map(|_| None),
+            StmtKind::Error { line, message } => Err(RoxError::runtime(*line, message.as_str())),
+        }
+    }
+
+    /// Pushes the frame for a call to `function` at `paren`, or fails if
+    /// there are too many calls in progress. `body` is the function's, for
+    /// a Lox function. Kept out of line, like `leave_call`, so recursion
+    /// doesn't pay for it per level.
+    #[inline(never)]
+    fn enter_call(
+        &mut self,
+        function: &str,
+        body: Option<&Arc<Vec<Stmt>>>,
+        paren: &Token,
+    ) -> ExecuteResult {
+        if self.call_stack.len() >= self.max_call_depth {
+            let message = format!("max call depth exceeded (limit {})", self.max_call_depth);
+            return Err(RoxError::runtime_at(paren, message));
+        }
+        self.call_stack.push(Call {
+            frame: Frame {
+                function: function.to_owned(),
+                line: paren.line,
+            },
+            body: body.cloned(),
+            depth_base: mem::replace(&mut self.depth_base, self.depth),
+        });
+        Ok(())
+    }
+
+    /// Pops the innermost call's frame, adding it to `result`'s error.
+    #[inline(never)]
+    fn leave_call<T>(&mut self, result: Result<T, RoxError>) -> Result<T, RoxError> {
+        let call = self.call_stack.pop().expect("a call's frame is pushed");
+        self.depth_base = call.depth_base;
+        result.map_err(|e| e.called_from(call.frame))
+    }
+
+    /// When `expr`, returned from a function, calls that same function by
+    /// name, evaluates the arguments and leaves them in `tail_call` for the
+    /// function to be run again with, rather than calling it from inside
+    /// itself. `None` for any other expression, which is evaluated as
+    /// usual. Tracing turns this off, so each call is traced as one.
+    #[inline(never)]
+    fn tail_call(&mut self, expr: &Expr) -> Option<ExecuteResult> {
+        let (name, paren, arguments) = match &expr.kind {
+            ExprKind::Call {
+                callee,
+                paren,
+                arguments,
+            } if self.tracer.is_none() => match &callee.kind {
+                ExprKind::Variable(name) => (name, paren, arguments),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        let running = self.call_stack.last()?.body.as_ref()?;
+        let function = match self.lookup(name) {
+            Ok(Value::Callable(Callable::Function(function)))
+                if Arc::ptr_eq(&function.body, running) =>
+            {
+                function
+            }
+            _ => return None,
+        };
+
+        let arguments = arguments
+            .iter()
+            .map(|arg| self.evaluate(arg))
+            .collect::<Result<Vec<_>, _>>();
+        Some(arguments.and_then(|arguments| {
+            let callable = Callable::Function(Arc::clone(&function));
+            if !callable.accepts(arguments.len()) {
+                return Err(arity_error(&callable, arguments.len(), paren));
+            }
+            self.tail_call = Some((function, arguments));
+            Ok(())
+        }))
+    }
+
+    /// Executes `statements` with `environment` as the innermost scope, then
+    /// restores the previous scope whether or not execution succeeded.
+    fn execute_block(
+        &mut self,
+        statements: &[Stmt],
+        environment: Arc<RwLock<Environment>>,
+    ) -> StmtResult {
+        let previous = mem::replace(&mut self.environment, environment);
+        let mut result = Ok(None);
+        for s in statements {
+            result = self.execute(s);
+            if let Ok(None) = result {
+                continue;
+            }
+            break;
+        }
+        self.environment = previous;
+        result
+    }
+
+    #[inline]
+    pub fn evaluate(&mut self, e: &Expr) -> EvalResult {
+        if self.tracer.is_some() {
+            return self.evaluate_traced(e);
+        }
+        self.evaluate_untraced(e)
+    }
+
+    #[inline(never)]
+    fn evaluate_traced(&mut self, e: &Expr) -> EvalResult {
+        let result = self.evaluate_untraced(e);
+        if let Ok(value) = &result {
+            if let Some(event) = trace::expr_event(e, value, self.depth) {
+                self.trace(&event);
             }
         }
+        result
     }
 
-    pub fn evaluate(&mut self, e: Expr) -> EvalResult {
-        match e {
-            Expr::Literal(v) => Ok(v),
-            Expr::Variable(ident) => self.environment.get(&ident).map(|lit_val| lit_val.clone()),
-            Expr::Assign { name, value } => {
-                let eval = self.evaluate(*value)?;
-                self.environment.assign(&name, eval)
+    fn evaluate_untraced(&mut self, e: &Expr) -> EvalResult {
+        // these don't evaluate anything else, so can't nest any deeper, and
+        // are too cheap to count against the budget
+        if let ExprKind::Literal(_)
+        | ExprKind::Variable(_)
+        | ExprKind::This(_)
+        | ExprKind::Super { .. }
+        | ExprKind::Defined(_)
+        | ExprKind::Lambda { .. }
+        | ExprKind::Increment { .. }
+        | ExprKind::Error { .. } = &e.kind
+        {
+            return self.evaluate_nested(e);
+        }
+
+        if let Some(message) = self.take_step() {
+            return Err(RoxError::BudgetExceeded {
+                line: Some(position(e).0),
+                message,
+            });
+        }
+
+        if self.depth - self.depth_base >= self.max_depth {
+            let message = format!("expression too deeply nested (limit {})", self.max_depth);
+            let (line, column) = position(e);
+            return Err(RoxError::Runtime {
+                line,
+                column,
+                message,
+                frames: Vec::new(),
+            });
+        }
+
+        self.depth += 1;
+        let result = stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT_SIZE, || {
+            self.evaluate_nested(e)
+        });
+        self.depth -= 1;
+        result
+    }
+
+    // the tracing is kept out of `execute` and `evaluate`, which recurse, so
+    // their frames don't grow with it
+    fn trace(&mut self, event: &TraceEvent) {
+        if let Some(tracer) = &mut self.tracer {
+            tracer(event);
+        }
+    }
+
+    #[inline(never)]
+    fn trace_stmt(&mut self, s: &Stmt) {
+        let depth = self.depth;
+        self.trace(&TraceEvent::StmtEnter { stmt: s, depth });
+    }
+
+    #[inline(never)]
+    fn trace_declaration(&mut self, name: &str, value: Option<&Value>) {
+        let depth = self.depth;
+        let value = value.unwrap_or(&Value::Nil);
+        self.trace(&TraceEvent::VarWrite { name, value, depth });
+    }
+
+    fn evaluate_nested(&mut self, e: &Expr) -> EvalResult {
+        match &e.kind {
+            ExprKind::Literal(v) => Ok(Value::from(v)),
+            ExprKind::Interpolation(parts) => {
+                let mut text = String::new();
+                for part in parts {
+                    text += &self.evaluate(part)?.to_string();
+                }
+                Ok(Value::Str(text))
             }
-            Expr::Grouping { expr } => self.evaluate(*expr),
-            Expr::Unary { operator, operand } => {
-                let evaluated = self.evaluate(*operand)?;
-                match (evaluated, operator.tag) {
-                    (LiteralValue::Number(n), TokenType::Minus) => Ok(LiteralValue::Number(-n)),
-                    (_, TokenType::Minus) => {
-                        Err("expected a number in negation expression".to_owned())
+            ExprKind::Variable(name) => self.read_variable(name),
+            ExprKind::This(name) => self.lookup(name),
+            ExprKind::Assign { name, value } => {
+                let eval = self.evaluate(value)?;
+                self.assign(name, eval)
+            }
+            ExprKind::Call {
+                callee,
+                paren,
+                arguments,
+            } => {
+                let callee = self.evaluate(callee)?;
+                let arguments = arguments
+                    .iter()
+                    .map(|arg| self.evaluate(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                match callee {
+                    Value::Callable(callable) => {
+                        if !callable.accepts(arguments.len()) {
+                            return Err(arity_error(&callable, arguments.len(), paren));
+                        }
+
+                        callable.call(self, arguments, paren)
                     }
-                    (v, TokenType::Bang) => Ok(LiteralValue::from(!v.is_truthy())),
-                    (_, o) => Err(format!("Did not expected {:#?} in unary expression", o)),
+                    other => Err(RoxError::runtime_at(
+                        paren,
+                        format!(
+                            "can only call functions and classes, got {}",
+                            other.type_name()
+                        ),
+                    )),
+                }
+            }
+            ExprKind::Grouping { expr } => self.evaluate(expr),
+            ExprKind::Unary { operator, operand } => {
+                let evaluated = self.evaluate(operand)?;
+                match (evaluated, &operator.tag) {
+                    (Value::Number(n), TokenType::Minus) => Ok(Value::Number(-n)),
+                    (v, TokenType::Minus) => Err(RoxError::runtime_at(
+                        operator,
+                        format!("Operand of unary '-' must be a number, got {}", v.inspect()),
+                    )),
+                    (v, TokenType::Bang) => Ok(Value::from(!v.is_truthy())),
+                    (_, o) => Err(RoxError::runtime_at(
+                        operator,
+                        format!("Did not expected {:#?} in unary expression", o),
+                    )),
                 }
             }
-            Expr::Binary {
+            ExprKind::Binary {
                 left,
                 operator,
                 right,
-            } => self.handle_binary_expression(*left, operator, *right),
-            Expr::Ternary {
+            } => self.handle_binary_expression(left, operator, right),
+            ExprKind::Ternary {
                 condition,
                 true_expr,
                 false_expr,
             } => {
-                let condition_evaluated = self.evaluate(*condition)?;
-                match condition_evaluated {
-                    LiteralValue::True => self.evaluate(*true_expr),
-                    LiteralValue::False => self.evaluate(*false_expr),
-                    _ => Err(
-                        "expected a boolean expression as condition in ternary statement"
-                            .to_owned(),
-                    ),
+                if self.evaluate(condition)?.is_truthy() {
+                    self.evaluate(true_expr)
+                } else {
+                    self.evaluate(false_expr)
                 }
             }
+            ExprKind::Increment { operator, name } => self.increment(operator, name),
+            ExprKind::Lambda { params, body } => Ok(Value::Callable(Callable::Function(Arc::new(
+                self.function("lambda".to_owned(), params, body),
+            )))),
+            ExprKind::Defined(name) => Ok(Value::Bool(
+                self.environment.read().unwrap().contains(&name.symbol),
+            )),
+            ExprKind::Get { object, name } => self.get_property(object, name),
+            ExprKind::Set {
+                object,
+                name,
+                value,
+            } => self.set_property(object, name, value),
+            ExprKind::Super { keyword, method } => self.super_method(keyword, method),
+            ExprKind::ArrayLiteral(elements) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| self.evaluate(element))
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::list(elements))
+            }
+            ExprKind::MapLiteral { brace, entries } => self.evaluate_map(brace, entries),
+            ExprKind::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                get_index(bracket, &object, &index)
+            }
+            ExprKind::SetIndex {
+                object,
+                bracket,
+                index,
+                value,
+            } => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                let value = self.evaluate(value)?;
+                set_index(bracket, &object, &index, value)
+            }
+            ExprKind::Error { line, message } => Err(RoxError::runtime(*line, message.as_str())),
+        }
+    }
+
+    /// A function closing over the current scope.
+    fn function(&self, name: String, params: &[Name], body: &Arc<Vec<Stmt>>) -> Function {
+        Function {
+            name,
+            params: params.iter().map(|p| Arc::clone(&p.symbol)).collect(),
+            body: Arc::clone(body),
+            closure: Arc::clone(&self.environment),
+            is_initializer: false,
+        }
+    }
+
+    /// Defines a class whose methods close over the current scope, with
+    /// `super` bound around them when it has a superclass.
+    #[inline(never)]
+    fn declare_class(
+        &mut self,
+        name: &Name,
+        superclass: Option<&Name>,
+        methods: &[Stmt],
+    ) -> ExecuteResult {
+        let superclass = match superclass {
+            Some(superclass) => match self.lookup(superclass)? {
+                Value::Callable(Callable::Class(class)) => Some(class),
+                other => {
+                    return Err(RoxError::runtime_at_name(
+                        superclass,
+                        format!("a superclass must be a class, got {}", other.type_name()),
+                    ))
+                }
+            },
+            None => None,
+        };
+
+        let enclosing = superclass.as_ref().map(|class| {
+            let mut environment = Environment::with_enclosing(Arc::clone(&self.environment));
+            let class = Value::Callable(Callable::Class(Arc::clone(class)));
+            environment.define("super".into(), Some(class));
+            mem::replace(&mut self.environment, Arc::new(RwLock::new(environment)))
+        });
+        let methods = methods
+            .iter()
+            .filter_map(|method| match &method.kind {
+                StmtKind::Function { name, params, body } => {
+                    let mut function = self.function(name.to_string(), params, body);
+                    function.is_initializer = &*name.symbol == "init";
+                    Some((Arc::clone(&name.symbol), Arc::new(function)))
+                }
+                _ => None,
+            })
+            .collect();
+        if let Some(enclosing) = enclosing {
+            self.environment = enclosing;
+        }
+
+        let class = Class {
+            name: name.to_string(),
+            superclass,
+            methods,
+        };
+        let value = Value::Callable(Callable::Class(Arc::new(class)));
+        self.environment
+            .write()
+            .unwrap()
+            .define(Arc::clone(&name.symbol), Some(value));
+        Ok(())
+    }
+
+    // property access is kept out of `evaluate_nested`, like `increment`, so
+    // its frame stays small
+    fn get_property(&mut self, object: &Expr, name: &Token) -> EvalResult {
+        let object = self.evaluate(object)?;
+        get_property(name, &object)
+    }
+
+    fn set_property(&mut self, object: &Expr, name: &Token, value: &Expr) -> EvalResult {
+        let object = self.evaluate(object)?;
+        let value = self.evaluate(value)?;
+        set_property(name, &object, value)
+    }
+
+    /// `super.method`: the method from the superclass of the class the
+    /// running method was declared in, bound to `this`. `this` is bound one
+    /// scope in from `super`.
+    fn super_method(&self, keyword: &Name, method: &Token) -> EvalResult {
+        let superclass = match self.lookup(keyword)? {
+            Value::Callable(Callable::Class(class)) => class,
+            _ => unreachable!("'super' is only ever bound to a class"),
+        };
+        let this = Name {
+            symbol: "this".into(),
+            binding: match keyword.binding {
+                Binding::Local(depth) => Binding::Local(depth - 1),
+                binding => binding,
+            },
+            ..keyword.clone()
+        };
+        let this = self.lookup(&this)?;
+
+        let name = property_name(method);
+        match superclass.find_method(&name) {
+            Some(method) => Ok(Value::Callable(Callable::Function(Arc::new(
+                method.bind(this),
+            )))),
+            None => Err(RoxError::runtime_at(
+                method,
+                format!("superclass '{}' has no method '{}'", superclass.name, name),
+            )),
+        }
+    }
+
+    /// The value of the variable `name` refers to, from the scope the
+    /// resolver bound it to, or by name if it wasn't resolved.
+    fn lookup(&self, name: &Name) -> EvalResult {
+        match name.binding {
+            Binding::Dynamic => self.environment.read().unwrap().get(name),
+            Binding::Local(depth) => self.environment.read().unwrap().get_at(depth, name),
+            Binding::Global => self.globals.read().unwrap().get(name),
+        }
+    }
+
+    /// `lookup` for a variable written in the script, which lenient mode
+    /// lets be undefined.
+    fn read_variable(&mut self, name: &Name) -> EvalResult {
+        match self.lookup(name) {
+            Err(_) if self.lenient && !self.is_bound(name) => {
+                self.warn_undefined(name)?;
+                Ok(Value::Nil)
+            }
+            value => value,
         }
     }
 
-    fn handle_binary_expression(&mut self, left: Expr, operator: Token, right: Expr) -> EvalResult {
+    fn warn_undefined(&mut self, name: &Name) -> ExecuteResult {
+        if !self.warned_undefined.insert(Arc::clone(&name.symbol)) {
+            return Ok(());
+        }
+        let warning = Warning {
+            line: name.line,
+            column: name.column,
+            message: format!("variable '{}' is not defined, so it reads as nil", name),
+        };
+        self.flush_printed()?;
+        writeln!(self.stderr, "{}", warning)
+            .and_then(|_| self.stderr.flush())
+            .map_err(|e| output_error(name.line, e))
+    }
+
+    /// Whether the variable `name` refers to exists.
+    fn is_bound(&self, name: &Name) -> bool {
+        match name.binding {
+            Binding::Dynamic => self.environment.read().unwrap().contains(&name.symbol),
+            Binding::Local(_) => true,
+            Binding::Global => self.globals.read().unwrap().contains(&name.symbol),
+        }
+    }
+
+    fn assign(&mut self, name: &Name, value: Value) -> EvalResult {
+        if self.lenient && !self.is_bound(name) {
+            let scope = match name.binding {
+                Binding::Global => &self.globals,
+                _ => &self.environment,
+            };
+            scope
+                .write()
+                .unwrap()
+                .define(Arc::clone(&name.symbol), Some(value.clone()));
+            return Ok(value);
+        }
+        match name.binding {
+            Binding::Dynamic => self.environment.write().unwrap().assign(name, value),
+            Binding::Local(depth) => self
+                .environment
+                .write()
+                .unwrap()
+                .assign_at(depth, name, value),
+            Binding::Global => self.globals.write().unwrap().assign(name, value),
+        }
+    }
+
+    /// `++name` or `--name`, which gives the variable's new value.
+    fn increment(&mut self, operator: &Token, name: &Name) -> EvalResult {
+        let delta = match operator.tag {
+            TokenType::PlusPlus => 1.0,
+            _ => -1.0,
+        };
+        match self.read_variable(name)? {
+            Value::Number(n) => self.assign(name, Value::Number(n + delta)),
+            other => Err(RoxError::runtime_at_name(
+                name,
+                format!(
+                    "'{}' needs a number for '{}', but it holds {}",
+                    name,
+                    operator.tag,
+                    other.inspect()
+                ),
+            )),
+        }
+    }
+
+    fn evaluate_map(&mut self, brace: &Token, entries: &[(Expr, Expr)]) -> EvalResult {
+        let mut map = BTreeMap::new();
+        for (key, value) in entries {
+            let key = self.evaluate(key)?;
+            let key = map_key(brace, &key)?;
+            let value = self.evaluate(value)?;
+            map.insert(key, value);
+        }
+        Ok(Value::map(map))
+    }
+
+    fn handle_binary_expression(
+        &mut self,
+        left: &Expr,
+        operator: &Token,
+        right: &Expr,
+    ) -> EvalResult {
         let left_evaluated = self.evaluate(left)?;
         let right_evaluated = self.evaluate(right)?;
 
-        match (operator.tag, left_evaluated, right_evaluated) {
-            (TokenType::Minus, LiteralValue::Number(l_num), LiteralValue::Number(r_num)) => {
-                Ok(LiteralValue::Number(l_num - r_num))
+        match (&operator.tag, left_evaluated, right_evaluated) {
+            (TokenType::Comma, _, right_val) => Ok(right_val),
+            (TokenType::Minus, Value::Number(l_num), Value::Number(r_num)) => {
+                Ok(Value::Number(l_num - r_num))
             }
-            (TokenType::Slash, LiteralValue::Number(l_num), LiteralValue::Number(r_num)) => {
-                if r_num == 0.0 {
-                    Err("Divide by zero error".to_string())
-                } else {
-                    Ok(LiteralValue::Number(l_num / r_num))
-                }
+            (TokenType::Slash, Value::Number(l_num), Value::Number(r_num)) => {
+                divide(operator, l_num, r_num)
             }
-            (TokenType::Star, LiteralValue::Number(l_num), LiteralValue::Number(r_num)) => {
-                Ok(LiteralValue::Number(l_num * r_num))
+            (TokenType::Star, Value::Number(l_num), Value::Number(r_num)) => {
+                Ok(Value::Number(l_num * r_num))
             }
-            (TokenType::Plus, LiteralValue::Number(l_num), LiteralValue::Number(r_num)) => {
-                Ok(LiteralValue::Number(l_num + r_num))
+            (TokenType::Star, Value::Str(string), Value::Number(count))
+            | (TokenType::Star, Value::Number(count), Value::Str(string)) => {
+                repeat_string(operator, &string, count)
             }
-            (TokenType::Plus, LiteralValue::STRING(l_str), LiteralValue::STRING(r_str)) => {
-                Ok(LiteralValue::STRING(l_str + &r_str))
+            (TokenType::Plus, Value::Number(l_num), Value::Number(r_num)) => {
+                Ok(Value::Number(l_num + r_num))
             }
-            (TokenType::Plus, ref left_val, ref right_val)
-                if !left_val.is_number() ^ !right_val.is_number() =>
-            {
-                Err("Can not add a 'String' and a 'Number' in addition operation".to_owned())
+            // a string on either side turns the other operand into a string,
+            // formatted the same way print would show it
+            (TokenType::Plus, Value::Str(l_str), right_val) => {
+                Ok(Value::Str(l_str + &right_val.to_string()))
             }
-            (TokenType::Greater, LiteralValue::Number(l_num), LiteralValue::Number(r_num)) => {
-                Ok(LiteralValue::from(l_num > r_num))
+            (TokenType::Plus, left_val, Value::Str(r_str)) => {
+                Ok(Value::Str(left_val.to_string() + &r_str))
             }
-            (TokenType::GreaterEqual, LiteralValue::Number(l_num), LiteralValue::Number(r_num)) => {
-                Ok(LiteralValue::from(l_num >= r_num))
+            (TokenType::Greater, Value::Number(l_num), Value::Number(r_num)) => {
+                Ok(Value::from(l_num > r_num))
             }
-            (TokenType::Less, LiteralValue::Number(l_num), LiteralValue::Number(r_num)) => {
-                Ok(LiteralValue::from(l_num < r_num))
+            (TokenType::GreaterEqual, Value::Number(l_num), Value::Number(r_num)) => {
+                Ok(Value::from(l_num >= r_num))
             }
-            (TokenType::LessEqual, LiteralValue::Number(l_num), LiteralValue::Number(r_num)) => {
-                Ok(LiteralValue::from(l_num <= r_num))
+            (TokenType::Less, Value::Number(l_num), Value::Number(r_num)) => {
+                Ok(Value::from(l_num < r_num))
             }
-            (TokenType::EqualEqual, left_val, right_val) => {
-                Ok(LiteralValue::from(left_val == right_val))
+            (TokenType::LessEqual, Value::Number(l_num), Value::Number(r_num)) => {
+                Ok(Value::from(l_num <= r_num))
             }
-            (TokenType::BangEqual, left_val, right_val) => {
-                Ok(LiteralValue::from(left_val != right_val))
+            (tt, Value::Str(l_str), Value::Str(r_str)) if is_comparison_operator(tt) => {
+                let ordering = l_str.cmp(&r_str);
+                Ok(Value::from(match tt {
+                    TokenType::Greater => ordering == Ordering::Greater,
+                    TokenType::GreaterEqual => ordering != Ordering::Less,
+                    TokenType::Less => ordering == Ordering::Less,
+                    _ => ordering != Ordering::Greater,
+                }))
             }
-            (ref tt, ref left_val, ref right_val)
-                if !left_val.is_number() || !right_val.is_number() =>
-            {
-                Err(format!(
-                    "Expected operands to be numbers in {} expression",
-                    tt
+            (ref tt, ref left_val, ref right_val) if is_comparison_operator(tt) => {
+                Err(RoxError::runtime_at(
+                    operator,
+                    format!(
+                        "cannot compare {} and {} with '{}'",
+                        left_val.type_name(),
+                        right_val.type_name(),
+                        tt
+                    ),
                 ))
             }
-            _ => unreachable!(),
+            (TokenType::EqualEqual, left_val, right_val) => Ok(Value::from(left_val == right_val)),
+            (TokenType::BangEqual, left_val, right_val) => Ok(Value::from(left_val != right_val)),
+            (tt, left_val, right_val) => {
+                let expected = match tt {
+                    TokenType::Plus => "two numbers or include a string",
+                    TokenType::Star => "numbers, or a string and a number",
+                    _ => "numbers",
+                };
+                Err(RoxError::runtime_at(
+                    operator,
+                    format!(
+                        "Operands of '{}' must be {}, got {} and {}",
+                        tt,
+                        expected,
+                        left_val.type_name(),
+                        right_val.type_name()
+                    ),
+                ))
+            }
+        }
+    }
+}
+
+/// Where `eprint` writes unless the host says otherwise: the process's
+/// stderr for the command line, nowhere for hosts that may not have one.
+fn default_stderr<'a>() -> Box<dyn Write + Send + 'a> {
+    if cfg!(feature = "cli") {
+        Box::new(io::stderr())
+    } else {
+        Box::new(io::sink())
+    }
+}
+
+/// Dividing by zero fails, whether it's 0 or -0, rather than giving an
+/// infinity whose sign depends on which. So does a division of finite
+/// numbers too large to represent, like `1e300 / 1e-300`. An infinity that
+/// was already there divides as usual.
+fn divide(operator: &Token, numerator: f64, divisor: f64) -> EvalResult {
+    let quotient = numerator / divisor;
+    let problem = if divisor == 0.0 {
+        "Divide by zero error"
+    } else if numerator.is_finite() && quotient.is_infinite() {
+        "Division overflowed"
+    } else {
+        return Ok(Value::Number(quotient));
+    };
+
+    Err(RoxError::runtime_at(
+        operator,
+        format!(
+            "{}: {} / {}",
+            problem,
+            number::format(numerator),
+            number::format(divisor)
+        ),
+    ))
+}
+
+fn repeat_string(operator: &Token, string: &str, count: f64) -> EvalResult {
+    if count < 0.0 || count.fract() != 0.0 {
+        return Err(RoxError::runtime_at(
+            operator,
+            format!(
+                "can only repeat a string a whole, non-negative number of times, got {}",
+                number::format(count)
+            ),
+        ));
+    }
+
+    let length = string.chars().count() as f64 * count;
+    if length > MAX_REPEATED_STRING_LENGTH as f64 {
+        return Err(RoxError::runtime_at(
+            operator,
+            format!(
+                "repeated string would be {} characters long, the limit is {}",
+                length, MAX_REPEATED_STRING_LENGTH
+            ),
+        ));
+    }
+
+    Ok(Value::Str(string.repeat(count as usize)))
+}
+
+/// `object[index]`. A key missing from a map reads as nil.
+/// The name after a `.`, which the parser only allows to be an identifier.
+fn property_name(name: &Token) -> Symbol {
+    match name.tag.get_identifier_value() {
+        Some(symbol) => Arc::clone(symbol),
+        None => name.tag.to_string().into(),
+    }
+}
+
+fn get_property(name: &Token, object: &Value) -> EvalResult {
+    let property = property_name(name);
+    match object {
+        Value::Instance(instance) => instance.get(&property, object).ok_or_else(|| {
+            RoxError::runtime_at(name, format!("{} has no property '{}'", instance, property))
+        }),
+        other => Err(RoxError::runtime_at(
+            name,
+            format!(
+                "no properties on this value, can't read '{}' from {}",
+                property,
+                other.type_name()
+            ),
+        )),
+    }
+}
+
+fn set_property(name: &Token, object: &Value, value: Value) -> EvalResult {
+    let property = property_name(name);
+    match object {
+        Value::Instance(instance) => {
+            instance.set(property, value.clone());
+            Ok(value)
         }
+        other => Err(RoxError::runtime_at(
+            name,
+            format!(
+                "no properties on this value, can't set '{}' on {}",
+                property,
+                other.type_name()
+            ),
+        )),
+    }
+}
+
+fn get_index(bracket: &Token, object: &Value, index: &Value) -> EvalResult {
+    if let Value::Map(map) = object {
+        let key = map_key(bracket, index)?;
+        return Ok(map.read().unwrap().get(&key).cloned().unwrap_or(Value::Nil));
+    }
+    let (list, i) = list_slot(bracket, object, index)?;
+    let element = list.read().unwrap()[i].clone();
+    Ok(element)
+}
+
+/// `object[index] = value`. Maps gain the key if it's new, but a list
+/// index must already be in range.
+fn set_index(bracket: &Token, object: &Value, index: &Value, value: Value) -> EvalResult {
+    if let Value::Map(map) = object {
+        let key = map_key(bracket, index)?;
+        map.write().unwrap().insert(key, value.clone());
+        return Ok(value);
+    }
+    let (list, i) = list_slot(bracket, object, index)?;
+    list.write().unwrap()[i] = value.clone();
+    Ok(value)
+}
+
+/// The list `object[index]` refers into and the position within it, or an
+/// error at `bracket` when `object` isn't a list or `index` isn't a whole
+/// number in range.
+fn list_slot<'v>(
+    bracket: &Token,
+    object: &'v Value,
+    index: &Value,
+) -> Result<(&'v RwLock<Vec<Value>>, usize), RoxError> {
+    let list = match object {
+        Value::List(list) => list,
+        other => {
+            return Err(RoxError::runtime_at(
+                bracket,
+                format!(
+                    "only lists and maps can be indexed, got {}",
+                    other.type_name()
+                ),
+            ))
+        }
+    };
+    let i = match index {
+        Value::Number(n) if n.fract() == 0.0 => *n,
+        other => {
+            return Err(RoxError::runtime_at(
+                bracket,
+                format!("list index must be a whole number, got {}", other.inspect()),
+            ))
+        }
+    };
+    let len = list.read().unwrap().len();
+    if i < 0.0 || i >= len as f64 {
+        return Err(RoxError::runtime_at(
+            bracket,
+            format!(
+                "list index {} out of range for a list of length {}",
+                number::format(i),
+                len
+            ),
+        ));
+    }
+    Ok((list, i as usize))
+}
+
+fn map_key(token: &Token, key: &Value) -> Result<String, RoxError> {
+    match key {
+        Value::Str(key) => Ok(key.clone()),
+        other => Err(RoxError::runtime_at(
+            token,
+            format!("map keys must be strings, got {}", other.inspect()),
+        )),
+    }
+}
+
+fn is_comparison_operator(tt: &TokenType) -> bool {
+    matches!(
+        tt,
+        TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual
+    )
+}
+
+/// The line and column to report an error about `e` at: its operator when
+/// it has one, otherwise wherever it starts.
+fn position(e: &Expr) -> (u32, Option<u32>) {
+    let token = match &e.kind {
+        ExprKind::Variable(name)
+        | ExprKind::This(name)
+        | ExprKind::Defined(name)
+        | ExprKind::Increment { name, .. }
+        | ExprKind::Assign { name, .. } => return (name.line, Some(name.column)),
+        ExprKind::Binary {
+            operator: token, ..
+        }
+        | ExprKind::Unary {
+            operator: token, ..
+        }
+        | ExprKind::Call { paren: token, .. }
+        | ExprKind::Get { name: token, .. }
+        | ExprKind::Set { name: token, .. }
+        | ExprKind::Super { method: token, .. }
+        | ExprKind::MapLiteral { brace: token, .. }
+        | ExprKind::Index { bracket: token, .. }
+        | ExprKind::SetIndex { bracket: token, .. } => token,
+        ExprKind::Error { line, .. } => return (*line, None),
+        ExprKind::Literal(_)
+        | ExprKind::Interpolation(_)
+        | ExprKind::Lambda { .. }
+        | ExprKind::ArrayLiteral(_)
+        | ExprKind::Grouping { .. }
+        | ExprKind::Ternary { .. } => return (e.span.line, Some(e.span.column)),
+    };
+    (token.line, Some(token.column))
+}
+
+/// Calling `callable` at `paren` with `count` arguments, which is the wrong
+/// number.
+fn arity_error(callable: &Callable, count: usize, paren: &Token) -> RoxError {
+    let at_least = match callable {
+        Callable::Native(native) if native.variadic => "at least ",
+        _ => "",
+    };
+    let message = format!(
+        "{} '{}' expected {}{} arguments but got {}",
+        callable.kind(),
+        callable.name(),
+        at_least,
+        callable.arity(),
+        count
+    );
+    RoxError::runtime_at(paren, message)
+}
+
+/// Printing fails when the output has gone away, for example a closed pipe.
+fn output_error(line: u32, error: io::Error) -> RoxError {
+    RoxError::runtime(line, format!("could not write output: {}", error))
+}
+
+fn check_for_parse_errors(statements: &[Stmt]) -> ExecuteResult {
+    match statements.iter().find_map(Stmt::error_line) {
+        Some(line) => Err(RoxError::runtime(line, "program contains parse errors")),
+        None => Ok(()),
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::expr::LiteralValue;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::test::on_main_thread_stack;
+
+    #[test]
+    fn test_interpreter_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Interpreter<'static>>();
+        assert_send::<Value>();
+        assert_send::<Stmt>();
+    }
 
     #[test]
     fn test_addition() {
         let mut interpreter = Interpreter::default();
-        let expr_1 = Expr::Binary {
-            left: Box::new(Expr::Literal(LiteralValue::Number(10.0))),
-            right: Box::new(Expr::Literal(LiteralValue::Number(2.0))),
+        let expr_1: Expr = ExprKind::Binary {
+            left: Box::new(ExprKind::Literal(LiteralValue::Number(10.0)).into()),
+            right: Box::new(ExprKind::Literal(LiteralValue::Number(2.0)).into()),
             operator: Token::new(TokenType::Plus, 0),
-        };
+        }
+        .into();
+
+        assert_eq!(interpreter.evaluate(&expr_1), Ok(Value::Number(12.0)));
 
-        let expr_2 = Expr::Binary {
-            left: Box::new(expr_1.clone()),
-            right: Box::new(Expr::Literal(LiteralValue::Number(-5.0))),
+        let expr_2: Expr = ExprKind::Binary {
+            left: Box::new(expr_1),
+            right: Box::new(ExprKind::Literal(LiteralValue::Number(-5.0)).into()),
             operator: Token::new(TokenType::Plus, 0),
+        }
+        .into();
+
+        assert_eq!(interpreter.evaluate(&expr_2), Ok(Value::Number(7.0)));
+    }
+
+    #[test]
+    fn test_comparison_type_matrix() {
+        let operands = [
+            LiteralValue::Number(1.0),
+            LiteralValue::STRING("a".into()),
+            LiteralValue::True,
+            LiteralValue::Nil,
+        ];
+        let operators = [
+            TokenType::Less,
+            TokenType::LessEqual,
+            TokenType::Greater,
+            TokenType::GreaterEqual,
+        ];
+
+        let mut interpreter = Interpreter::default();
+        for operator in operators.iter() {
+            for left in operands.iter() {
+                for right in operands.iter() {
+                    let expr: Expr = ExprKind::Binary {
+                        left: Box::new(ExprKind::Literal(left.clone()).into()),
+                        operator: Token::new(operator.clone(), 0),
+                        right: Box::new(ExprKind::Literal(right.clone()).into()),
+                    }
+                    .into();
+                    let expected = match (left, right, operator) {
+                        (LiteralValue::Number(_), LiteralValue::Number(_), TokenType::Less)
+                        | (LiteralValue::Number(_), LiteralValue::Number(_), TokenType::Greater) => {
+                            Ok(Value::Bool(false))
+                        }
+                        (LiteralValue::Number(_), LiteralValue::Number(_), _) => {
+                            Ok(Value::Bool(true))
+                        }
+                        (LiteralValue::STRING(_), LiteralValue::STRING(_), TokenType::Less)
+                        | (LiteralValue::STRING(_), LiteralValue::STRING(_), TokenType::Greater) => {
+                            Ok(Value::Bool(false))
+                        }
+                        (LiteralValue::STRING(_), LiteralValue::STRING(_), _) => {
+                            Ok(Value::Bool(true))
+                        }
+                        _ => Err(RoxError::runtime_at(
+                            &Token::new(operator.clone(), 0),
+                            format!(
+                                "cannot compare {} and {} with '{}'",
+                                Value::from(left).type_name(),
+                                Value::from(right).type_name(),
+                                operator
+                            ),
+                        )),
+                    };
+
+                    assert_eq!(interpreter.evaluate(&expr), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_comparison_error_names_both_types() {
+        let mut interpreter = Interpreter::default();
+        let operator = Token::with_column(TokenType::Less, 0, 5);
+        let expr: Expr = ExprKind::Binary {
+            left: Box::new(ExprKind::Literal(LiteralValue::True).into()),
+            operator: operator.clone(),
+            right: Box::new(ExprKind::Literal(LiteralValue::Number(1.0)).into()),
+        }
+        .into();
+
+        assert_eq!(
+            interpreter.evaluate(&expr),
+            Err(RoxError::runtime_at(
+                &operator,
+                "cannot compare boolean and number with '<'"
+            ))
+        );
+    }
+
+    /// Accepts `remaining` bytes, then fails like a closed pipe.
+    struct ClosingWriter {
+        remaining: usize,
+    }
+
+    impl Write for ClosingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if buf.len() > self.remaining {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            self.remaining -= buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn print(line: u32, s: &str) -> Stmt {
+        StmtKind::Print {
+            line,
+            values: vec![ExprKind::Literal(LiteralValue::STRING(s.into())).into()],
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_print_to_closed_writer_is_a_runtime_error() {
+        let mut interpreter = Interpreter::new(ClosingWriter { remaining: 8 });
+        let statements = [print(0, "hello"), print(1, "world"), print(2, "!")];
+
+        match interpreter.interpret(&statements) {
+            Err(RoxError::Runtime { line, message, .. }) => {
+                assert_eq!(line, 1);
+                assert!(
+                    message.starts_with("could not write output: "),
+                    "{}",
+                    message
+                );
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_set_writer_swaps_the_output() {
+        let mut first = Vec::new();
+        let mut second = Vec::new();
+        {
+            let mut interpreter = Interpreter::new(&mut first);
+            interpreter.interpret(&[print(0, "one")]).unwrap();
+            drop(interpreter.set_writer(&mut second));
+            interpreter.interpret(&[print(1, "two")]).unwrap();
+            interpreter.flush().unwrap();
+        }
+
+        assert_eq!("one\n", String::from_utf8(first).unwrap());
+        assert_eq!("two\n", String::from_utf8(second).unwrap());
+    }
+
+    fn negated(times: usize) -> Expr {
+        let mut expr: Expr = ExprKind::Literal(LiteralValue::Number(1.0)).into();
+        for column in (0..times as u32).rev() {
+            expr = ExprKind::Unary {
+                operator: Token::with_column(TokenType::Minus, 0, column),
+                operand: Box::new(expr),
+            }
+            .into();
+        }
+        expr
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let mut interpreter = Interpreter::with_max_depth(io::sink(), 4);
+
+        assert_eq!(interpreter.evaluate(&negated(4)), Ok(Value::Number(1.0)));
+        assert_eq!(
+            interpreter.evaluate(&negated(5)),
+            Err(RoxError::runtime_at(
+                &Token::with_column(TokenType::Minus, 0, 4),
+                "expression too deeply nested (limit 4)"
+            ))
+        );
+        // a failure leaves nothing behind
+        assert_eq!(interpreter.evaluate(&negated(2)), Ok(Value::Number(1.0)));
+    }
+
+    #[test]
+    fn test_errors_without_an_operator_point_at_the_expression() {
+        let tokens = Scanner::new("true ? (1) : 2".to_owned())
+            .scan_tokens()
+            .unwrap()
+            .to_vec();
+        let statements = Parser::for_repl(tokens).parse().unwrap();
+        let ternary = match &statements[0].kind {
+            StmtKind::Expr(expr) => expr,
+            other => panic!("expected an expression, got {:?}", other),
         };
 
-        assert_eq!(interpreter.evaluate(expr_1), Ok(LiteralValue::Number(12.0)));
-        assert_eq!(interpreter.evaluate(expr_2), Ok(LiteralValue::Number(7.0)));
+        let mut interpreter = Interpreter::with_max_depth(io::sink(), 1);
+        assert_eq!(
+            interpreter.evaluate(ternary),
+            Err(RoxError::Runtime {
+                line: 0,
+                column: Some(7),
+                message: "expression too deeply nested (limit 1)".to_owned(),
+                frames: Vec::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_default_nesting_limit() {
+        on_main_thread_stack(|| {
+            let mut interpreter = Interpreter::new(io::sink());
+
+            assert!(interpreter.evaluate(&negated(DEFAULT_MAX_DEPTH)).is_ok());
+            assert!(interpreter
+                .evaluate(&negated(DEFAULT_MAX_DEPTH + 1))
+                .is_err());
+        })
+    }
+
+    fn in_blocks(times: usize) -> Stmt {
+        let mut stmt = print(0, "deep");
+        for _ in 0..times {
+            stmt = StmtKind::Block(vec![stmt]).into();
+        }
+        stmt
+    }
+
+    #[test]
+    fn test_statements_count_towards_the_nesting_limit() {
+        let mut interpreter = Interpreter::with_max_depth(io::sink(), 3);
+
+        assert_eq!(interpreter.interpret(&[in_blocks(2)]), Ok(()));
+        assert_eq!(
+            interpreter.interpret(&[in_blocks(3)]),
+            Err(RoxError::Runtime {
+                line: 0,
+                column: Some(0),
+                message: "statement too deeply nested (limit 3)".to_owned(),
+                frames: Vec::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_pathological_statement_nesting_is_an_error() {
+        on_main_thread_stack(|| {
+            let mut interpreter = Interpreter::new(io::sink());
+            assert!(interpreter.interpret(&[in_blocks(10_000)]).is_err());
+        })
+    }
+
+    #[test]
+    fn test_tracer_sees_statements_variables_and_results() {
+        let source = "var a = 1;\nfun f(x) { return x * 2; }\na = f(a + 1);";
+        let tokens = Scanner::new(source.to_owned())
+            .scan_tokens()
+            .unwrap()
+            .to_vec();
+        let statements = Parser::new(tokens).parse().unwrap();
+
+        let mut events = Vec::new();
+        let mut interpreter = Interpreter::new(io::sink());
+        interpreter.set_tracer(|event| events.push(event.to_string()));
+        interpreter.interpret(&statements).unwrap();
+        drop(interpreter);
+
+        assert_eq!(
+            events,
+            vec![
+                "var a = 1;",
+                "  write a = 1",
+                "fun f(x) {",
+                "a = f(a + 1);",
+                "      read f = <fn f>",
+                "        read a = 1",
+                "      a + 1 => 2",
+                "      return x * 2;",
+                "          read x = 2",
+                "        x * 2 => 4",
+                "    f(a + 1) => 4",
+                "  write a = 4",
+            ]
+        );
     }
 }
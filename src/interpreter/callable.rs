@@ -0,0 +1,178 @@
+use super::class::{Class, Instance};
+use super::environment::Environment;
+use super::{EvalResult, Interpreter, Value};
+use crate::error::RoxError;
+use crate::statement::Stmt;
+use crate::symbol::Symbol;
+use crate::token::Token;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::sync::{Arc, RwLock};
+
+/// What a method calls the instance it was called on.
+const THIS: &str = "this";
+
+#[derive(Clone)]
+pub enum Callable {
+    Function(Arc<Function>),
+    Native(NativeFunction),
+    /// Calling a class makes an instance of it.
+    Class(Arc<Class>),
+}
+
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Symbol>,
+    pub body: Arc<Vec<Stmt>>,
+    pub closure: Arc<RwLock<Environment>>,
+    /// A class's `init`, which gives back `this` however it returns.
+    pub is_initializer: bool,
+}
+
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: &'static str,
+    pub arity: usize,
+    /// Whether it takes any number of arguments past its arity, which is
+    /// then the fewest it needs.
+    pub variadic: bool,
+    /// Natives get the interpreter calling them, for the few that need
+    /// to see its variables or print.
+    pub function: fn(&mut Interpreter, &[Value]) -> Result<Value, String>,
+}
+
+impl Callable {
+    pub fn name(&self) -> &str {
+        match self {
+            Callable::Function(function) => &function.name,
+            Callable::Native(native) => native.name,
+            Callable::Class(class) => &class.name,
+        }
+    }
+
+    /// What error messages call it.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Callable::Class(_) => "class",
+            _ => "function",
+        }
+    }
+
+    /// Whether it can be called with `count` arguments.
+    pub fn accepts(&self, count: usize) -> bool {
+        match self {
+            Callable::Native(native) if native.variadic => count >= native.arity,
+            _ => count == self.arity(),
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        match self {
+            Callable::Function(function) => function.params.len(),
+            Callable::Native(native) => native.arity,
+            Callable::Class(class) => class.arity(),
+        }
+    }
+
+    /// Calls the function with arguments whose count already matches its
+    /// arity. `paren` is the call site, which native errors are reported at.
+    pub fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Value>,
+        paren: &Token,
+    ) -> EvalResult {
+        match self {
+            Callable::Native(native) => {
+                interpreter.enter_call(native.name, None, paren)?;
+                let result = (native.function)(interpreter, &arguments)
+                    .map_err(|e| RoxError::runtime_at(paren, e));
+                interpreter.leave_call(result)
+            }
+            Callable::Function(function) => {
+                interpreter.enter_call(&function.name, Some(&function.body), paren)?;
+                // a `return` of a call of this same function leaves its
+                // arguments to be run with here, rather than nesting
+                let mut callee = Arc::clone(function);
+                let mut environment = callee.environment(arguments);
+                let result = loop {
+                    let result = interpreter.execute_block(&callee.body, environment);
+                    match interpreter.tail_call.take() {
+                        Some((function, arguments)) => {
+                            environment = function.environment(arguments);
+                            callee = function;
+                        }
+                        None => break result,
+                    }
+                };
+                let value = interpreter.leave_call(result)?.unwrap_or(Value::Nil);
+                if function.is_initializer {
+                    return Ok(function.this());
+                }
+                Ok(value)
+            }
+            Callable::Class(class) => Instance::create(class, interpreter, arguments, paren),
+        }
+    }
+}
+
+impl Function {
+    /// A scope for running the body in, with the parameters bound to
+    /// `arguments`. Kept out of line so a call's frame doesn't hold one.
+    #[inline(never)]
+    fn environment(&self, arguments: Vec<Value>) -> Arc<RwLock<Environment>> {
+        let mut environment = Environment::with_enclosing(Arc::clone(&self.closure));
+        for (param, argument) in self.params.iter().zip(arguments) {
+            environment.define(param.clone(), Some(argument));
+        }
+        Arc::new(RwLock::new(environment))
+    }
+
+    /// The instance a method is bound to.
+    fn this(&self) -> Value {
+        let this = self.closure.read().unwrap().local(THIS).cloned();
+        this.expect("only a method bound to an instance has a 'this'")
+    }
+
+    /// The method with `this` bound to `instance`, in a scope of its own
+    /// between the method and the scope it closes over.
+    pub fn bind(&self, instance: Value) -> Function {
+        let mut environment = Environment::with_enclosing(Arc::clone(&self.closure));
+        environment.define(THIS.into(), Some(instance));
+        Function {
+            name: self.name.clone(),
+            params: self.params.clone(),
+            body: Arc::clone(&self.body),
+            closure: Arc::new(RwLock::new(environment)),
+            is_initializer: self.is_initializer,
+        }
+    }
+}
+
+impl PartialEq for Callable {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Callable::Function(l), Callable::Function(r)) => Arc::ptr_eq(l, r),
+            (Callable::Native(l), Callable::Native(r)) => l.name == r.name,
+            (Callable::Class(l), Callable::Class(r)) => Arc::ptr_eq(l, r),
+            _ => false,
+        }
+    }
+}
+
+impl Display for Callable {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Callable::Function(function) => write!(f, "<fn {}>", function.name),
+            Callable::Native(native) => write!(f, "<native fn {}>", native.name),
+            Callable::Class(class) => write!(f, "{}", class.name),
+        }
+    }
+}
+
+// functions usually live in the environment they close over, so deriving
+// Debug would recurse forever
+impl Debug for Callable {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
@@ -0,0 +1,94 @@
+use super::callable::{Callable, Function};
+use super::{EvalResult, Interpreter, Value};
+use crate::symbol::Symbol;
+use crate::token::Token;
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::sync::{Arc, RwLock};
+
+pub struct Class {
+    pub name: String,
+    pub superclass: Option<Arc<Class>>,
+    pub methods: HashMap<Symbol, Arc<Function>>,
+}
+
+/// An object made by calling a class. Fields are created by assigning to
+/// them, and shadow any method of the same name.
+pub struct Instance {
+    pub class: Arc<Class>,
+    fields: RwLock<HashMap<Symbol, Value>>,
+}
+
+impl Class {
+    /// The method called `name`, from this class or the nearest superclass
+    /// that has one.
+    pub fn find_method(&self, name: &str) -> Option<&Arc<Function>> {
+        match (self.methods.get(name), &self.superclass) {
+            (Some(method), _) => Some(method),
+            (None, Some(superclass)) => superclass.find_method(name),
+            (None, None) => None,
+        }
+    }
+
+    /// `init`, which is run on every new instance.
+    pub fn initializer(&self) -> Option<&Arc<Function>> {
+        self.find_method("init")
+    }
+
+    /// As many arguments as `init` takes, or none without one.
+    pub fn arity(&self) -> usize {
+        self.initializer().map_or(0, |init| init.params.len())
+    }
+}
+
+impl Instance {
+    /// A new instance of `class`, with `init` run on it if there is one.
+    /// The arguments are for `init`, and already match its arity.
+    #[inline(never)]
+    pub fn create(
+        class: &Arc<Class>,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Value>,
+        paren: &Token,
+    ) -> EvalResult {
+        let instance = Value::Instance(Arc::new(Self {
+            class: Arc::clone(class),
+            fields: RwLock::new(HashMap::new()),
+        }));
+        if let Some(init) = class.initializer() {
+            let init = Callable::Function(Arc::new(init.bind(instance.clone())));
+            init.call(interpreter, arguments, paren)?;
+        }
+        Ok(instance)
+    }
+
+    /// The field called `name`, or else the method, bound to `instance`,
+    /// which must be this instance. The method holds on to the instance
+    /// itself, so calling it later sees the fields as they are then.
+    pub fn get(&self, name: &str, instance: &Value) -> Option<Value> {
+        if let Some(value) = self.fields.read().unwrap().get(name) {
+            return Some(value.clone());
+        }
+        let method = self.class.find_method(name)?;
+        let method = method.bind(instance.clone());
+        Some(Value::Callable(Callable::Function(Arc::new(method))))
+    }
+
+    pub fn set(&self, name: Symbol, value: Value) {
+        self.fields.write().unwrap().insert(name, value);
+    }
+}
+
+impl Display for Instance {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
+
+// an instance can hold itself in a field, so deriving Debug could recurse
+// forever
+impl Debug for Instance {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
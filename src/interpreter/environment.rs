@@ -1,51 +1,394 @@
-use crate::expr::LiteralValue;
-use crate::token::Token;
-use std::collections::HashMap;
+use super::Value;
+use crate::error::RoxError;
+use crate::symbol::Symbol;
+use crate::token::Name;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::sync::{Arc, RwLock};
+
+/// One binding as listed by `Environment::entries`.
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    pub name: Symbol,
+    pub value: Value,
+    /// How many scopes out the binding is, 0 being the innermost.
+    pub scope: usize,
+    /// Whether a scope further in binds the same name, hiding this one.
+    pub shadowed: bool,
+}
 
 #[derive(Debug)]
 pub struct Environment {
-    globals: HashMap<String, LiteralValue>,
+    values: HashMap<Symbol, Value>,
+    /// The names in `values` declared with `const`.
+    constants: HashSet<Symbol>,
+    enclosing: Option<Arc<RwLock<Environment>>>,
 }
 
 impl Environment {
     pub fn new() -> Self {
         Self {
-            globals: HashMap::new(),
+            values: HashMap::new(),
+            constants: HashSet::new(),
+            enclosing: None,
+        }
+    }
+
+    /// Creates an innermost scope whose lookups fall back to `enclosing`.
+    pub fn with_enclosing(enclosing: Arc<RwLock<Environment>>) -> Self {
+        Self {
+            values: HashMap::new(),
+            constants: HashSet::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    pub fn define(&mut self, key: Symbol, value: Option<Value>) {
+        self.constants.remove(&key);
+        self.values.insert(key, value.unwrap_or_else(|| Value::Nil));
+    }
+
+    /// Like `define`, but later assignments to `key` in this scope fail.
+    /// Redefining it, as the REPL does, replaces the constant.
+    pub fn define_constant(&mut self, key: Symbol, value: Value) {
+        self.constants.insert(Arc::clone(&key));
+        self.values.insert(key, value);
+    }
+
+    /// Whether `name` is bound in this scope or any enclosing one.
+    pub fn contains(&self, name: &str) -> bool {
+        if self.values.contains_key(name) {
+            return true;
         }
+        match &self.enclosing {
+            Some(enclosing) => enclosing.read().unwrap().contains(name),
+            None => false,
+        }
+    }
+
+    /// The names bound in this scope, not counting enclosing ones, in no
+    /// particular order.
+    pub fn bindings(&self) -> impl Iterator<Item = (&Symbol, &Value)> {
+        self.values.iter()
     }
 
-    pub fn define(&mut self, key: String, value: Option<LiteralValue>) {
-        self.globals
-            .insert(key, value.unwrap_or_else(|| LiteralValue::Nil));
+    /// How many names are bound in this scope, not counting enclosing ones.
+    pub fn len(&self) -> usize {
+        self.values.len()
     }
 
-    pub fn get(&self, token: &Token) -> Result<&LiteralValue, String> {
-        let var_name = &token
-            .tag
-            .get_identifier_value()
-            .expect("expected identifier token");
-        self.globals.get(var_name).ok_or_else(|| {
-            format!(
-                "[line {}] Error: variable '{}' is not defined",
-                token.line, var_name
-            )
-        })
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
     }
 
-    pub fn assign(&mut self, token: &Token, value: LiteralValue) -> Result<LiteralValue, String> {
-        let var_name = token
-            .tag
-            .get_identifier_value()
-            .expect("expected identifier token");
+    /// Every binding visible from this scope, innermost scope first and
+    /// sorted by name within each scope, so the order is the same every
+    /// time.
+    pub fn entries(&self) -> Vec<Entry> {
+        let mut entries = Vec::new();
+        self.collect_entries(0, &mut HashSet::new(), &mut entries);
+        entries
+    }
 
-        if self.globals.contains_key(&var_name) {
-            self.globals.insert(var_name, value.clone());
+    fn collect_entries(&self, scope: usize, inner: &mut HashSet<Symbol>, entries: &mut Vec<Entry>) {
+        let mut names: Vec<_> = self.values.iter().collect();
+        names.sort_by_key(|(name, _)| *name);
+        for (name, value) in names {
+            entries.push(Entry {
+                name: Arc::clone(name),
+                value: value.clone(),
+                scope,
+                shadowed: inner.contains(name),
+            });
+        }
+
+        inner.extend(self.values.keys().cloned());
+        if let Some(enclosing) = &self.enclosing {
+            enclosing
+                .read()
+                .unwrap()
+                .collect_entries(scope + 1, inner, entries);
+        }
+    }
+
+    /// `entries` as text, one `name = value` line each. Each enclosing
+    /// scope starts with a line of its own.
+    pub fn dump(&self) -> String {
+        let mut dump = String::new();
+        let mut scope = 0;
+        for entry in self.entries() {
+            while scope < entry.scope {
+                scope += 1;
+                dump.push_str("-- enclosing scope --\n");
+            }
+            let _ = write!(dump, "{} = {}", entry.name, entry.value.inspect());
+            if entry.shadowed {
+                dump.push_str(" (shadowed)");
+            }
+            dump.push('\n');
+        }
+        dump
+    }
+
+    /// The value of `name` in this scope only.
+    pub fn local(&self, name: &str) -> Option<&Value> {
+        self.values.get(name)
+    }
+
+    /// Removes `name` from this scope only, returning whether it was bound
+    /// here.
+    pub fn undefine(&mut self, name: &str) -> bool {
+        self.constants.remove(name);
+        self.values.remove(name).is_some()
+    }
+
+    pub fn get(&self, name: &Name) -> Result<Value, RoxError> {
+        match (self.values.get(&name.symbol), &self.enclosing) {
+            (Some(value), _) => Ok(value.clone()),
+            (None, Some(enclosing)) => enclosing.read().unwrap().get(name),
+            (None, None) => Err(not_defined(name)),
+        }
+    }
+
+    pub fn assign(&mut self, name: &Name, value: Value) -> Result<Value, RoxError> {
+        if let Some(slot) = self.values.get_mut(&name.symbol) {
+            if self.constants.contains(&name.symbol) {
+                return Err(constant_assigned(name));
+            }
+            *slot = value.clone();
             Ok(value)
+        } else if let Some(enclosing) = &self.enclosing {
+            enclosing.write().unwrap().assign(name, value)
         } else {
-            Err(format!(
-                "[line {}] Error: variable '{}' is not defined",
-                token.line, var_name
+            Err(not_defined(name))
+        }
+    }
+
+    /// Like `get`, but only looks in the scope `depth` out from this one,
+    /// where the resolver found `name` to be declared.
+    pub fn get_at(&self, depth: usize, name: &Name) -> Result<Value, RoxError> {
+        match (depth, &self.enclosing) {
+            (0, _) => self
+                .values
+                .get(&name.symbol)
+                .cloned()
+                .ok_or_else(|| not_defined(name)),
+            (_, Some(enclosing)) => enclosing.read().unwrap().get_at(depth - 1, name),
+            (_, None) => Err(not_defined(name)),
+        }
+    }
+
+    /// Like `assign`, but only looks in the scope `depth` out from this
+    /// one.
+    pub fn assign_at(
+        &mut self,
+        depth: usize,
+        name: &Name,
+        value: Value,
+    ) -> Result<Value, RoxError> {
+        match (depth, &self.enclosing) {
+            (0, _) => match self.values.get_mut(&name.symbol) {
+                Some(_) if self.constants.contains(&name.symbol) => Err(constant_assigned(name)),
+                Some(slot) => {
+                    *slot = value.clone();
+                    Ok(value)
+                }
+                None => Err(not_defined(name)),
+            },
+            (_, Some(enclosing)) => enclosing.write().unwrap().assign_at(depth - 1, name, value),
+            (_, None) => Err(not_defined(name)),
+        }
+    }
+}
+
+fn not_defined(name: &Name) -> RoxError {
+    RoxError::runtime_at_name(name, format!("variable '{}' is not defined", name))
+}
+
+fn constant_assigned(name: &Name) -> RoxError {
+    RoxError::runtime_at_name(name, format!("cannot assign to constant '{}'", name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::token::Binding;
+    fn ident(name: &str) -> Name {
+        Name {
+            symbol: name.into(),
+            line: 3,
+            column: 0,
+            binding: Binding::Dynamic,
+        }
+    }
+
+    fn number(n: f64) -> Option<Value> {
+        Some(Value::Number(n))
+    }
+
+    #[test]
+    fn test_entries_are_sorted_and_marked_shadowed() {
+        let outer = Arc::new(RwLock::new(Environment::new()));
+        for name in &["c", "a", "b"] {
+            outer.write().unwrap().define((*name).into(), number(1.0));
+        }
+        let mut inner = Environment::with_enclosing(Arc::clone(&outer));
+        inner.define("b".into(), number(2.0));
+
+        assert_eq!(outer.read().unwrap().len(), 3);
+        assert_eq!(
+            inner.entries()[..2],
+            [
+                Entry {
+                    name: "b".into(),
+                    value: Value::Number(2.0),
+                    scope: 0,
+                    shadowed: false,
+                },
+                Entry {
+                    name: "a".into(),
+                    value: Value::Number(1.0),
+                    scope: 1,
+                    shadowed: false,
+                },
+            ]
+        );
+        assert_eq!(
+            inner.dump(),
+            "b = 2\n-- enclosing scope --\na = 1\nb = 1 (shadowed)\nc = 1\n"
+        );
+    }
+
+    #[test]
+    fn test_inner_scope_shadows_outer() {
+        let outer = Arc::new(RwLock::new(Environment::new()));
+        outer.write().unwrap().define("a".into(), number(1.0));
+
+        let mut inner = Environment::with_enclosing(Arc::clone(&outer));
+        inner.define("a".into(), number(2.0));
+
+        assert_eq!(inner.get(&ident("a")), Ok(Value::Number(2.0)));
+        assert_eq!(
+            outer.read().unwrap().get(&ident("a")),
+            Ok(Value::Number(1.0))
+        );
+    }
+
+    #[test]
+    fn test_assignment_reaches_outer_scope() {
+        let outer = Arc::new(RwLock::new(Environment::new()));
+        outer.write().unwrap().define("a".into(), number(1.0));
+
+        let mut inner = Environment::with_enclosing(Arc::clone(&outer));
+        assert_eq!(
+            inner.assign(&ident("a"), Value::Number(5.0)),
+            Ok(Value::Number(5.0))
+        );
+
+        assert_eq!(
+            outer.read().unwrap().get(&ident("a")),
+            Ok(Value::Number(5.0))
+        );
+    }
+
+    #[test]
+    fn test_resolved_lookups_skip_shadowing_scopes() {
+        let outer = Arc::new(RwLock::new(Environment::new()));
+        outer.write().unwrap().define("a".into(), number(1.0));
+        let mut inner = Environment::with_enclosing(Arc::clone(&outer));
+        inner.define("a".into(), number(2.0));
+
+        assert_eq!(inner.get_at(1, &ident("a")), Ok(Value::Number(1.0)));
+        assert_eq!(
+            inner.assign_at(1, &ident("a"), Value::Number(3.0)),
+            Ok(Value::Number(3.0))
+        );
+        assert_eq!(inner.get_at(0, &ident("a")), Ok(Value::Number(2.0)));
+        assert_eq!(
+            outer.read().unwrap().get(&ident("a")),
+            Ok(Value::Number(3.0))
+        );
+
+        let missing = RoxError::runtime_at_name(&ident("b"), "variable 'b' is not defined");
+        assert_eq!(inner.get_at(1, &ident("b")), Err(missing.clone()));
+        assert!(inner.get_at(2, &ident("a")).is_err());
+        assert_eq!(inner.assign_at(0, &ident("b"), Value::Nil), Err(missing));
+    }
+
+    #[test]
+    fn test_constants_cannot_be_assigned_in_any_scope() {
+        let outer = Arc::new(RwLock::new(Environment::new()));
+        outer
+            .write()
+            .unwrap()
+            .define_constant("a".into(), Value::Number(1.0));
+        let mut inner = Environment::with_enclosing(Arc::clone(&outer));
+
+        let error = RoxError::runtime_at_name(&ident("a"), "cannot assign to constant 'a'");
+        assert_eq!(inner.assign(&ident("a"), Value::Nil), Err(error.clone()));
+        assert_eq!(inner.assign_at(1, &ident("a"), Value::Nil), Err(error));
+
+        // a variable of the same name further in is a variable
+        inner.define("a".into(), number(2.0));
+        assert_eq!(
+            inner.assign(&ident("a"), Value::Number(3.0)),
+            Ok(Value::Number(3.0))
+        );
+        assert_eq!(
+            outer.read().unwrap().get(&ident("a")),
+            Ok(Value::Number(1.0))
+        );
+
+        // redefining a constant in its own scope replaces it
+        outer.write().unwrap().define("a".into(), number(4.0));
+        assert_eq!(
+            inner.assign_at(1, &ident("a"), Value::Number(5.0)),
+            Ok(Value::Number(5.0))
+        );
+    }
+
+    #[test]
+    fn test_contains_and_undefine() {
+        let outer = Arc::new(RwLock::new(Environment::new()));
+        outer.write().unwrap().define("a".into(), number(1.0));
+        let mut inner = Environment::with_enclosing(Arc::clone(&outer));
+
+        assert!(inner.contains("a"));
+        assert!(!inner.contains("b"));
+
+        // only the innermost scope is touched
+        assert!(!inner.undefine("a"));
+        assert!(outer.write().unwrap().undefine("a"));
+        assert!(!inner.contains("a"));
+        assert_eq!(
+            inner.get(&ident("a")),
+            Err(RoxError::runtime_at_name(
+                &ident("a"),
+                "variable 'a' is not defined"
             ))
+        );
+    }
+
+    #[test]
+    fn test_inner_definitions_vanish_with_their_scope() {
+        let outer = Arc::new(RwLock::new(Environment::new()));
+        {
+            let mut inner = Environment::with_enclosing(Arc::clone(&outer));
+            inner.define("b".into(), number(1.0));
+            assert!(inner.get(&ident("b")).is_ok());
         }
+
+        let undefined = RoxError::runtime_at_name(&ident("b"), "variable 'b' is not defined");
+        assert_eq!(
+            outer.read().unwrap().get(&ident("b")),
+            Err(undefined.clone())
+        );
+        assert_eq!(
+            outer
+                .write()
+                .unwrap()
+                .assign(&ident("b"), Value::Number(1.0)),
+            Err(undefined)
+        );
     }
 }
@@ -0,0 +1,279 @@
+//! The templates `format` fills in: text with a `{}` for each value, which
+//! is shown the way `print` would show it. A placeholder can also lay its
+//! value out, as `{:>8}`, `{:.2}` or both, `{:>8.2}`: an alignment of `<`,
+//! `>` or `^`, a width to pad to, and a number of decimals, which only
+//! numbers can have. Without an alignment numbers go on the right and
+//! everything else on the left. `{{` and `}}` are literal braces.
+
+use super::Value;
+use crate::number;
+use std::mem;
+
+/// The widest a value can be padded to, and the most decimals it can have,
+/// so a typo can't ask for a gigabyte of spaces.
+const MAX_WIDTH: usize = 1024;
+
+enum Piece {
+    Text(String),
+    Value(Spec),
+}
+
+struct Spec {
+    /// The placeholder as written, for errors.
+    text: String,
+    align: Option<char>,
+    width: usize,
+    precision: Option<usize>,
+}
+
+/// Fills in `template` with `values`, showing each as `show` does unless
+/// its placeholder gives it decimals. There must be exactly as many values
+/// as placeholders.
+pub fn format(
+    template: &str,
+    values: &[Value],
+    show: impl Fn(&Value) -> String,
+) -> Result<String, String> {
+    let pieces = parse(template)?;
+    let placeholders = pieces
+        .iter()
+        .filter(|piece| matches!(piece, Piece::Value(_)))
+        .count();
+    if placeholders != values.len() {
+        return Err(format!(
+            "format string has {} placeholders but was given {} values",
+            placeholders,
+            values.len()
+        ));
+    }
+
+    let mut values = values.iter();
+    let mut formatted = String::new();
+    for piece in &pieces {
+        match piece {
+            Piece::Text(text) => formatted += text,
+            Piece::Value(spec) => formatted += &spec.lay_out(values.next().unwrap(), &show)?,
+        }
+    }
+    Ok(formatted)
+}
+
+fn parse(template: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(at) = rest.find(['{', '}']) {
+        text += &rest[..at];
+        rest = &rest[at..];
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            text += &rest[..1];
+            rest = &rest[2..];
+        } else if rest.starts_with('}') {
+            return Err("unmatched '}' in format string, '}}' is a literal one".to_owned());
+        } else {
+            let end = rest
+                .find('}')
+                .ok_or_else(|| "unclosed '{' in format string".to_owned())?;
+            if !text.is_empty() {
+                pieces.push(Piece::Text(mem::take(&mut text)));
+            }
+            pieces.push(Piece::Value(Spec::parse(&rest[..=end])?));
+            rest = &rest[end + 1..];
+        }
+    }
+    text += rest;
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(pieces)
+}
+
+impl Spec {
+    /// Reads a placeholder, braces included.
+    fn parse(text: &str) -> Result<Spec, String> {
+        let malformed = || format!("malformed format spec '{}'", text);
+        let mut spec = Spec {
+            text: text.to_owned(),
+            align: None,
+            width: 0,
+            precision: None,
+        };
+        let inner = &text[1..text.len() - 1];
+        if inner.is_empty() {
+            return Ok(spec);
+        }
+        let mut rest = inner.strip_prefix(':').ok_or_else(malformed)?;
+
+        if let Some(align) = rest.chars().next().filter(|c| "<>^".contains(*c)) {
+            spec.align = Some(align);
+            rest = &rest[1..];
+        }
+        let (width, after) = digits(rest);
+        if !width.is_empty() {
+            spec.width = bounded(width, text)?;
+        }
+        rest = after;
+        if let Some(after_dot) = rest.strip_prefix('.') {
+            let (precision, after) = digits(after_dot);
+            if precision.is_empty() {
+                return Err(malformed());
+            }
+            spec.precision = Some(bounded(precision, text)?);
+            rest = after;
+        }
+
+        if rest.is_empty() {
+            Ok(spec)
+        } else {
+            Err(malformed())
+        }
+    }
+
+    fn lay_out(&self, value: &Value, show: impl Fn(&Value) -> String) -> Result<String, String> {
+        let text = match (self.precision, value) {
+            (Some(decimals), Value::Number(n)) => number::format_fixed(*n, decimals),
+            (Some(_), other) => {
+                return Err(format!(
+                    "format spec '{}' needs a number, got {}",
+                    self.text,
+                    other.type_name()
+                ))
+            }
+            (None, value) => show(value),
+        };
+
+        let width = self.width;
+        let default = if let Value::Number(_) = value {
+            '>'
+        } else {
+            '<'
+        };
+        Ok(match self.align.unwrap_or(default) {
+            '<' => format!("{:<width$}", text, width = width),
+            '^' => format!("{:^width$}", text, width = width),
+            _ => format!("{:>width$}", text, width = width),
+        })
+    }
+}
+
+/// The ASCII digits `text` starts with, and what comes after them.
+fn digits(text: &str) -> (&str, &str) {
+    let end = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    text.split_at(end)
+}
+
+fn bounded(digits: &str, spec: &str) -> Result<usize, String> {
+    match digits.parse() {
+        Ok(n) if n <= MAX_WIDTH => Ok(n),
+        _ => Err(format!(
+            "format spec '{}' asks for {}, the most is {}",
+            spec, digits, MAX_WIDTH
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fill(template: &str, values: &[Value]) -> Result<String, String> {
+        format(template, values, Value::to_string)
+    }
+
+    #[test]
+    fn test_placeholders_are_filled_in_order() {
+        let values = [Value::Number(1.0), Value::Str("two".to_owned()), Value::Nil];
+
+        assert_eq!(fill("{} {} {}", &values), Ok("1 two nil".to_owned()));
+        assert_eq!(fill("{{{}}}", &values[..1]), Ok("{1}".to_owned()));
+        assert_eq!(
+            fill("no placeholders", &[]),
+            Ok("no placeholders".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_widths_pad_and_align() {
+        let number = [Value::Number(3.5)];
+        let string = [Value::Str("ab".to_owned())];
+
+        assert_eq!(fill("[{:6}]", &number), Ok("[   3.5]".to_owned()));
+        assert_eq!(fill("[{:6}]", &string), Ok("[ab    ]".to_owned()));
+        assert_eq!(fill("[{:<6}]", &number), Ok("[3.5   ]".to_owned()));
+        assert_eq!(fill("[{:>6}]", &string), Ok("[    ab]".to_owned()));
+        assert_eq!(fill("[{:^6}]", &string), Ok("[  ab  ]".to_owned()));
+        // too wide to pad
+        assert_eq!(fill("[{:>1}]", &string), Ok("[ab]".to_owned()));
+        // characters, not bytes
+        assert_eq!(
+            fill("[{:>3}]", &[Value::Str("é".to_owned())]),
+            Ok("[  é]".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_decimals() {
+        assert_eq!(
+            fill("{:.2}", &[Value::Number(1.23456)]),
+            Ok("1.23".to_owned())
+        );
+        assert_eq!(fill("{:.0}", &[Value::Number(2.5)]), Ok("2".to_owned()));
+        assert_eq!(fill("{:.3}", &[Value::Number(1.0)]), Ok("1.000".to_owned()));
+        assert_eq!(
+            fill("[{:>8.2}]", &[Value::Number(-1.005)]),
+            Ok("[   -1.00]".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_only_numbers_have_decimals() {
+        assert_eq!(
+            fill("{:.2}", &[Value::Str("1.23".to_owned())]),
+            Err("format spec '{:.2}' needs a number, got string".to_owned())
+        );
+        assert_eq!(
+            fill("{:>8.1}", &[Value::Nil]),
+            Err("format spec '{:>8.1}' needs a number, got nil".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_value_count_must_match() {
+        assert_eq!(
+            fill("{} and {}", &[Value::Nil]),
+            Err("format string has 2 placeholders but was given 1 values".to_owned())
+        );
+        assert_eq!(
+            fill("{}", &[Value::Nil, Value::Nil]),
+            Err("format string has 1 placeholders but was given 2 values".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_malformed_templates() {
+        let one = [Value::Number(1.0)];
+        for (template, error) in [
+            ("{x}", "malformed format spec '{x}'"),
+            ("{:x}", "malformed format spec '{:x}'"),
+            ("{:.}", "malformed format spec '{:.}'"),
+            ("{:8<}", "malformed format spec '{:8<}'"),
+            ("{:.2.2}", "malformed format spec '{:.2.2}'"),
+            ("{", "unclosed '{' in format string"),
+            ("{:>2", "unclosed '{' in format string"),
+            ("}", "unmatched '}' in format string, '}}' is a literal one"),
+            (
+                "{:9999}",
+                "format spec '{:9999}' asks for 9999, the most is 1024",
+            ),
+            (
+                "{:.99999999999999999999}",
+                "format spec '{:.99999999999999999999}' asks for 99999999999999999999, the \
+                 most is 1024",
+            ),
+        ] {
+            assert_eq!(fill(template, &one), Err(error.to_owned()), "{}", template);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use super::{ExecuteResult, Interpreter};
+use crate::error::RoxError;
+use crate::parser::Parser;
+use crate::resolver::resolve;
+use crate::scanner::Scanner;
+use crate::statement::Stmt;
+use crate::token::Token;
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+impl<'a> Interpreter<'a> {
+    /// `import "path";`, which runs the file at `path`, relative to the
+    /// directory of the file doing the importing, in the global scope. Each
+    /// file is only run once, however often and from wherever it's
+    /// imported, so imports that go round in a circle stop.
+    #[inline(never)]
+    pub(super) fn import(&mut self, keyword: &Token, path: &str) -> ExecuteResult {
+        let full_path = match &self.script_path {
+            Some(script) => script.parent().unwrap_or_else(|| Path::new("")).join(path),
+            None => PathBuf::from(path),
+        };
+        let not_found =
+            |e| RoxError::runtime_at(keyword, format!("could not import '{}': {}", path, e));
+        let canonical = fs::canonicalize(&full_path).map_err(not_found)?;
+        if self.imported.contains(&canonical) {
+            return Ok(());
+        }
+        let source = fs::read_to_string(&canonical).map_err(not_found)?;
+        let statements = parse(source).map_err(|e| in_import(keyword, path, e))?;
+
+        let script_path = self.set_script_path(Some(canonical));
+        let environment = mem::replace(&mut self.environment, Arc::clone(&self.globals));
+        let result = statements
+            .iter()
+            .try_for_each(|s| self.execute(s).map(|_| ()));
+        self.environment = environment;
+        self.script_path = script_path;
+        result.map_err(|e| in_import(keyword, path, e))
+    }
+}
+
+/// The statements of an imported file, ready to run, or the first error
+/// found in it.
+fn parse(source: String) -> Result<Vec<Stmt>, RoxError> {
+    let tokens = Scanner::new(source)
+        .scan_tokens()
+        .map_err(|mut errors| errors.swap_remove(0))?
+        .to_vec();
+    let mut statements = Parser::new(tokens)
+        .parse()
+        .map_err(|mut errors| errors.swap_remove(0))?;
+    match resolve(&mut statements).errors.into_iter().next() {
+        Some(e) => Err(e),
+        None => Ok(statements),
+    }
+}
+
+/// `error`, found in the file imported from `path`, as the same kind of
+/// error at the `import`, whose message says where in that file it was. A
+/// file that doesn't parse fails the import as a program that doesn't
+/// parse, not as one that failed while running. Running past a limit isn't
+/// the imported file's fault, so is passed on as it is.
+fn in_import(keyword: &Token, path: &str, error: RoxError) -> RoxError {
+    let message = format!("in imported file '{}': {}", path, error);
+    let (line, column) = (keyword.line, keyword.column);
+    let at = keyword.tag.to_string();
+    match error {
+        RoxError::Scan { .. } => RoxError::Scan {
+            line,
+            column,
+            lexeme: at,
+            message,
+        },
+        RoxError::Parse { .. } => RoxError::Parse {
+            line,
+            column,
+            token: Some(at),
+            message,
+        },
+        RoxError::Resolve { .. } => RoxError::Resolve {
+            line,
+            column,
+            name: at,
+            message,
+        },
+        RoxError::Runtime { frames, .. } => frames.into_iter().fold(
+            RoxError::runtime_at(keyword, message),
+            RoxError::called_from,
+        ),
+        RoxError::BudgetExceeded { .. } => error,
+    }
+}
@@ -0,0 +1,205 @@
+use super::callable::{Callable, NativeFunction};
+use super::environment::Environment;
+use super::format_string;
+use super::value::Value;
+use super::version;
+use super::Interpreter;
+use crate::number;
+use std::io::Write;
+
+/// Defines the builtin globals. They are ordinary variables, so a script is
+/// free to shadow or reassign them.
+pub fn define_globals(environment: &mut Environment) {
+    environment.define(
+        "ROX_VERSION".into(),
+        Some(Value::Str(version::ROX_VERSION.to_owned())),
+    );
+    // replaced by `Interpreter::set_args` when the script was given any
+    environment.define("argc".into(), Some(Value::Number(0.0)));
+
+    let natives = [
+        NativeFunction {
+            name: "version",
+            arity: 0,
+            variadic: false,
+            function: |_, _| Ok(Value::Str(version::ROX_VERSION.to_owned())),
+        },
+        NativeFunction {
+            name: "requireVersion",
+            arity: 1,
+            variadic: false,
+            function: require_version,
+        },
+        NativeFunction {
+            name: "type",
+            arity: 1,
+            variadic: false,
+            function: |_, arguments| Ok(Value::Str(arguments[0].type_name().to_owned())),
+        },
+        NativeFunction {
+            name: "str",
+            arity: 1,
+            variadic: false,
+            function: |_, arguments| Ok(Value::Str(arguments[0].to_string())),
+        },
+        NativeFunction {
+            name: "num",
+            arity: 1,
+            variadic: false,
+            function: num,
+        },
+        NativeFunction {
+            name: "debugEnv",
+            arity: 0,
+            variadic: false,
+            function: debug_env,
+        },
+        NativeFunction {
+            name: "len",
+            arity: 1,
+            variadic: false,
+            function: len,
+        },
+        NativeFunction {
+            name: "keys",
+            arity: 1,
+            variadic: false,
+            function: keys,
+        },
+        NativeFunction {
+            name: "format",
+            arity: 1,
+            variadic: true,
+            function: format,
+        },
+        NativeFunction {
+            name: "substr",
+            arity: 3,
+            variadic: false,
+            function: substr,
+        },
+    ];
+
+    for native in natives.iter() {
+        environment.define(
+            native.name.into(),
+            Some(Value::Callable(Callable::Native(native.clone()))),
+        );
+    }
+}
+
+/// Prints every variable visible where it's called, as `:env` in the REPL
+/// does.
+fn debug_env(interpreter: &mut Interpreter, _: &[Value]) -> Result<Value, String> {
+    let dump = interpreter.environment.read().unwrap().dump();
+    write!(interpreter.stdout, "{}", dump)
+        .and_then(|_| interpreter.stdout.flush())
+        .map_err(|e| e.to_string())?;
+    Ok(Value::Nil)
+}
+
+/// Parses a string as a number, giving nil when it isn't one. Surrounding
+/// whitespace is ignored, and "nan" and "inf" are not numbers.
+fn num(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    match &arguments[0] {
+        Value::Str(s) => Ok(number::parse(s).map_or(Value::Nil, Value::Number)),
+        other => Err(format!("num expects a string, got {}", other.type_name())),
+    }
+}
+
+/// The number of characters in a string, elements in a list or entries in
+/// a map.
+fn len(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    match &arguments[0] {
+        Value::Str(s) => Ok(Value::Number(s.chars().count() as f64)),
+        Value::List(list) => Ok(Value::Number(list.read().unwrap().len() as f64)),
+        Value::Map(map) => Ok(Value::Number(map.read().unwrap().len() as f64)),
+        other => Err(format!(
+            "len expects a string, list or map, got {}",
+            other.type_name()
+        )),
+    }
+}
+
+/// A map's keys as a new list, in the order the map prints them.
+fn keys(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    match &arguments[0] {
+        Value::Map(map) => Ok(Value::list(
+            map.read()
+                .unwrap()
+                .keys()
+                .cloned()
+                .map(Value::Str)
+                .collect(),
+        )),
+        other => Err(format!("keys expects a map, got {}", other.type_name())),
+    }
+}
+
+/// `format(template, values...)` fills in the template's placeholders with
+/// the values, as `format_string` describes.
+fn format(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    match &arguments[0] {
+        Value::Str(template) => {
+            format_string::format(template, &arguments[1..], |value| interpreter.show(value))
+                .map(Value::Str)
+        }
+        other => Err(format!(
+            "format expects a string, got {}",
+            other.type_name()
+        )),
+    }
+}
+
+/// `substr(s, start, length)`. Like `len`, positions count characters rather
+/// than bytes, so a multi-byte character is never cut in half.
+fn substr(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let s = match &arguments[0] {
+        Value::Str(s) => s,
+        other => {
+            return Err(format!(
+                "substr expects a string, got {}",
+                other.type_name()
+            ))
+        }
+    };
+    let start = whole_number("start", &arguments[1])?;
+    let length = whole_number("length", &arguments[2])?;
+
+    let len = s.chars().count() as f64;
+    if start < 0.0 || length < 0.0 || start + length > len {
+        return Err(format!(
+            "substr range {}..{} is out of bounds for a string of length {}",
+            number::format(start),
+            number::format(start + length),
+            len
+        ));
+    }
+    Ok(Value::Str(
+        s.chars()
+            .skip(start as usize)
+            .take(length as usize)
+            .collect(),
+    ))
+}
+
+fn whole_number(what: &str, value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Number(n) if n.fract() == 0.0 => Ok(*n),
+        other => Err(format!(
+            "substr expects a whole number for {}, got {}",
+            what,
+            other.inspect()
+        )),
+    }
+}
+
+fn require_version(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    match &arguments[0] {
+        Value::Str(required) => version::require(required).map(|_| Value::Nil),
+        other => Err(format!(
+            "requireVersion expects a string, got {}",
+            other.type_name()
+        )),
+    }
+}
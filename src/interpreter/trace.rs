@@ -0,0 +1,96 @@
+use super::value::Value;
+use crate::expr::{Expr, ExprKind};
+use crate::formatter::{format_expr, format_program};
+use crate::statement::Stmt;
+use std::fmt::{self, Display, Formatter};
+use std::slice;
+
+/// Something the interpreter is about to do, or just did, reported to the
+/// tracer installed with `Interpreter::set_tracer`. `depth` is how many
+/// statements and evaluations enclose it: a statement's expressions are one
+/// deeper than the statement, and a function's body is deeper than the
+/// call.
+#[derive(Debug, Clone, Copy)]
+pub enum TraceEvent<'e> {
+    /// A statement is about to run.
+    StmtEnter { stmt: &'e Stmt, depth: usize },
+    /// A variable was read.
+    VarRead {
+        name: &'e str,
+        value: &'e Value,
+        depth: usize,
+    },
+    /// A variable was declared, assigned or incremented.
+    VarWrite {
+        name: &'e str,
+        value: &'e Value,
+        depth: usize,
+    },
+    /// An expression made of other expressions was worked out. Literals,
+    /// variables and function expressions don't get one.
+    ExprResult {
+        expr: &'e Expr,
+        value: &'e Value,
+        depth: usize,
+    },
+}
+
+impl<'e> TraceEvent<'e> {
+    pub fn depth(&self) -> usize {
+        match self {
+            TraceEvent::StmtEnter { depth, .. }
+            | TraceEvent::VarRead { depth, .. }
+            | TraceEvent::VarWrite { depth, .. }
+            | TraceEvent::ExprResult { depth, .. } => *depth,
+        }
+    }
+}
+
+/// One line, indented two spaces per level of depth. A statement shows the
+/// first line it formats to, so a block or function isn't shown whole
+/// before each of its statements is.
+impl<'e> Display for TraceEvent<'e> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:width$}", "", width = self.depth() * 2)?;
+        match self {
+            TraceEvent::StmtEnter { stmt, .. } => {
+                let formatted = format_program(slice::from_ref(*stmt));
+                write!(f, "{}", formatted.lines().next().unwrap_or_default())
+            }
+            TraceEvent::VarRead { name, value, .. } => {
+                write!(f, "read {} = {}", name, value.inspect())
+            }
+            TraceEvent::VarWrite { name, value, .. } => {
+                write!(f, "write {} = {}", name, value.inspect())
+            }
+            TraceEvent::ExprResult { expr, value, .. } => {
+                write!(f, "{} => {}", format_expr(expr), value.inspect())
+            }
+        }
+    }
+}
+
+/// The event for `expr` having evaluated to `value`, if it gets one.
+pub(super) fn expr_event<'e>(
+    expr: &'e Expr,
+    value: &'e Value,
+    depth: usize,
+) -> Option<TraceEvent<'e>> {
+    Some(match &expr.kind {
+        ExprKind::Variable(name) | ExprKind::This(name) => TraceEvent::VarRead {
+            name: &name.symbol,
+            value,
+            depth,
+        },
+        ExprKind::Assign { name, .. } | ExprKind::Increment { name, .. } => TraceEvent::VarWrite {
+            name: &name.symbol,
+            value,
+            depth,
+        },
+        ExprKind::Literal(_)
+        | ExprKind::Defined(_)
+        | ExprKind::Lambda { .. }
+        | ExprKind::Error { .. } => return None,
+        _ => TraceEvent::ExprResult { expr, value, depth },
+    })
+}
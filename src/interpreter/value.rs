@@ -0,0 +1,240 @@
+use super::callable::Callable;
+use super::class::Instance;
+use crate::expr::LiteralValue;
+use crate::number;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::sync::{Arc, RwLock};
+
+/// Everything a Lox expression can evaluate to. Literals in the source are
+/// converted into values as they are evaluated.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Number(f64),
+    Str(String),
+    Bool(bool),
+    Nil,
+    Callable(Callable),
+    /// Shared by every variable holding it, so a change made through one
+    /// is seen through the others.
+    List(Arc<RwLock<Vec<Value>>>),
+    /// Shared like lists. Keys are strings, kept sorted so maps print and
+    /// iterate the same way every time.
+    Map(Arc<RwLock<BTreeMap<String, Value>>>),
+    /// Shared like lists.
+    Instance(Arc<Instance>),
+}
+
+impl Value {
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Bool(false) | Value::Nil)
+    }
+
+    /// The name `type()` returns, and that error messages use, for the
+    /// kind of value this is.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Bool(_) => "boolean",
+            Value::Nil => "nil",
+            Value::Str(_) => "string",
+            Value::Number(_) => "number",
+            Value::Callable(Callable::Class(_)) => "class",
+            Value::Callable(_) => "function",
+            Value::Instance(_) => "instance",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+        }
+    }
+
+    pub fn list(elements: Vec<Value>) -> Self {
+        Value::List(Arc::new(RwLock::new(elements)))
+    }
+
+    pub fn map(entries: BTreeMap<String, Value>) -> Self {
+        Value::Map(Arc::new(RwLock::new(entries)))
+    }
+
+    /// How the REPL and error messages show a value. The same as `Display`,
+    /// except that strings are quoted and escaped, so the string "nil" can
+    /// be told apart from nil.
+    pub fn inspect(&self) -> String {
+        self.render(true, &mut Vec::new())
+    }
+
+    /// `quoted` picks between `inspect` and `Display` for strings. `open`
+    /// holds the lists and maps being rendered further out, so one that
+    /// contains itself is shown as `[...]` or `{...}` rather than recursing
+    /// forever.
+    fn render(&self, quoted: bool, open: &mut Vec<*const ()>) -> String {
+        match self {
+            Value::List(list) => {
+                let pointer = Arc::as_ptr(list) as *const ();
+                if open.contains(&pointer) {
+                    return "[...]".to_owned();
+                }
+
+                open.push(pointer);
+                let elements: Vec<_> = list
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .map(|element| element.render(quoted, open))
+                    .collect();
+                open.pop();
+                format!("[{}]", elements.join(", "))
+            }
+            Value::Map(map) => {
+                let pointer = Arc::as_ptr(map) as *const ();
+                if open.contains(&pointer) {
+                    return "{...}".to_owned();
+                }
+
+                open.push(pointer);
+                let entries: Vec<_> = map
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .map(|(key, value)| {
+                        let key = if quoted { quote(key) } else { key.clone() };
+                        format!("{}: {}", key, value.render(quoted, open))
+                    })
+                    .collect();
+                open.pop();
+                format!("{{{}}}", entries.join(", "))
+            }
+            Value::Str(s) if quoted => quote(s),
+            Value::Number(n) => number::format(*n),
+            Value::Str(s) => s.clone(),
+            Value::Bool(b) => b.to_string(),
+            Value::Nil => "nil".to_owned(),
+            Value::Callable(c) => c.to_string(),
+            Value::Instance(instance) => instance.to_string(),
+        }
+    }
+}
+
+fn quote(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&c.escape_unicode().to_string()),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Values of different types are never equal, as nothing is converted:
+/// `1 == "1"` and `true == 1` are false, and `nil` is equal only to `nil`.
+/// Lists, maps and instances are equal only to themselves, like functions, not to
+/// another with the same contents.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Nil, Value::Nil) => true,
+            (Value::Callable(a), Value::Callable(b)) => a == b,
+            (Value::List(a), Value::List(b)) => Arc::ptr_eq(a, b),
+            (Value::Map(a), Value::Map(b)) => Arc::ptr_eq(a, b),
+            (Value::Instance(a), Value::Instance(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<&LiteralValue> for Value {
+    fn from(literal: &LiteralValue) -> Self {
+        match literal {
+            LiteralValue::True => Value::Bool(true),
+            LiteralValue::False => Value::Bool(false),
+            LiteralValue::Nil => Value::Nil,
+            LiteralValue::STRING(s) => Value::Str(s.to_string()),
+            LiteralValue::Number(n) => Value::Number(*n),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.render(false, &mut Vec::new()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Value;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_inspect_quotes_and_escapes_strings() {
+        let value = Value::Str("say \"hi\"\nnow\\then\u{7}".to_owned());
+
+        assert_eq!(value.to_string(), "say \"hi\"\nnow\\then\u{7}");
+        assert_eq!(value.inspect(), r#""say \"hi\"\nnow\\then\u{7}""#);
+        assert_eq!(Value::Str("nil".to_owned()).inspect(), "\"nil\"");
+        assert_eq!(Value::Nil.inspect(), "nil");
+        assert_eq!(Value::Number(2.5).inspect(), "2.5");
+    }
+
+    #[test]
+    fn test_lists_render_their_elements() {
+        let inner = Value::list(vec![Value::Nil, Value::Str("two".to_owned())]);
+        let list = Value::list(vec![Value::Number(1.0), inner, Value::list(vec![])]);
+
+        assert_eq!(list.to_string(), "[1, [nil, two], []]");
+        assert_eq!(list.inspect(), "[1, [nil, \"two\"], []]");
+    }
+
+    #[test]
+    fn test_list_containing_itself() {
+        let list = Value::list(vec![Value::Number(1.0)]);
+        if let Value::List(elements) = &list {
+            elements.write().unwrap().push(list.clone());
+        }
+
+        assert_eq!(list.to_string(), "[1, [...]]");
+    }
+
+    #[test]
+    fn test_maps_render_sorted_by_key() {
+        let mut entries = BTreeMap::new();
+        entries.insert("b".to_owned(), Value::Str("two".to_owned()));
+        entries.insert("a".to_owned(), Value::list(vec![]));
+        let map = Value::map(entries);
+        if let Value::Map(entries) = &map {
+            entries
+                .write()
+                .unwrap()
+                .insert("self".to_owned(), map.clone());
+        }
+
+        assert_eq!(map.to_string(), "{a: [], b: two, self: {...}}");
+        assert_eq!(
+            map.inspect(),
+            "{\"a\": [], \"b\": \"two\", \"self\": {...}}"
+        );
+    }
+
+    #[test]
+    fn test_lists_are_equal_only_to_themselves() {
+        let list = Value::list(vec![Value::Number(1.0)]);
+
+        assert_eq!(list, list.clone());
+        assert_ne!(list, Value::list(vec![Value::Number(1.0)]));
+    }
+}
@@ -0,0 +1,88 @@
+use std::cmp::Ordering;
+
+/// The version of this interpreter, visible to scripts as `ROX_VERSION`.
+pub const ROX_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Compares two dotted version strings numerically component by component.
+/// Missing components count as zero, so "0.3" and "0.3.0" are equal.
+pub fn compare(left: &str, right: &str) -> Result<Ordering, String> {
+    let left = parse(left)?;
+    let right = parse(right)?;
+    let len = left.len().max(right.len());
+
+    for i in 0..len {
+        let l = left.get(i).cloned().unwrap_or(0);
+        let r = right.get(i).cloned().unwrap_or(0);
+        if l != r {
+            return Ok(l.cmp(&r));
+        }
+    }
+
+    Ok(Ordering::Equal)
+}
+
+/// Fails with a message naming both versions when the running interpreter is
+/// older than `required`.
+pub fn require(required: &str) -> Result<(), String> {
+    if compare(ROX_VERSION, required)? == Ordering::Less {
+        Err(format!(
+            "this script requires rox {} or newer (running {})",
+            required, ROX_VERSION
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn parse(version: &str) -> Result<Vec<u64>, String> {
+    version
+        .trim()
+        .split('.')
+        .map(|component| {
+            component
+                .parse::<u64>()
+                .map_err(|_| format!("invalid version '{}'", version))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(compare("0.3", "0.3"), Ok(Ordering::Equal));
+        assert_eq!(compare("0.3", "0.3.0"), Ok(Ordering::Equal));
+        assert_eq!(compare("0.3.1", "0.3"), Ok(Ordering::Greater));
+        assert_eq!(compare("0.2.9", "0.3"), Ok(Ordering::Less));
+        assert_eq!(compare("0.10", "0.9"), Ok(Ordering::Greater));
+        assert_eq!(compare("1", "0.99.99"), Ok(Ordering::Greater));
+    }
+
+    #[test]
+    fn test_compare_rejects_malformed_versions() {
+        assert_eq!(
+            compare("0.x", "0.1"),
+            Err("invalid version '0.x'".to_owned())
+        );
+        assert_eq!(compare("0.1", ""), Err("invalid version ''".to_owned()));
+        assert_eq!(
+            compare("0..1", "0.1"),
+            Err("invalid version '0..1'".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_require() {
+        assert_eq!(require(ROX_VERSION), Ok(()));
+        assert_eq!(require("0"), Ok(()));
+        assert_eq!(
+            require("999.0"),
+            Err(format!(
+                "this script requires rox 999.0 or newer (running {})",
+                ROX_VERSION
+            ))
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//! A tree-walk interpreter for Lox.
+//!
+//! `run_source` runs a whole script in one go, and `eval_expr` works out a
+//! single expression, calculator style. `check_source` finds what's wrong
+//! with a script without running it. The scanner, parser, resolver
+//! and interpreter are exported for hosts that want to drive each stage
+//! themselves, for example to keep one `Interpreter` alive across inputs.
+//! `spec` runs scripts against the output written in their comments, as
+//! `rox test` does.
+//!
+//! With the `serde` feature the syntax tree, `Stmt`, `Expr` and the tokens
+//! and names in them, can be serialized and deserialized, so another tool
+//! can build a program for `Interpreter::interpret` or read one `rox ast
+//! --json` printed. Structs are objects of their fields. Enums are
+//! externally tagged: a variant without data is its name as a string, such
+//! as `"Nil"`, and any other is an object with the name as its only key,
+//! such as `{"Number": 1.5}` or `{"Print": {"line": 0, "values": [...]}}`.
+//! Numbers round-trip exactly. The names are those of the Rust types, so
+//! the format only changes when they do.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+pub mod error;
+mod expr;
+mod formatter;
+mod interpreter;
+mod number;
+mod optimizer;
+mod parser;
+mod resolver;
+mod scanner;
+mod span;
+pub mod spec;
+mod statement;
+mod symbol;
+mod token;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+#[cfg(test)]
+mod test;
+
+pub use error::{Diagnostic, RoxError, Warning};
+pub use expr::{print, Expr, ExprKind, LiteralValue};
+pub use formatter::{format_expr, format_program};
+pub use interpreter::{Callable, Interpreter, TraceEvent, Value};
+pub use optimizer::fold;
+pub use parser::Parser;
+pub use resolver::{resolve, Resolution};
+pub use scanner::{Scanner, ScannerOptions, TokenStream, KEYWORDS};
+pub use span::Span;
+pub use statement::{Program, Stmt, StmtKind};
+pub use symbol::Symbol;
+pub use token::{Binding, Name, Token, TokenType};
+#[cfg(feature = "wasm")]
+pub use wasm::run_to_string;
+
+/// Scans, parses, resolves and runs `source`, writing anything it prints to
+/// `out`. Fails with the first error found. Nothing runs if the source
+/// doesn't scan, parse or resolve. Resolver warnings are ignored.
+pub fn run_source(source: &str, out: impl Write + Send) -> Result<(), RoxError> {
+    ScannerOptions::default().check_source_size(source)?;
+    let mut scanner = Scanner::new(source.to_owned());
+    let tokens = scanner
+        .scan_tokens()
+        .map_err(|mut errors| errors.swap_remove(0))?
+        .to_vec();
+    let mut statements = Parser::new(tokens)
+        .parse()
+        .map_err(|mut errors| errors.swap_remove(0))?;
+    if let Some(e) = resolve(&mut statements).errors.into_iter().next() {
+        return Err(e);
+    }
+
+    Interpreter::new(out).interpret(&statements)
+}
+
+/// Scans, parses and resolves `source` without running any of it, and
+/// returns every error and warning found, in the order they are in the
+/// source. Nothing is parsed if scanning fails, or resolved if parsing does,
+/// so one mistake isn't also reported as the errors it leads to.
+pub fn check_source(source: &str) -> Vec<Diagnostic> {
+    let errors = |errors: Vec<RoxError>| -> Vec<Diagnostic> {
+        errors.into_iter().map(Diagnostic::Error).collect()
+    };
+    if let Err(e) = ScannerOptions::default().check_source_size(source) {
+        return errors(vec![e]);
+    }
+    let tokens = match Scanner::new(source.to_owned()).scan_tokens() {
+        Ok(tokens) => tokens.to_vec(),
+        Err(e) => return errors(e),
+    };
+    let mut statements = match Parser::new(tokens).parse() {
+        Ok(statements) => statements,
+        Err(e) => return errors(e),
+    };
+
+    let resolution = resolve(&mut statements);
+    let mut diagnostics = errors(resolution.errors);
+    diagnostics.extend(resolution.warnings.into_iter().map(Diagnostic::Warning));
+    diagnostics.sort_by_key(Diagnostic::position);
+    diagnostics
+}
+
+/// Evaluates a single expression, such as `(a + b) * 2`, with `bindings`
+/// defined as globals. There are no statements and no `;`, and anything the
+/// expression prints is thrown away. Fails with the first error found, as
+/// `run_source` does.
+pub fn eval_expr(source: &str, bindings: &HashMap<String, Value>) -> Result<Value, RoxError> {
+    ScannerOptions::default().check_source_size(source)?;
+    let mut scanner = Scanner::new(source.to_owned());
+    let tokens = scanner
+        .scan_tokens()
+        .map_err(|mut errors| errors.swap_remove(0))?
+        .to_vec();
+    let expr = Parser::new(tokens)
+        .parse_expression()
+        .map_err(|mut errors| errors.swap_remove(0))?;
+
+    let mut interpreter = Interpreter::new(io::sink());
+    for (name, value) in bindings {
+        interpreter.define(name, value.clone());
+    }
+    interpreter.evaluate(&expr)
+}
@@ -1,59 +1,386 @@
 use rustyline::error::ReadlineError;
-use rustyline::Editor;
+use rustyline::{Config, Editor};
 
 use std::env;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, Read};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process;
 
-mod error;
-mod expr;
-mod interpreter;
-mod parser;
-mod scanner;
-mod statement;
-mod token;
+mod commands;
+mod editor;
+mod history;
+mod watch;
 
-#[cfg(test)]
-mod test;
+use editor::ReplHelper;
+use rox::error::{self, RoxError};
+use rox::spec;
+use rox::{print, resolve, Diagnostic, Interpreter, Parser, Scanner, Stmt, StmtKind, Token};
+
+/// The exit code for a command used wrongly, such as an unknown flag or
+/// too many paths.
+const USAGE_ERROR: i32 = 64;
 
-use interpreter::Interpreter;
-use parser::Parser;
-use scanner::Scanner;
+/// The exit code for `rox test` when a spec fails.
+const TESTS_FAILED: i32 = 1;
 
+/// The exit code for `rox test` when its report can't be written.
+const REPORT_ERROR: i32 = 74;
+
+/// Runs the subcommand or flag named by the first argument, with the rest,
+/// or a script when the first argument is a path. With no arguments it
+/// starts the prompt.
 fn main() -> io::Result<()> {
-    let args: Vec<_> = env::args().collect();
+    let args: Vec<_> = env::args().skip(1).collect();
+    let rest = args.get(1..).unwrap_or_default();
 
-    if args.len() > 2 {
-        process::exit(64);
-    } else if args.len() == 2 {
-        run_file(&args[1])?;
-    } else {
-        run_prompt();
-    }
+    let code = match args.first().map(String::as_str) {
+        None => {
+            run_prompt();
+            0
+        }
+        Some("check") => check_command(rest)?,
+        Some("test") => test_command(rest)?,
+        Some("fmt") => fmt_command(rest)?,
+        Some("--tokens") => tokens_command(rest)?,
+        Some("ast") | Some("--ast") => ast_command(rest)?,
+        Some("--watch") => watch_command(rest),
+        Some("--optimize") => run_command(rest, Mode::Optimized)?,
+        Some("--trace") => run_command(rest, Mode::Traced)?,
+        Some(flag) if flag.starts_with("--") => USAGE_ERROR,
+        Some(_) => run_command(&args, Mode::Plain)?,
+    };
 
+    if code != 0 {
+        process::exit(code);
+    }
     Ok(())
 }
 
-fn run_file<P: AsRef<Path>>(path: P) -> io::Result<()> {
+/// `rox check file.lox` reports what's wrong with the file without running
+/// it.
+fn check_command(args: &[String]) -> io::Result<i32> {
+    match args {
+        [path] => check_file(path),
+        _ => Ok(USAGE_ERROR),
+    }
+}
+
+/// `rox test [--report json out.json] spec.lox...` runs each spec, or
+/// every spec in a directory, and prints how each went. With `--report` the
+/// results are written to `out.json` as well.
+fn test_command(args: &[String]) -> io::Result<i32> {
+    let (report, specs) = match args {
+        [flag, format, path, specs @ ..] if flag == "--report" && format == "json" => {
+            (Some(path), specs)
+        }
+        [flag, ..] if flag == "--report" => return Ok(USAGE_ERROR),
+        specs => (None, specs),
+    };
+    if specs.is_empty() {
+        return Ok(USAGE_ERROR);
+    }
+
+    let paths: Vec<_> = specs.iter().map(PathBuf::from).collect();
+    let run = spec::run_all(&paths)?;
+    for result in &run.specs {
+        let status = if result.passed() { "PASS" } else { "FAIL" };
+        println!(
+            "{} {} ({:.1}ms)",
+            status,
+            result.path.display(),
+            result.duration.as_secs_f64() * 1000.0
+        );
+        for mismatch in &result.mismatches {
+            println!("  {}", mismatch);
+        }
+    }
+    println!(
+        "{} passed, {} failed, in {:.1}ms",
+        run.passed(),
+        run.failed(),
+        run.duration.as_secs_f64() * 1000.0
+    );
+
+    if let Some(path) = report {
+        if let Err(e) = fs::write(path, spec::report::json(&run)) {
+            eprintln!("could not write the report to {}: {}", path, e);
+            return Ok(REPORT_ERROR);
+        }
+    }
+    Ok(if run.failed() > 0 { TESTS_FAILED } else { 0 })
+}
+
+/// `rox fmt [--write] file.lox`
+fn fmt_command(args: &[String]) -> io::Result<i32> {
+    let write = args.iter().any(|arg| arg == "--write");
+    let paths: Vec<_> = args.iter().filter(|arg| *arg != "--write").collect();
+    match &paths[..] {
+        [path] if args.len() <= 2 => Ok(match format_file(path, write)? {
+            Ok(()) => 0,
+            Err(FormatError::Invalid(e)) => exit_code(&e),
+            Err(FormatError::HasComments) => {
+                eprintln!(
+                    "{} has comments, which formatting would remove, so it was left as is",
+                    path
+                );
+                65
+            }
+        }),
+        _ => Ok(USAGE_ERROR),
+    }
+}
+
+/// `rox --tokens [file.lox]` lists the tokens of the file, or of stdin.
+fn tokens_command(args: &[String]) -> io::Result<i32> {
+    let source = match args {
+        [] => {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source)?;
+            source
+        }
+        [path] => fs::read_to_string(path)?,
+        _ => return Ok(USAGE_ERROR),
+    };
+    Ok(status(dump_tokens(source)))
+}
+
+/// `rox ast [--json] file.lox`, or `rox --ast file.lox`
+fn ast_command(args: &[String]) -> io::Result<i32> {
+    match args {
+        [path] => print_file_ast(path, false).map(status),
+        [json, path] if json == "--json" && cfg!(feature = "serde") => {
+            print_file_ast(path, true).map(status)
+        }
+        [json, _] if json == "--json" => {
+            eprintln!("rox was built without JSON support, rebuild it with `--features serde`");
+            Ok(USAGE_ERROR)
+        }
+        _ => Ok(USAGE_ERROR),
+    }
+}
+
+/// `rox --watch file.lox` runs the file again every time it changes, each
+/// time from scratch, until interrupted. It only returns when used wrongly.
+fn watch_command(args: &[String]) -> i32 {
+    match args {
+        [path] => watch::watch_file(Path::new(path), watch::DEFAULT_INTERVAL, |path| {
+            run_file(path, &[], Mode::Plain).map(|_| ())
+        }),
+        _ => USAGE_ERROR,
+    }
+}
+
+/// `rox [--optimize | --trace] file.lox [args...]`
+fn run_command(args: &[String], mode: Mode) -> io::Result<i32> {
+    match args {
+        [path, script_args @ ..] => run_file(path, script_args, mode).map(status),
+        [] => Ok(USAGE_ERROR),
+    }
+}
+
+/// 0 for success, or the exit code for the error.
+fn status(result: Result<(), RoxError>) -> i32 {
+    result.err().map_or(0, |e| exit_code(&e))
+}
+
+/// Scan, parse and resolve errors mean nothing was executed, runtime errors
+/// mean execution started and stopped part way through.
+fn exit_code(error: &RoxError) -> i32 {
+    match error {
+        RoxError::Scan { .. } | RoxError::Parse { .. } | RoxError::Resolve { .. } => 65,
+        RoxError::Runtime { .. } | RoxError::BudgetExceeded { .. } => 70,
+    }
+}
+
+/// How `run_file` runs a script, picked by the flag before its path.
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Plain,
+    /// With its constant expressions folded first.
+    Optimized,
+    /// Logging each statement, variable access and result to stderr.
+    Traced,
+}
+
+/// Runs the script at `path`, with `script_args` available to it as globals.
+/// `--watch` calls this for every run, so each reads the file afresh.
+fn run_file<P: AsRef<Path>>(
+    path: P,
+    script_args: &[String],
+    mode: Mode,
+) -> io::Result<Result<(), RoxError>> {
+    let mut file = File::open(&path)?;
+    let mut buffer = String::new();
+
+    file.read_to_string(&mut buffer)?;
+    Ok(run_script(buffer, path.as_ref(), script_args, mode))
+}
+
+/// Runs `source`, read from `path`, in a new interpreter, so nothing is
+/// left over from any script run before it.
+fn run_script(
+    source: String,
+    path: &Path,
+    script_args: &[String],
+    mode: Mode,
+) -> Result<(), RoxError> {
+    let mut interpreter = Interpreter::default();
+    interpreter.set_script_path(Some(path.to_owned()));
+    interpreter.set_args(script_args);
+    if mode == Mode::Traced {
+        interpreter.set_tracer(|event| eprintln!("{}", event));
+    }
+    run(source, &mut interpreter, mode == Mode::Optimized)
+}
+
+/// Scans, parses and resolves the file without running it, and reports
+/// everything found. Warnings alone don't fail it.
+fn check_file(path: &str) -> io::Result<i32> {
+    let source = fs::read_to_string(path)?;
+    let diagnostics = rox::check_source(&source);
+    for diagnostic in &diagnostics {
+        eprintln!("{}", error::render_diagnostic(diagnostic, &source));
+    }
+    Ok(if diagnostics.iter().any(Diagnostic::is_error) {
+        65
+    } else {
+        0
+    })
+}
+
+/// Prints each statement of the file as an s-expression, or with `json`
+/// the whole program as a JSON array of statements.
+fn print_file_ast<P: AsRef<Path>>(path: P, json: bool) -> io::Result<Result<(), RoxError>> {
     let mut file = File::open(path)?;
     let mut buffer = String::new();
 
     file.read_to_string(&mut buffer)?;
-    run(buffer.clone(), &mut Interpreter::default());
+    Ok(scan(&buffer).and_then(|tokens| {
+        let statements = Parser::new(tokens)
+            .parse()
+            .map_err(|errors| report_all(errors, &buffer))?;
 
-    Ok(())
+        if json {
+            print_json(&statements);
+            return Ok(());
+        }
+        for statement in &statements {
+            println!("{}", print::print_stmt(statement));
+        }
+        Ok(())
+    }))
+}
+
+#[cfg(feature = "serde")]
+fn print_json(statements: &[Stmt]) {
+    println!(
+        "{}",
+        serde_json::to_string(statements).expect("syntax trees serialize")
+    );
+}
+
+#[cfg(not(feature = "serde"))]
+fn print_json(_: &[Stmt]) {
+    unreachable!("`ast_command` only asks for JSON with the serde feature")
+}
+
+/// Why `format_file` left a file as it was.
+enum FormatError {
+    /// It doesn't scan or parse, already reported.
+    Invalid(RoxError),
+    /// It has comments, which formatting would remove, and `--write` was
+    /// given.
+    HasComments,
+}
+
+/// Prints the file in the canonical layout, or with `write` replaces its
+/// contents with that. Formatting drops comments, so a file with any is
+/// never rewritten.
+fn format_file(path: &str, write: bool) -> io::Result<Result<(), FormatError>> {
+    let source = fs::read_to_string(path)?;
+    let mut scanner = Scanner::new(source.clone());
+    let tokens = match scanner.scan_tokens() {
+        Ok(tokens) => tokens.to_vec(),
+        Err(errors) => return Ok(Err(FormatError::Invalid(report_all(errors, &source)))),
+    };
+    let statements = match Parser::new(tokens).parse() {
+        Ok(statements) => statements,
+        Err(errors) => return Ok(Err(FormatError::Invalid(report_all(errors, &source)))),
+    };
+
+    let formatted = rox::format_program(&statements);
+    if !write {
+        print!("{}", formatted);
+    } else if scanner.has_comments() {
+        return Ok(Err(FormatError::HasComments));
+    } else {
+        fs::write(path, formatted)?;
+    }
+    Ok(Ok(()))
+}
+
+/// Prints every token on its own line followed by a count. Scan errors are
+/// listed in between, where they happened, as well as going to stderr.
+fn dump_tokens(source: String) -> Result<(), RoxError> {
+    let mut scanner = Scanner::new(source.clone());
+    let errors = scanner.scan_tokens().err().unwrap_or_default();
+    let tokens = scanner.tokens();
+
+    let position = |e: &RoxError| match e {
+        RoxError::Scan { line, column, .. } => (*line, *column),
+        _ => (0, 0),
+    };
+    let mut pending = errors.iter().peekable();
+    for token in tokens {
+        while let Some(e) = pending.peek() {
+            if position(e) > (token.line, token.column) {
+                break;
+            }
+            println!("{}", e);
+            pending.next();
+        }
+        println!("{}", token);
+    }
+    pending.for_each(|e| println!("{}", e));
+    println!("{} tokens", tokens.len());
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(report_all(errors, &source))
+    }
 }
 
 fn run_prompt() {
-    let mut rl = Editor::<()>::new();
+    let config = Config::builder()
+        .max_history_size(history::history_size())
+        .history_ignore_dups(true)
+        .build();
+    let mut rl = Editor::<ReplHelper>::with_config(config);
+    rl.set_helper(Some(ReplHelper::default()));
+    let history_path = history::history_path();
+    if let Some(path) = &history_path {
+        // there is no history file yet on the first run
+        let _ = rl.load_history(path);
+    }
+
     let mut interpreter = Interpreter::default();
     loop {
+        if let Some(helper) = rl.helper_mut() {
+            helper.refresh(&interpreter);
+        }
         match rl.readline("> ") {
             Ok(line) => {
                 rl.add_history_entry(&line);
-                run(line, &mut interpreter);
+                match commands::execute(&line, &mut interpreter, &mut io::stdout()) {
+                    Some(Ok(commands::Flow::Quit)) => break,
+                    Some(Ok(commands::Flow::Continue)) => {}
+                    Some(Err(e)) => eprintln!("Error: {}", e),
+                    None => {
+                        let _ = run_repl_line(line, &mut interpreter);
+                    }
+                }
             }
             Err(ReadlineError::Interrupted) => {
                 println!("Ctrl-C");
@@ -69,14 +396,122 @@ fn run_prompt() {
             }
         }
     }
+
+    if let Some(path) = &history_path {
+        if let Err(e) = rl.save_history(path) {
+            eprintln!("could not save history to {}: {}", path.display(), e);
+        }
+    }
 }
 
-fn run(source: String, interpreter: &mut Interpreter) {
-    let mut s = Scanner::new(source);
-    let tokens = s.scan_tokens().to_vec();
-    let mut parser = Parser::new(tokens);
+fn scan(source: &str) -> Result<Vec<Token>, RoxError> {
+    let mut s = Scanner::new(source.to_owned());
+    match s.scan_tokens() {
+        Ok(tokens) => Ok(tokens.to_vec()),
+        Err(errors) => Err(report_all(errors, source)),
+    }
+}
 
-    if let Err(e) = parser.parse().and_then(|stms| interpreter.interpret(&stms)) {
-        eprintln!("{}", e)
+/// Reports every error in `errors`, which can't be empty, against the
+/// `source` they were found in, and returns the first.
+fn report_all(mut errors: Vec<RoxError>, source: &str) -> RoxError {
+    for e in &errors {
+        eprintln!("{}", error::render(e, source));
+    }
+    errors.swap_remove(0)
+}
+
+/// Reports everything the resolver finds, and returns the first error.
+/// Warnings alone don't count as failing.
+fn check(statements: &mut [Stmt], source: &str) -> Result<(), RoxError> {
+    let resolution = resolve(statements);
+    for warning in &resolution.warnings {
+        eprintln!("{}", error::render_warning(warning, source));
+    }
+
+    if resolution.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(report_all(resolution.errors, source))
+    }
+}
+
+fn run(source: String, interpreter: &mut Interpreter, optimize: bool) -> Result<(), RoxError> {
+    let tokens = scan(&source)?;
+    let mut statements = Parser::new(tokens)
+        .parse()
+        .map_err(|errors| report_all(errors, &source))?;
+    check(&mut statements, &source)?;
+    if optimize {
+        statements = rox::fold(statements);
+    }
+
+    // runtime errors go to the interpreter's error stream, with anything
+    // the script wrote there itself
+    interpreter.interpret(&statements).inspect_err(|e| {
+        let _ = interpreter.report(e, &source);
+    })
+}
+
+/// Like `run`, but the last statement may leave out its semicolon, and has
+/// its value printed if it's an expression. A runtime error doesn't stop
+/// the statements after it.
+fn run_repl_line(source: String, interpreter: &mut Interpreter) -> Result<(), RoxError> {
+    let tokens = scan(&source)?;
+    let mut statements = Parser::for_repl(tokens)
+        .parse()
+        .map_err(|errors| report_all(errors, &source))?;
+    check(&mut statements, &source)?;
+
+    let trailing = match statements.pop() {
+        Some(Stmt {
+            kind: StmtKind::Expr(expr),
+            ..
+        }) => Some(expr),
+        other => {
+            statements.extend(other);
+            None
+        }
+    };
+
+    // one failing statement doesn't stop the rest of the line from running
+    let mut errors = interpreter.interpret_continuing(&statements);
+    if let Some(expr) = trailing {
+        match interpreter.evaluate_expr_to_string(&expr) {
+            Ok(value) => println!("{}", value),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    for e in &errors {
+        let _ = interpreter.report(e, &source);
+    }
+    match errors.into_iter().next() {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_each_run_of_a_changed_file_starts_afresh() {
+        // what --watch does when the file is edited between runs
+        let path = env::temp_dir().join("rox_watch_rerun.lox");
+        fs::write(&path, "var a = 1;").unwrap();
+        assert_eq!(run_file(&path, &[], Mode::Plain).unwrap(), Ok(()));
+
+        fs::write(&path, "print a;").unwrap();
+        let result = run_file(&path, &[], Mode::Plain).unwrap();
+
+        fs::remove_file(&path).unwrap();
+        match result {
+            Err(RoxError::Runtime { message, .. }) => {
+                assert_eq!(message, "variable 'a' is not defined")
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
     }
 }
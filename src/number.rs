@@ -0,0 +1,56 @@
+//! Converting numbers to and from text. Everything that shows a number to
+//! the user goes through `format`, so a value prints the same way in the
+//! REPL, in `print`, in `str()` and in error messages.
+
+/// Whole numbers are written without a fractional part, so 2.0 is "2" while
+/// 2.5 is "2.5". No exponent is used, however large or small the number.
+pub fn format(n: f64) -> String {
+    n.to_string()
+}
+
+/// `n` with exactly `decimals` digits after the point, rounded, as a
+/// format spec like `{:.2}` or a fixed print precision asks for.
+pub fn format_fixed(n: f64, decimals: usize) -> String {
+    format!("{:.*}", decimals, n)
+}
+
+/// Reads a number written the way a script would, ignoring whitespace
+/// around it. Anything that isn't a finite number, such as "nan" or "inf",
+/// gives `None`.
+pub fn parse(s: &str) -> Option<f64> {
+    match s.trim().parse::<f64>() {
+        Ok(n) if n.is_finite() => Some(n),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format(2.0), "2");
+        assert_eq!(format(2.5), "2.5");
+        assert_eq!(format(-0.125), "-0.125");
+        assert_eq!(format(1e21), "1000000000000000000000");
+    }
+
+    #[test]
+    fn test_format_fixed() {
+        assert_eq!(format_fixed(2.0, 2), "2.00");
+        assert_eq!(format_fixed(1.0 / 3.0, 3), "0.333");
+        assert_eq!(format_fixed(0.125, 0), "0");
+        assert_eq!(format_fixed(-2.5, 1), "-2.5");
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("2"), Some(2.0));
+        assert_eq!(parse(" \t-2.5\n"), Some(-2.5));
+        assert_eq!(parse("1e3"), Some(1000.0));
+        for rejected in &["", "two", "2 3", "nan", "NaN", "inf", "-infinity", "1e999"] {
+            assert_eq!(parse(rejected), None, "{:?}", rejected);
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use crate::expr::{Expr, ExprKind, LiteralValue};
+use crate::interpreter::{Interpreter, Value};
+use crate::statement::{Stmt, StmtKind};
+use std::io;
+use std::sync::Arc;
+
+/// Works out constant expressions ahead of time: operators whose operands
+/// are literals, ternaries with a literal condition, and parentheses around
+/// a literal. Folding is done by the interpreter itself, so the results are
+/// exactly what running them would give. Anything that fails, like `1 / 0`
+/// or `true + 1`, is left as it was, to fail at runtime where it would have.
+pub fn fold(statements: Vec<Stmt>) -> Vec<Stmt> {
+    let mut folder = Folder {
+        interpreter: Interpreter::new(io::sink()),
+    };
+    folder.statements(statements)
+}
+
+struct Folder {
+    interpreter: Interpreter<'static>,
+}
+
+impl Folder {
+    fn statements(&mut self, statements: Vec<Stmt>) -> Vec<Stmt> {
+        statements.into_iter().map(|s| self.statement(s)).collect()
+    }
+
+    fn statement(&mut self, s: Stmt) -> Stmt {
+        let kind = match s.kind {
+            StmtKind::Expr(e) => StmtKind::Expr(self.expr(e)),
+            StmtKind::Print { line, values } => StmtKind::Print {
+                line,
+                values: self.exprs(values),
+            },
+            StmtKind::Eprint { line, values } => StmtKind::Eprint {
+                line,
+                values: self.exprs(values),
+            },
+            StmtKind::VarDec { name, initializer } => StmtKind::VarDec {
+                name,
+                initializer: initializer.map(|e| self.expr(e)),
+            },
+            StmtKind::Const { name, initializer } => StmtKind::Const {
+                name,
+                initializer: self.expr(initializer),
+            },
+            StmtKind::Block(statements) => StmtKind::Block(self.statements(statements)),
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => StmtKind::If {
+                condition: self.expr(condition),
+                then_branch: Box::new(self.statement(*then_branch)),
+                else_branch: else_branch.map(|s| Box::new(self.statement(*s))),
+            },
+            StmtKind::While { condition, body } => StmtKind::While {
+                condition: self.expr(condition),
+                body: Box::new(self.statement(*body)),
+            },
+            StmtKind::DoWhile { body, condition } => StmtKind::DoWhile {
+                body: Box::new(self.statement(*body)),
+                condition: self.expr(condition),
+            },
+            StmtKind::Function { name, params, body } => StmtKind::Function {
+                name,
+                params,
+                body: self.body(body),
+            },
+            StmtKind::Class {
+                name,
+                superclass,
+                methods,
+            } => StmtKind::Class {
+                name,
+                superclass,
+                methods: self.statements(methods),
+            },
+            StmtKind::Return { line, value } => StmtKind::Return {
+                line,
+                value: value.map(|e| self.expr(e)),
+            },
+            // a failed assertion shows its condition as written
+            kind @ StmtKind::Assert { .. }
+            | kind @ StmtKind::Import { .. }
+            | kind @ StmtKind::Error { .. } => kind,
+        };
+        Stmt::new(kind, s.span)
+    }
+
+    // a body that's already shared with a function value is left be
+    fn body(&mut self, body: Arc<Vec<Stmt>>) -> Arc<Vec<Stmt>> {
+        match Arc::try_unwrap(body) {
+            Ok(body) => Arc::new(self.statements(body)),
+            Err(shared) => shared,
+        }
+    }
+
+    fn exprs(&mut self, exprs: Vec<Expr>) -> Vec<Expr> {
+        exprs.into_iter().map(|e| self.expr(e)).collect()
+    }
+
+    fn expr(&mut self, e: Expr) -> Expr {
+        let span = e.span;
+        let kind = match e.kind {
+            ExprKind::Binary {
+                left,
+                operator,
+                right,
+            } => ExprKind::Binary {
+                left: Box::new(self.expr(*left)),
+                operator,
+                right: Box::new(self.expr(*right)),
+            },
+            ExprKind::Unary { operator, operand } => ExprKind::Unary {
+                operator,
+                operand: Box::new(self.expr(*operand)),
+            },
+            ExprKind::Grouping { expr } => {
+                let expr = self.expr(*expr);
+                if let ExprKind::Literal(_) = expr.kind {
+                    return Expr::new(expr.kind, span);
+                }
+                ExprKind::Grouping {
+                    expr: Box::new(expr),
+                }
+            }
+            ExprKind::Ternary {
+                condition,
+                true_expr,
+                false_expr,
+            } => {
+                let condition = self.expr(*condition);
+                if let ExprKind::Literal(literal) = &condition.kind {
+                    let truthy = !matches!(literal, LiteralValue::False | LiteralValue::Nil);
+                    return self.expr(if truthy { *true_expr } else { *false_expr });
+                }
+                ExprKind::Ternary {
+                    condition: Box::new(condition),
+                    true_expr: Box::new(self.expr(*true_expr)),
+                    false_expr: Box::new(self.expr(*false_expr)),
+                }
+            }
+            ExprKind::Assign { name, value } => ExprKind::Assign {
+                name,
+                value: Box::new(self.expr(*value)),
+            },
+            ExprKind::Call {
+                callee,
+                paren,
+                arguments,
+            } => ExprKind::Call {
+                callee: Box::new(self.expr(*callee)),
+                paren,
+                arguments: self.exprs(arguments),
+            },
+            ExprKind::Get { object, name } => ExprKind::Get {
+                object: Box::new(self.expr(*object)),
+                name,
+            },
+            ExprKind::Set {
+                object,
+                name,
+                value,
+            } => ExprKind::Set {
+                object: Box::new(self.expr(*object)),
+                name,
+                value: Box::new(self.expr(*value)),
+            },
+            ExprKind::ArrayLiteral(elements) => ExprKind::ArrayLiteral(self.exprs(elements)),
+            ExprKind::Interpolation(parts) => ExprKind::Interpolation(self.exprs(parts)),
+            ExprKind::MapLiteral { brace, entries } => ExprKind::MapLiteral {
+                brace,
+                entries: entries
+                    .into_iter()
+                    .map(|(key, value)| (self.expr(key), self.expr(value)))
+                    .collect(),
+            },
+            ExprKind::Index {
+                object,
+                bracket,
+                index,
+            } => ExprKind::Index {
+                object: Box::new(self.expr(*object)),
+                bracket,
+                index: Box::new(self.expr(*index)),
+            },
+            ExprKind::SetIndex {
+                object,
+                bracket,
+                index,
+                value,
+            } => ExprKind::SetIndex {
+                object: Box::new(self.expr(*object)),
+                bracket,
+                index: Box::new(self.expr(*index)),
+                value: Box::new(self.expr(*value)),
+            },
+            ExprKind::Lambda { params, body } => ExprKind::Lambda {
+                params,
+                body: self.body(body),
+            },
+            kind @ ExprKind::Literal(_)
+            | kind @ ExprKind::Variable(_)
+            | kind @ ExprKind::This(_)
+            | kind @ ExprKind::Super { .. }
+            | kind @ ExprKind::Increment { .. }
+            | kind @ ExprKind::Defined(_)
+            | kind @ ExprKind::Error { .. } => kind,
+        };
+
+        let e = Expr::new(kind, span);
+        let constant = match &e.kind {
+            ExprKind::Binary { left, right, .. } => is_literal(left) && is_literal(right),
+            ExprKind::Unary { operand, .. } => is_literal(operand),
+            _ => false,
+        };
+        if constant {
+            self.evaluated(e)
+        } else {
+            e
+        }
+    }
+
+    /// `e` replaced by its value, unless evaluating it fails.
+    fn evaluated(&mut self, e: Expr) -> Expr {
+        let literal = match self.interpreter.evaluate(&e) {
+            Ok(Value::Number(n)) => LiteralValue::Number(n),
+            Ok(Value::Str(s)) => LiteralValue::STRING(s.as_str().into()),
+            Ok(Value::Bool(true)) => LiteralValue::True,
+            Ok(Value::Bool(false)) => LiteralValue::False,
+            Ok(Value::Nil) => LiteralValue::Nil,
+            _ => return e,
+        };
+        Expr::new(ExprKind::Literal(literal), e.span)
+    }
+}
+
+fn is_literal(e: &Expr) -> bool {
+    matches!(e.kind, ExprKind::Literal(_))
+}
+
+#[cfg(test)]
+mod test {
+    use super::fold;
+    use crate::expr::print::print_stmt;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn folded(source: &str) -> Vec<String> {
+        let tokens = Scanner::new(source.to_owned())
+            .scan_tokens()
+            .unwrap()
+            .to_vec();
+        let statements = Parser::new(tokens).parse().unwrap();
+        fold(statements).iter().map(print_stmt).collect()
+    }
+
+    #[test]
+    fn test_constants_are_folded() {
+        assert_eq!(
+            folded("print 1 + 2 * 3; print -(4 - 6); print !nil; print \"a\" + 1 + (2 < 3);"),
+            vec!["(print 7)", "(print 2)", "(print true)", "(print a1true)"]
+        );
+        assert_eq!(
+            folded("print true ? 1 + 1 : x; print nil ? x : (\"no\");"),
+            vec!["(print 2)", "(print no)"]
+        );
+    }
+
+    #[test]
+    fn test_only_constant_parts_are_folded() {
+        assert_eq!(
+            folded("var a = x + (2 * 3); fun f() { return [1 + 1, g(2 - 2)]; }"),
+            vec![
+                "(var 'a' (+ variable: 'x' 6))",
+                "(fun 'f' () (return (list 2 (call variable: 'g' 0))))"
+            ]
+        );
+        assert_eq!(
+            folded("print x ? 1 + 1 : 2;"),
+            vec!["(print (? variable: 'x' 2 2))"]
+        );
+    }
+
+    #[test]
+    fn test_failing_operations_are_left_for_runtime() {
+        assert_eq!(
+            folded("print (1 + 1) / 0; print true + 1; print -\"a\";"),
+            vec!["(print (/ 2 0))", "(print (+ true 1))", "(print (- a))"]
+        );
+        assert_eq!(folded("assert 1 > 2;"), vec!["(assert (> 1 2))"]);
+    }
+}
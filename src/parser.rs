@@ -1,54 +1,241 @@
-use crate::error;
-use crate::expr::{Expr, LiteralValue};
-use crate::statement::Stmt;
-use crate::token::{Token, TokenType};
+use crate::error::RoxError;
+use crate::expr::{Expr, ExprKind, LiteralValue};
+use crate::span::Span;
+use crate::statement::{Program, Stmt, StmtKind};
+use crate::token::{Binding, Name, Token, TokenType};
+use std::mem;
+use std::sync::Arc;
 
 pub struct Parser {
+    /// The tokens read from `source` and not yet let go of. The first one is
+    /// token number `offset`, so `current` and the starts the parse methods
+    /// keep count from the beginning of the input, not of the buffer.
     tokens: Vec<Token>,
+    source: Box<dyn Iterator<Item = Token>>,
+    offset: usize,
     current: usize,
+    diagnostics: Vec<RoxError>,
+    /// In the REPL a statement at the very end of the input may leave out
+    /// its semicolon, so `1 + 2` or `print x` can be typed as is.
+    repl: bool,
+    /// How many blocks the parser is inside, so that recovering from an
+    /// error can tell a block's closing brace from a stray one.
+    block_depth: usize,
+    /// How many `if` branches the parser is inside, outside of any block
+    /// in them, so that recovering from an error in a branch can stop at
+    /// the `else` after it.
+    branch_depth: usize,
+    /// How many expressions and statements the parser is inside, counting
+    /// each grouping, call argument, unary operand and right-hand side of
+    /// an assignment or ternary, each operator in a chain like `a + b + c`
+    /// or `f()()`, and each block and body of an `if` or loop. Past
+    /// `max_depth` parsing fails rather than overflowing the stack.
+    depth: usize,
+    max_depth: usize,
+    /// Set once parsing went past `max_depth`. Every statement around the
+    /// one that did fails with it, so the rest of the input is skipped and
+    /// nothing after the first error is reported.
+    too_deep: bool,
 }
 
-type ParseResult<T> = Result<T, String>;
+type ParseResult<T> = Result<T, RoxError>;
+
+const MAX_ARGUMENTS: usize = 255;
+
+/// The most characters of a statement an error message quotes.
+const MAX_QUOTED_LENGTH: usize = 40;
+
+/// Deep enough for any expression a person would write. A debug build needs
+/// about 3MB of stack to reach it, well within the 8MB a main thread gets.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+#[derive(Clone, Copy)]
+pub(crate) enum Associativity {
+    Left,
+    // nothing needs this yet, an exponent operator would
+    #[allow(dead_code)]
+    Right,
+}
+
+/// Every binary operator above the ternary, with its precedence and
+/// associativity. A higher precedence binds more tightly, so `1 + 2 * 3`
+/// multiplies first. The comma operator sits below assignment and is
+/// parsed on its own.
+const BINARY_OPERATORS: &[(TokenType, u8, Associativity)] = &[
+    (TokenType::BangEqual, 1, Associativity::Left),
+    (TokenType::EqualEqual, 1, Associativity::Left),
+    (TokenType::Greater, 2, Associativity::Left),
+    (TokenType::GreaterEqual, 2, Associativity::Left),
+    (TokenType::Less, 2, Associativity::Left),
+    (TokenType::LessEqual, 2, Associativity::Left),
+    (TokenType::Minus, 3, Associativity::Left),
+    (TokenType::Plus, 3, Associativity::Left),
+    (TokenType::Slash, 4, Associativity::Left),
+    (TokenType::Star, 4, Associativity::Left),
+];
+
+const LOWEST_BINARY_PRECEDENCE: u8 = 1;
+
+pub(crate) fn binary_operator(tag: &TokenType) -> Option<(u8, Associativity)> {
+    BINARY_OPERATORS
+        .iter()
+        .find(|(operator, _, _)| operator == tag)
+        .map(|&(_, precedence, associativity)| (precedence, associativity))
+}
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+        Self::with_max_depth(tokens, DEFAULT_MAX_DEPTH)
+    }
+
+    /// A parser that fails on expressions and statements nested more than
+    /// `max_depth` deep.
+    pub fn with_max_depth(tokens: Vec<Token>, max_depth: usize) -> Self {
+        let mut parser = Self::new_from_iter(tokens.into_iter());
+        parser.max_depth = max_depth;
+        parser
+    }
+
+    /// A parser that reads tokens from `tokens` as it needs them, such as
+    /// from a `TokenStream`, holding on to no more than the declaration it's
+    /// in the middle of and the token after the current one.
+    pub fn new_from_iter(tokens: impl Iterator<Item = Token> + 'static) -> Self {
+        let mut parser = Self {
+            tokens: Vec::new(),
+            source: Box::new(tokens),
+            offset: 0,
+            current: 0,
+            diagnostics: Vec::new(),
+            repl: false,
+            block_depth: 0,
+            branch_depth: 0,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            too_deep: false,
+        };
+        parser.fill();
+        parser
+    }
+
+    pub fn for_repl(tokens: Vec<Token>) -> Self {
+        Self {
+            repl: true,
+            ..Self::new(tokens)
+        }
+    }
+
+    /// Parses the whole token stream, failing with every error found, in
+    /// source order. Use `parse_program` to also get the statements that
+    /// did parse.
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<RoxError>> {
+        let program = self.parse_program();
+
+        if program.diagnostics.is_empty() {
+            Ok(program.statements)
+        } else {
+            Err(program.diagnostics)
+        }
     }
 
-    pub fn parse(&mut self) -> ParseResult<Vec<Stmt>> {
-        let mut stmts = Vec::new();
-        let mut has_parse_error = false;
+    /// Parses the whole token stream without giving up on the first error.
+    /// Declarations that fail to parse are kept as `StmtKind::Error` nodes so
+    /// the rest of the program is still available to tooling.
+    pub fn parse_program(&mut self) -> Program {
+        let mut statements = Vec::new();
         while !self.is_at_end() {
-            match self.declaration() {
-                Ok(stmt) => stmts.push(stmt),
-                Err(e) => {
-                    has_parse_error = true;
-                    eprintln!("{}", e)
+            statements.push(self.declaration());
+            self.let_go();
+        }
+
+        Program {
+            statements,
+            diagnostics: mem::take(&mut self.diagnostics),
+        }
+    }
+
+    /// Parses the tokens as a single expression, such as `(a + b) * 2`, with
+    /// nothing but the end of the input after it. Fails with every error
+    /// found, in source order.
+    pub fn parse_expression(&mut self) -> Result<Expr, Vec<RoxError>> {
+        if self.is_at_end() {
+            return Err(vec![self.error("expected an expression")]);
+        }
+
+        match self.expression() {
+            Ok(expr) => {
+                if !self.is_at_end() {
+                    let error = self.error("expected the end of the expression");
+                    self.report(error);
                 }
+                if self.diagnostics.is_empty() {
+                    return Ok(expr);
+                }
+            }
+            Err(error) => self.report(error),
+        }
+        Err(mem::take(&mut self.diagnostics))
+    }
+
+    /// Token number `index`, if it has been read and not let go of.
+    fn token(&self, index: usize) -> Option<&Token> {
+        self.tokens.get(index.checked_sub(self.offset)?)
+    }
+
+    /// The tokens from number `start` up to the current one.
+    fn tokens_since(&self, start: usize) -> &[Token] {
+        &self.tokens[start - self.offset..self.current - self.offset]
+    }
+
+    /// Moves on to the next token, reading another from `source` to keep
+    /// the one after it in view.
+    fn bump(&mut self) {
+        self.current += 1;
+        self.fill();
+    }
+
+    fn fill(&mut self) {
+        while self.offset + self.tokens.len() < self.current + 2 {
+            match self.source.next() {
+                Some(token) => self.tokens.push(token),
+                None => break,
             }
         }
+    }
 
-        if has_parse_error {
-            Err("Parsing error(s) found".to_string())
-        } else {
-            Ok(stmts)
+    /// Drops the tokens of declarations already parsed, keeping the last
+    /// one for errors at the end of the input to point at.
+    fn let_go(&mut self) {
+        let keep = self.current.saturating_sub(1);
+        if keep > self.offset {
+            self.tokens.drain(..keep - self.offset);
+            self.offset = keep;
         }
     }
 
     fn match_token(&mut self, types: &[TokenType]) -> Option<&Token> {
-        let matched = self
-            .tokens
-            .get(self.current)
-            .filter(|token| types.contains(&token.tag));
-
-        if matched.is_some() {
-            self.current += 1;
+        if !matches!(self.peek(), Some(token) if types.contains(&token.tag)) {
+            return None;
         }
-        matched
+        self.advance()
     }
 
     fn peek(&self) -> Option<&Token> {
-        self.tokens.get(self.current)
+        self.token(self.current)
+    }
+
+    /// Whether the token after the current one is `token`.
+    fn check_next(&self, token: &TokenType) -> bool {
+        match self.token(self.current + 1) {
+            Some(t) => t.tag.eq(token),
+            None => false,
+        }
+    }
+
+    fn check(&self, token: &TokenType) -> bool {
+        match self.peek() {
+            Some(t) => t.tag.eq(token),
+            None => false,
+        }
     }
 
     fn is_at_end(&self) -> bool {
@@ -63,8 +250,29 @@ impl Parser {
         if self.is_at_end() {
             None
         } else {
-            self.current += 1;
-            self.tokens.get(self.current - 1)
+            self.bump();
+            self.token(self.current - 1)
+        }
+    }
+
+    /// Records an error to fail the parse with, unless parsing already went
+    /// too deep.
+    fn report(&mut self, error: RoxError) {
+        if !self.too_deep {
+            self.diagnostics.push(error);
+        }
+    }
+
+    /// Builds an error pointing at the token the parser is currently looking at.
+    fn error(&self, message: impl Into<String>) -> RoxError {
+        match self.peek().or_else(|| self.tokens.last()) {
+            Some(token) => error_at(token, message),
+            None => RoxError::Parse {
+                line: 0,
+                column: 0,
+                token: None,
+                message: message.into(),
+            },
         }
     }
 
@@ -72,44 +280,135 @@ impl Parser {
         let res = self
             .peek()
             .filter(|t| t.tag.eq(token))
-            .ok_or_else(|| err_message.to_owned())
+            .ok_or_else(|| self.error(err_message))
             .map(|_| ());
 
         if res.is_ok() {
-            self.current += 1;
+            self.bump();
         }
 
         res
     }
 
-    fn consume_identifier(&mut self, err_message: &str) -> ParseResult<String> {
-        let res = self
-            .peek()
-            .and_then(|t| t.tag.get_identifier_value())
-            .ok_or_else(|| err_message.to_owned());
+    /// The source covered by the tokens consumed since `start`. Nothing
+    /// consumed gives an empty span where the next token begins.
+    fn span_since(&self, start: usize) -> Span {
+        let first = match self.token(start) {
+            Some(token) => token.span(),
+            None => return Span::default(),
+        };
+        if self.current > start {
+            first.to(self.tokens[self.current - 1 - self.offset].span())
+        } else {
+            Span {
+                end: first.start,
+                ..first
+            }
+        }
+    }
 
-        if res.is_ok() {
-            self.current += 1;
+    fn expr_since(&self, start: usize, kind: ExprKind) -> Expr {
+        Expr::new(kind, self.span_since(start))
+    }
+
+    fn stmt_since(&self, start: usize, kind: StmtKind) -> Stmt {
+        Stmt::new(kind, self.span_since(start))
+    }
+
+    /// Consumes the `;` ending the statement that began at token `start`.
+    /// In the REPL the end of the input ends a statement too. A missing one
+    /// is reported at the statement's last token, quoting the statement, as
+    /// whatever comes next may be lines away or not there at all.
+    fn end_statement(&mut self, start: usize) -> ParseResult<()> {
+        if self.match_token(&[TokenType::Semicolon]).is_some() || (self.repl && self.is_at_end()) {
+            return Ok(());
         }
 
-        res
+        match self.token(self.current.wrapping_sub(1)) {
+            Some(last) if self.current > start => Err(error_at(
+                last,
+                format!("expected a semicolon after '{}'", self.text_since(start)),
+            )),
+            _ => Err(self.error("expected a semicolon following statement")),
+        }
+    }
+
+    /// The source of the tokens consumed since `start`, rebuilt from the
+    /// tokens and shortened if it's long.
+    fn text_since(&self, start: usize) -> String {
+        let mut text = String::new();
+        let mut previous_end = None;
+        let mut previous = None;
+        for token in self.tokens_since(start) {
+            if matches!(previous_end, Some(end) if token.start > end) {
+                text.push(' ');
+            }
+            match &token.tag {
+                // the text after an interpolation carries on the same string
+                TokenType::STRING(s) if previous == Some(&TokenType::InterpolationEnd) => {
+                    text += &format!("{}\"", s)
+                }
+                TokenType::STRING(s) => text += &format!("\"{}\"", s),
+                TokenType::InterpolationStart => {
+                    // and the text before one is left open
+                    text.pop();
+                    text += "${";
+                }
+                tag => text += &tag.to_string(),
+            }
+            previous = Some(&token.tag);
+            previous_end = Some(token.end);
+        }
+
+        if text.chars().count() > MAX_QUOTED_LENGTH {
+            text = text.chars().take(MAX_QUOTED_LENGTH - 3).collect::<String>() + "...";
+        }
+        text
+    }
+
+    fn consume_identifier(&mut self, err_message: &str) -> ParseResult<Name> {
+        let name = self
+            .peek()
+            .and_then(Token::identifier_name)
+            .ok_or_else(|| self.error(err_message))?;
+
+        self.bump();
+        Ok(name)
     }
 
-    fn declaration(&mut self) -> ParseResult<Stmt> {
+    fn declaration(&mut self) -> Stmt {
+        let line = self.peek().map_or(0, |t| t.line);
+        let start = self.current;
         let stmt = if self.match_token(&[TokenType::Var]).is_some() {
             self.finish_var_declaration()
+        } else if self.match_token(&[TokenType::Const]).is_some() {
+            self.finish_const_declaration()
+        } else if self.check(&TokenType::Fun) && !self.check_next(&TokenType::LeftParen) {
+            // `fun (` starts a function expression instead
+            self.bump();
+            self.finish_function_declaration()
+        } else if self.match_token(&[TokenType::Class]).is_some() {
+            self.finish_class_declaration()
         } else {
-            self.statement()
+            self.statement_kind()
         };
+        self.recovered(start, line, stmt)
+    }
 
-        if stmt.is_err() {
-            self.synchronize();
-        }
-
-        stmt
+    /// The statement parsed from token `start` on, or if that failed, an
+    /// error statement in its place, with the parser moved past it.
+    fn recovered(&mut self, start: usize, line: u32, stmt: ParseResult<StmtKind>) -> Stmt {
+        let kind = stmt.unwrap_or_else(|error| {
+            self.synchronize(start);
+            let message = error.message().to_owned();
+            self.report(error);
+            StmtKind::Error { line, message }
+        });
+        self.stmt_since(start, kind)
     }
 
-    fn finish_var_declaration(&mut self) -> ParseResult<Stmt> {
+    fn finish_var_declaration(&mut self) -> ParseResult<StmtKind> {
+        let start = self.current - 1;
         let name = self.consume_identifier("expected an identifer after 'var' keyword")?;
         let initializer = if self.match_token(&[TokenType::Equal]).is_some() {
             Some(self.expression()?)
@@ -117,282 +416,898 @@ impl Parser {
             None
         };
 
+        self.end_statement(start)?;
+        Ok(StmtKind::VarDec { name, initializer })
+    }
+
+    fn finish_const_declaration(&mut self) -> ParseResult<StmtKind> {
+        let start = self.current - 1;
+        let name = self.consume_identifier("expected an identifer after 'const' keyword")?;
         self.consume(
-            &TokenType::Semicolon,
-            "expected a semicolon following statement",
+            &TokenType::Equal,
+            &format!(
+                "expected '=' after 'const {}', a constant needs a value",
+                name
+            ),
         )?;
-        Ok(Stmt::VarDec { name, initializer })
+        let initializer = self.expression()?;
+
+        self.end_statement(start)?;
+        Ok(StmtKind::Const { name, initializer })
+    }
+
+    fn finish_function_declaration(&mut self) -> ParseResult<StmtKind> {
+        let name = self.consume_identifier("expected a function name after 'fun' keyword")?;
+        self.consume(&TokenType::LeftParen, "expected '(' after function name")?;
+        let (params, body) = self.finish_function(&format!("function '{}'", name))?;
+
+        Ok(StmtKind::Function { name, params, body })
+    }
+
+    /// An error in a method's header is reported, and the rest of the class
+    /// body skipped, keeping the methods before it.
+    fn finish_class_declaration(&mut self) -> ParseResult<StmtKind> {
+        let name = self.consume_identifier("expected a class name after 'class' keyword")?;
+        let superclass = if self.match_token(&[TokenType::Less]).is_some() {
+            Some(self.consume_identifier("expected a superclass name after '<'")?)
+        } else {
+            None
+        };
+        let brace = self.current;
+        self.consume(&TokenType::LeftBrace, "expected '{' before class body")?;
+
+        let mut methods = Vec::new();
+        while !self.is_at_end() && !self.check(&TokenType::RightBrace) {
+            match self.method() {
+                Ok(method) => methods.push(method),
+                Err(error) => {
+                    self.report(error);
+                    self.skip_past_closer(brace);
+                    return Ok(StmtKind::Class {
+                        name,
+                        superclass,
+                        methods,
+                    });
+                }
+            }
+        }
+        self.consume(&TokenType::RightBrace, "expected '}' after class body")?;
+
+        Ok(StmtKind::Class {
+            name,
+            superclass,
+            methods,
+        })
+    }
+
+    fn method(&mut self) -> ParseResult<Stmt> {
+        let start = self.current;
+        let name = self.consume_identifier("expected a method name")?;
+        self.consume(&TokenType::LeftParen, "expected '(' after method name")?;
+        let (params, body) = self.finish_function(&format!("method '{}'", name))?;
+        Ok(self.stmt_since(start, StmtKind::Function { name, params, body }))
+    }
+
+    /// Moves to the end of the input, once parsing went too deep to carry on.
+    fn skip_to_end(&mut self) {
+        while !self.is_at_end() {
+            self.bump();
+        }
+    }
+
+    /// Moves past the bracket closing the one at token `opener`, or to the
+    /// end of input if it's never closed.
+    fn skip_past_closer(&mut self, opener: usize) {
+        if self.too_deep {
+            return self.skip_to_end();
+        }
+        let mut open = Vec::new();
+        for token in self.tokens_since(opener) {
+            track_bracket(&mut open, &token.tag);
+        }
+        while !open.is_empty() && !self.is_at_end() {
+            let tag = self.tokens[self.current - self.offset].tag.clone();
+            track_bracket(&mut open, &tag);
+            self.bump();
+        }
+    }
+
+    /// A function expression, after its `fun`. Only a declaration can name
+    /// a function: a name here is reported, then parsed past.
+    fn finish_lambda(&mut self) -> ParseResult<ExprKind> {
+        if let Some(name) = self.peek().and_then(Token::identifier_name) {
+            let message = format!(
+                "a function expression can't be named, declare 'fun {}' on its own instead",
+                name
+            );
+            self.report(self.error(message));
+            self.bump();
+        }
+        self.consume(&TokenType::LeftParen, "expected '(' after 'fun'")?;
+        let (params, body) = self.finish_function("a function")?;
+
+        Ok(ExprKind::Lambda { params, body })
+    }
+
+    /// The parameters and body of a function, after the `(` before its
+    /// parameters. `what` names it in errors.
+    fn finish_function(&mut self, what: &str) -> ParseResult<(Vec<Name>, Arc<Vec<Stmt>>)> {
+        let mut params = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    return Err(self.error(format!(
+                        "{} can't have more than {} parameters",
+                        what, MAX_ARGUMENTS
+                    )));
+                }
+                params.push(self.consume_identifier("expected a parameter name")?);
+
+                if self.match_token(&[TokenType::Comma]).is_none() {
+                    break;
+                }
+            }
+        }
+        self.consume(&TokenType::RightParen, "expected ')' after parameters")?;
+
+        self.consume(&TokenType::LeftBrace, "expected '{' before function body")?;
+        let body = Arc::new(self.finish_block()?);
+        Ok((params, body))
     }
 
     fn statement(&mut self) -> ParseResult<Stmt> {
-        if self.match_token(&[TokenType::Print]).is_some() {
-            self.finish_print_statement()
+        let start = self.current;
+        let kind = self.descend("statement", Self::statement_kind)?;
+        Ok(self.stmt_since(start, kind))
+    }
+
+    fn statement_kind(&mut self) -> ParseResult<StmtKind> {
+        if let Some(line) = self.match_token(&[TokenType::Print]).map(|t| t.line) {
+            let values = self.finish_print_statement()?;
+            Ok(StmtKind::Print { line, values })
+        } else if let Some(line) = self.match_token(&[TokenType::Eprint]).map(|t| t.line) {
+            let values = self.finish_print_statement()?;
+            Ok(StmtKind::Eprint { line, values })
+        } else if self.match_token(&[TokenType::LeftBrace]).is_some() {
+            Ok(StmtKind::Block(self.finish_block()?))
+        } else if let Some(keyword) = self.match_token(&[TokenType::If]).cloned() {
+            self.finish_if_statement(keyword)
+        } else if self.match_token(&[TokenType::While]).is_some() {
+            self.finish_while_statement()
+        } else if self.match_token(&[TokenType::Do]).is_some() {
+            self.finish_do_while_statement()
+        } else if self.match_token(&[TokenType::For]).is_some() {
+            self.finish_for_statement()
+        } else if let Some(line) = self.match_token(&[TokenType::Return]).map(|t| t.line) {
+            self.finish_return_statement(line)
+        } else if let Some(keyword) = self.match_token(&[TokenType::Assert]).cloned() {
+            self.finish_assert_statement(keyword)
+        } else if let Some(keyword) = self.match_token(&[TokenType::Import]).cloned() {
+            self.finish_import_statement(keyword)
         } else {
             self.expression_statement()
         }
     }
 
-    fn finish_print_statement(&mut self) -> ParseResult<Stmt> {
-        let expr = self.expression()?;
+    /// The values of a `print` or `eprint`. The commas between them separate
+    /// them, so a comma expression has to be in parentheses to be printed.
+    fn finish_print_statement(&mut self) -> ParseResult<Vec<Expr>> {
+        let start = self.current - 1;
+        if self.check(&TokenType::Semicolon) {
+            return Err(self.error("expected a value to print"));
+        }
+        let mut values = vec![self.nested(Self::assignment)?];
+        while self.match_token(&[TokenType::Comma]).is_some() {
+            if self.check(&TokenType::Semicolon) {
+                return Err(self.error("expected a value after ',' in print"));
+            }
+            values.push(self.nested(Self::assignment)?);
+        }
+
+        self.end_statement(start)?;
+        Ok(values)
+    }
+
+    fn finish_assert_statement(&mut self, keyword: Token) -> ParseResult<StmtKind> {
+        let condition = self.expression()?;
+        self.consume(&TokenType::Semicolon, "expected ';' after assertion")?;
+        Ok(StmtKind::Assert { keyword, condition })
+    }
+
+    /// The path of an `import`, which has to be a plain string literal.
+    fn finish_import_statement(&mut self, keyword: Token) -> ParseResult<StmtKind> {
+        let start = self.current - 1;
+        let path = match self.peek().map(|t| &t.tag) {
+            Some(TokenType::STRING(path)) => path.to_string(),
+            _ => return Err(self.error("expected a path string after 'import'")),
+        };
+        self.bump();
+
+        self.end_statement(start)?;
+        Ok(StmtKind::Import { keyword, path })
+    }
+
+    fn finish_block(&mut self) -> ParseResult<Vec<Stmt>> {
+        self.descend("block", |parser| {
+            let mut stmts = Vec::new();
+            parser.block_depth += 1;
+            let branch_depth = mem::replace(&mut parser.branch_depth, 0);
+            while !parser.is_at_end() && !parser.check(&TokenType::RightBrace) {
+                stmts.push(parser.declaration());
+            }
+            parser.branch_depth = branch_depth;
+            parser.block_depth -= 1;
+
+            parser.consume(&TokenType::RightBrace, "expected '}' after block")?;
+            Ok(stmts)
+        })
+    }
+
+    /// `else if` needs no special casing: the `if` is the else branch. A
+    /// missing parenthesis is recovered from in place and blamed on its own
+    /// `if`, so the rest of a chain is still parsed.
+    fn finish_if_statement(&mut self, keyword: Token) -> ParseResult<StmtKind> {
+        if self.match_token(&[TokenType::LeftParen]).is_none() {
+            let error = error_at(&keyword, "expected '(' after 'if'");
+            self.report(error);
+        }
+        let condition = self.expression()?;
+        if self.match_token(&[TokenType::RightParen]).is_none() {
+            let error = self.error("expected ')' after if condition");
+            self.report(error);
+        }
+
+        let then_branch = self.branch();
+        // an else always binds to the nearest preceding if
+        let else_branch = if self.match_token(&[TokenType::Else]).is_some() {
+            Some(self.branch())
+        } else {
+            None
+        };
+
+        Ok(StmtKind::If {
+            condition,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    /// The body of an `if` or `else`. An error in it is recovered from
+    /// there, rather than by skipping the whole `if`, so an `else` after it
+    /// still belongs to the `if`.
+    fn branch(&mut self) -> Box<Stmt> {
+        let line = self.peek().map_or(0, |t| t.line);
+        let start = self.current;
+        self.branch_depth += 1;
+        let kind = self.descend("statement", Self::statement_kind);
+        let stmt = self.recovered(start, line, kind);
+        self.branch_depth -= 1;
+        Box::new(stmt)
+    }
+
+    fn finish_while_statement(&mut self) -> ParseResult<StmtKind> {
+        self.consume(&TokenType::LeftParen, "expected '(' after 'while'")?;
+        let condition = self.expression()?;
+        self.consume(&TokenType::RightParen, "expected ')' after while condition")?;
+        let body = Box::new(self.statement()?);
+
+        Ok(StmtKind::While { condition, body })
+    }
+
+    fn finish_do_while_statement(&mut self) -> ParseResult<StmtKind> {
+        let start = self.current;
+        self.consume(&TokenType::LeftBrace, "expected '{' after 'do'")?;
+        let block = StmtKind::Block(self.finish_block()?);
+        let body = Box::new(self.stmt_since(start, block));
+        self.consume(&TokenType::While, "expected 'while' after do-while body")?;
+        self.consume(&TokenType::LeftParen, "expected '(' after 'while'")?;
+        let condition = self.expression()?;
+        self.consume(&TokenType::RightParen, "expected ')' after while condition")?;
         self.consume(
             &TokenType::Semicolon,
-            "expected a semicolon following statement",
+            "expected ';' after the condition of a do-while loop",
         )?;
-        Ok(Stmt::Print(expr))
+
+        Ok(StmtKind::DoWhile { body, condition })
+    }
+
+    /// A for loop has no node of its own, it is desugared into a while loop
+    /// wrapped in a block that holds the initializer.
+    fn finish_for_statement(&mut self) -> ParseResult<StmtKind> {
+        let keyword = self.current - 1;
+        self.consume(&TokenType::LeftParen, "expected '(' after 'for'")?;
+
+        let start = self.current;
+        let initializer = if self.match_token(&[TokenType::Semicolon]).is_some() {
+            None
+        } else if self.match_token(&[TokenType::Var]).is_some() {
+            let declaration = self.finish_var_declaration()?;
+            Some(self.stmt_since(start, declaration))
+        } else {
+            let statement = self.expression_statement()?;
+            Some(self.stmt_since(start, statement))
+        };
+
+        let condition = if self.check(&TokenType::Semicolon) {
+            self.expr_since(self.current, ExprKind::Literal(LiteralValue::True))
+        } else {
+            self.expression()?
+        };
+        self.consume(&TokenType::Semicolon, "expected ';' after loop condition")?;
+
+        let increment = if self.check(&TokenType::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(&TokenType::RightParen, "expected ')' after for clauses")?;
+
+        let mut body = self.statement()?;
+        if let Some(increment) = increment {
+            let (body_span, increment_span) = (body.span, increment.span);
+            let increment = Stmt::new(StmtKind::Expr(increment), increment_span);
+            body = Stmt::new(StmtKind::Block(vec![body, increment]), body_span);
+        }
+
+        let mut desugared = StmtKind::While {
+            condition,
+            body: Box::new(body),
+        };
+        if let Some(initializer) = initializer {
+            let while_loop = self.stmt_since(keyword, desugared);
+            desugared = StmtKind::Block(vec![initializer, while_loop]);
+        }
+
+        Ok(desugared)
     }
 
-    fn expression_statement(&mut self) -> ParseResult<Stmt> {
+    fn finish_return_statement(&mut self, line: u32) -> ParseResult<StmtKind> {
+        let start = self.current - 1;
+        let value = if self.check(&TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+
+        self.end_statement(start)?;
+        Ok(StmtKind::Return { line, value })
+    }
+
+    fn expression_statement(&mut self) -> ParseResult<StmtKind> {
+        let start = self.current;
         let expr = self.expression()?;
-        self.consume(
-            &TokenType::Semicolon,
-            "expected a semicolon following statement",
-        )?;
-        Ok(Stmt::Expr(expr))
+        self.end_statement(start)?;
+        Ok(StmtKind::Expr(expr))
     }
 
     fn expression(&mut self) -> ParseResult<Expr> {
-        self.assignment()
+        self.nested(Self::comma)
     }
 
-    fn assignment(&mut self) -> ParseResult<Expr> {
-        let expr = self.ternary()?;
+    fn nested(&mut self, parse: impl FnOnce(&mut Self) -> ParseResult<Expr>) -> ParseResult<Expr> {
+        self.descend("expression", parse)
+    }
 
-        if self.match_token(&[TokenType::Equal]).is_some() {
-            let value = self.assignment()?;
+    /// Parses one level deeper, failing once `max_depth` is reached. `what`
+    /// names what would have been too deep in the error.
+    fn descend<T>(
+        &mut self,
+        what: &str,
+        parse: impl FnOnce(&mut Self) -> ParseResult<T>,
+    ) -> ParseResult<T> {
+        self.deepen(what)?;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
 
-            if let Expr::Variable(token) = expr {
-                return Ok(Expr::Assign {
-                    name: token,
-                    value: Box::new(value),
-                });
-            } else {
-                eprintln!("invalid assignment target");
-            }
+    /// Goes one level deeper, failing once `max_depth` is reached. The
+    /// error is reported right away, as whatever catches it is skipped.
+    fn deepen(&mut self, what: &str) -> ParseResult<()> {
+        if self.depth >= self.max_depth {
+            let error = self.error(format!(
+                "{} too deeply nested (limit {})",
+                what, self.max_depth
+            ));
+            self.report(error.clone());
+            self.too_deep = true;
+            return Err(error);
         }
 
-        Ok(expr)
+        self.depth += 1;
+        Ok(())
     }
 
-    fn equality(&mut self) -> ParseResult<Expr> {
-        let operators = &[TokenType::BangEqual, TokenType::EqualEqual];
-        if let Some(operator) = self.match_token(operators) {
-            error::report(
-                operator.line,
-                "missing left-hand-side operand for equality expression",
-            );
-            self.comparison().and_then(|_| self.expression())
-        } else {
-            let mut expr = self.comparison()?;
+    /// Parses a chain of operators, each of which takes what came before it
+    /// as its operand, so `a + b + c` nests `a + b` and goes a level deeper
+    /// for each operator. The depth is back where it started after.
+    fn chain(&mut self, parse: impl FnOnce(&mut Self) -> ParseResult<Expr>) -> ParseResult<Expr> {
+        let depth = self.depth;
+        let result = parse(self);
+        self.depth = depth;
+        result
+    }
 
-            while let Some(operator) = self.match_token(operators) {
-                let op = operator.clone();
-                let right_expr = self.comparison()?;
-                expr = Expr::Binary {
+    /// The comma operator sits below assignment, so `a = 1, b = 2` is two
+    /// assignments. Call arguments are parsed with `assignment` so their
+    /// separating commas are never swallowed by this rule.
+    fn comma(&mut self) -> ParseResult<Expr> {
+        self.chain(|parser| {
+            let start = parser.current;
+            let mut expr = parser.assignment()?;
+
+            while let Some(operator) = parser.peek().filter(|t| t.tag == TokenType::Comma).cloned()
+            {
+                parser.deepen("expression")?;
+                parser.advance();
+                let right_expr = parser.assignment()?;
+                let binary = ExprKind::Binary {
                     left: Box::new(expr),
-                    operator: op,
+                    operator,
                     right: Box::new(right_expr),
-                }
+                };
+                expr = parser.expr_since(start, binary);
             }
 
             Ok(expr)
+        })
+    }
+
+    fn assignment(&mut self) -> ParseResult<Expr> {
+        let start = self.current;
+        let expr = self.ternary()?;
+
+        if let Some(equals) = self.match_token(&[TokenType::Equal]).cloned() {
+            let value = self.nested(Self::assignment)?;
+
+            // only a bare variable, index or property can be assigned to,
+            // so not `(a) = 1`, as in jlox
+            let kind = match expr.kind {
+                ExprKind::Variable(name) => ExprKind::Assign {
+                    name,
+                    value: Box::new(value),
+                },
+                ExprKind::Index {
+                    object,
+                    bracket,
+                    index,
+                } => ExprKind::SetIndex {
+                    object,
+                    bracket,
+                    index,
+                    value: Box::new(value),
+                },
+                ExprKind::Get { object, name } => ExprKind::Set {
+                    object,
+                    name,
+                    value: Box::new(value),
+                },
+                _ => return Err(error_at(&equals, "invalid assignment target")),
+            };
+            return Ok(self.expr_since(start, kind));
         }
+
+        Ok(expr)
     }
 
-    fn comparison(&mut self) -> ParseResult<Expr> {
-        let operators = &[
-            TokenType::Greater,
-            TokenType::GreaterEqual,
-            TokenType::Less,
-            TokenType::LessEqual,
-        ];
-        if let Some(operator) = self.match_token(operators) {
-            error::report(
-                operator.line,
-                "missing left-hand-side operand for comparison expression",
+    /// Parses a chain of binary operators by precedence climbing: operators
+    /// binding at least as tightly as `min_precedence` are taken here, and
+    /// looser ones are left for the caller.
+    fn binary(&mut self, min_precedence: u8) -> ParseResult<Expr> {
+        if let Some((token, precedence)) = self.peek().and_then(|t| {
+            binary_operator(&t.tag)
+                .filter(|(precedence, _)| *precedence >= min_precedence)
+                // a leading '-' is negation
+                .filter(|_| t.tag != TokenType::Minus)
+                .map(|(precedence, _)| (t.clone(), precedence))
+        }) {
+            self.bump();
+            let error = error_at(
+                &token,
+                format!("missing left-hand-side operand for '{}'", token.tag),
             );
-            self.comparison().and_then(|_| self.expression())
-        } else {
-            let mut expr = self.addition()?;
+            // the right operand is consumed so that it isn't reported too,
+            // any error inside it is less useful than this one
+            let _ = self.binary(precedence + 1);
+            return Err(error);
+        }
+
+        self.chain(|parser| {
+            let start = parser.current;
+            let mut expr = parser.unary()?;
 
-            while let Some(operator) = self.match_token(operators) {
-                let op = operator.clone();
-                let right_expr = self.addition()?;
-                expr = Expr::Binary {
+            while let Some(operator) = parser.peek().cloned() {
+                let (precedence, associativity) = match binary_operator(&operator.tag) {
+                    Some(found) if found.0 >= min_precedence => found,
+                    _ => break,
+                };
+                parser.deepen("expression")?;
+                parser.advance();
+
+                let right_precedence = match associativity {
+                    Associativity::Left => precedence + 1,
+                    Associativity::Right => precedence,
+                };
+                let right_expr = parser.binary(right_precedence)?;
+                let binary = ExprKind::Binary {
                     left: Box::new(expr),
-                    operator: op,
+                    operator,
                     right: Box::new(right_expr),
-                }
+                };
+                expr = parser.expr_since(start, binary);
             }
 
             Ok(expr)
-        }
+        })
     }
 
-    fn addition(&mut self) -> ParseResult<Expr> {
-        let operators = &[TokenType::Plus, TokenType::Minus];
-        if let Some(operator) = self.match_token(&[TokenType::Plus]) {
-            error::report(
-                operator.line,
-                "missing left-hand-side operand for addition expression",
-            );
-            self.comparison().and_then(|_| self.expression())
-        } else {
-            let mut expr = self.multiplication()?;
+    fn unary(&mut self) -> ParseResult<Expr> {
+        let start = self.current;
+        if self.match_token(&[TokenType::Defined]).is_some() {
+            let name = self.consume_identifier("expected a variable name after 'defined'")?;
+            return Ok(self.expr_since(start, ExprKind::Defined(name)));
+        }
 
-            while let Some(operator) = self.match_token(operators) {
-                let op = operator.clone();
-                let right_expr = self.multiplication()?;
-                expr = Expr::Binary {
-                    left: Box::new(expr),
-                    operator: op,
-                    right: Box::new(right_expr),
+        if let Some(operator) = self
+            .match_token(&[TokenType::PlusPlus, TokenType::MinusMinus])
+            .cloned()
+        {
+            return match self.nested(Self::unary)?.kind {
+                ExprKind::Variable(name) => {
+                    Ok(self.expr_since(start, ExprKind::Increment { operator, name }))
                 }
-            }
+                _ => Err(error_at(
+                    &operator,
+                    format!("operand of '{}' must be a variable", operator.tag),
+                )),
+            };
+        }
 
-            Ok(expr)
+        match self.match_token(&[TokenType::Bang, TokenType::Minus]) {
+            Some(token) => {
+                let operator = token.clone();
+                let operand = self.nested(Self::unary)?;
+                let unary = ExprKind::Unary {
+                    operator,
+                    operand: Box::new(operand),
+                };
+                Ok(self.expr_since(start, unary))
+            }
+            None => self.call(),
         }
     }
 
-    fn multiplication(&mut self) -> ParseResult<Expr> {
-        let operators = &[TokenType::Slash, TokenType::Star];
-        if let Some(operator) = self.match_token(operators) {
-            error::report(
-                operator.line,
-                "missing left-hand-side operand for multiplication expression",
-            );
-            self.comparison().and_then(|_| self.expression())
-        } else {
-            let mut expr = self.unary()?;
+    fn call(&mut self) -> ParseResult<Expr> {
+        self.chain(|parser| {
+            let start = parser.current;
+            let mut expr = parser.primary()?;
 
-            while let Some(operator) = self.match_token(operators) {
-                let op = operator.clone();
-                let right_expr = self.unary()?;
-                expr = Expr::Binary {
-                    left: Box::new(expr),
-                    operator: op,
-                    right: Box::new(right_expr),
+            // the first call, index or property nests only its own operands,
+            // which count for themselves
+            let mut chained = false;
+            while parser.check(&TokenType::LeftParen)
+                || parser.check(&TokenType::LeftBracket)
+                || parser.check(&TokenType::Dot)
+            {
+                if mem::replace(&mut chained, true) {
+                    parser.deepen("expression")?;
+                }
+                if let Some(paren) = parser.match_token(&[TokenType::LeftParen]) {
+                    let paren = paren.clone();
+                    let call = parser.finish_call(expr, paren)?;
+                    expr = parser.expr_since(start, call);
+                } else if let Some(bracket) = parser.match_token(&[TokenType::LeftBracket]).cloned()
+                {
+                    let index = parser.expression()?;
+                    parser.consume(&TokenType::RightBracket, "expected ']' after index")?;
+                    let index = ExprKind::Index {
+                        object: Box::new(expr),
+                        bracket,
+                        index: Box::new(index),
+                    };
+                    expr = parser.expr_since(start, index);
+                } else {
+                    parser.bump();
+                    let name = parser
+                        .peek()
+                        .filter(|t| t.tag.get_identifier_value().is_some())
+                        .cloned()
+                        .ok_or_else(|| parser.error("expected a property name after '.'"))?;
+                    parser.bump();
+                    let get = ExprKind::Get {
+                        object: Box::new(expr),
+                        name,
+                    };
+                    expr = parser.expr_since(start, get);
                 }
             }
 
             Ok(expr)
+        })
+    }
+
+    /// A string with interpolations, which the scanner split into the text
+    /// around them and the tokens inside them.
+    fn interpolation(&mut self) -> ParseResult<ExprKind> {
+        let mut parts = vec![self.string_text()?];
+        while self.match_token(&[TokenType::InterpolationStart]).is_some() {
+            parts.push(self.expression()?);
+            self.consume(
+                &TokenType::InterpolationEnd,
+                "expected '}' after the interpolated expression",
+            )?;
+            parts.push(self.string_text()?);
         }
+
+        Ok(ExprKind::Interpolation(parts))
     }
 
-    fn unary(&mut self) -> ParseResult<Expr> {
-        match self.match_token(&[TokenType::Bang, TokenType::Minus]) {
-            Some(token) => Ok(Expr::Unary {
-                operator: (token.clone()),
-                operand: Box::new(self.unary()?),
-            }),
-            None => self.primary(),
+    /// The text of a string between its quotes and interpolations.
+    fn string_text(&mut self) -> ParseResult<Expr> {
+        let start = self.current;
+        let text = match self.peek().map(|t| &t.tag) {
+            Some(TokenType::STRING(text)) => text.clone(),
+            _ => return Err(self.error("expected the rest of the string")),
+        };
+        self.bump();
+        Ok(self.expr_since(start, ExprKind::Literal(LiteralValue::STRING(text))))
+    }
+
+    fn finish_list(&mut self) -> ParseResult<ExprKind> {
+        let mut elements = Vec::new();
+        if !self.check(&TokenType::RightBracket) {
+            loop {
+                elements.push(self.nested(Self::assignment)?);
+                if self.match_token(&[TokenType::Comma]).is_none() {
+                    break;
+                }
+            }
         }
+        self.consume(&TokenType::RightBracket, "expected ']' after list elements")?;
+
+        Ok(ExprKind::ArrayLiteral(elements))
     }
 
-    fn primary(&mut self) -> ParseResult<Expr> {
+    fn finish_map(&mut self, brace: Token) -> ParseResult<ExprKind> {
+        let mut entries = Vec::new();
+        if !self.check(&TokenType::RightBrace) {
+            loop {
+                let key = self.nested(Self::assignment)?;
+                self.consume(&TokenType::Colon, "expected ':' after map key")?;
+                let value = self.nested(Self::assignment)?;
+                entries.push((key, value));
+                if self.match_token(&[TokenType::Comma]).is_none() {
+                    break;
+                }
+            }
+        }
+        self.consume(&TokenType::RightBrace, "expected '}' after map entries")?;
+
+        Ok(ExprKind::MapLiteral { brace, entries })
+    }
+
+    fn finish_call(&mut self, callee: Expr, paren: Token) -> ParseResult<ExprKind> {
+        let mut arguments = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    return Err(error_at(
+                        &paren,
+                        format!("can't have more than {} arguments", MAX_ARGUMENTS),
+                    ));
+                }
+                arguments.push(self.nested(Self::assignment)?);
+
+                if self.match_token(&[TokenType::Comma]).is_none() {
+                    break;
+                }
+            }
+        }
+        self.consume(&TokenType::RightParen, "expected ')' after arguments")?;
+
+        Ok(ExprKind::Call {
+            callee: Box::new(callee),
+            paren,
+            arguments,
+        })
+    }
+
+    fn primary(&mut self) -> ParseResult<Expr> {
+        let start = self.current;
+        let kind = self.primary_kind()?;
+        Ok(self.expr_since(start, kind))
+    }
+
+    fn primary_kind(&mut self) -> ParseResult<ExprKind> {
         let token = self.peek();
         let pair = token.map(|t| (&t.tag, t.line));
         match pair {
             Some((TokenType::True, _)) => {
-                self.current += 1;
-                Ok(Expr::Literal(LiteralValue::True))
+                self.bump();
+                Ok(ExprKind::Literal(LiteralValue::True))
             }
             Some((TokenType::False, _)) => {
-                self.current += 1;
-                Ok(Expr::Literal(LiteralValue::False))
+                self.bump();
+                Ok(ExprKind::Literal(LiteralValue::False))
             }
             Some((TokenType::Number(n), _)) => {
                 let num = *n;
-                self.current += 1;
-                Ok(Expr::Literal(LiteralValue::Number(num)))
+                self.bump();
+                Ok(ExprKind::Literal(LiteralValue::Number(num)))
             }
             Some((TokenType::Nil, _)) => {
-                self.current += 1;
-                Ok(Expr::Literal(LiteralValue::Nil))
+                self.bump();
+                Ok(ExprKind::Literal(LiteralValue::Nil))
+            }
+            Some((TokenType::STRING(_), _)) if self.check_next(&TokenType::InterpolationStart) => {
+                self.interpolation()
             }
             Some((TokenType::STRING(val), _)) => {
-                let s = val.to_owned();
-                self.current += 1;
-                Ok(Expr::Literal(LiteralValue::STRING(s)))
+                let s = val.clone();
+                self.bump();
+                Ok(ExprKind::Literal(LiteralValue::STRING(s)))
             }
             Some((TokenType::Identifer(_), _)) => {
-                let token_c = token.unwrap().clone();
-                self.current += 1;
-                Ok(Expr::Variable(token_c))
+                let name = self.consume_identifier("expected a variable name")?;
+                Ok(ExprKind::Variable(name))
             }
-            Some((TokenType::LeftParen, line)) => {
-                self.current += 1;
+            Some((TokenType::LeftParen, _)) => {
+                self.bump();
                 let expr = self.expression()?;
-                match self.consume(&TokenType::RightParen, "expected ')' after expression") {
-                    Ok(_) => Ok(Expr::Grouping {
-                        expr: Box::new(expr),
-                    }),
-                    Err(e) => {
-                        error::report(line, &e);
-                        Err(e)
-                    }
+                // an error inside was reported already, and is likely what
+                // the ')' is missing for
+                if self.match_token(&[TokenType::RightParen]).is_none()
+                    && expr.error_line().is_none()
+                {
+                    return Err(self.error("expected ')' after expression"));
                 }
+                Ok(ExprKind::Grouping {
+                    expr: Box::new(expr),
+                })
+            }
+            Some((TokenType::LeftBracket, _)) => {
+                self.bump();
+                self.finish_list()
+            }
+            // a brace in statement position was already taken as a block,
+            // so one reaching here starts a map
+            Some((TokenType::LeftBrace, _)) => {
+                let brace = self.tokens[self.current - self.offset].clone();
+                self.bump();
+                self.finish_map(brace)
+            }
+            Some((TokenType::Fun, _)) => {
+                self.bump();
+                self.finish_lambda()
+            }
+            Some((TokenType::This, line)) => {
+                let column = self.tokens[self.current - self.offset].column;
+                self.bump();
+                Ok(ExprKind::This(Name {
+                    symbol: "this".into(),
+                    line,
+                    column,
+                    binding: Binding::Dynamic,
+                }))
+            }
+            Some((TokenType::Super, line)) => {
+                let column = self.tokens[self.current - self.offset].column;
+                self.bump();
+                let keyword = Name {
+                    symbol: "super".into(),
+                    line,
+                    column,
+                    binding: Binding::Dynamic,
+                };
+                self.consume(&TokenType::Dot, "expected '.' after 'super'")?;
+                let method = self
+                    .peek()
+                    .filter(|t| t.tag.get_identifier_value().is_some())
+                    .cloned()
+                    .ok_or_else(|| self.error("expected a superclass method name after '.'"))?;
+                self.bump();
+                Ok(ExprKind::Super { keyword, method })
             }
+            Some((TokenType::Dot, _)) => Err(self.error("expected an expression before '.'")),
             Some((token_type, line)) => {
+                // a missing operand right before a token that ends the
+                // expression anyway can be recovered from in place
+                let recoverable = matches!(
+                    token_type,
+                    TokenType::Semicolon
+                        | TokenType::RightParen
+                        | TokenType::RightBrace
+                        | TokenType::EOF
+                );
                 let message = format!("unexpected '{}'", token_type);
-                error::report(line, &message);
-                Err(message)
-            }
-            None => {
-                let message = "unexpected end of input".to_owned();
-                error::report(0, &message);
-                Err(message)
+
+                if recoverable {
+                    self.report(self.error(message.clone()));
+                    Ok(ExprKind::Error { line, message })
+                } else {
+                    Err(self.error(message))
+                }
             }
+            None => Err(self.error("unexpected end of input")),
         }
     }
 
+    /// Like C, the true branch is a full expression since the ':' closes it.
+    /// The false branch is parsed with `assignment`, which comes back through
+    /// here, so a ternary in the false branch nests to the right and
+    /// `a ? b : c ? d : e` means `a ? b : (c ? d : e)`. Unlike C, an
+    /// assignment is allowed there too, so `c ? a = 1 : b = 2` assigns to
+    /// `b` rather than to the whole ternary.
     fn ternary(&mut self) -> ParseResult<Expr> {
-        let condition = self.equality()?;
+        let start = self.current;
+        let condition = self.binary(LOWEST_BINARY_PRECEDENCE)?;
         match self.match_token(&[TokenType::QuestionMark]) {
-            Some(token) => {
-                let line = token.line;
-                let true_expr = self.equality()?;
-                let consumed = self.consume(
-                    &TokenType::Colon,
-                    &format!("uh oh expected ':' in ternary expression, line: {}", line),
-                );
-                match consumed {
-                    Ok(_) => {
-                        let false_expr = self.equality()?;
-                        let expr = Expr::Ternary {
-                            condition: Box::new(condition),
-                            true_expr: Box::new(true_expr),
-                            false_expr: Box::new(false_expr),
-                        };
-                        Ok(expr)
-                    }
-                    Err(e) => {
-                        error::report(line, &e);
-                        Err(e)
-                    }
-                }
+            Some(_) => {
+                let true_expr = self.expression()?;
+                self.consume(&TokenType::Colon, "expected ':' in ternary expression")?;
+                let false_expr = self.nested(Self::assignment)?;
+
+                let ternary = ExprKind::Ternary {
+                    condition: Box::new(condition),
+                    true_expr: Box::new(true_expr),
+                    false_expr: Box::new(false_expr),
+                };
+                Ok(self.expr_since(start, ternary))
             }
             _ => Ok(condition),
         }
     }
 
-    fn synchronize(&mut self) {
+    /// Skips the rest of the failed statement that started at `start`, so
+    /// parsing can carry on with the next one. Brackets the statement left
+    /// open are skipped to their closers first, so an error inside them
+    /// doesn't leave the parser mid-expression. A '}' closing an enclosing
+    /// block is left for that block, and other closers with nothing to
+    /// close are skipped.
+    fn synchronize(&mut self, start: usize) {
+        if self.too_deep {
+            return self.skip_to_end();
+        }
+        let mut open = Vec::new();
+        for token in self.tokens_since(start) {
+            track_bracket(&mut open, &token.tag);
+        }
+
         while let Some(token) = self.peek() {
             match token.tag {
-                TokenType::EOF | TokenType::Semicolon => {
+                TokenType::EOF => return,
+                TokenType::Semicolon if open.is_empty() => {
                     self.advance();
                     return;
                 }
+                TokenType::RightBrace
+                    if !open.contains(&TokenType::LeftBrace) && self.block_depth > 0 =>
+                {
+                    return;
+                }
+                TokenType::Else if open.is_empty() && self.branch_depth > 0 => return,
                 TokenType::Class
                 | TokenType::Fun
                 | TokenType::Var
+                | TokenType::Const
                 | TokenType::For
                 | TokenType::If
                 | TokenType::While
+                | TokenType::Do
                 | TokenType::Print
-                | TokenType::Return => {
+                | TokenType::Eprint
+                | TokenType::Return
+                | TokenType::Assert
+                | TokenType::Import
+                    // always skip something, or the statement would be
+                    // parsed again from the same place
+                    if self.current > start =>
+                {
                     return;
                 }
                 _ => {
+                    let tag = token.tag.clone();
+                    track_bracket(&mut open, &tag);
                     self.advance();
                 }
             }
@@ -400,10 +1315,64 @@ impl Parser {
     }
 }
 
+/// Keeps `open` as the stack of brackets that haven't been closed yet.
+/// Closers that don't match anything are ignored.
+fn track_bracket(open: &mut Vec<TokenType>, tag: &TokenType) {
+    match tag {
+        TokenType::LeftParen | TokenType::LeftBracket | TokenType::LeftBrace => {
+            open.push(tag.clone())
+        }
+        TokenType::RightParen if open.last() == Some(&TokenType::LeftParen) => {
+            open.pop();
+        }
+        TokenType::RightBracket if open.last() == Some(&TokenType::LeftBracket) => {
+            open.pop();
+        }
+        TokenType::RightBrace => {
+            if let Some(i) = open.iter().rposition(|t| *t == TokenType::LeftBrace) {
+                open.truncate(i);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn error_at(token: &Token, message: impl Into<String>) -> RoxError {
+    let lexeme = match token.tag {
+        TokenType::EOF => None,
+        _ => Some(token.tag.to_string()),
+    };
+
+    RoxError::Parse {
+        line: token.line,
+        column: token.column,
+        token: lexeme,
+        message: message.into(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::expr::{Expr, LiteralValue};
+    use crate::expr::print::{print_ast, print_stmt};
+    use crate::scanner::Scanner;
+    use crate::test::on_main_thread_stack;
+    use crate::token::Binding;
+
+    fn parse_source(source: &str) -> Program {
+        let mut scanner = Scanner::new(source.to_owned());
+        let tokens = scanner.scan_tokens().unwrap().to_vec();
+        Parser::new(tokens).parse_program()
+    }
+
+    fn ident(name: &str, line: u32, column: u32) -> Name {
+        Name {
+            symbol: name.into(),
+            line,
+            column,
+            binding: Binding::Dynamic,
+        }
+    }
 
     #[test]
     fn test_unary_minus() {
@@ -418,13 +1387,14 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(
             result,
-            Ok(vec![Stmt::Expr(Expr::Unary {
-                operator: Token {
-                    tag: TokenType::Minus,
-                    line: 0
-                },
-                operand: Box::new(Expr::Literal(LiteralValue::Number(9.0))),
-            })],)
+            Ok(vec![StmtKind::Expr(
+                ExprKind::Unary {
+                    operator: Token::new(TokenType::Minus, 0),
+                    operand: Box::new(ExprKind::Literal(LiteralValue::Number(9.0)).into()),
+                }
+                .into()
+            )
+            .into()],)
         )
     }
 
@@ -441,13 +1411,14 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(
             result,
-            Ok(vec![Stmt::Expr(Expr::Unary {
-                operator: Token {
-                    tag: TokenType::Bang,
-                    line: 0
-                },
-                operand: Box::new(Expr::Literal(LiteralValue::Number(10.0))),
-            })],)
+            Ok(vec![StmtKind::Expr(
+                ExprKind::Unary {
+                    operator: Token::new(TokenType::Bang, 0),
+                    operand: Box::new(ExprKind::Literal(LiteralValue::Number(10.0)).into()),
+                }
+                .into()
+            )
+            .into()],)
         )
     }
 
@@ -479,40 +1450,1267 @@ mod tests {
         assert!(result_2.is_ok());
         assert_eq!(
             result,
-            Ok(vec![Stmt::Expr(Expr::Binary {
-                left: Box::new(Expr::Literal(LiteralValue::Number(10.0))),
-                operator: Token {
-                    tag: TokenType::Plus,
-                    line: 0
-                },
-                right: Box::new(Expr::Binary {
-                    left: Box::new(Expr::Literal(LiteralValue::Number(2.0))),
-                    operator: Token {
-                        tag: TokenType::Star,
-                        line: 0
-                    },
-                    right: Box::new(Expr::Literal(LiteralValue::Number(6.0))),
-                }),
-            })],)
+            Ok(vec![StmtKind::Expr(
+                ExprKind::Binary {
+                    left: Box::new(ExprKind::Literal(LiteralValue::Number(10.0)).into()),
+                    operator: Token::new(TokenType::Plus, 0),
+                    right: Box::new(
+                        ExprKind::Binary {
+                            left: Box::new(ExprKind::Literal(LiteralValue::Number(2.0)).into()),
+                            operator: Token::new(TokenType::Star, 0),
+                            right: Box::new(ExprKind::Literal(LiteralValue::Number(6.0)).into()),
+                        }
+                        .into()
+                    ),
+                }
+                .into()
+            )
+            .into()],)
         );
 
         assert_eq!(
             result_2,
-            Ok(vec![Stmt::Expr(Expr::Binary {
-                right: Box::new(Expr::Literal(LiteralValue::Number(11.0))),
-                operator: Token {
-                    tag: TokenType::Plus,
-                    line: 0
+            Ok(vec![StmtKind::Expr(
+                ExprKind::Binary {
+                    right: Box::new(ExprKind::Literal(LiteralValue::Number(11.0)).into()),
+                    operator: Token::new(TokenType::Plus, 0),
+                    left: Box::new(
+                        ExprKind::Binary {
+                            left: Box::new(ExprKind::Literal(LiteralValue::Number(4.0)).into()),
+                            operator: Token::new(TokenType::Star, 0),
+                            right: Box::new(ExprKind::Literal(LiteralValue::Number(24.0)).into()),
+                        }
+                        .into()
+                    ),
+                }
+                .into()
+            )
+            .into()],)
+        );
+    }
+
+    fn parse_expression_ast(source: &str) -> String {
+        let program = parse_source(source);
+        assert!(program.diagnostics.is_empty(), "{:?}", program.diagnostics);
+        match &program.statements[..] {
+            [statement] => match &statement.kind {
+                StmtKind::Expr(expr)
+                | StmtKind::VarDec {
+                    initializer: Some(expr),
+                    ..
+                } => print_ast(expr),
+                other => panic!("expected a single expression, got {:?}", other),
+            },
+            other => panic!("expected a single expression, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_binary_precedence_and_associativity() {
+        assert_eq!(parse_expression_ast("1 - 2 - 3;"), "(- (- 1 2) 3)");
+        assert_eq!(parse_expression_ast("8 / 4 * 2;"), "(* (/ 8 4) 2)");
+        assert_eq!(
+            parse_expression_ast("1 + 2 * 3 - 4 / 5;"),
+            "(- (+ 1 (* 2 3)) (/ 4 5))"
+        );
+        assert_eq!(
+            parse_expression_ast("1 < 2 == 3 >= 4 != 5;"),
+            "(!= (== (< 1 2) (>= 3 4)) 5)"
+        );
+        assert_eq!(
+            parse_expression_ast("a == b < c + d * -e;"),
+            "(== variable: 'a' (< variable: 'b' (+ variable: 'c' (* variable: 'd' (- variable: 'e')))))"
+        );
+        assert_eq!(
+            parse_expression_ast("(1 + 2) * 3;"),
+            "(* (grouping (+ 1 2)) 3)"
+        );
+    }
+
+    #[test]
+    fn test_missing_left_operand_rejects_the_statement() {
+        for operator in ["==", "!=", ">", ">=", "<", "<=", "+", "/", "*"].iter() {
+            let source = format!("{} 3 * 4;\nprint 1;", operator);
+            let program = parse_source(&source);
+
+            assert_eq!(
+                program.diagnostics,
+                vec![RoxError::Parse {
+                    line: 0,
+                    column: 0,
+                    token: Some(operator.to_string()),
+                    message: format!("missing left-hand-side operand for '{}'", operator),
+                }],
+                "{}",
+                source
+            );
+            assert!(program.statements[0].error_line().is_some());
+            assert!(
+                matches!(program.statements[1].kind, StmtKind::Print { line: 1, .. }),
+                "{:?}",
+                program.statements
+            );
+
+            let tokens = Scanner::new(source).scan_tokens().unwrap().to_vec();
+            assert!(Parser::new(tokens).parse().is_err());
+        }
+    }
+
+    #[test]
+    fn test_missing_operand_inside_an_expression() {
+        let program = parse_source("print 1 + * 2;\nprint 3;");
+
+        assert_eq!(
+            program.diagnostics,
+            vec![RoxError::Parse {
+                line: 0,
+                column: 10,
+                token: Some("*".to_owned()),
+                message: "missing left-hand-side operand for '*'".to_owned(),
+            }]
+        );
+        assert_eq!(program.statements.len(), 2);
+        assert_eq!(program.statements[1].error_line(), None);
+    }
+
+    #[test]
+    fn test_invalid_assignment_targets() {
+        for (source, column) in [
+            ("1 = 2;", 2),
+            ("\"a\" = 2;", 4),
+            ("(a) = 3;", 4),
+            ("a + b = 3;", 6),
+        ]
+        .iter()
+        {
+            let source = format!("{}\nprint 1;", source);
+            let program = parse_source(&source);
+
+            assert_eq!(
+                program.diagnostics,
+                vec![RoxError::Parse {
+                    line: 0,
+                    column: *column,
+                    token: Some("=".to_owned()),
+                    message: "invalid assignment target".to_owned(),
+                }],
+                "{}",
+                source
+            );
+            assert!(matches!(program.statements[1].kind, StmtKind::Print { .. }));
+
+            let tokens = Scanner::new(source).scan_tokens().unwrap().to_vec();
+            assert!(Parser::new(tokens).parse().is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_returns_every_error() {
+        let tokens = Scanner::new("var = 1;\nprint 2;\nprint (3;".to_owned())
+            .scan_tokens()
+            .unwrap()
+            .to_vec();
+
+        assert_eq!(
+            Parser::new(tokens).parse(),
+            Err(vec![
+                RoxError::Parse {
+                    line: 0,
+                    column: 4,
+                    token: Some("=".to_owned()),
+                    message: "expected an identifer after 'var' keyword".to_owned(),
+                },
+                RoxError::Parse {
+                    line: 2,
+                    column: 8,
+                    token: Some(";".to_owned()),
+                    message: "expected ')' after expression".to_owned(),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_layout_does_not_change_the_structure() {
+        let one_line = "class A < B { f(x) { return super.f(x)[0] = -x; } } \
+            var a = fun (y) { print y ? [1] : {\"k\": ++n}; }; \
+            for (var i = 0; i < 3; ++i) { a(i).p = i, nil; } assert !a;";
+        let many_lines = "
+class A < B {
+    f(x) {
+        return super.f(x)[0] =
+            -x;
+    }
+}
+var a = fun (y) {
+    print y
+        ? [1]
+        : {\"k\": ++n};
+};
+for (var i = 0;
+     i < 3;
+     ++i) {
+    a(i).p = i,
+        nil;
+}
+assert !a;";
+        let one_line = parse_source(one_line).statements;
+        let many_lines = parse_source(many_lines).statements;
+
+        assert_eq!(one_line.len(), 4);
+        assert_ne!(one_line, many_lines);
+        for (a, b) in one_line.iter().zip(&many_lines) {
+            assert!(a.structural_eq(b), "{:?}\n{:?}", a, b);
+        }
+
+        // anything other than the layout still matters
+        for (a, b) in [
+            ("print a - b;", "print a + b;"),
+            ("print a;", "eprint a;"),
+            ("f(a, b);", "f(a);"),
+            ("x.a = 1;", "x.b = 1;"),
+            ("var a;", "var a = nil;"),
+        ] {
+            let a = &parse_source(a).statements[0];
+            let b = &parse_source(b).statements[0];
+            assert!(!a.structural_eq(b), "{:?}\n{:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_const_needs_an_initializer() {
+        let program = parse_source("const a = 1;\nconst b;\nprint a;");
+        assert_eq!(
+            program.diagnostics,
+            vec![RoxError::Parse {
+                line: 1,
+                column: 7,
+                token: Some(";".to_owned()),
+                message: "expected '=' after 'const b', a constant needs a value".to_owned(),
+            }]
+        );
+        assert_eq!(
+            program.statements[0].kind,
+            StmtKind::Const {
+                name: ident("a", 0, 6),
+                initializer: ExprKind::Literal(LiteralValue::Number(1.0)).into(),
+            }
+        );
+        assert_eq!(program.statements.len(), 3);
+    }
+
+    /// Parses `source`, expecting exactly one diagnostic, and returns the
+    /// statements that came out without errors.
+    fn parse_with_one_error(source: &str) -> Vec<Stmt> {
+        let program = parse_source(source);
+        assert_eq!(program.diagnostics.len(), 1, "{:?}", program.diagnostics);
+
+        program
+            .statements
+            .into_iter()
+            .filter(|s| s.error_line().is_none())
+            .collect()
+    }
+
+    #[test]
+    fn test_one_bad_statement_among_good_ones() {
+        let source = "
+            print 1;
+            var a = 2;
+            print (a + (3 ; 4));
+            print a;
+            { print 5; }
+            fun f() { return 6; }
+        ";
+        assert_eq!(parse_with_one_error(source).len(), 5);
+    }
+
+    #[test]
+    fn test_synchronize_skips_unmatched_closers() {
+        assert_eq!(parse_with_one_error("print 1);\nprint 2;").len(), 1);
+        assert_eq!(parse_with_one_error("print (1));\nprint 2;").len(), 1);
+        assert_eq!(parse_with_one_error("print 1 }\nprint 2;").len(), 1);
+    }
+
+    #[test]
+    fn test_synchronize_leaves_the_enclosing_block_closer() {
+        let good = parse_with_one_error("{ print (1 2 ; }\nprint 2;");
+
+        assert_eq!(good.len(), 1);
+        assert!(matches!(good[0].kind, StmtKind::Print { line: 1, .. }));
+    }
+
+    #[test]
+    fn test_synchronize_always_makes_progress() {
+        assert_eq!(parse_with_one_error("class {}\nprint 1;").len(), 1);
+    }
+
+    #[test]
+    fn test_leading_minus_is_negation() {
+        assert_eq!(parse_expression_ast("- 3 * 4;"), "(* (- 3) 4)");
+    }
+
+    #[test]
+    fn test_equality_binds_tighter_than_ternary() {
+        assert_eq!(
+            parse_expression_ast("a == b ? c : d;"),
+            "(? (== variable: 'a' variable: 'b') variable: 'c' variable: 'd')"
+        );
+        assert_eq!(
+            parse_expression_ast("a ? b + 1 : c * 2;"),
+            "(? variable: 'a' (+ variable: 'b' 1) (* variable: 'c' 2))"
+        );
+    }
+
+    #[test]
+    fn test_nested_ternaries() {
+        assert_eq!(
+            parse_expression_ast("a ? b : c ? d : e;"),
+            "(? variable: 'a' variable: 'b' (? variable: 'c' variable: 'd' variable: 'e'))"
+        );
+        assert_eq!(
+            parse_expression_ast("a ? b ? 1 : 2 : 3;"),
+            "(? variable: 'a' (? variable: 'b' 1 2) 3)"
+        );
+        assert_eq!(
+            parse_expression_ast("a ? b = 1 : c = 2;"),
+            "(? variable: 'a' (assign 'b' 1) (assign 'c' 2))"
+        );
+        assert_eq!(
+            parse_expression_ast("a ? 1, 2 : 3;"),
+            "(? variable: 'a' (, 1 2) 3)"
+        );
+    }
+
+    #[test]
+    fn test_ternary_as_initializer() {
+        assert_eq!(
+            parse_expression_ast("var x = a > 1 ? \"big\" : \"small\";"),
+            "(? (> variable: 'a' 1) big small)"
+        );
+    }
+
+    #[test]
+    fn test_comma_expression() {
+        let program = parse_source("f(1, (2, 3));");
+
+        assert!(program.diagnostics.is_empty());
+        match &program.statements[0].kind {
+            StmtKind::Expr(Expr {
+                kind: ExprKind::Call { arguments, .. },
+                ..
+            }) => {
+                assert_eq!(arguments.len(), 2);
+                match &arguments[1].kind {
+                    ExprKind::Grouping { expr } => assert!(matches!(
+                        expr.kind,
+                        ExprKind::Binary {
+                            operator: Token {
+                                tag: TokenType::Comma,
+                                ..
+                            },
+                            ..
+                        }
+                    )),
+                    other => panic!("expected a grouping, got {:?}", other),
+                }
+            }
+            other => panic!("expected a call, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_print_separates_values_with_commas() {
+        let program = parse_source("print 1, (2, 3), a = 4;");
+
+        assert!(program.diagnostics.is_empty());
+        match &program.statements[0].kind {
+            StmtKind::Print { values, .. } => {
+                let values: Vec<_> = values.iter().map(print_ast).collect();
+                assert_eq!(values, vec!["1", "(grouping (, 2 3))", "(assign 'a' 4)"]);
+            }
+            other => panic!("expected a print, got {:?}", other),
+        }
+
+        for (source, message) in &[
+            (
+                "print;",
+                "[line 1, col 6] Error at ';': expected a value to print",
+            ),
+            (
+                "print 1, 2,;",
+                "[line 1, col 12] Error at ';': expected a value after ',' in print",
+            ),
+        ] {
+            let program = parse_source(source);
+            let errors: Vec<_> = program
+                .diagnostics
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            assert_eq!(errors, vec![message.to_owned()]);
+        }
+    }
+
+    #[test]
+    fn test_parse_expression() {
+        let parse = |source: &str| {
+            let tokens = Scanner::new(source.to_owned())
+                .scan_tokens()
+                .unwrap()
+                .to_vec();
+            Parser::new(tokens)
+                .parse_expression()
+                .map(|expr| print_ast(&expr))
+                .map_err(|errors| errors.iter().map(ToString::to_string).collect::<Vec<_>>())
+        };
+
+        assert_eq!(parse("a ? 1 : 2"), Ok("(? variable: 'a' 1 2)".to_owned()));
+        assert_eq!(
+            parse("1 + 2 3"),
+            Err(vec![
+                "[line 1, col 7] Error at '3': expected the end of the expression".to_owned()
+            ])
+        );
+        assert_eq!(
+            parse("1;"),
+            Err(vec![
+                "[line 1, col 2] Error at ';': expected the end of the expression".to_owned()
+            ])
+        );
+        assert_eq!(
+            parse(""),
+            Err(vec![
+                "[line 1, col 1] Error at end: expected an expression".to_owned()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_repl_allows_a_trailing_expression_without_semicolon() {
+        let tokens = |source: &str| {
+            let mut scanner = Scanner::new(source.to_owned());
+            scanner.scan_tokens().unwrap().to_vec()
+        };
+
+        let statements = Parser::for_repl(tokens("var a = 1; a")).parse().unwrap();
+        assert_eq!(statements.len(), 2);
+        assert!(matches!(
+            &statements[1].kind,
+            StmtKind::Expr(expr) if matches!(expr.kind, ExprKind::Variable(_))
+        ));
+
+        assert!(Parser::for_repl(tokens("a b")).parse().is_err());
+        assert!(Parser::new(tokens("var a = 1; a")).parse().is_err());
+
+        for source in &["print 1 + 2", "var b = 2", "eprint 1, 2"] {
+            let statements = Parser::for_repl(tokens(source)).parse().unwrap();
+            assert_eq!(statements.len(), 1, "{}", source);
+            assert!(Parser::new(tokens(source)).parse().is_err(), "{}", source);
+        }
+        assert!(Parser::for_repl(tokens("print 1 print 2")).parse().is_err());
+    }
+
+    #[test]
+    fn test_error_position_after_comments() {
+        let program = parse_source(
+            "// setup\n/* a block\n   comment */ var a = 1;\nprint a;\n  var b = (a + 2;",
+        );
+
+        assert_eq!(
+            program.diagnostics,
+            vec![RoxError::Parse {
+                line: 4,
+                column: 16,
+                token: Some(";".to_owned()),
+                message: "expected ')' after expression".to_owned(),
+            }]
+        );
+        assert_eq!(
+            program.diagnostics[0].to_string(),
+            "[line 5, col 17] Error at ';': expected ')' after expression"
+        );
+    }
+
+    #[test]
+    fn test_end_of_input_errors_point_at_the_last_code() {
+        let program = parse_source("var a = 1;\nprint a\n\n// trailing\n\n");
+
+        assert_eq!(
+            program.diagnostics,
+            vec![RoxError::Parse {
+                line: 1,
+                column: 6,
+                token: Some("a".to_owned()),
+                message: "expected a semicolon after 'print a'".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_missing_semicolon_quotes_the_statement() {
+        let program = parse_source("var a = 1\nprint \"x\";\nreturn f(a, 2)\n");
+        let messages: Vec<_> = program
+            .diagnostics
+            .iter()
+            .map(RoxError::to_string)
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "[line 1, col 9] Error at '1': expected a semicolon after 'var a = 1'",
+                "[line 3, col 14] Error at ')': expected a semicolon after 'return f(a, 2)'",
+            ]
+        );
+
+        let program = parse_source("print 1 + 2 + 3 + 4 + 5 + 6 + 7 + 8 + 9 + 10 + 11\n");
+        assert_eq!(
+            program.diagnostics[0].message(),
+            "expected a semicolon after 'print 1 + 2 + 3 + 4 + 5 + 6 + 7 + 8 +...'"
+        );
+    }
+
+    #[test]
+    fn test_parse_program_keeps_good_statements() {
+        let program = parse_source("print 1;\nvar = 2;\nprint 3;\nvar 4;\nprint 5;");
+
+        assert_eq!(program.diagnostics.len(), 2);
+        assert_eq!(program.statements.len(), 5);
+        assert_eq!(
+            program.statements[1].kind,
+            StmtKind::Error {
+                line: 1,
+                message: "expected an identifer after 'var' keyword".to_owned(),
+            }
+        );
+        assert_eq!(
+            program.statements[3].kind,
+            StmtKind::Error {
+                line: 3,
+                message: "expected an identifer after 'var' keyword".to_owned(),
+            }
+        );
+        for (i, n) in [(0, 1.0), (2, 3.0), (4, 5.0)].iter() {
+            assert_eq!(
+                program.statements[*i].kind,
+                StmtKind::Print {
+                    line: *i as u32,
+                    values: vec![ExprKind::Literal(LiteralValue::Number(*n)).into()],
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_program_missing_operand_is_an_error_expression() {
+        let program = parse_source("var a = ;\nprint 1 + ;");
+
+        assert_eq!(program.diagnostics.len(), 2);
+        assert_eq!(
+            program.statements[0].kind,
+            StmtKind::VarDec {
+                name: ident("a", 0, 4),
+                initializer: Some(
+                    ExprKind::Error {
+                        line: 0,
+                        message: "unexpected ';'".to_owned(),
+                    }
+                    .into()
+                ),
+            }
+        );
+        assert!(program.statements[1].error_line().is_some());
+    }
+
+    #[test]
+    fn test_missing_operand_in_grouping_is_one_error() {
+        for source in &["var x = (1 + ;", "(;", "print (-);"] {
+            let program = parse_source(source);
+
+            assert_eq!(program.diagnostics.len(), 1, "{}", source);
+            assert!(program.diagnostics[0].message().starts_with("unexpected"));
+        }
+    }
+
+    #[test]
+    fn test_parse_program_errors_inside_block() {
+        let program = parse_source("{ print 1; var; }");
+
+        assert_eq!(program.diagnostics.len(), 1);
+        match &program.statements[0].kind {
+            StmtKind::Block(inner) => {
+                assert_eq!(inner.len(), 2);
+                assert_eq!(inner[0].error_line(), None);
+                assert!(inner[1].error_line().is_some());
+            }
+            other => panic!("expected a single block, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_for_desugars_into_while() {
+        let program = parse_source("for (var i = 0; i < 5; i = i + 1) print i;");
+        assert!(program.diagnostics.is_empty());
+
+        assert_eq!(program.statements.len(), 1);
+        match &program.statements[0].kind {
+            StmtKind::Block(outer) => match (&outer[0].kind, &outer[1].kind) {
+                (StmtKind::VarDec { name, .. }, StmtKind::While { body, .. }) => {
+                    assert_eq!(name.to_string(), "i");
+                    match &body.kind {
+                        StmtKind::Block(inner) => {
+                            assert!(matches!(inner[0].kind, StmtKind::Print { .. }));
+                            assert!(matches!(inner[1].kind, StmtKind::Expr(_)));
+                        }
+                        other => panic!("expected the body to be a block, got {:?}", other),
+                    }
+                }
+                other => panic!("expected an initializer and a loop, got {:?}", other),
+            },
+            other => panic!("expected a single block, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_increment_needs_a_variable() {
+        let program = parse_source("++a; --(a); ++1;");
+
+        assert!(matches!(
+            &program.statements[0].kind,
+            StmtKind::Expr(Expr {
+                kind: ExprKind::Increment { name, .. },
+                ..
+            }) if name == &ident("a", 0, 2)
+        ));
+        assert_eq!(
+            program.diagnostics,
+            vec![
+                error_at(
+                    &Token::with_column(TokenType::MinusMinus, 0, 5),
+                    "operand of '--' must be a variable"
+                ),
+                error_at(
+                    &Token::with_column(TokenType::PlusPlus, 0, 12),
+                    "operand of '++' must be a variable"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_do_while() {
+        let program = parse_source("do { print 1; } while (false);");
+        assert!(program.diagnostics.is_empty());
+        assert!(matches!(
+            &program.statements[0].kind,
+            StmtKind::DoWhile { body, .. } if matches!(body.kind, StmtKind::Block(_))
+        ));
+
+        let errors = |source| {
+            parse_source(source)
+                .diagnostics
+                .iter()
+                .map(|e| e.message().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            errors("do { print 1; } while (false)"),
+            vec!["expected ';' after the condition of a do-while loop"]
+        );
+        assert_eq!(
+            errors("do print 1; while (false);")[0],
+            "expected '{' after 'do'"
+        );
+        assert_eq!(
+            errors("do { print 1; } (false);"),
+            vec!["expected 'while' after do-while body"]
+        );
+    }
+
+    #[test]
+    fn test_list_and_map_syntax() {
+        let program = parse_source("a[0] = [1, [2]][1];");
+        assert!(program.diagnostics.is_empty());
+        assert!(matches!(
+            &program.statements[0].kind,
+            StmtKind::Expr(Expr {
+                kind: ExprKind::SetIndex { value, .. },
+                ..
+            }) if matches!(value.kind, ExprKind::Index { .. })
+        ));
+
+        let errors = |source| {
+            parse_source(source)
+                .diagnostics
+                .iter()
+                .map(|e| e.message().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            errors("print [1, 2;"),
+            vec!["expected ']' after list elements"]
+        );
+        assert_eq!(errors("print a[0;"), vec!["expected ']' after index"]);
+        assert_eq!(
+            errors("print {\"a\" 1};"),
+            vec!["expected ':' after map key"]
+        );
+        assert_eq!(
+            errors("print {\"a\": 1;"),
+            vec!["expected '}' after map entries"]
+        );
+    }
+
+    #[test]
+    fn test_for_with_empty_clauses() {
+        let program = parse_source("for (;;) print 1;");
+        assert!(program.diagnostics.is_empty());
+
+        assert_eq!(
+            program.statements,
+            vec![StmtKind::While {
+                condition: ExprKind::Literal(LiteralValue::True).into(),
+                body: Box::new(
+                    StmtKind::Print {
+                        line: 0,
+                        values: vec![ExprKind::Literal(LiteralValue::Number(1.0)).into()],
+                    }
+                    .into()
+                ),
+            }
+            .into()]
+        );
+    }
+
+    #[test]
+    fn test_call_expressions() {
+        let program = parse_source("f(1, g(2))();");
+        assert!(program.diagnostics.is_empty());
+
+        match &program.statements[0].kind {
+            StmtKind::Expr(Expr {
+                kind: ExprKind::Call {
+                    callee, arguments, ..
                 },
-                left: Box::new(Expr::Binary {
-                    left: Box::new(Expr::Literal(LiteralValue::Number(4.0))),
-                    operator: Token {
-                        tag: TokenType::Star,
-                        line: 0
-                    },
-                    right: Box::new(Expr::Literal(LiteralValue::Number(24.0))),
-                }),
-            })],)
+                ..
+            }) => {
+                assert!(arguments.is_empty());
+                match &callee.kind {
+                    ExprKind::Call { arguments, .. } => {
+                        assert_eq!(arguments.len(), 2);
+                        assert!(matches!(arguments[1].kind, ExprKind::Call { .. }));
+                    }
+                    other => panic!("expected a nested call, got {:?}", other),
+                }
+            }
+            other => panic!("expected a call statement, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_function_declaration() {
+        let program = parse_source("fun add(a, b) { print a + b; }");
+        assert!(program.diagnostics.is_empty());
+
+        match &program.statements[0].kind {
+            StmtKind::Function { name, params, body } => {
+                assert_eq!(name, &ident("add", 0, 4));
+                assert_eq!(params, &[ident("a", 0, 8), ident("b", 0, 11)]);
+                assert_eq!(body.len(), 1);
+            }
+            other => panic!("expected a function declaration, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_function_expressions() {
+        let (statements, errors) = printed(
+            "var twice = fun (x) { return x + x; };\nfun (x) { print x; }(42);\nfun f() {}",
+        );
+
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            statements,
+            vec![
+                "(var 'twice' (fun (x) (return (+ variable: 'x' variable: 'x'))))",
+                "(expr (call (fun (x) (print variable: 'x')) 42))",
+                "(fun 'f' ())",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_class_declaration() {
+        let (statements, errors) =
+            printed("class Point { init(x) { this.x = x; } sum() { return this.x; } }");
+
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            statements,
+            vec![
+                "(class 'Point' (fun 'init' (x) (expr (set 'x' this variable: 'x'))) \
+                 (fun 'sum' () (return (get 'x' this))))"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_subclass_declaration() {
+        let (statements, errors) = printed("class B < A { m() { return super.m; } }");
+
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            statements,
+            vec!["(class 'B' < 'A' (fun 'm' () (return (super 'm'))))"]
+        );
+
+        let (_, errors) = printed("class B < { }\nclass C < A { m() { super(); } }");
+        assert_eq!(
+            errors,
+            vec![
+                "[line 1, col 11] Error at '{': expected a superclass name after '<'",
+                "[line 2, col 26] Error at '(': expected '.' after 'super'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_import_statement() {
+        let (statements, errors) = printed("import \"lib/util.lox\";\nprint 1;");
+
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(statements, vec!["(import \"lib/util.lox\")", "(print 1)"]);
+
+        let (statements, errors) = printed("import util;\nimport \"a.lox\"\nprint 1;");
+        assert_eq!(
+            errors,
+            vec![
+                "[line 1, col 8] Error at 'util': expected a path string after 'import'",
+                "[line 2, col 8] Error at 'a.lox': expected a semicolon after 'import \"a.lox\"'",
+            ]
+        );
+        assert_eq!(statements.last().unwrap(), "(print 1)");
+    }
+
+    #[test]
+    fn test_class_body_holds_only_methods() {
+        let (statements, errors) = printed("class A { var a; }\nprint 1;");
+
+        assert_eq!(
+            errors,
+            vec!["[line 1, col 11] Error at 'var': expected a method name"]
+        );
+        assert_eq!(statements, vec!["(class 'A')", "(print 1)"]);
+
+        let (statements, errors) = printed("class A { m() {} n( }\nprint 1;");
+
+        assert_eq!(
+            errors,
+            vec!["[line 1, col 21] Error at '}': expected a parameter name"]
         );
+        assert_eq!(statements, vec!["(class 'A' (fun 'm' ()))", "(print 1)"]);
+    }
+
+    #[test]
+    fn test_named_function_expression_is_an_error() {
+        let (statements, errors) = printed("var f = fun g() { return 1; };\nprint f;");
+
+        assert_eq!(
+            errors,
+            vec![
+                "[line 1, col 13] Error at 'g': a function expression can't be named, \
+                 declare 'fun g' on its own instead"
+            ]
+        );
+        assert_eq!(statements.len(), 2);
+        assert_eq!(statements[1], "(print variable: 'f')");
+    }
+
+    #[test]
+    fn test_spans_cover_the_source_they_were_parsed_from() {
+        let source = "print 0;\n  true ? (1) : 2 + nil;";
+        let program = parse_source(source);
+        assert!(program.diagnostics.is_empty());
+        let text = |span: Span| &source[span.start..span.end];
+
+        let statement = &program.statements[1];
+        assert_eq!(text(statement.span), "true ? (1) : 2 + nil;");
+        assert_eq!((statement.span.line, statement.span.column), (1, 2));
+        let ternary = match &statement.kind {
+            StmtKind::Expr(expr) => expr,
+            other => panic!("expected an expression statement, got {:?}", other),
+        };
+        assert_eq!(text(ternary.span), "true ? (1) : 2 + nil");
+        match &ternary.kind {
+            ExprKind::Ternary {
+                condition,
+                true_expr,
+                false_expr,
+            } => {
+                assert_eq!(text(condition.span), "true");
+                assert_eq!(text(true_expr.span), "(1)");
+                assert_eq!(text(false_expr.span), "2 + nil");
+                assert_eq!((false_expr.span.line, false_expr.span.column), (1, 15));
+            }
+            other => panic!("expected a ternary, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_chained_property_access() {
+        let program = parse_source("a.b.c;");
+        assert!(program.diagnostics.is_empty());
+
+        assert_eq!(
+            program.statements,
+            vec![StmtKind::Expr(
+                ExprKind::Get {
+                    object: Box::new(
+                        ExprKind::Get {
+                            object: Box::new(ExprKind::Variable(ident("a", 0, 0)).into()),
+                            name: Token::with_column(TokenType::Identifer("b".into()), 0, 2),
+                        }
+                        .into()
+                    ),
+                    name: Token::with_column(TokenType::Identifer("c".into()), 0, 4),
+                }
+                .into()
+            )
+            .into()]
+        );
+    }
+
+    #[test]
+    fn test_property_access_needs_a_name() {
+        let program = parse_source("a.1;");
+
+        assert_eq!(
+            program.diagnostics,
+            vec![RoxError::Parse {
+                line: 0,
+                column: 2,
+                token: Some("1".to_owned()),
+                message: "expected a property name after '.'".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_leading_dot() {
+        let program = parse_source(".foo;");
+
+        assert_eq!(
+            program.diagnostics,
+            vec![RoxError::Parse {
+                line: 0,
+                column: 0,
+                token: Some(".".to_owned()),
+                message: "expected an expression before '.'".to_owned(),
+            }]
+        );
+    }
+
+    fn parse_with_max_depth(source: &str, max_depth: usize) -> Result<Vec<Stmt>, Vec<RoxError>> {
+        let tokens = Scanner::new(source.to_owned())
+            .scan_tokens()
+            .unwrap()
+            .to_vec();
+        Parser::with_max_depth(tokens, max_depth).parse()
+    }
+
+    fn too_deep(column: u32, token: &str, limit: usize) -> Vec<RoxError> {
+        vec![RoxError::Parse {
+            line: 0,
+            column,
+            token: Some(token.to_owned()),
+            message: format!("expression too deeply nested (limit {})", limit),
+        }]
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        // the statement's own expression is one level, each grouping another
+        assert!(parse_with_max_depth("print ((1));", 3).is_ok());
+        assert_eq!(
+            parse_with_max_depth("print ((1));", 2),
+            Err(too_deep(8, "1", 2))
+        );
+
+        assert!(parse_with_max_depth("print !-1;", 3).is_ok());
+        assert_eq!(
+            parse_with_max_depth("print !-!1;", 3),
+            Err(too_deep(9, "1", 3))
+        );
+
+        assert!(parse_with_max_depth("f(g(1));", 3).is_ok());
+        assert_eq!(
+            parse_with_max_depth("f(g(1));", 2),
+            Err(too_deep(4, "1", 2))
+        );
+
+        // each operator in a chain nests the ones before it
+        assert!(parse_with_max_depth("1 + 2 + 3;", 3).is_ok());
+        assert_eq!(
+            parse_with_max_depth("1 + 2 + 3;", 2),
+            Err(too_deep(6, "+", 2))
+        );
+        assert_eq!(
+            parse_with_max_depth("1, 2, 3;", 2),
+            Err(too_deep(4, ",", 2))
+        );
+        assert!(parse_with_max_depth("f()();", 2).is_ok());
+        assert_eq!(
+            parse_with_max_depth("f()()();", 2),
+            Err(too_deep(5, "(", 2))
+        );
+
+        // statements share the count with expressions
+        assert!(parse_with_max_depth("{ { } }", 2).is_ok());
+        assert_eq!(
+            parse_with_max_depth("{ { { } } }", 2),
+            Err(vec![RoxError::Parse {
+                line: 0,
+                column: 6,
+                token: Some("}".to_owned()),
+                message: "block too deeply nested (limit 2)".to_owned(),
+            }])
+        );
+    }
+
+    #[test]
+    fn test_pathological_nesting_is_an_error() {
+        on_main_thread_stack(pathological_nesting)
+    }
+
+    fn pathological_nesting() {
+        let below = format!(
+            "{}1{};",
+            "(".repeat(DEFAULT_MAX_DEPTH - 1),
+            ")".repeat(DEFAULT_MAX_DEPTH - 1)
+        );
+        assert!(parse_with_max_depth(&below, DEFAULT_MAX_DEPTH).is_ok());
+
+        let above = format!(
+            "{}1{};",
+            "(".repeat(DEFAULT_MAX_DEPTH),
+            ")".repeat(DEFAULT_MAX_DEPTH)
+        );
+        assert_eq!(
+            parse_with_max_depth(&above, DEFAULT_MAX_DEPTH),
+            Err(too_deep(DEFAULT_MAX_DEPTH as u32, "1", DEFAULT_MAX_DEPTH))
+        );
+
+        // only the first error is reported, not one for every statement
+        // around it
+        for source in &[
+            format!("{}1;", "(".repeat(10_000)),
+            format!("{}1;", "!".repeat(10_000)),
+            format!("print 1{};", " + 1".repeat(20_000)),
+            format!("f{};", "()".repeat(20_000)),
+            format!("print {}1;", "fun() { return ".repeat(10_000)),
+        ] {
+            let errors = parse_with_max_depth(source, DEFAULT_MAX_DEPTH).unwrap_err();
+            assert_eq!(errors.len(), 1);
+            assert_eq!(
+                errors[0].message(),
+                "expression too deeply nested (limit 256)"
+            );
+        }
+
+        for (source, what) in &[
+            (format!("{}print 1;", "{".repeat(10_000)), "block"),
+            (format!("{}print 1;", "fun f() {".repeat(10_000)), "block"),
+            // each `if` and `while` nests its condition, which gets there first
+            (
+                format!("{}print 1;", "if (x) ".repeat(10_000)),
+                "expression",
+            ),
+            (
+                format!("{}print 1;", "while (x) ".repeat(10_000)),
+                "expression",
+            ),
+        ] {
+            let errors = parse_with_max_depth(source, DEFAULT_MAX_DEPTH).unwrap_err();
+            assert_eq!(errors.len(), 1);
+            assert_eq!(
+                errors[0].message(),
+                format!("{} too deeply nested (limit 256)", what)
+            );
+        }
+    }
+
+    fn printed(source: &str) -> (Vec<String>, Vec<String>) {
+        let program = parse_source(source);
+        (
+            program.statements.iter().map(print_stmt).collect(),
+            program
+                .diagnostics
+                .iter()
+                .map(RoxError::to_string)
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_else_if_chains_nest_to_the_right() {
+        let (statements, errors) =
+            printed("if (a) print 1; else if (b) print 2; else if (c) print 3; else print 4;");
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            statements,
+            vec![
+                "(if variable: 'a' (print 1) (if variable: 'b' (print 2) \
+                 (if variable: 'c' (print 3) (print 4))))"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_long_else_if_chain() {
+        let mut source = String::new();
+        for i in 0..100 {
+            source += &format!("if (x == {}) print {}; else ", i, i);
+        }
+        source += "print -1;";
+        let (statements, errors) = printed(&source);
+
+        assert!(errors.is_empty());
+        assert_eq!(statements.len(), 1);
+        assert_eq!(statements[0].matches("(if ").count(), 100);
+    }
+
+    #[test]
+    fn test_dangling_else_binds_to_the_innermost_if() {
+        let (statements, _) = printed("if (a) if (b) if (c) print 1; else print 2;");
+        assert_eq!(
+            statements,
+            vec!["(if variable: 'a' (if variable: 'b' (if variable: 'c' (print 1) (print 2))))"]
+        );
+
+        // braces are how an else gets to an outer if
+        let (statements, _) = printed("if (a) { if (b) if (c) print 1; } else print 2;");
+        assert_eq!(
+            statements,
+            vec![
+                "(if variable: 'a' (block (if variable: 'b' (if variable: 'c' (print 1)))) \
+                 (print 2))"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_paren_is_blamed_on_its_own_if() {
+        let (statements, errors) =
+            printed("if (a) print 1;\nelse if (b) print 2;\nelse if c) print 3;\nelse print 4;");
+
+        assert_eq!(
+            errors,
+            vec!["[line 3, col 6] Error at 'if': expected '(' after 'if'"]
+        );
+        assert_eq!(statements.len(), 1);
+        assert_eq!(
+            statements[0],
+            "(if variable: 'a' (print 1) (if variable: 'b' (print 2) \
+             (if variable: 'c' (print 3) (print 4))))"
+        );
+    }
+
+    #[test]
+    fn test_missing_parens_in_several_ifs_of_a_chain() {
+        let (statements, errors) =
+            printed("if a) print 1;\nelse if (b print 2;\nelse if c) print 3;\nelse print 4;");
+
+        assert_eq!(
+            errors,
+            vec![
+                "[line 1, col 1] Error at 'if': expected '(' after 'if'",
+                "[line 2, col 12] Error at 'print': expected ')' after if condition",
+                "[line 3, col 6] Error at 'if': expected '(' after 'if'",
+            ]
+        );
+        assert_eq!(statements.len(), 1);
+    }
+
+    #[test]
+    fn test_error_in_then_branch_keeps_the_else() {
+        let (statements, errors) = printed("if (a) print ; else print 2;\nprint 3;");
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            statements,
+            vec!["(if variable: 'a' (error) (print 2))", "(print 3)"]
+        );
+    }
+
+    #[test]
+    fn test_missing_semicolon_before_else_keeps_the_else() {
+        let (statements, errors) = printed("if (a) print 1 else print 2;");
+
+        assert_eq!(
+            errors,
+            vec!["[line 1, col 14] Error at '1': expected a semicolon after 'print 1'"]
+        );
+        assert_eq!(statements, vec!["(if variable: 'a' (error) (print 2))"]);
+    }
+
+    #[test]
+    fn test_error_in_else_if_branch_keeps_the_rest_of_the_chain() {
+        let (statements, errors) =
+            printed("if (a) print 1; else if (b) print 1 2; else if (c) print 3; else print 4;");
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            statements,
+            vec![
+                "(if variable: 'a' (print 1) (if variable: 'b' (error) \
+                 (if variable: 'c' (print 3) (print 4))))"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_empty_then_branch_keeps_the_else() {
+        let (statements, errors) = printed("if (a) else print 2;");
+
+        assert_eq!(
+            errors,
+            vec!["[line 1, col 8] Error at 'else': unexpected 'else'"]
+        );
+        assert_eq!(statements, vec!["(if variable: 'a' (error) (print 2))"]);
+    }
+
+    #[test]
+    fn test_stray_else_in_a_block_inside_a_branch() {
+        let (statements, errors) = printed("if (a) { print 1; else print 2; }\nprint 3;");
+
+        assert!(!errors.is_empty());
+        assert_eq!(statements.last().unwrap(), "(print 3)");
+    }
+
+    #[test]
+    fn test_stray_else_at_top_level() {
+        let (statements, errors) = printed("else print 1;\nprint 2;");
+
+        assert_eq!(
+            errors,
+            vec!["[line 1, col 1] Error at 'else': unexpected 'else'"]
+        );
+        assert_eq!(statements, vec!["(error)", "(print 1)", "(print 2)"]);
+    }
+
+    #[test]
+    fn test_streamed_tokens_are_let_go_of_after_each_declaration() {
+        let source = "print 1;\n".repeat(100) + "fun f() { return 1 +; }";
+        let tokens = Scanner::new(source).into_iter().map(Result::unwrap);
+        let mut parser = Parser::new_from_iter(tokens);
+        assert_eq!(parser.tokens.len(), 2);
+
+        let program = parser.parse_program();
+        assert_eq!(program.statements.len(), 101);
+        assert_eq!(program.diagnostics.len(), 1);
+        // everything but the last token of the function and the EOF
+        assert_eq!(parser.tokens.len(), 2);
+        assert_eq!(parser.offset, 300 + 9);
     }
 }
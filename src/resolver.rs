@@ -0,0 +1,745 @@
+use crate::error::{RoxError, Warning};
+use crate::expr::{Expr, ExprKind};
+use crate::statement::{Stmt, StmtKind};
+use crate::symbol::Symbol;
+use crate::token::{Binding, Name};
+use std::collections::HashMap;
+use std::mem;
+use std::sync::Arc;
+
+/// Everything the resolver found. Only errors stop a program from running.
+#[derive(Debug, Default, PartialEq)]
+pub struct Resolution {
+    pub errors: Vec<RoxError>,
+    pub warnings: Vec<Warning>,
+}
+
+/// Checks a parsed program for mistakes that can be found without running
+/// it. Errors are in source order, as are warnings.
+///
+/// Like the interpreter, only local scopes are tracked: globals can be
+/// referenced before they are declared and redeclared freely, so
+/// `var a = a;` at the top level is left for the interpreter to report.
+/// Inside a block the same declaration is always an error, even when an
+/// outer `a` exists, because the new `a` is in scope from the moment it is
+/// declared.
+///
+/// Every variable used is bound to the declaration it refers to where it's
+/// written, so a closure keeps seeing the same variable even after a
+/// declaration of the same name further in. A function body already shared
+/// with a function value, because the program has run, is left as it is.
+pub fn resolve(statements: &mut [Stmt]) -> Resolution {
+    let mut resolver = Resolver::default();
+    resolver.resolve_stmts(statements);
+
+    let mut resolution = resolver.resolution;
+    resolution
+        .warnings
+        .sort_by_key(|warning| (warning.line, warning.column));
+    resolution
+}
+
+struct Local {
+    declaration: Name,
+    /// `false` between the declaration and the end of its initializer.
+    initialized: bool,
+    read: bool,
+    /// Declared with `const`, so never assigned after its initializer.
+    constant: bool,
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+enum ClassKind {
+    #[default]
+    None,
+    Class,
+    Subclass,
+}
+
+#[derive(Default)]
+struct Resolver {
+    /// One map per local scope, innermost last, mirroring the environments
+    /// the interpreter will create.
+    scopes: Vec<HashMap<Symbol, Local>>,
+    /// The innermost class around the code being resolved, so `this` and
+    /// `super` where they mean nothing can be reported.
+    class: ClassKind,
+    /// Whether the innermost function is a class's `init`, which can't
+    /// return a value.
+    in_initializer: bool,
+    resolution: Resolution,
+}
+
+impl Resolver {
+    fn resolve_stmts(&mut self, statements: &mut [Stmt]) {
+        for statement in statements {
+            self.resolve_stmt(statement);
+        }
+    }
+
+    fn resolve_stmt(&mut self, statement: &mut Stmt) {
+        let span = statement.span;
+        match &mut statement.kind {
+            StmtKind::Expr(e) | StmtKind::Assert { condition: e, .. } => self.resolve_expr(e),
+            StmtKind::Print { values, .. } | StmtKind::Eprint { values, .. } => {
+                values.iter_mut().for_each(|e| self.resolve_expr(e))
+            }
+            StmtKind::VarDec { name, initializer } => {
+                self.declare(name);
+                if let Some(initializer) = initializer {
+                    self.resolve_expr(initializer);
+                }
+                self.define(name);
+            }
+            StmtKind::Const { name, initializer } => {
+                self.declare(name);
+                self.resolve_expr(initializer);
+                self.define(name);
+                if let Some(local) = self.scopes.last_mut().and_then(|s| s.get_mut(&name.symbol)) {
+                    local.constant = true;
+                }
+            }
+            StmtKind::Block(statements) => {
+                self.begin_scope();
+                self.resolve_stmts(statements);
+                self.end_scope();
+            }
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_stmt(else_branch);
+                }
+            }
+            StmtKind::While { condition, body } => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(body);
+            }
+            StmtKind::DoWhile { body, condition } => {
+                self.resolve_stmt(body);
+                self.resolve_expr(condition);
+            }
+            StmtKind::Function { name, params, body } => {
+                // defined straight away so the function can call itself
+                self.declare_used(name);
+                self.resolve_function(params, body, false);
+            }
+            StmtKind::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                self.declare_used(name);
+                let enclosing = mem::replace(&mut self.class, ClassKind::Class);
+                if let Some(superclass) = superclass {
+                    if superclass.symbol == name.symbol {
+                        self.resolution.errors.push(RoxError::Resolve {
+                            line: superclass.line,
+                            column: superclass.column,
+                            name: superclass.to_string(),
+                            message: format!("class '{}' can't inherit from itself", name),
+                        });
+                    }
+                    self.read(superclass);
+                    self.bind(superclass);
+
+                    // the scope `super` is bound in, around every method
+                    self.class = ClassKind::Subclass;
+                    self.begin_scope();
+                    self.declare_used(&Name {
+                        symbol: "super".into(),
+                        line: superclass.line,
+                        column: superclass.column,
+                        binding: Binding::Dynamic,
+                    });
+                }
+                for method in methods {
+                    if let StmtKind::Function { name, params, body } = &mut method.kind {
+                        // the scope `this` is bound in, between the method
+                        // and the class
+                        self.begin_scope();
+                        self.declare_used(&Name {
+                            symbol: "this".into(),
+                            line: name.line,
+                            column: name.column,
+                            binding: Binding::Dynamic,
+                        });
+                        self.resolve_function(params, body, &*name.symbol == "init");
+                        self.end_scope();
+                    }
+                }
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+                self.class = enclosing;
+            }
+            StmtKind::Return { value, .. } => {
+                if let Some(value) = value {
+                    if self.in_initializer {
+                        self.resolution.errors.push(RoxError::Resolve {
+                            line: span.line,
+                            column: span.column,
+                            name: "return".to_owned(),
+                            message: "can't return a value from 'init'".to_owned(),
+                        });
+                    }
+                    self.resolve_expr(value);
+                }
+            }
+            StmtKind::Import { keyword, .. } => {
+                // the file runs in the global scope wherever it's imported,
+                // which a block or function around it would belie
+                if !self.scopes.is_empty() {
+                    self.resolution.errors.push(RoxError::Resolve {
+                        line: keyword.line,
+                        column: keyword.column,
+                        name: keyword.tag.to_string(),
+                        message: "can only import at the top level of a script".to_owned(),
+                    });
+                }
+            }
+            StmtKind::Error { .. } => {}
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &mut Expr) {
+        match &mut expr.kind {
+            ExprKind::Variable(name) => {
+                self.read(name);
+                self.bind(name);
+            }
+            ExprKind::Increment { name, .. } => {
+                self.read(name);
+                self.check_assignable(name);
+                self.bind(name);
+            }
+            // asking is allowed anywhere, even in the variable's own initializer
+            ExprKind::Defined(name) => {
+                if let Some(local) = self.lookup(name) {
+                    local.read = true;
+                }
+            }
+            ExprKind::Assign { name, value } => {
+                self.resolve_expr(value);
+                self.check_assignable(name);
+                self.bind(name);
+            }
+            ExprKind::Binary { left, right, .. } => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            ExprKind::Grouping { expr } => self.resolve_expr(expr),
+            ExprKind::Unary { operand, .. } => self.resolve_expr(operand),
+            ExprKind::Ternary {
+                condition,
+                true_expr,
+                false_expr,
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_expr(true_expr);
+                self.resolve_expr(false_expr);
+            }
+            ExprKind::Call {
+                callee, arguments, ..
+            } => {
+                self.resolve_expr(callee);
+                for argument in arguments {
+                    self.resolve_expr(argument);
+                }
+            }
+            ExprKind::Get { object, .. } => self.resolve_expr(object),
+            ExprKind::Set { object, value, .. } => {
+                self.resolve_expr(object);
+                self.resolve_expr(value);
+            }
+            ExprKind::This(name) => {
+                if self.class == ClassKind::None {
+                    self.resolution.errors.push(RoxError::Resolve {
+                        line: name.line,
+                        column: name.column,
+                        name: name.to_string(),
+                        message: "can't use 'this' outside of a class".to_owned(),
+                    });
+                }
+                self.bind(name);
+            }
+            ExprKind::Super { keyword, .. } => {
+                let message = match self.class {
+                    ClassKind::None => Some("can't use 'super' outside of a class"),
+                    ClassKind::Class => Some("can't use 'super' in a class with no superclass"),
+                    ClassKind::Subclass => None,
+                };
+                if let Some(message) = message {
+                    self.resolution.errors.push(RoxError::Resolve {
+                        line: keyword.line,
+                        column: keyword.column,
+                        name: keyword.to_string(),
+                        message: message.to_owned(),
+                    });
+                }
+                self.bind(keyword);
+            }
+            ExprKind::ArrayLiteral(elements) | ExprKind::Interpolation(elements) => {
+                for element in elements {
+                    self.resolve_expr(element);
+                }
+            }
+            ExprKind::MapLiteral { entries, .. } => {
+                for (key, value) in entries {
+                    self.resolve_expr(key);
+                    self.resolve_expr(value);
+                }
+            }
+            ExprKind::Index { object, index, .. } => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            }
+            ExprKind::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+                self.resolve_expr(value);
+            }
+            ExprKind::Lambda { params, body } => self.resolve_function(params, body, false),
+            ExprKind::Literal(_) | ExprKind::Error { .. } => {}
+        }
+    }
+
+    fn resolve_function(
+        &mut self,
+        params: &[Name],
+        body: &mut Arc<Vec<Stmt>>,
+        is_initializer: bool,
+    ) {
+        let enclosing = mem::replace(&mut self.in_initializer, is_initializer);
+        self.begin_scope();
+        for param in params {
+            self.declare_used(param);
+        }
+        if let Some(body) = Arc::get_mut(body) {
+            self.resolve_stmts(body);
+        }
+        self.end_scope();
+        self.in_initializer = enclosing;
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        let scope = self.scopes.pop().expect("ended a scope that wasn't begun");
+        for (name, local) in scope {
+            if !local.read {
+                self.resolution.warnings.push(Warning {
+                    line: local.declaration.line,
+                    column: local.declaration.column,
+                    message: format!("variable '{}' is declared but never read", name),
+                });
+            }
+        }
+    }
+
+    fn declare(&mut self, name: &Name) {
+        let scope = match self.scopes.last_mut() {
+            Some(scope) => scope,
+            None => return,
+        };
+
+        let key = &name.symbol;
+        if scope.contains_key(key) {
+            self.resolution.errors.push(RoxError::Resolve {
+                line: name.line,
+                column: name.column,
+                name: key.to_string(),
+                message: format!("variable '{}' already declared in this scope", key),
+            });
+            return;
+        }
+
+        scope.insert(
+            Arc::clone(key),
+            Local {
+                declaration: name.clone(),
+                initialized: false,
+                read: false,
+                constant: false,
+            },
+        );
+    }
+
+    fn define(&mut self, name: &Name) {
+        if let Some(local) = self
+            .scopes
+            .last_mut()
+            .and_then(|scope| scope.get_mut(&name.symbol))
+        {
+            local.initialized = true;
+        }
+    }
+
+    /// Declares a function or parameter. Neither is warned about when
+    /// unused: a parameter can be needed to match how a function is called.
+    fn declare_used(&mut self, name: &Name) {
+        self.declare(name);
+        self.define(name);
+        self.read(name);
+    }
+
+    /// The innermost local `name` refers to, or `None` for a global.
+    fn lookup(&mut self, name: &Name) -> Option<&mut Local> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(&name.symbol))
+    }
+
+    /// Records which scope `name` refers to, counting out from the
+    /// innermost one.
+    fn bind(&self, name: &mut Name) {
+        name.binding = self
+            .scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(&name.symbol))
+            .map_or(Binding::Global, Binding::Local);
+    }
+
+    /// Reports an assignment to a local constant. Global constants are
+    /// left for the interpreter, as globals aren't tracked.
+    fn check_assignable(&mut self, name: &Name) {
+        if matches!(self.lookup(name), Some(local) if local.constant) {
+            self.resolution.errors.push(RoxError::Resolve {
+                line: name.line,
+                column: name.column,
+                name: name.to_string(),
+                message: format!("cannot assign to constant '{}'", name),
+            });
+        }
+    }
+
+    fn read(&mut self, name: &Name) {
+        if let Some(local) = self.lookup(name) {
+            local.read = true;
+            if !local.initialized {
+                self.resolution.errors.push(RoxError::Resolve {
+                    line: name.line,
+                    column: name.column,
+                    name: name.to_string(),
+                    message: "Cannot read local variable in its own initializer".to_owned(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{resolve, Resolution};
+    use crate::error::{RoxError, Warning};
+    use crate::expr::ExprKind;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::statement::{Stmt, StmtKind};
+    use crate::token::Binding;
+
+    fn resolve_source(source: &str) -> Resolution {
+        let tokens = Scanner::new(source.to_owned())
+            .scan_tokens()
+            .unwrap()
+            .to_vec();
+        resolve(&mut Parser::new(tokens).parse().unwrap())
+    }
+
+    fn resolve_error(line: u32, column: u32, name: &str, message: &str) -> RoxError {
+        RoxError::Resolve {
+            line,
+            column,
+            name: name.to_owned(),
+            message: message.to_owned(),
+        }
+    }
+
+    fn own_initializer(line: u32, column: u32) -> RoxError {
+        resolve_error(
+            line,
+            column,
+            "a",
+            "Cannot read local variable in its own initializer",
+        )
+    }
+
+    fn never_read(line: u32, column: u32, name: &str) -> Warning {
+        Warning {
+            line,
+            column,
+            message: format!("variable '{}' is declared but never read", name),
+        }
+    }
+
+    #[test]
+    fn test_local_read_in_own_initializer() {
+        assert_eq!(
+            resolve_source("{\n  var a = a;\n}").errors,
+            vec![own_initializer(1, 10)]
+        );
+        assert_eq!(
+            resolve_source("fun f() { var a = -a; }").errors,
+            vec![own_initializer(0, 19)]
+        );
+    }
+
+    // The inner `a` shadows the outer one as soon as it is declared, so this
+    // is an error rather than a read of the outer `a`, as in the book.
+    #[test]
+    fn test_outer_variable_is_shadowed_in_initializer() {
+        assert_eq!(
+            resolve_source("var a = 1; { var a = a + 1; }").errors,
+            vec![own_initializer(0, 21)]
+        );
+    }
+
+    #[test]
+    fn test_globals_are_not_checked() {
+        assert_eq!(
+            resolve_source("var a = a; var a = 2; var unused;"),
+            Resolution::default()
+        );
+    }
+
+    #[test]
+    fn test_valid_locals() {
+        let source = "
+            var a = 1;
+            {
+                var b = a + 1;
+                var a = b;
+                { var c = a; print c; }
+            }
+            fun f(a) { var b = a; return b; }
+            { var a = 1; fun g() { return a; } }
+        ";
+        assert_eq!(resolve_source(source), Resolution::default());
+    }
+
+    #[test]
+    fn test_redeclaration_in_same_scope() {
+        let message = "variable 'a' already declared in this scope";
+        assert_eq!(
+            resolve_source("{ var a = 1; var a = 2; print a; }").errors,
+            vec![resolve_error(0, 17, "a", message)]
+        );
+        assert_eq!(
+            resolve_source("fun f(a) { var a; print a; }").errors,
+            vec![resolve_error(0, 15, "a", message)]
+        );
+        assert_eq!(
+            resolve_source("fun f(a, a) {}").errors,
+            vec![resolve_error(0, 9, "a", message)]
+        );
+    }
+
+    #[test]
+    fn test_assignments_to_local_constants() {
+        let source = "
+{
+  const a = 1;
+  a = 2;
+  {
+    ++a;
+    fun f() { a = 3; }
+    f();
+  }
+  { var a = 4; a = 5; const b = a; print b; }
+  { const a = 6; print a; }
+}";
+        let message = "cannot assign to constant 'a'";
+        assert_eq!(
+            resolve_source(source),
+            Resolution {
+                errors: vec![
+                    resolve_error(3, 2, "a", message),
+                    resolve_error(5, 6, "a", message),
+                    resolve_error(6, 14, "a", message),
+                ],
+                warnings: vec![],
+            }
+        );
+
+        // globals are left for the interpreter
+        assert_eq!(resolve_source("const a = 1; a = 2;"), Resolution::default());
+    }
+
+    #[test]
+    fn test_unused_locals_are_warned_about() {
+        let source = "
+{
+  var b = 1;
+  var a;
+  b = 2;
+  { var c = 3; print c; }
+}";
+        let resolution = resolve_source(source);
+
+        assert!(resolution.errors.is_empty());
+        assert_eq!(
+            resolution.warnings,
+            vec![never_read(2, 6, "b"), never_read(3, 6, "a")]
+        );
+    }
+
+    #[test]
+    fn test_unused_parameters_and_functions_are_not_warned_about() {
+        assert_eq!(
+            resolve_source("{ fun f(a, b) { return 1; } }"),
+            Resolution::default()
+        );
+    }
+
+    fn bindings(source: &str) -> Vec<(String, Binding)> {
+        let tokens = Scanner::new(source.to_owned())
+            .scan_tokens()
+            .unwrap()
+            .to_vec();
+        let mut statements = Parser::new(tokens).parse().unwrap();
+        resolve(&mut statements);
+
+        let mut found = Vec::new();
+        collect_bindings(&statements, &mut found);
+        found
+    }
+
+    fn collect_bindings(statements: &[Stmt], found: &mut Vec<(String, Binding)>) {
+        for statement in statements {
+            match &statement.kind {
+                StmtKind::Expr(expr)
+                | StmtKind::Return {
+                    value: Some(expr), ..
+                } => {
+                    if let ExprKind::Variable(name)
+                    | ExprKind::This(name)
+                    | ExprKind::Assign { name, .. } = &expr.kind
+                    {
+                        found.push((name.to_string(), name.binding));
+                    }
+                }
+                StmtKind::Block(statements) => collect_bindings(statements, found),
+                StmtKind::Function { body, .. } => collect_bindings(body, found),
+                StmtKind::Class { methods, .. } => collect_bindings(methods, found),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_variables_are_bound_to_their_scope() {
+        let source = "
+            var a;
+            a;
+            {
+                var b;
+                b = 1;
+                a;
+                fun f(c) {
+                    c;
+                    b;
+                    a;
+                    return d;
+                }
+            }
+        ";
+        assert_eq!(
+            bindings(source),
+            vec![
+                ("a".to_owned(), Binding::Global),
+                ("b".to_owned(), Binding::Local(0)),
+                ("a".to_owned(), Binding::Global),
+                ("c".to_owned(), Binding::Local(0)),
+                ("b".to_owned(), Binding::Local(1)),
+                ("a".to_owned(), Binding::Global),
+                ("d".to_owned(), Binding::Global),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_this_is_bound_to_the_scope_around_the_method() {
+        let source = "class A { m(a) { this; fun f() { return this; } } }";
+        assert_eq!(
+            bindings(source),
+            vec![
+                ("this".to_owned(), Binding::Local(1)),
+                ("this".to_owned(), Binding::Local(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_this_outside_a_class_and_values_returned_from_init() {
+        assert_eq!(
+            resolve_source("print this;\nfun f() { return this; }").errors,
+            vec![
+                resolve_error(0, 6, "this", "can't use 'this' outside of a class"),
+                resolve_error(1, 17, "this", "can't use 'this' outside of a class"),
+            ]
+        );
+        assert_eq!(
+            resolve_source("class A { init() { fun f() { return 1; } return 2; } }").errors,
+            vec![resolve_error(
+                0,
+                41,
+                "return",
+                "can't return a value from 'init'"
+            )]
+        );
+        assert_eq!(
+            resolve_source("class A { init() { return; } other() { return 1; } }"),
+            Resolution::default()
+        );
+    }
+
+    #[test]
+    fn test_imports_only_at_the_top_level() {
+        assert_eq!(
+            resolve_source("import \"a.lox\";\nif (true) import \"b.lox\";"),
+            Resolution::default()
+        );
+        assert_eq!(
+            resolve_source("{ import \"a.lox\"; }\nfun f() { import \"b.lox\"; }").errors,
+            vec![
+                resolve_error(
+                    0,
+                    2,
+                    "import",
+                    "can only import at the top level of a script"
+                ),
+                resolve_error(
+                    1,
+                    10,
+                    "import",
+                    "can only import at the top level of a script"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_a_later_declaration_does_not_capture_earlier_uses() {
+        let source = "{ var a; { fun f() { return a; } var a; a; } }";
+        assert_eq!(
+            bindings(source),
+            vec![
+                ("a".to_owned(), Binding::Local(2)),
+                ("a".to_owned(), Binding::Local(0)),
+            ]
+        );
+    }
+}
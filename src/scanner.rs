@@ -1,48 +1,157 @@
-use crate::error::report;
+use crate::error::RoxError;
+use crate::span::Span;
+use crate::symbol::Interner;
 use crate::token::{Token, TokenType};
-use lazy_static::lazy_static;
-use std::collections::HashMap;
-
-lazy_static! {
-    static ref KEYWORDS: HashMap<String, TokenType> = {
-        let mut m = HashMap::new();
-        m.insert("for".to_owned(), TokenType::For);
-        m.insert("fun".to_owned(), TokenType::Fun);
-        m.insert("if".to_owned(), TokenType::If);
-        m.insert("or".to_owned(), TokenType::Or);
-        m.insert("nil".to_owned(), TokenType::Nil);
-        m.insert("print".to_owned(), TokenType::Print);
-        m.insert("return".to_owned(), TokenType::Return);
-        m.insert("super".to_owned(), TokenType::Super);
-        m.insert("this".to_owned(), TokenType::This);
-        m.insert("true".to_owned(), TokenType::True);
-        m.insert("false".to_owned(), TokenType::False);
-        m.insert("var".to_owned(), TokenType::Var);
-        m.insert("while".to_owned(), TokenType::While);
-        m.insert("if".to_owned(), TokenType::If);
-        m
+
+/// Every word the scanner reads as a keyword rather than an identifier.
+pub const KEYWORDS: &[&str] = &[
+    "assert", "class", "const", "defined", "do", "else", "eprint", "false", "for", "fun", "if",
+    "import", "nil", "or", "print", "return", "super", "this", "true", "var", "while",
+];
+
+fn keyword(text: &str) -> Option<TokenType> {
+    let tag = match text {
+        "assert" => TokenType::Assert,
+        "class" => TokenType::Class,
+        "const" => TokenType::Const,
+        "defined" => TokenType::Defined,
+        "do" => TokenType::Do,
+        "else" => TokenType::Else,
+        "eprint" => TokenType::Eprint,
+        "for" => TokenType::For,
+        "fun" => TokenType::Fun,
+        "if" => TokenType::If,
+        "import" => TokenType::Import,
+        "or" => TokenType::Or,
+        "nil" => TokenType::Nil,
+        "print" => TokenType::Print,
+        "return" => TokenType::Return,
+        "super" => TokenType::Super,
+        "this" => TokenType::This,
+        "true" => TokenType::True,
+        "false" => TokenType::False,
+        "var" => TokenType::Var,
+        "while" => TokenType::While,
+        _ => return None,
     };
+    Some(tag)
+}
+
+/// Resource limits that keep pathological input from exhausting memory.
+/// Exceeding any of them aborts the scan, unlike other scan errors which are
+/// collected so that every one of them can be reported in a single pass.
+#[derive(Debug, Clone)]
+pub struct ScannerOptions {
+    /// Maximum size of the whole source, in bytes.
+    pub max_source_size: usize,
+    /// Maximum length of a single string literal, in characters, not
+    /// counting its quotes.
+    pub max_string_length: usize,
+    /// Maximum number of tokens, not counting the final EOF.
+    pub max_tokens: usize,
 }
 
+impl Default for ScannerOptions {
+    fn default() -> Self {
+        Self {
+            max_source_size: 64 * 1024 * 1024,
+            max_string_length: 1024 * 1024,
+            max_tokens: 4 * 1024 * 1024,
+        }
+    }
+}
+
+impl ScannerOptions {
+    /// Fails the way scanning `source` would if it's over `max_source_size`,
+    /// so a caller can check before copying it into a `Scanner`.
+    pub fn check_source_size(&self, source: &str) -> Result<(), RoxError> {
+        if source.len() <= self.max_source_size {
+            return Ok(());
+        }
+        Err(RoxError::Scan {
+            line: 0,
+            column: 0,
+            lexeme: String::new(),
+            message: format!(
+                "input too large: source is {} bytes, the limit is {} bytes",
+                source.len(),
+                self.max_source_size
+            ),
+        })
+    }
+}
+
+/// `start` and `current` are byte offsets into `source`, always on a
+/// character boundary, so the text between them can be sliced out as is.
 pub struct Scanner {
     source: String,
-    chars: Vec<char>,
     tokens: Vec<Token>,
     start: usize,
+    /// Where the token starting at `start` begins. Tokens can span lines, so
+    /// this is captured up front rather than worked out when they are added.
+    start_line: u32,
+    start_column: u32,
     current: usize,
     line: u32,
+    /// Characters, not bytes, between the start of the line and `current`.
+    column: u32,
+    options: ScannerOptions,
+    errors: Vec<RoxError>,
+    interner: Interner,
+    /// Whether a comment was skipped, for tools that rewrite the source and
+    /// would lose it.
+    saw_comment: bool,
+    /// Every `//` comment, from the slashes to the end of its line.
+    line_comments: Vec<Span>,
+    /// Whether the source ended inside a string, block comment or
+    /// interpolation.
+    unterminated: bool,
+    /// Where the last token ended. EOF goes there rather than at the very
+    /// end of the source, so errors about a missing `;` or `}` point at the
+    /// last line with code on it, not at trailing blank lines or comments.
+    end_line: u32,
+    end_column: u32,
+    end: usize,
+    /// Whether the scan is inside the `${...}` of a string, where strings
+    /// can't have interpolations of their own.
+    interpolating: bool,
+    /// Set once `scan_tokens` has run, so calling it again returns the same
+    /// result instead of scanning on from the end.
+    scanned: bool,
+    /// Set once the EOF is added or a limit stops the scan.
+    finished: bool,
+    /// Tokens added so far, including any a `TokenStream` has let go of.
+    token_count: usize,
 }
 
 impl Scanner {
     pub fn new(source: String) -> Self {
-        let chars = source.chars().collect();
+        Self::with_options(source, ScannerOptions::default())
+    }
+
+    pub fn with_options(source: String, options: ScannerOptions) -> Self {
         Self {
             source,
-            chars,
             tokens: Vec::new(),
             start: 0,
+            start_line: 0,
+            start_column: 0,
             current: 0,
             line: 0,
+            column: 0,
+            options,
+            errors: Vec::new(),
+            interner: Interner::default(),
+            saw_comment: false,
+            line_comments: Vec::new(),
+            unterminated: false,
+            end_line: 0,
+            end_column: 0,
+            end: 0,
+            scanned: false,
+            finished: false,
+            token_count: 0,
+            interpolating: false,
         }
     }
 
@@ -50,54 +159,149 @@ impl Scanner {
         self.current >= self.source.len()
     }
 
-    pub fn advance(&mut self) -> char {
-        self.current += 1;
-        self.chars[self.current - 1]
+    /// Consumes the next character, or returns `None` at the end of the
+    /// source, where there's nothing to consume.
+    pub fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.current += c.len_utf8();
+        self.column += 1;
+        Some(c)
     }
 
     pub fn match_char(&mut self, expected: char) -> bool {
-        if self.is_at_end() {
+        if self.peek() != Some(expected) {
             return false;
         }
 
-        if self.chars[self.current] != expected {
-            return false;
-        }
-
-        self.current += 1;
+        self.advance();
         true
     }
 
     pub fn peek(&self) -> Option<char> {
-        self.chars.get(self.current).cloned()
+        self.source[self.current..].chars().next()
     }
 
     pub fn peek_next(&self) -> Option<char> {
-        self.chars.get(self.current + 1).cloned()
+        self.source[self.current..].chars().nth(1)
+    }
+
+    /// The tokens scanned so far. After `scan_tokens` fails this still holds
+    /// everything that did scan, for tools that want to show both.
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// Whether the source scanned so far had any comments in it.
+    pub fn has_comments(&self) -> bool {
+        self.saw_comment
+    }
+
+    /// Whether the source ended inside a string, block comment or
+    /// interpolation, so more input could finish it.
+    pub fn is_unterminated(&self) -> bool {
+        self.unterminated
+    }
+
+    /// Where the `//` comments scanned so far are, in source order, for
+    /// tools that read what they say.
+    pub fn line_comments(&self) -> &[Span] {
+        &self.line_comments
     }
 
-    pub fn scan_tokens(&mut self) -> &[Token] {
-        while !self.is_at_end() {
-            self.start = self.current;
-            self.scan_token();
+    /// Scans the whole source. Unexpected characters and unterminated
+    /// strings don't stop the scan, so on failure every error found is
+    /// returned, in source order. The source is only scanned the first
+    /// time; later calls return the same result again.
+    pub fn scan_tokens(&mut self) -> Result<&[Token], Vec<RoxError>> {
+        if !self.scanned {
+            self.scanned = true;
+            self.scan_all();
         }
 
-        self.add_token(TokenType::EOF);
+        if self.errors.is_empty() {
+            Ok(&self.tokens)
+        } else {
+            Err(self.errors.clone())
+        }
+    }
 
-        &self.tokens
+    /// Scans until the end of the source, or until a limit is exceeded,
+    /// which stops the scan without an EOF.
+    fn scan_all(&mut self) {
+        while self.scan_step() {}
+    }
+
+    /// Scans the next token, along with any whitespace, comments and errors
+    /// before it, and returns whether there's more to scan.
+    fn scan_step(&mut self) -> bool {
+        if self.finished {
+            return false;
+        }
+
+        if self.current == 0 {
+            if let Err(error) = self.options.check_source_size(&self.source) {
+                return self.stop(error);
+            }
+        }
+
+        if self.is_at_end() {
+            let mut eof = Token::with_column(TokenType::EOF, self.end_line, self.end_column);
+            eof.start = self.end;
+            eof.end = self.end;
+            self.tokens.push(eof);
+            self.finished = true;
+            return false;
+        }
+
+        self.begin_token();
+        if let Err(error) = self.scan_token() {
+            return self.stop(error);
+        }
+
+        if self.token_count > self.options.max_tokens {
+            let error = self.error(format!(
+                "input too large: more than {} tokens",
+                self.options.max_tokens
+            ));
+            return self.stop(error);
+        }
+        true
+    }
+
+    fn stop(&mut self, error: RoxError) -> bool {
+        self.errors.push(error);
+        self.finished = true;
+        false
     }
 
-    pub fn scan_token(&mut self) {
-        let c = self.advance();
+    pub fn scan_token(&mut self) -> Result<(), RoxError> {
+        let c = match self.advance() {
+            Some(c) => c,
+            None => return Ok(()),
+        };
         match c {
             '(' => self.add_token(TokenType::LeftParen),
             ')' => self.add_token(TokenType::RightParen),
             '{' => self.add_token(TokenType::LeftBrace),
+            '[' => self.add_token(TokenType::LeftBracket),
+            ']' => self.add_token(TokenType::RightBracket),
             '}' => self.add_token(TokenType::RightBrace),
             ',' => self.add_token(TokenType::Comma),
-            '.' => self.add_token(TokenType::Dot),
-            '-' => self.add_token(TokenType::Minus),
-            '+' => self.add_token(TokenType::Plus),
+            '.' => self.handle_dot(),
+            '-' => {
+                if self.match_char('-') {
+                    self.add_token(TokenType::MinusMinus)
+                } else {
+                    self.add_token(TokenType::Minus)
+                }
+            }
+            '+' => {
+                if self.match_char('+') {
+                    self.add_token(TokenType::PlusPlus)
+                } else {
+                    self.add_token(TokenType::Plus)
+                }
+            }
             ';' => self.add_token(TokenType::Semicolon),
             '*' => self.add_token(TokenType::Star),
             '?' => self.add_token(TokenType::QuestionMark),
@@ -132,6 +336,7 @@ impl Scanner {
             }
             '/' => {
                 if self.match_char('/') {
+                    self.saw_comment = true;
                     while let Some(c) = self.peek() {
                         if c == '\n' {
                             break;
@@ -139,86 +344,338 @@ impl Scanner {
 
                         let _ = self.advance();
                     }
+                    self.line_comments.push(Span {
+                        line: self.start_line,
+                        column: self.start_column,
+                        start: self.start,
+                        end: self.current,
+                    });
                 } else if self.match_char('*') {
+                    self.saw_comment = true;
                     self.handle_block_comment()
                 } else {
                     self.add_token(TokenType::Slash)
                 }
             }
-            '\n' => self.line += 1,
+            '\n' => self.new_line(),
             '\t' | '\r' | ' ' => (), // Ignore whitespace
-            '"' => self.handle_string(),
-            d if d.is_digit(10) => self.handle_number(),
+            '"' => self.handle_string()?,
+            d if d.is_ascii_digit() => self.handle_number(),
             a if is_alpha(a) => self.handle_identifier(),
-            _ => report(self.line, &format!("Unexpected character: {}", c)),
+            _ => {
+                let error = self.error(format!("Unexpected character: {}", c));
+                self.errors.push(error);
+            }
         };
+
+        Ok(())
     }
 
     pub fn add_token(&mut self, t: TokenType) {
-        self.tokens.push(Token::new(t, self.line));
+        let span = Span {
+            line: self.start_line,
+            column: self.start_column,
+            start: self.start,
+            end: self.current,
+        };
+        self.push_token(t, span);
+    }
+
+    fn push_token(&mut self, t: TokenType, span: Span) {
+        let mut token = Token::with_column(t, span.line, span.column);
+        token.start = span.start;
+        token.end = span.end;
+        self.tokens.push(token);
+        self.token_count += 1;
+
+        self.end_line = self.line;
+        self.end_column = self.column;
+        self.end = self.current;
+    }
+
+    /// Builds an error pointing at the lexeme currently being scanned.
+    fn error(&self, message: String) -> RoxError {
+        let lexeme = self.source[self.start..self.current].to_owned();
+
+        RoxError::Scan {
+            line: self.start_line,
+            column: self.start_column,
+            lexeme,
+            message,
+        }
+    }
+
+    /// An empty span at `current`.
+    fn here(&self) -> Span {
+        Span {
+            line: self.line,
+            column: self.column,
+            start: self.current,
+            end: self.current,
+        }
+    }
+
+    fn begin_token(&mut self) {
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.column;
     }
 
+    /// Called once a newline has been consumed.
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.column = 0;
+    }
+
+    /// Skips a block comment whose opening `/*` has been consumed. Comments
+    /// nest, and the depth is counted rather than recursed on so that deep
+    /// nesting can't overflow the stack.
     fn handle_block_comment(&mut self) {
+        let mut depth = 1;
         while let Some(c) = self.peek() {
             match (c, self.peek_next()) {
                 ('*', Some('/')) => {
                     // consume both the closing star and slash
                     self.advance();
                     self.advance();
-                    break;
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
+                    }
                 }
                 ('/', Some('*')) => {
                     // consume both the opening slash and star
                     self.advance();
                     self.advance();
-                    self.handle_block_comment();
+                    depth += 1;
                 }
                 ('\n', _) => {
-                    self.line += 1;
                     self.advance();
+                    self.new_line();
                 }
                 _ => {
                     self.advance();
                 }
             }
         }
+
+        self.unterminated = true;
+        self.errors.push(RoxError::Scan {
+            line: self.start_line,
+            column: self.start_column,
+            lexeme: "/*".to_owned(),
+            message: format!(
+                "Unterminated block comment starting at line {}",
+                self.start_line + 1
+            ),
+        });
     }
 
-    fn handle_string(&mut self) {
-        while let Some(c) = self.peek() {
-            match c {
-                '"' => break, // closing double quote
-                '\n' => self.line += 1,
-                _ => {
+    /// `"a ${x} b"` scans as the string `a `, an `InterpolationStart`, the
+    /// tokens of `x`, an `InterpolationEnd` and the string ` b`. `\$` is a
+    /// `$` that doesn't start one.
+    fn handle_string(&mut self) -> Result<(), RoxError> {
+        let quote = (self.start, self.start_line, self.start_column);
+        let mut text = String::new();
+        // where `text` starts, at the quote or the end of an interpolation
+        let mut piece = Span {
+            line: self.start_line,
+            column: self.start_column,
+            start: self.start,
+            end: self.start,
+        };
+        let mut interpolated = false;
+        // characters between the quotes, so a string of exactly the limit fits
+        let mut length = 0;
+        loop {
+            match (self.peek(), self.peek_next()) {
+                (None, _) | (Some('"'), _) => break,
+                (Some('\n'), _) => {
                     self.advance();
+                    self.new_line();
+                    text.push('\n');
                 }
+                (Some('\\'), Some('$')) => {
+                    self.advance();
+                    self.advance();
+                    text.push('$');
+                }
+                (Some('$'), Some('{')) if self.interpolating => {
+                    self.errors.push(RoxError::Scan {
+                        line: self.line,
+                        column: self.column,
+                        lexeme: "${".to_owned(),
+                        message: "a string inside an interpolation can't be interpolated"
+                            .to_owned(),
+                    });
+                    self.advance();
+                    text.push('$');
+                }
+                (Some('$'), Some('{')) => {
+                    let open = self.here();
+                    self.advance();
+                    self.advance();
+                    interpolated = true;
+                    let value = self.interner.intern(&text);
+                    text.clear();
+                    piece.end = open.start;
+                    self.push_token(TokenType::STRING(value), piece);
+                    self.push_token(
+                        TokenType::InterpolationStart,
+                        Span {
+                            end: self.current,
+                            ..open
+                        },
+                    );
+
+                    if !self.handle_interpolation(open)? {
+                        return Ok(());
+                    }
+                    piece = self.here();
+                    // scanning the interpolation moved these on
+                    self.start = quote.0;
+                    self.start_line = quote.1;
+                    self.start_column = quote.2;
+                }
+                (Some(c), _) => {
+                    self.advance();
+                    text.push(c);
+                }
+            }
+
+            length += 1;
+            if length > self.options.max_string_length {
+                return Err(self.error(format!(
+                    "string literal is longer than {} characters",
+                    self.options.max_string_length
+                )));
             }
         }
 
         if self.is_at_end() {
-            report(self.line, "Unterminated string");
-            return;
+            self.unterminated = true;
+            let error = self.error("Unterminated string".to_owned());
+            self.errors.push(error);
+            return Ok(());
         }
 
+        let value = self.interner.intern(&text);
         let _ = self.advance();
-        let value = self.source[self.start + 1..self.current - 1].to_owned();
-        self.add_token(TokenType::STRING(value));
+        piece.end = self.current;
+        if interpolated {
+            self.push_token(TokenType::STRING(value), piece);
+        } else {
+            self.add_token(TokenType::STRING(value));
+        }
+        Ok(())
+    }
+
+    /// Scans the expression of an interpolation whose `${` has been
+    /// consumed, and the `}` that closes it. Returns false when the source
+    /// ends first, which has been reported.
+    fn handle_interpolation(&mut self, open: Span) -> Result<bool, RoxError> {
+        let first = self.tokens.len();
+        let errors = self.errors.len();
+        // braces of maps and blocks in the expression
+        let mut depth = 0;
+        self.interpolating = true;
+        let closed = loop {
+            match self.peek() {
+                None => break false,
+                Some('}') if depth == 0 => break true,
+                Some('{') => depth += 1,
+                Some('}') => depth -= 1,
+                _ => {}
+            }
+            self.begin_token();
+            if let Err(error) = self.scan_token() {
+                self.interpolating = false;
+                return Err(error);
+            }
+        };
+        self.interpolating = false;
+
+        let error = |message: String| RoxError::Scan {
+            line: open.line,
+            column: open.column,
+            lexeme: "${".to_owned(),
+            message,
+        };
+        if !closed {
+            // before anything found inside it, to keep the errors in order
+            self.unterminated = true;
+            let message = format!(
+                "Unterminated interpolation starting at line {}",
+                open.line + 1
+            );
+            self.errors.insert(errors, error(message));
+            return Ok(false);
+        }
+        if self.tokens.len() == first {
+            self.errors
+                .push(error("expected an expression inside '${}'".to_owned()));
+        }
+
+        let close = self.here();
+        self.advance();
+        self.push_token(
+            TokenType::InterpolationEnd,
+            Span {
+                end: self.current,
+                ..close
+            },
+        );
+        Ok(true)
     }
 
     fn handle_number(&mut self) {
         self.take_numbers();
 
         match (self.peek(), self.peek_next()) {
-            (Some('.'), Some(c)) if c.is_digit(10) => {
+            (Some('.'), Some(c)) if c.is_ascii_digit() => {
                 self.advance(); // consume the '.'
                 self.take_numbers();
             }
+            // `1.foo` is a property access, but `1.` on its own is a typo
+            (Some('.'), next) if !next.iter().any(|&c| is_alpha(c)) => {
+                self.advance();
+                let error =
+                    self.error("malformed number literal, expected a digit after '.'".to_owned());
+                self.errors.push(error);
+                return;
+            }
             _ => (),
         }
 
-        let s_literal = &self.source[self.start..self.current];
-        if let Ok(n) = s_literal.parse::<f32>() {
-            self.add_token(TokenType::Number(n));
+        match self.source[self.start..self.current].parse::<f64>() {
+            Ok(n) if n.is_finite() => self.add_token(TokenType::Number(n)),
+            _ => {
+                let error = self.error("number literal is too large".to_owned());
+                self.errors.push(error);
+            }
+        }
+    }
+
+    /// A dot directly before a digit is only a property access when it follows
+    /// something that can have properties, otherwise it is a number like `.5`
+    /// missing its leading zero.
+    fn handle_dot(&mut self) {
+        let follows_operand = matches!(
+            self.tokens.last().map(|t| &t.tag),
+            Some(TokenType::Identifer(_))
+                | Some(TokenType::Number(_))
+                | Some(TokenType::STRING(_))
+                | Some(TokenType::RightParen)
+        );
+
+        match self.peek() {
+            Some(c) if c.is_ascii_digit() && !follows_operand => {
+                self.take_numbers();
+                let error =
+                    self.error("malformed number literal, expected a digit before '.'".to_owned());
+                self.errors.push(error);
+            }
+            _ => self.add_token(TokenType::Dot),
         }
     }
 
@@ -231,18 +688,17 @@ impl Scanner {
             }
         }
 
-        let literal = self.source[self.start..self.current].to_owned();
-
-        let token_type = match KEYWORDS.get(&literal) {
-            Some(t) => (*t).clone(),
-            None => TokenType::Identifer(literal),
+        let literal = &self.source[self.start..self.current];
+        let token_type = match keyword(literal) {
+            Some(tag) => tag,
+            None => TokenType::Identifer(self.interner.intern(literal)),
         };
         self.add_token(token_type);
     }
 
     fn take_numbers(&mut self) {
         while let Some(c) = self.peek() {
-            if c.is_digit(10) {
+            if c.is_ascii_digit() {
                 self.advance();
             } else {
                 break;
@@ -251,10 +707,599 @@ impl Scanner {
     }
 }
 
+/// Scans the source a token at a time, as the tokens are asked for, rather
+/// than all at once.
+impl IntoIterator for Scanner {
+    type Item = Result<Token, RoxError>;
+    type IntoIter = TokenStream;
+
+    fn into_iter(self) -> TokenStream {
+        TokenStream {
+            scanner: self,
+            tokens_sent: 0,
+            errors_sent: 0,
+        }
+    }
+}
+
+/// The tokens of a source and the errors in it, in source order, scanned
+/// only as far as has been asked for. As with `scan_tokens`, an error
+/// doesn't end the stream unless it's a limit being exceeded, in which case
+/// there's no EOF.
+pub struct TokenStream {
+    scanner: Scanner,
+    tokens_sent: usize,
+    errors_sent: usize,
+}
+
+impl TokenStream {
+    fn next_scanned(&mut self) -> Option<Result<Token, RoxError>> {
+        if let Some(error) = self.scanner.errors.get(self.errors_sent) {
+            self.errors_sent += 1;
+            return Some(Err(error.clone()));
+        }
+        let token = self.scanner.tokens.get(self.tokens_sent)?.clone();
+        self.tokens_sent += 1;
+        Some(Ok(token))
+    }
+}
+
+impl Iterator for TokenStream {
+    type Item = Result<Token, RoxError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(next) = self.next_scanned() {
+                return Some(next);
+            }
+            // the last token stays, a dot after it scans differently
+            if self.tokens_sent > 1 {
+                self.scanner.tokens.drain(..self.tokens_sent - 1);
+                self.tokens_sent = 1;
+            }
+            if !self.scanner.scan_step() {
+                return self.next_scanned();
+            }
+        }
+    }
+}
+
+/// Identifiers may use letters from any script, so `π` and `café` are
+/// variable names. Only ASCII digits start a number, though.
 fn is_alpha(c: char) -> bool {
-    c.is_ascii_alphabetic() || c == '_'
+    c.is_alphabetic() || c == '_'
 }
 
 fn is_alphanumeric(c: char) -> bool {
-    c.is_ascii_alphanumeric() || c == '_'
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limited(
+        max_source_size: usize,
+        max_string_length: usize,
+        max_tokens: usize,
+    ) -> ScannerOptions {
+        ScannerOptions {
+            max_source_size,
+            max_string_length,
+            max_tokens,
+        }
+    }
+
+    fn tags(source: &str) -> Vec<TokenType> {
+        let mut scanner = Scanner::new(source.to_owned());
+        let tokens = scanner.scan_tokens().unwrap();
+        tokens.iter().map(|t| t.tag.clone()).collect()
+    }
+
+    #[test]
+    fn test_dots_next_to_numbers() {
+        let ident = |s: &str| TokenType::Identifer(s.into());
+
+        assert_eq!(
+            tags("1.foo"),
+            vec![
+                TokenType::Number(1.0),
+                TokenType::Dot,
+                ident("foo"),
+                TokenType::EOF
+            ]
+        );
+        assert_eq!(
+            tags("1 .foo"),
+            vec![
+                TokenType::Number(1.0),
+                TokenType::Dot,
+                ident("foo"),
+                TokenType::EOF
+            ]
+        );
+        assert_eq!(
+            tags("1 . 2"),
+            vec![
+                TokenType::Number(1.0),
+                TokenType::Dot,
+                TokenType::Number(2.0),
+                TokenType::EOF
+            ]
+        );
+        assert_eq!(
+            tags("1.5.a"),
+            vec![
+                TokenType::Number(1.5),
+                TokenType::Dot,
+                ident("a"),
+                TokenType::EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multi_byte_characters() {
+        assert_eq!(
+            tags("\"né\" x1"),
+            vec![
+                TokenType::STRING("né".into()),
+                TokenType::Identifer("x1".into()),
+                TokenType::EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn test_non_ascii_identifiers_and_columns() {
+        let mut scanner = Scanner::new("var π = 1; print \"héllo\" + π;\n  ünï @".to_owned());
+        let errors = scanner.scan_tokens().unwrap_err();
+        let tokens: Vec<_> = scanner
+            .tokens()
+            .iter()
+            .map(|t| (t.tag.clone(), t.line, t.column))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenType::Var, 0, 0),
+                (TokenType::Identifer("π".into()), 0, 4),
+                (TokenType::Equal, 0, 6),
+                (TokenType::Number(1.0), 0, 8),
+                (TokenType::Semicolon, 0, 9),
+                (TokenType::Print, 0, 11),
+                (TokenType::STRING("héllo".into()), 0, 17),
+                (TokenType::Plus, 0, 25),
+                (TokenType::Identifer("π".into()), 0, 27),
+                (TokenType::Semicolon, 0, 28),
+                (TokenType::Identifer("ünï".into()), 1, 2),
+                (TokenType::EOF, 1, 5),
+            ]
+        );
+        assert_eq!(
+            errors,
+            vec![RoxError::Scan {
+                line: 1,
+                column: 6,
+                lexeme: "@".to_owned(),
+                message: "Unexpected character: @".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unicode_identifier_is_one_token() {
+        assert_eq!(
+            tags("var café = 1;"),
+            vec![
+                TokenType::Var,
+                TokenType::Identifer("café".into()),
+                TokenType::Equal,
+                TokenType::Number(1.0),
+                TokenType::Semicolon,
+                TokenType::EOF
+            ]
+        );
+        assert_eq!(
+            tags("日本語_2x٣"),
+            vec![TokenType::Identifer("日本語_2x٣".into()), TokenType::EOF]
+        );
+    }
+
+    #[test]
+    fn test_doubled_plus_and_minus() {
+        let ident = || TokenType::Identifer("x".into());
+        assert_eq!(
+            tags("--x - -x ++x+x"),
+            vec![
+                TokenType::MinusMinus,
+                ident(),
+                TokenType::Minus,
+                TokenType::Minus,
+                ident(),
+                TokenType::PlusPlus,
+                ident(),
+                TokenType::Plus,
+                ident(),
+                TokenType::EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn test_default_limits_allow_normal_programs() {
+        let mut scanner = Scanner::new("var a = \"hello\"; print a + a;".to_owned());
+        let tokens = scanner.scan_tokens().unwrap();
+
+        assert_eq!(tokens.len(), 11);
+    }
+
+    #[test]
+    fn test_oversized_source_is_rejected_before_scanning() {
+        let source = "1;".repeat(100);
+        let mut scanner = Scanner::with_options(source, limited(199, 1024, 1024));
+
+        assert_eq!(
+            scanner.scan_tokens(),
+            Err(vec![RoxError::Scan {
+                line: 0,
+                column: 0,
+                lexeme: String::new(),
+                message: "input too large: source is 200 bytes, the limit is 199 bytes".to_owned()
+            }])
+        );
+    }
+
+    #[test]
+    fn test_unterminated_long_string_stops_at_limit() {
+        let source = format!("print \"{}", "a".repeat(10_000));
+        let mut scanner = Scanner::with_options(source, limited(1 << 20, 100, 1024));
+
+        assert_eq!(
+            scanner.scan_tokens(),
+            Err(vec![RoxError::Scan {
+                line: 0,
+                column: 6,
+                lexeme: format!("\"{}", "a".repeat(101)),
+                message: "string literal is longer than 100 characters".to_owned()
+            }])
+        );
+    }
+
+    #[test]
+    fn test_string_at_limit_is_accepted() {
+        let source = format!("\"{}\"", "a".repeat(100));
+        let mut scanner = Scanner::with_options(source, limited(1 << 20, 100, 1024));
+        assert!(scanner.scan_tokens().is_ok());
+
+        let source = format!("\"{}\"", "a".repeat(101));
+        let mut scanner = Scanner::with_options(source, limited(1 << 20, 100, 1024));
+        assert!(scanner.scan_tokens().is_err());
+    }
+
+    #[test]
+    fn test_too_many_tokens() {
+        let source = "*".repeat(1000);
+        let mut scanner = Scanner::with_options(source.clone(), limited(1 << 20, 1024, 999));
+        assert_eq!(
+            scanner.scan_tokens(),
+            Err(vec![RoxError::Scan {
+                line: 0,
+                column: 999,
+                lexeme: "*".to_owned(),
+                message: "input too large: more than 999 tokens".to_owned()
+            }])
+        );
+
+        let mut scanner = Scanner::with_options(source, limited(1 << 20, 1024, 1000));
+        assert_eq!(scanner.scan_tokens().map(|t| t.len()), Ok(1001));
+    }
+
+    #[test]
+    fn test_reports_every_error_in_one_pass() {
+        let mut scanner = Scanner::new("var a = 1 @ 2;\nprint #a;\n\"open".to_owned());
+
+        assert_eq!(
+            scanner.scan_tokens(),
+            Err(vec![
+                RoxError::Scan {
+                    line: 0,
+                    column: 10,
+                    lexeme: "@".to_owned(),
+                    message: "Unexpected character: @".to_owned()
+                },
+                RoxError::Scan {
+                    line: 1,
+                    column: 6,
+                    lexeme: "#".to_owned(),
+                    message: "Unexpected character: #".to_owned()
+                },
+                RoxError::Scan {
+                    line: 2,
+                    column: 0,
+                    lexeme: "\"open".to_owned(),
+                    message: "Unterminated string".to_owned()
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_string_spanning_lines() {
+        let mut scanner = Scanner::new("\"line1\nline2\" ;".to_owned());
+        let tokens = scanner.scan_tokens().unwrap();
+
+        assert_eq!(
+            tokens,
+            &[
+                Token::with_column(TokenType::STRING("line1\nline2".into()), 0, 0),
+                Token::with_column(TokenType::Semicolon, 1, 7),
+                Token::with_column(TokenType::EOF, 1, 8),
+            ][..]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_string_points_at_its_start() {
+        let mut scanner = Scanner::new("print \"abc\ndef".to_owned());
+
+        assert_eq!(
+            scanner.scan_tokens(),
+            Err(vec![RoxError::Scan {
+                line: 0,
+                column: 6,
+                lexeme: "\"abc\ndef".to_owned(),
+                message: "Unterminated string".to_owned()
+            }])
+        );
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        let mut scanner = Scanner::new("print 1;\n  /* oops\nprint 2;".to_owned());
+
+        assert_eq!(
+            scanner.scan_tokens(),
+            Err(vec![RoxError::Scan {
+                line: 1,
+                column: 2,
+                lexeme: "/*".to_owned(),
+                message: "Unterminated block comment starting at line 2".to_owned()
+            }])
+        );
+    }
+
+    #[test]
+    fn test_deeply_nested_block_comments() {
+        let source = format!("{}{}\nprint 1;", "/*".repeat(1000), "*/".repeat(1000));
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+        assert_eq!(tokens[0], Token::with_column(TokenType::Print, 1, 0));
+
+        let mut scanner = Scanner::new(format!("{}{}", "/*".repeat(1000), "*/".repeat(999)));
+        assert_eq!(scanner.scan_tokens().unwrap_err().len(), 1);
+    }
+
+    #[test]
+    fn test_block_comment_contents_are_ignored() {
+        assert_eq!(
+            tags("/* say \"hi // there */ print 1;"),
+            vec![
+                TokenType::Print,
+                TokenType::Number(1.0),
+                TokenType::Semicolon,
+                TokenType::EOF
+            ]
+        );
+
+        let mut scanner = Scanner::new("/* one\ntwo /* three\n*/ */ @".to_owned());
+        match &scanner.scan_tokens().unwrap_err()[..] {
+            [RoxError::Scan { line, column, .. }] => assert_eq!((*line, *column), (2, 6)),
+            other => panic!("expected one scan error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_line_comments() {
+        let source = "print \"// no\"; // yes\n/* // no */\n  // é\r\n";
+        let mut scanner = Scanner::new(source.to_owned());
+        scanner.scan_tokens().unwrap();
+
+        let comments: Vec<_> = scanner
+            .line_comments()
+            .iter()
+            .map(|span| (span.line, span.column, &source[span.start..span.end]))
+            .collect();
+        assert_eq!(comments, vec![(0, 15, "// yes"), (2, 2, "// é\r")]);
+    }
+
+    #[test]
+    fn test_malformed_number_literals() {
+        let malformed = |source: &str| {
+            let mut scanner = Scanner::new(source.to_owned());
+            scanner
+                .scan_tokens()
+                .unwrap_err()
+                .into_iter()
+                .map(|e| match e {
+                    RoxError::Scan {
+                        column,
+                        lexeme,
+                        message,
+                        ..
+                    } => (column, lexeme, message),
+                    other => panic!("expected a scan error, got {:?}", other),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            malformed("print 123.;"),
+            vec![(
+                6,
+                "123.".to_owned(),
+                "malformed number literal, expected a digit after '.'".to_owned()
+            )]
+        );
+        assert_eq!(
+            malformed("print .5;"),
+            vec![(
+                6,
+                ".5".to_owned(),
+                "malformed number literal, expected a digit before '.'".to_owned()
+            )]
+        );
+
+        let huge = "9".repeat(400);
+        assert_eq!(
+            malformed(&format!("print {};", huge)),
+            vec![(6, huge, "number literal is too large".to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_advancing_past_the_end() {
+        let mut scanner = Scanner::new("é".to_owned());
+
+        assert_eq!(scanner.advance(), Some('é'));
+        assert_eq!(scanner.advance(), None);
+        assert!(scanner.scan_token().is_ok());
+        assert!(scanner.is_at_end());
+    }
+
+    #[test]
+    fn test_eof_is_on_the_last_line_with_a_token() {
+        let mut scanner = Scanner::new("var a;\n  print a;\n\n// done\n".to_owned());
+        let eof = scanner.scan_tokens().unwrap().last().unwrap();
+
+        assert_eq!(eof, &Token::with_column(TokenType::EOF, 1, 10));
+        assert_eq!((eof.start, eof.end), (17, 17));
+
+        let mut scanner = Scanner::new("\n  \n".to_owned());
+        assert_eq!(
+            scanner.scan_tokens().unwrap(),
+            &[Token::with_column(TokenType::EOF, 0, 0)][..]
+        );
+    }
+
+    #[test]
+    fn test_scanning_twice_gives_the_same_result() {
+        let mut scanner = Scanner::new("print 1;".to_owned());
+        let first = scanner.scan_tokens().unwrap().to_vec();
+        assert_eq!(scanner.scan_tokens().unwrap(), &first[..]);
+
+        let mut scanner = Scanner::new("print @;".to_owned());
+        let first = scanner.scan_tokens().unwrap_err();
+        assert_eq!(scanner.scan_tokens().unwrap_err(), first);
+        assert_eq!(scanner.tokens().len(), 3);
+    }
+
+    #[test]
+    fn test_interpolation_scans_as_its_pieces() {
+        let mut scanner = Scanner::new("\"a ${x + 1} b\"".to_owned());
+        let tokens: Vec<_> = scanner
+            .scan_tokens()
+            .unwrap()
+            .iter()
+            .map(|t| (t.tag.to_string(), t.column, t.start, t.end))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                ("a ".to_owned(), 0, 0, 3),
+                ("${".to_owned(), 3, 3, 5),
+                ("x".to_owned(), 5, 5, 6),
+                ("+".to_owned(), 7, 7, 8),
+                ("1".to_owned(), 9, 9, 10),
+                ("}".to_owned(), 10, 10, 11),
+                (" b".to_owned(), 11, 11, 14),
+                ("EOF".to_owned(), 14, 14, 14),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_escaped_dollar_is_text() {
+        assert_eq!(
+            tags(r#""\${a} \$ \x $""#),
+            vec![TokenType::STRING(r#"${a} $ \x $"#.into()), TokenType::EOF]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_input() {
+        let unterminated = |source: &str| {
+            let mut scanner = Scanner::new(source.to_owned());
+            let _ = scanner.scan_tokens();
+            scanner.is_unterminated()
+        };
+
+        assert!(unterminated("print \"two"));
+        assert!(unterminated("/* a comment"));
+        assert!(unterminated("print \"${1 +"));
+        assert!(!unterminated("print \"two\"; /* a comment */"));
+        assert!(!unterminated("print @;"));
+    }
+
+    #[test]
+    fn test_interpolation_errors() {
+        let errors = |source: &str| -> Vec<_> {
+            Scanner::new(source.to_owned())
+                .scan_tokens()
+                .unwrap_err()
+                .into_iter()
+                .map(|e| e.to_string())
+                .collect()
+        };
+
+        assert_eq!(
+            errors("print \"a ${b;\n"),
+            vec!["[line 1, col 10] Error: Unterminated interpolation starting at line 1"]
+        );
+        assert_eq!(
+            errors("print \"${}\";"),
+            vec!["[line 1, col 8] Error: expected an expression inside '${}'"]
+        );
+        assert_eq!(
+            errors("print \"${\"${1}\"}\";"),
+            vec!["[line 1, col 11] Error: a string inside an interpolation can't be interpolated"]
+        );
+    }
+
+    #[test]
+    fn test_keywords_are_not_identifiers() {
+        for word in KEYWORDS {
+            assert!(keyword(word).is_some(), "{}", word);
+        }
+    }
+
+    #[test]
+    fn test_token_stream_scans_only_as_far_as_asked() {
+        let mut stream = Scanner::new("print 1;\n\"open".to_owned()).into_iter();
+
+        assert_eq!(
+            stream.next().map(|t| t.map(|t| t.tag)),
+            Some(Ok(TokenType::Print))
+        );
+        assert_eq!(stream.scanner.errors, vec![]);
+
+        let rest: Vec<_> = stream.collect();
+        assert_eq!(rest.len(), 4);
+        assert_eq!(
+            rest[2].as_ref().map_err(|e| e.to_string()),
+            Err("[line 2, col 1] Error: Unterminated string".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_token_stream_stops_at_a_limit() {
+        let source = "*".repeat(1000);
+        let stream = Scanner::with_options(source, limited(1 << 20, 1024, 999)).into_iter();
+        let (tokens, errors): (Vec<_>, Vec<_>) = stream.partition(Result::is_ok);
+
+        assert_eq!(tokens.len(), 1000);
+        assert_eq!(errors.len(), 1);
+    }
 }
@@ -0,0 +1,52 @@
+/// Where a piece of syntax was written. `line` and `column` are where it
+/// starts, counted from 0 like everywhere else, and `start..end` is the
+/// byte range it covers in the source, so tools can slice its text out.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub line: u32,
+    pub column: u32,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// From the start of `self` to the end of `other`, which comes later.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end,
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Span;
+
+    #[test]
+    fn test_to_covers_both() {
+        let first = Span {
+            line: 1,
+            column: 4,
+            start: 10,
+            end: 12,
+        };
+        let last = Span {
+            line: 2,
+            column: 0,
+            start: 20,
+            end: 25,
+        };
+
+        assert_eq!(
+            first.to(last),
+            Span {
+                line: 1,
+                column: 4,
+                start: 10,
+                end: 25,
+            }
+        );
+    }
+}
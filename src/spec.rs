@@ -0,0 +1,343 @@
+//! Runs spec files, scripts that say what they should print in their
+//! comments:
+//!
+//! - `// expect: text` for each line the script prints, in order,
+//! - `// expect-error: text` for each error, as the error displays.
+//!
+//! `rox test` prints a summary of the results, and `report` writes them in
+//! a form other tools can read.
+
+use std::ffi::OsStr;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::{fold, resolve, Interpreter, Parser, RoxError, Scanner};
+
+pub mod report;
+
+const EXPECT: &str = "// expect: ";
+const EXPECT_ERROR: &str = "// expect-error: ";
+
+/// Which of a script's results an expectation is about.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stream {
+    /// The lines it printed.
+    Output,
+    /// The errors it stopped with.
+    Error,
+}
+
+impl Stream {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stream::Output => "output",
+            Stream::Error => "error",
+        }
+    }
+
+    /// What starts a comment expecting a line of this stream.
+    pub fn marker(self) -> &'static str {
+        match self {
+            Stream::Output => EXPECT,
+            Stream::Error => EXPECT_ERROR,
+        }
+    }
+}
+
+/// One line of a script's output or errors that isn't what was expected.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub stream: Stream,
+    /// Which line of the output, or which error, counted from 0.
+    pub index: usize,
+    /// The line of the spec the expectation is on, or `None` if there was
+    /// no expectation left for this line.
+    pub source_line: Option<u32>,
+    /// `None` if the script stopped short of this line.
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let stream = self.stream.name();
+        if let Some(line) = self.source_line {
+            write!(f, "line {}: ", line + 1)?;
+        }
+        match (&self.expected, &self.actual) {
+            (Some(expected), Some(actual)) => {
+                write!(f, "expected {} {:?}, got {:?}", stream, expected, actual)
+            }
+            (Some(expected), None) => write!(f, "missing {} {:?}", stream, expected),
+            (None, Some(actual)) => write!(f, "unexpected {} {:?}", stream, actual),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// What running one spec found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpecResult {
+    pub path: PathBuf,
+    pub duration: Duration,
+    /// Every output line and error that differs, outputs first. Empty when
+    /// the spec passed.
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl SpecResult {
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// What running a set of specs found, in the order they were run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestRun {
+    pub specs: Vec<SpecResult>,
+    pub duration: Duration,
+}
+
+impl TestRun {
+    pub fn passed(&self) -> usize {
+        self.specs.iter().filter(|spec| spec.passed()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.specs.len() - self.passed()
+    }
+}
+
+/// Runs every spec in `paths`, in order. A directory stands for the `.lox`
+/// files directly in it, sorted by name. Fails only if a spec can't be read.
+pub fn run_all(paths: &[PathBuf]) -> io::Result<TestRun> {
+    let start = Instant::now();
+    let mut specs = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut files = Vec::new();
+            for entry in fs::read_dir(path)? {
+                let file = entry?.path();
+                if file.extension() == Some(OsStr::new("lox")) {
+                    files.push(file);
+                }
+            }
+            files.sort();
+            for file in files {
+                specs.push(run(&file)?);
+            }
+        } else {
+            specs.push(run(path)?);
+        }
+    }
+
+    Ok(TestRun {
+        specs,
+        duration: start.elapsed(),
+    })
+}
+
+/// Runs the spec at `path` in a new interpreter, as `rox` would run it.
+pub fn run(path: &Path) -> io::Result<SpecResult> {
+    let source = fs::read_to_string(path)?;
+    let start = Instant::now();
+
+    let expectations = expectations(&source);
+    let actual = outcome(&source, path, false);
+    let mut mismatches = compare(Stream::Output, &expectations, &actual.output);
+    mismatches.extend(compare(Stream::Error, &expectations, &actual.errors));
+    Ok(SpecResult {
+        path: path.to_owned(),
+        duration: start.elapsed(),
+        mismatches,
+    })
+}
+
+/// What a script printed and the errors it stopped with, or what its
+/// comments say it should have.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Outcome {
+    pub output: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+/// Runs `source` the way `rox` would run the script at `path`, or `rox
+/// --optimize` with `optimize`, collecting every error reported before it
+/// stopped.
+pub fn outcome(source: &str, path: &Path, optimize: bool) -> Outcome {
+    let mut printed = Vec::new();
+    let errors = run_into(source, path, optimize, &mut printed);
+    Outcome {
+        output: String::from_utf8_lossy(&printed)
+            .lines()
+            .map(str::to_owned)
+            .collect(),
+        errors: errors.iter().map(RoxError::to_string).collect(),
+    }
+}
+
+fn run_into(source: &str, path: &Path, optimize: bool, printed: &mut Vec<u8>) -> Vec<RoxError> {
+    let tokens = match Scanner::new(source.to_owned()).scan_tokens() {
+        Ok(tokens) => tokens.to_vec(),
+        Err(errors) => return errors,
+    };
+    let mut statements = match Parser::new(tokens).parse() {
+        Ok(statements) => statements,
+        Err(errors) => return errors,
+    };
+    let resolution = resolve(&mut statements);
+    if !resolution.errors.is_empty() {
+        return resolution.errors;
+    }
+    if optimize {
+        statements = fold(statements);
+    }
+
+    let mut interpreter = Interpreter::new(printed);
+    interpreter.set_script_path(Some(path.to_owned()));
+    interpreter
+        .interpret(&statements)
+        .err()
+        .into_iter()
+        .collect()
+}
+
+/// A comment that says what a script should print or stop with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expectation {
+    pub stream: Stream,
+    /// The line the comment is on, and how many characters into the line
+    /// it starts.
+    pub line: u32,
+    pub column: u32,
+    /// What follows the marker.
+    pub text: String,
+}
+
+/// The expectations in `source`, in order. Only comments count, so a
+/// marker inside a string is just part of the string.
+pub fn expectations(source: &str) -> Vec<Expectation> {
+    let mut scanner = Scanner::new(source.to_owned());
+    // a spec that doesn't scan still has comments saying what errors it
+    // expects
+    let _ = scanner.scan_tokens();
+    scanner
+        .line_comments()
+        .iter()
+        .filter_map(|span| {
+            let comment = source[span.start..span.end].trim_end_matches('\r');
+            let (stream, text) = [Stream::Output, Stream::Error]
+                .iter()
+                .find_map(|&stream| Some((stream, comment.strip_prefix(stream.marker())?)))?;
+            Some(Expectation {
+                stream,
+                line: span.line,
+                column: span.column,
+                text: text.to_owned(),
+            })
+        })
+        .collect()
+}
+
+/// What the expectations in `source` say it should do.
+pub fn expected(source: &str) -> Outcome {
+    let mut outcome = Outcome::default();
+    for expectation in expectations(source) {
+        match expectation.stream {
+            Stream::Output => outcome.output.push(expectation.text),
+            Stream::Error => outcome.errors.push(expectation.text),
+        }
+    }
+    outcome
+}
+
+/// `source` with its expectations rewritten to match `actual`: existing
+/// ones are updated in order, ones that no longer happen are removed, and
+/// new ones are added at the end of the script.
+pub fn updated(source: &str, actual: &Outcome) -> String {
+    let mut expectations = expectations(source).into_iter().peekable();
+    let mut output = actual.output.iter();
+    let mut errors = actual.errors.iter();
+    let mut lines = Vec::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let expectation = match expectations.next_if(|e| e.line as usize == number) {
+            Some(expectation) => expectation,
+            None => {
+                lines.push(line.to_owned());
+                continue;
+            }
+        };
+        let at = line
+            .char_indices()
+            .nth(expectation.column as usize)
+            .map_or(line.len(), |(at, _)| at);
+        let next = match expectation.stream {
+            Stream::Output => output.next(),
+            Stream::Error => errors.next(),
+        };
+        let code = line[..at].trim_end();
+        match next {
+            Some(text) => lines.push(format!(
+                "{}{}{}",
+                &line[..at],
+                expectation.stream.marker(),
+                text
+            )),
+            // the expectation no longer happens, keep whatever code it was on
+            None if !code.is_empty() => lines.push(code.to_owned()),
+            None => {}
+        }
+    }
+
+    lines.extend(output.map(|text| format!("{}{}", EXPECT, text)));
+    lines.extend(errors.map(|text| format!("{}{}", EXPECT_ERROR, text)));
+    lines.join("\n") + "\n"
+}
+
+fn compare(stream: Stream, expectations: &[Expectation], actual: &[String]) -> Vec<Mismatch> {
+    let expected: Vec<_> = expectations
+        .iter()
+        .filter(|expectation| expectation.stream == stream)
+        .collect();
+    (0..expected.len().max(actual.len()))
+        .filter_map(|index| {
+            let expectation = expected.get(index);
+            let expected = expectation.map(|expectation| expectation.text.clone());
+            let actual = actual.get(index).cloned();
+            if expected == actual {
+                return None;
+            }
+            Some(Mismatch {
+                stream,
+                index,
+                source_line: expectation.map(|expectation| expectation.line),
+                expected,
+                actual,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{expected, Outcome};
+
+    #[test]
+    fn test_markers_are_only_read_from_comments() {
+        let source = "print \"// expect: no\"; // expect: // expect: no\n\
+                      /* // expect: no */\n\
+                      // expect-error: yes\n";
+
+        assert_eq!(
+            expected(source),
+            Outcome {
+                output: vec!["// expect: no".to_owned()],
+                errors: vec!["yes".to_owned()],
+            }
+        );
+    }
+}
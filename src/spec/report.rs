@@ -0,0 +1,190 @@
+//! Test results for other tools, such as a CI dashboard. The JSON is an
+//! object with a `summary` of the counts and the total duration, and the
+//! `specs` in the order they ran, each with its `path`, `status`,
+//! `duration_ms` and `mismatches`. Keys are always written in the same
+//! order, so only the durations change from one run to the next.
+
+use std::fmt::Write;
+use std::time::Duration;
+
+use super::{Mismatch, SpecResult, TestRun};
+
+/// `run` as pretty-printed JSON, ending in a newline.
+pub fn json(run: &TestRun) -> String {
+    let mut out = String::new();
+    out += "{\n  \"summary\": {\n";
+    out += &format!("    \"specs\": {},\n", run.specs.len());
+    out += &format!("    \"passed\": {},\n", run.passed());
+    out += &format!("    \"failed\": {},\n", run.failed());
+    out += &format!("    \"duration_ms\": {}\n", millis(run.duration));
+    out += "  },\n  \"specs\": [";
+    for (i, spec) in run.specs.iter().enumerate() {
+        out += if i == 0 { "\n" } else { ",\n" };
+        spec_json(&mut out, spec);
+    }
+    out += if run.specs.is_empty() {
+        "]\n}\n"
+    } else {
+        "\n  ]\n}\n"
+    };
+    out
+}
+
+fn spec_json(out: &mut String, spec: &SpecResult) {
+    let status = if spec.passed() { "pass" } else { "fail" };
+    *out += "    {\n";
+    *out += &format!(
+        "      \"path\": {},\n",
+        string(&spec.path.display().to_string())
+    );
+    *out += &format!("      \"status\": \"{}\",\n", status);
+    *out += &format!("      \"duration_ms\": {},\n", millis(spec.duration));
+    *out += "      \"mismatches\": [";
+    for (i, mismatch) in spec.mismatches.iter().enumerate() {
+        *out += if i == 0 { "\n" } else { ",\n" };
+        mismatch_json(out, mismatch);
+    }
+    *out += if spec.mismatches.is_empty() {
+        "]\n    }"
+    } else {
+        "\n      ]\n    }"
+    };
+}
+
+fn mismatch_json(out: &mut String, mismatch: &Mismatch) {
+    let optional = |text: &Option<String>| text.as_deref().map_or("null".to_owned(), string);
+    *out += "        {\n";
+    *out += &format!("          \"stream\": \"{}\",\n", mismatch.stream.name());
+    *out += &format!("          \"output_line\": {},\n", mismatch.index);
+    *out += &format!(
+        "          \"source_line\": {},\n",
+        mismatch
+            .source_line
+            .map_or("null".to_owned(), |line| line.to_string())
+    );
+    *out += &format!(
+        "          \"expected\": {},\n",
+        optional(&mismatch.expected)
+    );
+    *out += &format!("          \"actual\": {}\n", optional(&mismatch.actual));
+    *out += "        }";
+}
+
+/// Milliseconds to the microsecond, as a JSON number.
+fn millis(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64() * 1000.0)
+}
+
+/// `text` as a JSON string.
+fn string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            '\n' => out += "\\n",
+            '\r' => out += "\\r",
+            '\t' => out += "\\t",
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::json;
+    use crate::spec::{Mismatch, SpecResult, Stream, TestRun};
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    #[test]
+    fn test_json_layout() {
+        let run = TestRun {
+            specs: vec![
+                SpecResult {
+                    path: PathBuf::from("specs/pass.lox"),
+                    duration: Duration::from_micros(1500),
+                    mismatches: Vec::new(),
+                },
+                SpecResult {
+                    path: PathBuf::from("specs/\"fail\".lox"),
+                    duration: Duration::from_micros(250),
+                    mismatches: vec![
+                        Mismatch {
+                            stream: Stream::Output,
+                            index: 1,
+                            source_line: Some(3),
+                            expected: Some("2".to_owned()),
+                            actual: Some("a\tb".to_owned()),
+                        },
+                        Mismatch {
+                            stream: Stream::Error,
+                            index: 0,
+                            source_line: None,
+                            expected: None,
+                            actual: Some("[line 1] Error: x".to_owned()),
+                        },
+                    ],
+                },
+            ],
+            duration: Duration::from_millis(2),
+        };
+
+        assert_eq!(
+            json(&run),
+            r#"{
+  "summary": {
+    "specs": 2,
+    "passed": 1,
+    "failed": 1,
+    "duration_ms": 2.000
+  },
+  "specs": [
+    {
+      "path": "specs/pass.lox",
+      "status": "pass",
+      "duration_ms": 1.500,
+      "mismatches": []
+    },
+    {
+      "path": "specs/\"fail\".lox",
+      "status": "fail",
+      "duration_ms": 0.250,
+      "mismatches": [
+        {
+          "stream": "output",
+          "output_line": 1,
+          "source_line": 3,
+          "expected": "2",
+          "actual": "a\tb"
+        },
+        {
+          "stream": "error",
+          "output_line": 0,
+          "source_line": null,
+          "expected": null,
+          "actual": "[line 1] Error: x"
+        }
+      ]
+    }
+  ]
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_no_specs() {
+        let run = TestRun {
+            specs: Vec::new(),
+            duration: Duration::default(),
+        };
+
+        assert!(json(&run).ends_with("\"specs\": []\n}\n"));
+    }
+}
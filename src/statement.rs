@@ -1,11 +1,223 @@
-use crate::expr::Expr;
+use crate::error::RoxError;
+use crate::expr::{Expr, Structural};
+use crate::span::Span;
+use crate::token::{Name, Token};
+use std::sync::Arc;
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum Stmt {
+/// A statement and the source it was parsed from.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StmtKind {
     Expr(Expr),
-    Print(Expr),
+    /// `print a, b;` prints its values separated by spaces.
+    Print {
+        line: u32,
+        values: Vec<Expr>,
+    },
+    /// Like `print`, to the interpreter's error stream.
+    Eprint {
+        line: u32,
+        values: Vec<Expr>,
+    },
     VarDec {
-        name: String,
+        name: Name,
         initializer: Option<Expr>,
     },
+    /// `const name = initializer;`, a variable that can't be assigned to
+    /// after it's declared.
+    Const {
+        name: Name,
+        initializer: Expr,
+    },
+    Block(Vec<Stmt>),
+    If {
+        condition: Expr,
+        then_branch: Box<Stmt>,
+        else_branch: Option<Box<Stmt>>,
+    },
+    While {
+        condition: Expr,
+        body: Box<Stmt>,
+    },
+    /// `do { ... } while (condition);`, which runs the body before the
+    /// first check. The body is always a block.
+    DoWhile {
+        body: Box<Stmt>,
+        condition: Expr,
+    },
+    /// The body is shared with every function value created from it.
+    Function {
+        name: Name,
+        params: Vec<Name>,
+        body: Arc<Vec<Stmt>>,
+    },
+    /// `class Name < Superclass { method() { ... } }`. Each method is a
+    /// `Function`, written without the `fun`.
+    Class {
+        name: Name,
+        superclass: Option<Name>,
+        methods: Vec<Stmt>,
+    },
+    Return {
+        line: u32,
+        value: Option<Expr>,
+    },
+    /// Fails with a runtime error, pointing at `keyword`, when `condition`
+    /// is falsy.
+    Assert {
+        keyword: Token,
+        condition: Expr,
+    },
+    /// `import "path";` runs the file at `path` in the global scope, unless
+    /// it has been run already.
+    Import {
+        keyword: Token,
+        path: String,
+    },
+    Error {
+        line: u32,
+        message: String,
+    },
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    /// The line of the first error node in this statement, if it has any.
+    pub fn error_line(&self) -> Option<u32> {
+        match &self.kind {
+            StmtKind::Error { line, .. } => Some(*line),
+            StmtKind::Import { .. } => None,
+            StmtKind::Expr(e) => e.error_line(),
+            StmtKind::Print { values, .. } | StmtKind::Eprint { values, .. } => {
+                values.iter().find_map(Expr::error_line)
+            }
+            StmtKind::VarDec { initializer, .. } => initializer.as_ref().and_then(Expr::error_line),
+            StmtKind::Const { initializer, .. } => initializer.error_line(),
+            StmtKind::Block(statements) => statements.iter().find_map(Stmt::error_line),
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => condition
+                .error_line()
+                .or_else(|| then_branch.error_line())
+                .or_else(|| else_branch.as_ref().and_then(|s| s.error_line())),
+            StmtKind::While { condition, body } | StmtKind::DoWhile { body, condition } => {
+                condition.error_line().or_else(|| body.error_line())
+            }
+            StmtKind::Function { body, .. } => body.iter().find_map(Stmt::error_line),
+            StmtKind::Class { methods, .. } => methods.iter().find_map(Stmt::error_line),
+            StmtKind::Return { value, .. } => value.as_ref().and_then(Expr::error_line),
+            StmtKind::Assert { condition, .. } => condition.error_line(),
+        }
+    }
+
+    /// Whether `self` and `other` are the same tree wherever they were
+    /// written, like `Expr::structural_eq`.
+    pub fn structural_eq(&self, other: &Self) -> bool {
+        use StmtKind::*;
+        match (&self.kind, &other.kind) {
+            (Expr(e), Expr(other)) => e.same(other),
+            (Print { values, .. }, Print { values: v, .. })
+            | (Eprint { values, .. }, Eprint { values: v, .. }) => values.same(v),
+            (
+                VarDec { name, initializer },
+                VarDec {
+                    name: n,
+                    initializer: i,
+                },
+            ) => name.same(n) && initializer.same(i),
+            (
+                Const { name, initializer },
+                Const {
+                    name: n,
+                    initializer: i,
+                },
+            ) => name.same(n) && initializer.same(i),
+            (Block(statements), Block(s)) => statements.same(s),
+            (
+                If {
+                    condition,
+                    then_branch,
+                    else_branch,
+                },
+                If {
+                    condition: c,
+                    then_branch: t,
+                    else_branch: e,
+                },
+            ) => condition.same(c) && then_branch.same(t) && else_branch.same(e),
+            (
+                While { condition, body },
+                While {
+                    condition: c,
+                    body: b,
+                },
+            )
+            | (
+                DoWhile { body, condition },
+                DoWhile {
+                    body: b,
+                    condition: c,
+                },
+            ) => condition.same(c) && body.same(b),
+            (
+                Function { name, params, body },
+                Function {
+                    name: n,
+                    params: p,
+                    body: b,
+                },
+            ) => name.same(n) && params.same(p) && body.same(b),
+            (
+                Class {
+                    name,
+                    superclass,
+                    methods,
+                },
+                Class {
+                    name: n,
+                    superclass: s,
+                    methods: m,
+                },
+            ) => name.same(n) && superclass.same(s) && methods.same(m),
+            (Return { value, .. }, Return { value: v, .. }) => value.same(v),
+            (Assert { condition, .. }, Assert { condition: c, .. }) => condition.same(c),
+            (Import { path, .. }, Import { path: p, .. }) => path == p,
+            (Error { message, .. }, Error { message: m, .. }) => message == m,
+            _ => false,
+        }
+    }
+}
+
+/// Statements are compared by what they say, not where they were written.
+impl PartialEq for Stmt {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+/// A statement that wasn't parsed, so has no source to point at.
+impl From<StmtKind> for Stmt {
+    fn from(kind: StmtKind) -> Self {
+        Self::new(kind, Span::default())
+    }
+}
+
+/// The result of an error-tolerant parse: every declaration that could be
+/// parsed, with the ones that failed left in place as error nodes.
+#[derive(Debug, PartialEq)]
+pub struct Program {
+    pub statements: Vec<Stmt>,
+    pub diagnostics: Vec<RoxError>,
 }
@@ -0,0 +1,43 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// An interned identifier name or string literal. Equal symbols from the
+/// same scanner share one allocation, so cloning one is only a reference
+/// count bump, and it can be used as a key wherever a `&str` is looked up.
+pub type Symbol = Arc<str>;
+
+/// Hands out one shared `Symbol` per distinct piece of text.
+#[derive(Default)]
+pub struct Interner {
+    symbols: HashSet<Symbol>,
+}
+
+impl Interner {
+    pub fn intern(&mut self, text: &str) -> Symbol {
+        match self.symbols.get(text) {
+            Some(symbol) => Arc::clone(symbol),
+            None => {
+                let symbol: Symbol = text.into();
+                self.symbols.insert(Arc::clone(&symbol));
+                symbol
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_equal_text_shares_a_symbol() {
+        let mut interner = Interner::default();
+        let first = interner.intern("total");
+        let second = interner.intern("total");
+        let other = interner.intern("count");
+
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(!Arc::ptr_eq(&first, &other));
+        assert_eq!(&*other, "count");
+    }
+}
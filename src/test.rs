@@ -1,46 +1,107 @@
 use proptest::prelude::*;
-use std::io::Write;
+use std::io::{self, Write};
+use std::thread;
 
+use crate::error::{Frame, RoxError};
 use crate::interpreter::{ExecuteResult, Interpreter};
+use crate::number;
 use crate::parser::Parser;
+use crate::resolver::resolve;
 use crate::scanner::Scanner;
+use crate::statement::{Stmt, StmtKind};
 
-fn scan_parse_and_interpret(source: String, buffer: &mut impl Write) -> ExecuteResult {
+fn scan_parse_and_interpret(source: String, buffer: &mut (impl Write + Send)) -> ExecuteResult {
     let mut interpreter = Interpreter::new(buffer);
     let mut s = Scanner::new(source);
-    let tokens = s.scan_tokens().to_vec();
+    let tokens = s
+        .scan_tokens()
+        .map_err(|mut errors| errors.swap_remove(0))?
+        .to_vec();
     let mut parser = Parser::new(tokens);
 
-    parser
-        .parse()
-        .and_then(|statements| interpreter.interpret(&statements))
+    let mut statements = parser.parse().map_err(|mut errors| errors.swap_remove(0))?;
+    if let Some(e) = resolve(&mut statements).errors.into_iter().next() {
+        return Err(e);
+    }
+    interpreter.interpret(&statements)
+}
+
+/// Runs `f` on a thread with the 8MB stack a main thread gets, rather than
+/// the 2MB of a test thread, for tests that nest as deeply as the default
+/// limits allow.
+pub(crate) fn on_main_thread_stack<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    thread::Builder::new()
+        .stack_size(8 * 1024 * 1024)
+        .spawn(f)
+        .unwrap()
+        .join()
+        .unwrap()
+}
+
+fn runtime_error(line: u32, column: u32, message: impl Into<String>) -> ExecuteResult {
+    Err(RoxError::Runtime {
+        line,
+        column: Some(column),
+        message: message.into(),
+        frames: Vec::new(),
+    })
+}
+
+/// `result` with its error having unwound through a call to `function` made
+/// on `line`.
+fn called_from(result: ExecuteResult, function: &str, line: u32) -> ExecuteResult {
+    result.map_err(|e| {
+        e.called_from(Frame {
+            function: function.to_owned(),
+            line,
+        })
+    })
 }
 
 proptest! {
 
     #[test]
-    fn test_binary_operations(op1 in any::<f32>(), op2 in any::<f32>(), op3 in any::<f32>()) {
+    fn test_binary_operations(
+        op1 in any::<f64>(),
+        op2 in any::<f64>(),
+        // zeros and tiny numbers are rare otherwise, and dividing by them is
+        // where division goes wrong
+        op3 in prop_oneof![
+            4 => any::<f64>(),
+            1 => Just(0.0),
+            1 => Just(-0.0),
+            1 => Just(f64::MIN_POSITIVE),
+            1 => Just(-1e-300),
+        ],
+    ) {
         let mut buffer = Vec::new();
         let input = format!("print {} + {} * {};", op1, op2, op3);
         scan_parse_and_interpret(input, &mut buffer).unwrap();
 
-        prop_assert_eq!((op1 + op2 * op3).to_string() + "\n", String::from_utf8(buffer.clone()).unwrap());
+        prop_assert_eq!(number::format(op1 + op2 * op3) + "\n", String::from_utf8(buffer.clone()).unwrap());
         buffer.clear();
 
         let input = format!("print {} * {} + {};", op1, op2, op3);
         scan_parse_and_interpret(input, &mut buffer).unwrap();
 
-        prop_assert_eq!((op1 * op2 + op3).to_string() + "\n", String::from_utf8(buffer.clone()).unwrap());
+        prop_assert_eq!(number::format(op1 * op2 + op3) + "\n", String::from_utf8(buffer.clone()).unwrap());
         buffer.clear();
 
         let input = format!("print {} * {} / {};", op1, op2, op3);
         let result = scan_parse_and_interpret(input, &mut buffer);
 
-        if op3 != 0.0 {
-            prop_assert_eq!((op1 * op2 / op3).to_string() + "\n", String::from_utf8(buffer.clone()).unwrap());
+        let (numerator, quotient) = (op1 * op2, op1 * op2 / op3);
+        let problem = if op3 == 0.0 {
+            "Divide by zero error"
+        } else if numerator.is_finite() && quotient.is_infinite() {
+            "Division overflowed"
         } else {
-            prop_assert_eq!(Err("Divide by zero error".to_string()), result)
-        }
+            prop_assert_eq!(number::format(quotient) + "\n", String::from_utf8(buffer.clone()).unwrap());
+            return Ok(());
+        };
+        let column = format!("print {} * {} ", op1, op2).len() as u32;
+        let message = format!("{}: {} / {}", problem, number::format(numerator), number::format(op3));
+        prop_assert_eq!(runtime_error(0, column, message), result)
     }
 
     #[test]
@@ -54,20 +115,1434 @@ proptest! {
     }
 
     #[test]
-    fn test_variable_declarations(op1 in any::<f32>(), op2 in any::<f32>()) {
+    fn test_variable_declarations(op1 in any::<f64>(), op2 in any::<f64>()) {
         let mut buffer = Vec::new();
         let input = format!(r#"var a = {}; var b = {}; print a + b;"#, op1, op2);
         scan_parse_and_interpret(input, &mut buffer).unwrap();
 
-        prop_assert_eq!(format!("{}\n", op1 + op2), String::from_utf8(buffer).unwrap())
+        prop_assert_eq!(format!("{}\n", number::format(op1 + op2)), String::from_utf8(buffer).unwrap())
     }
 
     #[test]
-    fn test_assignments(op1 in any::<f32>(), op2 in any::<f32>()) {
+    fn test_assignments(op1 in any::<f64>(), op2 in any::<f64>()) {
         let mut buffer = Vec::new();
         let input = format!(r#"var a = {}; print a; a = {}; print a;"#, op1, op2);
         scan_parse_and_interpret(input, &mut buffer).unwrap();
 
-        prop_assert_eq!(format!("{}\n{}\n", op1, op2), String::from_utf8(buffer).unwrap())
+        prop_assert_eq!(format!("{}\n{}\n", number::format(op1), number::format(op2)), String::from_utf8(buffer).unwrap())
+    }
+
+    #[test]
+    fn test_string_comparisons(left in "[a-zA-Z0-9 ]*", right in "[a-zA-Z0-9 ]*") {
+        let mut buffer = Vec::new();
+        let input = format!(
+            r#"var l = "{0}"; var r = "{1}"; print l < r; print l <= r; print l > r; print l >= r; print l == r;"#,
+            left, right
+        );
+        scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+        let expected = format!(
+            "{}\n{}\n{}\n{}\n{}\n",
+            left < right, left <= right, left > right, left >= right, left == right
+        );
+        prop_assert_eq!(expected, String::from_utf8(buffer).unwrap())
+    }
+
+    #[test]
+    fn test_substr_matches_slicing(s in "[a-zA-Z0-9 ]*", start in 0usize..40, length in 0usize..40) {
+        let mut buffer = Vec::new();
+        let input = format!(r#"print len("{}"); print substr("{}", {}, {});"#, s, s, start, length);
+        let result = scan_parse_and_interpret(input, &mut buffer);
+
+        match s.get(start..start + length) {
+            Some(slice) => {
+                result.unwrap();
+                prop_assert_eq!(format!("{}\n{}\n", s.len(), slice), String::from_utf8(buffer).unwrap())
+            }
+            None => {
+                let column = format!(r#"print len("{}"); print substr"#, s).len() as u32;
+                let message = format!(
+                    "substr range {}..{} is out of bounds for a string of length {}",
+                    start, start + length, s.len()
+                );
+                prop_assert_eq!(called_from(runtime_error(0, column, message), "substr", 0), result)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_format_errors_point_at_the_call() {
+    for (source, column, message) in [
+        (
+            "print \"x\";\nformat(\"{} {}\", 1);",
+            6,
+            "format string has 2 placeholders but was given 1 values",
+        ),
+        (
+            "print \"x\";\nformat(\"{:q}\", 1);",
+            6,
+            "malformed format spec '{:q}'",
+        ),
+        (
+            "print \"x\";\nformat(1);",
+            6,
+            "format expects a string, got number",
+        ),
+    ] {
+        let result = scan_parse_and_interpret(source.to_owned(), &mut io::sink());
+        assert_eq!(
+            called_from(runtime_error(1, column, message), "format", 1),
+            result
+        );
+    }
+
+    let result = scan_parse_and_interpret("format();".to_owned(), &mut io::sink());
+    assert_eq!(
+        runtime_error(
+            0,
+            6,
+            "function 'format' expected at least 1 arguments but got 0"
+        ),
+        result
+    );
+}
+
+#[test]
+fn test_len_and_substr_count_characters() {
+    let mut buffer = Vec::new();
+    let input = "
+var s = \"héllo, 世界\";
+print len(s);
+print substr(s, 1, 4);
+print substr(s, 7, 2);
+print len([1, [2, 3]]);
+substr(s, 0.5, 1);"
+        .to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+
+    assert_eq!("9\néllo\n世界\n2\n", String::from_utf8(buffer).unwrap());
+    assert_eq!(
+        called_from(
+            runtime_error(6, 6, "substr expects a whole number for start, got 0.5"),
+            "substr",
+            6
+        ),
+        result
+    );
+    assert_eq!(
+        called_from(
+            runtime_error(0, 3, "len expects a string, list or map, got number"),
+            "len",
+            0
+        ),
+        scan_parse_and_interpret("len(1);".to_string(), &mut Vec::new())
+    );
+}
+
+#[test]
+fn test_mixed_comparison_and_equality() {
+    let mut buffer = Vec::new();
+    let input = "print \"1\" == 1; print 1 != \"1\";".to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+    assert_eq!("false\ntrue\n", String::from_utf8(buffer).unwrap());
+
+    let mut buffer = Vec::new();
+    let input = "print \"a\" < 1;".to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+    assert_eq!(
+        runtime_error(0, 10, "cannot compare string and number with '<'"),
+        result
+    );
+}
+
+#[test]
+fn test_equality_never_converts_between_types() {
+    let mut buffer = Vec::new();
+    let input = "
+var list = [];
+print nil == nil, nil == false, nil != 0;
+print 1 == \"1\", \"1\" == 1, 1 == 1.0, -0 == 0;
+print true == 1, 0 == false, \"true\" == true;
+print list == list, [] == [], {} == {}, len == len;"
+        .to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+    assert_eq!(
+        "true false true\nfalse false true true\nfalse false false\ntrue false false true\n",
+        String::from_utf8(buffer).unwrap()
+    );
+}
+
+/// Every binary operator against every pairing of operand types either
+/// gives a value or a runtime error naming the operator and both types.
+#[test]
+fn test_binary_operators_against_every_type_pairing() {
+    let operands = [
+        ("1", "number"),
+        ("\"s\"", "string"),
+        ("true", "boolean"),
+        ("nil", "nil"),
+        ("[]", "list"),
+        ("{}", "map"),
+        ("len", "function"),
+    ];
+    let operators = ["+", "-", "*", "/", "<", "<=", ">", ">=", "==", "!="];
+
+    for operator in operators {
+        for (left, left_type) in operands {
+            for (right, right_type) in operands {
+                let expected = match (operator, left_type, right_type) {
+                    (_, "number", "number") | ("==", _, _) | ("!=", _, _) => None,
+                    ("+", "string", _) | ("+", _, "string") => None,
+                    ("+", ..) => Some("must be two numbers or include a string"),
+                    ("*", "string", "number") | ("*", "number", "string") => None,
+                    ("*", ..) => Some("must be numbers, or a string and a number"),
+                    ("-", ..) | ("/", ..) => Some("must be numbers"),
+                    (_, "string", "string") => None,
+                    _ => Some("compare"),
+                };
+                let expected = expected.map(|problem| match problem {
+                    "compare" => format!(
+                        "cannot compare {} and {} with '{}'",
+                        left_type, right_type, operator
+                    ),
+                    _ => format!(
+                        "Operands of '{}' {}, got {} and {}",
+                        operator, problem, left_type, right_type
+                    ),
+                });
+
+                let source = format!("print {} {} {};", left, operator, right);
+                let column = "print ".len() + left.len() + 1;
+                let result = scan_parse_and_interpret(source.clone(), &mut io::sink());
+                match expected {
+                    None => assert_eq!(result, Ok(()), "{}", source),
+                    Some(message) => {
+                        assert_eq!(
+                            result,
+                            runtime_error(0, column as u32, message),
+                            "{}",
+                            source
+                        )
+                    }
+                }
+            }
+        }
     }
 }
+
+#[test]
+fn test_block_scoping() {
+    let mut buffer = Vec::new();
+    let input = "var a = 1; { var a = 2; print a; } print a;".to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!("2\n1\n", String::from_utf8(buffer).unwrap())
+}
+
+#[test]
+fn test_block_assigns_to_enclosing_scope() {
+    let mut buffer = Vec::new();
+    let input = "var a = 1; { { a = 3; } } print a;".to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!("3\n", String::from_utf8(buffer).unwrap())
+}
+
+#[test]
+fn test_block_local_is_undefined_after_block() {
+    let mut buffer = Vec::new();
+    let input = "{ var b = 2; }\nprint b;".to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+
+    assert_eq!(runtime_error(1, 6, "variable 'b' is not defined"), result)
+}
+
+#[test]
+fn test_if_else() {
+    let mut buffer = Vec::new();
+    let input = r#"if (1 < 2) print "yes"; else print "no";
+        if (nil) print "yes"; else print "no";
+        if (false) print "skipped";"#
+        .to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!("yes\nno\n", String::from_utf8(buffer).unwrap())
+}
+
+#[test]
+fn test_dangling_else_binds_to_nearest_if() {
+    let mut buffer = Vec::new();
+    let input = r#"if (true) if (false) print "inner"; else print "else";"#.to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!("else\n", String::from_utf8(buffer).unwrap())
+}
+
+#[test]
+fn test_program_with_errors_is_not_executed() {
+    let mut buffer = Vec::new();
+    let mut s = Scanner::new("print 1; var = 2;".to_string());
+    let tokens = s.scan_tokens().unwrap().to_vec();
+    let program = Parser::new(tokens).parse_program();
+    let result = Interpreter::new(&mut buffer).interpret(&program.statements);
+
+    assert_eq!(
+        Err(RoxError::runtime(0, "program contains parse errors")),
+        result
+    );
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn test_while_loop() {
+    let mut buffer = Vec::new();
+    let input = "var i = 3; while (i > 0) { print i; i = i - 1; }".to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!("3\n2\n1\n", String::from_utf8(buffer).unwrap())
+}
+
+#[test]
+fn test_do_while_loop() {
+    let mut buffer = Vec::new();
+    let input = "
+var i = 5; do { print i; i = i + 1; } while (i < 3);
+do { print i; i = i - 1; } while (i > 3);
+fun first() { do { return \"returned\"; } while (true); }
+print first();"
+        .to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!("5\n6\n5\n4\nreturned\n", String::from_utf8(buffer).unwrap())
+}
+
+#[test]
+fn test_for_loop() {
+    let mut buffer = Vec::new();
+    let input = "for (var i = 0; i < 5; i = i + 1) print i;".to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!("0\n1\n2\n3\n4\n", String::from_utf8(buffer).unwrap())
+}
+
+#[test]
+fn test_for_loop_with_expression_initializer() {
+    let mut buffer = Vec::new();
+    let input = "var i; for (i = 2; i > 0;) { print i; i = i - 1; } print i;".to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!("2\n1\n0\n", String::from_utf8(buffer).unwrap())
+}
+
+#[test]
+fn test_rox_version_global() {
+    let mut buffer = Vec::new();
+    let input = "print ROX_VERSION;".to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!(
+        format!("{}\n", env!("CARGO_PKG_VERSION")),
+        String::from_utf8(buffer).unwrap()
+    )
+}
+
+#[test]
+fn test_chained_assignment() {
+    let mut buffer = Vec::new();
+    let input = "var a; var b; a = b = 3; print a; print b; print a = 4;".to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!("3\n3\n4\n", String::from_utf8(buffer).unwrap())
+}
+
+#[test]
+fn test_assignment_to_undefined_variable() {
+    let mut buffer = Vec::new();
+    let input = "var a = 1;\nb = a;".to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+
+    assert_eq!(runtime_error(1, 0, "variable 'b' is not defined"), result)
+}
+
+#[test]
+fn test_function_call() {
+    let mut buffer = Vec::new();
+    let input = "fun add(a, b) { print a + b; } add(1, 2); print add;".to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!("3\n<fn add>\n", String::from_utf8(buffer).unwrap())
+}
+
+#[test]
+fn test_function_closes_over_its_scope() {
+    let mut buffer = Vec::new();
+    let input = r#"
+        var greeting = "outer";
+        {
+            var greeting = "inner";
+            fun greet() { print greeting; }
+            greet();
+        }
+        fun count(n) { if (n > 0) { print n; count(n - 1); } }
+        count(2);
+    "#
+    .to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!("inner\n2\n1\n", String::from_utf8(buffer).unwrap())
+}
+
+#[test]
+fn test_closure_keeps_its_binding_after_a_later_declaration() {
+    let mut buffer = Vec::new();
+    let input = r#"
+        var a = "global";
+        {
+            fun showA() { print a; }
+            showA();
+            var a = "block";
+            showA();
+        }
+    "#
+    .to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!("global\nglobal\n", String::from_utf8(buffer).unwrap())
+}
+
+#[test]
+fn test_closure_assigns_the_variable_it_was_bound_to() {
+    let mut buffer = Vec::new();
+    let input = r#"
+        fun counter() {
+            var count = 0;
+            fun next() {
+                ++count;
+                count = count + 1;
+                return count;
+            }
+            return next;
+        }
+        var a = counter();
+        var b = counter();
+        {
+            var count = 100;
+            print a(), a(), b();
+            print count;
+        }
+    "#
+    .to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!("2 4 2\n100\n", String::from_utf8(buffer).unwrap())
+}
+
+#[test]
+fn test_function_arity_mismatch() {
+    let mut buffer = Vec::new();
+    let input = "fun add(a, b) { print a + b; }\nadd(1);".to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+
+    assert_eq!(
+        runtime_error(1, 3, "function 'add' expected 2 arguments but got 1"),
+        result
+    )
+}
+
+#[test]
+fn test_calling_a_non_function() {
+    let mut buffer = Vec::new();
+    let input = "\"add\"(1);".to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+
+    assert_eq!(
+        runtime_error(0, 5, "can only call functions and classes, got string"),
+        result
+    )
+}
+
+#[test]
+fn test_version_natives() {
+    let mut buffer = Vec::new();
+    let input = "print version() == ROX_VERSION; requireVersion(\"0.0.1\");".to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+    assert_eq!("true\n", String::from_utf8(buffer).unwrap());
+
+    let mut buffer = Vec::new();
+    let input = "\nrequireVersion(\"999.1\");".to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+    assert_eq!(
+        called_from(
+            runtime_error(
+                1,
+                14,
+                format!(
+                    "this script requires rox 999.1 or newer (running {})",
+                    env!("CARGO_PKG_VERSION")
+                )
+            ),
+            "requireVersion",
+            1
+        ),
+        result
+    );
+}
+
+#[test]
+fn test_property_access_is_rejected_at_runtime() {
+    let mut buffer = Vec::new();
+    let input = "var a = 1;\n\nprint a.b;".to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+
+    assert_eq!(
+        runtime_error(
+            2,
+            8,
+            "no properties on this value, can't read 'b' from number"
+        ),
+        result
+    )
+}
+
+#[test]
+fn test_return_values() {
+    let mut buffer = Vec::new();
+    let input = r#"
+        fun fib(n) {
+            if (n < 2) return n;
+            return fib(n - 1) + fib(n - 2);
+        }
+        fun nothing() { return; }
+        print fib(10);
+        print nothing();
+    "#
+    .to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!("55\nnil\n", String::from_utf8(buffer).unwrap())
+}
+
+#[test]
+fn test_return_unwinds_nested_loops() {
+    let mut buffer = Vec::new();
+    let input = r#"
+        fun first_over(limit) {
+            for (var i = 0; ; i = i + 1) {
+                while (true) {
+                    if (i > limit) return i;
+                    i = i + 1;
+                }
+            }
+        }
+        print first_over(3);
+    "#
+    .to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!("4\n", String::from_utf8(buffer).unwrap())
+}
+
+#[test]
+fn test_assert() {
+    let mut buffer = Vec::new();
+    let input = "assert 1 + 1 == 2;\nprint 1;\n  assert !(1 < 2);\nprint 2;".to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+
+    assert_eq!(
+        Err(RoxError::Runtime {
+            line: 2,
+            column: Some(2),
+            message: "Assertion failed [line 3]: (! (grouping (< 1 2)))".to_owned(),
+            frames: Vec::new(),
+        }),
+        result
+    );
+    assert_eq!("1\n", String::from_utf8(buffer).unwrap())
+}
+
+#[test]
+fn test_defined() {
+    let mut buffer = Vec::new();
+    let input = "
+print defined a;
+var a;
+print defined a;
+fun f(b) { print defined b; print defined c; }
+f(1);
+{ var c = defined c; print c; }"
+        .to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!(
+        "false\ntrue\ntrue\nfalse\nfalse\n",
+        String::from_utf8(buffer).unwrap()
+    );
+}
+
+#[test]
+fn test_undefine_global() {
+    let mut buffer = Vec::new();
+    let mut interpreter = Interpreter::new(&mut buffer);
+    let parse = |source: &str| {
+        let tokens = Scanner::new(source.to_string())
+            .scan_tokens()
+            .unwrap()
+            .to_vec();
+        Parser::new(tokens).parse().unwrap()
+    };
+
+    interpreter.interpret(&parse("var a = 1;")).unwrap();
+    assert!(interpreter.undefine("a"));
+    assert!(!interpreter.undefine("a"));
+    interpreter.interpret(&parse("print defined a;")).unwrap();
+    assert_eq!(
+        interpreter.interpret(&parse("print a;")),
+        Err(RoxError::Runtime {
+            line: 0,
+            column: Some(6),
+            message: "variable 'a' is not defined".to_owned(),
+            frames: Vec::new(),
+        })
+    );
+    drop(interpreter);
+    assert_eq!("false\n", String::from_utf8(buffer).unwrap());
+}
+
+#[test]
+fn test_return_at_top_level() {
+    let mut buffer = Vec::new();
+    let input = "print 1;\nreturn 2;".to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+
+    assert_eq!(
+        Err(RoxError::runtime(1, "can't return from top-level code")),
+        result
+    )
+}
+
+#[test]
+fn test_failed_call_leaves_no_frames_behind() {
+    let mut interpreter = Interpreter::new(Vec::new());
+    let parse = |source: &str| {
+        let tokens = Scanner::new(source.to_string())
+            .scan_tokens()
+            .unwrap()
+            .to_vec();
+        Parser::new(tokens).parse().unwrap()
+    };
+    let failure = || {
+        called_from(
+            runtime_error(
+                0,
+                21,
+                "Operands of '+' must be two numbers or include a string, got nil and number",
+            ),
+            "f",
+            0,
+        )
+    };
+
+    assert_eq!(
+        failure(),
+        interpreter.interpret(&parse("fun f() { return nil + 1; } f();"))
+    );
+    // as the REPL does, carry on with the same interpreter
+    assert_eq!(failure(), interpreter.interpret(&parse("f();")));
+    assert_eq!(
+        Err(RoxError::runtime(0, "can't return from top-level code")),
+        interpreter.interpret(&parse("return 2;"))
+    );
+}
+
+#[test]
+fn test_repl_line_echoes_trailing_expression() {
+    let mut buffer = Vec::new();
+    let mut interpreter = Interpreter::new(&mut buffer);
+    let mut s = Scanner::new("var a = 1; a * 2".to_string());
+    let tokens = s.scan_tokens().unwrap().to_vec();
+    let mut statements = Parser::for_repl(tokens).parse().unwrap();
+
+    let trailing = match statements.pop() {
+        Some(Stmt {
+            kind: StmtKind::Expr(expr),
+            ..
+        }) => expr,
+        other => panic!("expected a trailing expression, got {:?}", other),
+    };
+    interpreter.interpret(&statements).unwrap();
+
+    assert_eq!(
+        Ok("2".to_string()),
+        interpreter.evaluate_expr_to_string(&trailing)
+    );
+}
+
+#[test]
+fn test_repl_echo_quotes_strings_but_print_does_not() {
+    let mut buffer = Vec::new();
+    let mut interpreter = Interpreter::new(&mut buffer);
+    let mut s = Scanner::new("print \"nil\"; \"nil\"".to_string());
+    let tokens = s.scan_tokens().unwrap().to_vec();
+    let mut statements = Parser::for_repl(tokens).parse().unwrap();
+
+    let trailing = match statements.pop() {
+        Some(Stmt {
+            kind: StmtKind::Expr(expr),
+            ..
+        }) => expr,
+        other => panic!("expected a trailing expression, got {:?}", other),
+    };
+    interpreter.interpret(&statements).unwrap();
+
+    assert_eq!(
+        Ok("\"nil\"".to_string()),
+        interpreter.evaluate_expr_to_string(&trailing)
+    );
+    drop(interpreter);
+    assert_eq!(String::from_utf8(buffer).unwrap(), "nil\n");
+}
+
+#[test]
+fn test_large_program() {
+    let mut buffer = Vec::new();
+    let mut input = String::new();
+    for i in 0..5_000 {
+        input.push_str(&format!("var a{} = {};\n", i, i));
+    }
+    input.push_str(
+        "var total = 0;
+        for (var i = 0; i < 20000; i = i + 1) { total = total + a4999 - a4998; }
+        print total;",
+    );
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!("20000\n", String::from_utf8(buffer).unwrap())
+}
+
+#[test]
+fn test_string_repetition() {
+    let mut buffer = Vec::new();
+    let input = r#"print "ab" * 3; print 2 * "-"; print "x" * 0;"#.to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+    assert_eq!("ababab\n--\n\n", String::from_utf8(buffer).unwrap());
+
+    let mut buffer = Vec::new();
+    let result = scan_parse_and_interpret(r#"print "ab" * 1.5;"#.to_string(), &mut buffer);
+    assert_eq!(
+        runtime_error(
+            0,
+            11,
+            "can only repeat a string a whole, non-negative number of times, got 1.5"
+        ),
+        result
+    );
+
+    let mut buffer = Vec::new();
+    let result = scan_parse_and_interpret(r#"print "ab" * -1;"#.to_string(), &mut buffer);
+    assert_eq!(
+        runtime_error(
+            0,
+            11,
+            "can only repeat a string a whole, non-negative number of times, got -1"
+        ),
+        result
+    );
+
+    let mut buffer = Vec::new();
+    let result = scan_parse_and_interpret(r#"print "ab" * 600000;"#.to_string(), &mut buffer);
+    assert_eq!(
+        runtime_error(
+            0,
+            11,
+            "repeated string would be 1200000 characters long, the limit is 1000000"
+        ),
+        result
+    );
+
+    let mut buffer = Vec::new();
+    let result = scan_parse_and_interpret(r#"print "ab" * "c";"#.to_string(), &mut buffer);
+    assert_eq!(
+        runtime_error(
+            0,
+            11,
+            "Operands of '*' must be numbers, or a string and a number, got string and string"
+        ),
+        result
+    );
+}
+
+#[test]
+fn test_string_concatenation_with_other_values() {
+    let mut buffer = Vec::new();
+    let input = r#"
+        print "x=" + 1.5;
+        print 1.5;
+        print 3 + " items";
+        print "" + nil + true + false;
+        print 1 + 2 + "3";
+    "#
+    .to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+    assert_eq!(
+        "x=1.5\n1.5\n3 items\nniltruefalse\n33\n",
+        String::from_utf8(buffer).unwrap()
+    );
+
+    let mut buffer = Vec::new();
+    let result = scan_parse_and_interpret("print true + 1;".to_string(), &mut buffer);
+    assert_eq!(
+        runtime_error(
+            0,
+            11,
+            "Operands of '+' must be two numbers or include a string, got boolean and number"
+        ),
+        result
+    );
+}
+
+#[test]
+fn test_string_interpolation() {
+    let mut buffer = Vec::new();
+    let input = r#"
+        var a = 1; var b = 2.5;
+        print "sum is ${a + b}!";
+        print "${a}${b}" + 1;
+        print "${a > b ? "big" : "small"} and ${[a, nil]}";
+        print "costs \${a}, not ${ {"k": a}["k"] }";
+        print "${"\${"}";
+    "#
+    .to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!(
+        "sum is 3.5!\n12.51\nsmall and [1, nil]\ncosts ${a}, not 1\n${\n",
+        String::from_utf8(buffer).unwrap()
+    );
+}
+
+#[test]
+fn test_type_names() {
+    let mut buffer = Vec::new();
+    let input = "
+print type(1);
+print type(\"1\");
+print type(true);
+print type(nil);
+print type(type);
+print type([]);
+print type({});
+print type(1) == type(2);
+print \"a\" - nil;"
+        .to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+
+    assert_eq!(
+        "number\nstring\nboolean\nnil\nfunction\nlist\nmap\ntrue\n",
+        String::from_utf8(buffer).unwrap()
+    );
+    assert_eq!(
+        runtime_error(9, 10, "Operands of '-' must be numbers, got string and nil"),
+        result
+    );
+}
+
+#[test]
+fn test_comma_expression() {
+    let mut buffer = Vec::new();
+    let input = r#"
+        print (1, 2, 3);
+        var a; var b;
+        a = 1, b = 2;
+        print a + b;
+        fun add(x, y) { return x + y; }
+        print add((a, 10), b);
+    "#
+    .to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!("3\n3\n12\n", String::from_utf8(buffer).unwrap())
+}
+
+#[test]
+fn test_print_several_values() {
+    let mut buffer = Vec::new();
+    let input = r#"
+        var a = 1;
+        print a, "two", nil, [3];
+        print (a, 2), a = 5;
+        print a;
+    "#
+    .to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!(
+        "1 two nil [3]\n2 5\n5\n",
+        String::from_utf8(buffer).unwrap()
+    );
+
+    let mut buffer = Vec::new();
+    let result = scan_parse_and_interpret("print 1, -\"a\";".to_string(), &mut buffer);
+    assert!(result.is_err());
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn test_increment_and_decrement() {
+    let mut buffer = Vec::new();
+    let input = "
+var i = 1;
+print ++i;
+print i;
+{ var j = i; print --j + 10; print j; }
+print - -i;
+var s = \"a\";
+print ++s;"
+        .to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+
+    assert_eq!("2\n2\n11\n1\n2\n", String::from_utf8(buffer).unwrap());
+    assert_eq!(
+        runtime_error(7, 8, "'s' needs a number for '++', but it holds \"a\""),
+        result
+    );
+}
+
+#[test]
+fn test_lists() {
+    let mut buffer = Vec::new();
+    let input = "
+var a = [1, 2, \"three\"];
+var b = a;
+b[0] = [];
+print a;
+print a[2] + \"!\";
+print [] == [];
+print a == b;
+a[1.5];"
+        .to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+
+    assert_eq!(
+        "[[], 2, three]\nthree!\nfalse\ntrue\n",
+        String::from_utf8(buffer).unwrap()
+    );
+    assert_eq!(
+        runtime_error(8, 1, "list index must be a whole number, got 1.5"),
+        result
+    );
+}
+
+#[test]
+fn test_maps() {
+    let mut buffer = Vec::new();
+    let input = "
+var m = {\"b\": 2, \"a\": [1]};
+var alias = m;
+alias[\"c\"] = 3;
+print m;
+print m[\"a\"][0] + m[\"c\"];
+print m[\"missing\"];
+print keys(m);
+print len(m);
+print {} == {};
+{ print \"a block\"; }
+m[1];"
+        .to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+
+    assert_eq!(
+        "{a: [1], b: 2, c: 3}\n4\nnil\n[a, b, c]\n3\nfalse\na block\n",
+        String::from_utf8(buffer).unwrap()
+    );
+    assert_eq!(
+        runtime_error(11, 1, "map keys must be strings, got 1"),
+        result
+    );
+}
+
+#[test]
+fn test_list_index_errors() {
+    for (input, message) in &[
+        (
+            "[1][1];",
+            "list index 1 out of range for a list of length 1",
+        ),
+        (
+            "[1][-1];",
+            "list index -1 out of range for a list of length 1",
+        ),
+        (
+            "[1][\"0\"] = 2;",
+            "list index must be a whole number, got \"0\"",
+        ),
+        (
+            "\"abc\"[0];",
+            "only lists and maps can be indexed, got string",
+        ),
+    ] {
+        let result = scan_parse_and_interpret(input.to_string(), &mut Vec::new());
+        let column = input.find("][").map_or(5, |i| i + 1);
+        assert_eq!(
+            runtime_error(0, column as u32, *message),
+            result,
+            "{}",
+            input
+        );
+    }
+}
+
+#[test]
+fn test_unicode_identifiers() {
+    let mut buffer = Vec::new();
+    let input =
+        "var café = \"crème\";\n{ var π = 3.14; café = café + π; }\nprint café;\nprint naïve;"
+            .to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+
+    assert_eq!("crème3.14\n", String::from_utf8(buffer).unwrap());
+    assert_eq!(
+        runtime_error(3, 6, "variable 'naïve' is not defined"),
+        result
+    );
+}
+
+#[test]
+fn test_unary_minus_error_has_position() {
+    let mut buffer = Vec::new();
+    let input = "var a = 1;\nprint -a;\n\nprint   -true;\nprint -nil;".to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+
+    assert_eq!("-1\n", String::from_utf8(buffer).unwrap());
+    assert_eq!(
+        runtime_error(3, 8, "Operand of unary '-' must be a number, got true"),
+        result
+    );
+    assert_eq!(
+        "[line 4, col 9] Error: Operand of unary '-' must be a number, got true",
+        result.unwrap_err().to_string()
+    );
+}
+
+#[test]
+fn test_division_errors_show_the_operands() {
+    // there's no exponent syntax, so large and small numbers are written out
+    let (big, tiny) = (number::format(1e300), number::format(1e-300));
+    let cases = vec![
+        (
+            "print 1 / 0;".to_owned(),
+            runtime_error(0, 8, "Divide by zero error: 1 / 0"),
+        ),
+        (
+            "var a = 6;\nprint a / -0;".to_owned(),
+            runtime_error(1, 8, "Divide by zero error: 6 / -0"),
+        ),
+        (
+            format!("print {} / {};", big, tiny),
+            runtime_error(
+                0,
+                big.len() as u32 + 7,
+                format!("Division overflowed: {} / {}", big, tiny),
+            ),
+        ),
+    ];
+    for (input, expected) in cases {
+        let mut buffer = Vec::new();
+        let result = scan_parse_and_interpret(input.clone(), &mut buffer);
+        assert_eq!(expected, result, "{}", input);
+    }
+
+    // an infinity from earlier isn't an overflow of the division
+    let mut buffer = Vec::new();
+    let input = format!("print ({0} * {0}) / 2; print 1 / {1} > 1;", big, tiny);
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+    assert_eq!("inf\ntrue\n", String::from_utf8(buffer).unwrap());
+}
+
+#[test]
+fn test_ternary_uses_truthiness() {
+    let mut buffer = Vec::new();
+    let input = r#"
+        print 1 ? "a" : "b";
+        print nil ? "a" : "b";
+        print "" ? "a" : "b";
+        print false ? 1 : nil ? 2 : 3;
+        print true ? "taken" : undefined;
+        print false ? undefined : "taken";
+        var a; var b;
+        true ? a = 1 : b = 2;
+        false ? a = 3 : b = 4;
+        print a + b;
+    "#
+    .to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!(
+        "a\nb\na\n3\ntaken\ntaken\n5\n",
+        String::from_utf8(buffer).unwrap()
+    )
+}
+
+#[test]
+fn test_number_precision_and_formatting() {
+    let mut buffer = Vec::new();
+    let input =
+        "print 16777217; print 2.0; print 0.1 + 0.2; print 1000000000000000000000;".to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!(
+        "16777217\n2\n0.30000000000000004\n1000000000000000000000\n",
+        String::from_utf8(buffer).unwrap()
+    )
+}
+
+#[test]
+fn test_str_and_num() {
+    let mut buffer = Vec::new();
+    let input = r#"
+print str(2.0) + str(2.5);
+print str([1, "a"]) == "[1, a]";
+print num(" 12.5 ") + 1;
+print num("1e3");
+print num("nan");
+print num("inf");
+print num("12abc");
+print num(str(0.1 + 0.2)) == 0.1 + 0.2;
+num(1);"#
+        .to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+
+    assert_eq!(
+        "22.5\ntrue\n13.5\n1000\nnil\nnil\nnil\ntrue\n",
+        String::from_utf8(buffer).unwrap()
+    );
+    assert_eq!(
+        called_from(
+            runtime_error(9, 3, "num expects a string, got number"),
+            "num",
+            9
+        ),
+        result
+    );
+}
+
+fn interpret_with_limits(source: &str, max_steps: u64, max_millis: u64) -> ExecuteResult {
+    let tokens = Scanner::new(source.to_owned())
+        .scan_tokens()
+        .unwrap()
+        .to_vec();
+    let statements = Parser::new(tokens).parse().unwrap();
+    Interpreter::new(Vec::new())
+        .with_limits(max_steps, max_millis)
+        .interpret(&statements)
+}
+
+#[test]
+fn test_step_budget() {
+    // each statement is one step for itself and one for its expression
+    let source = "1 + 2;\n".repeat(500);
+
+    assert_eq!(interpret_with_limits(&source, 1000, 0), Ok(()));
+    assert_eq!(
+        interpret_with_limits(&source, 999, 0),
+        Err(RoxError::BudgetExceeded {
+            line: Some(499),
+            message: "execution budget exceeded: more than 999 steps".to_owned(),
+        })
+    );
+    assert_eq!(interpret_with_limits(&source, 0, 0), Ok(()));
+}
+
+#[test]
+fn test_time_budget() {
+    let result = interpret_with_limits("while (true) {}", 0, 20);
+
+    assert_eq!(
+        result,
+        Err(RoxError::BudgetExceeded {
+            line: None,
+            message: "execution budget exceeded: ran for more than 20ms".to_owned(),
+        })
+    );
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "Error: execution budget exceeded: ran for more than 20ms"
+    );
+}
+
+#[test]
+fn test_debug_env_lists_variables_in_scope() {
+    let mut buffer = Vec::new();
+    let input = "var c = 3; var a = \"one\"; var b = 2; fun f() { var a = nil; debugEnv(); } f();";
+    let mut interpreter = Interpreter::new(&mut buffer);
+    // leave out the other natives, to keep the listing short
+    for (name, _) in interpreter.globals() {
+        if &*name != "debugEnv" {
+            interpreter.undefine(&name);
+        }
+    }
+    let tokens = Scanner::new(input.to_owned())
+        .scan_tokens()
+        .unwrap()
+        .to_vec();
+    let statements = Parser::new(tokens).parse().unwrap();
+    interpreter.interpret(&statements).unwrap();
+    drop(interpreter);
+
+    assert_eq!(
+        "a = nil\n-- enclosing scope --\na = \"one\" (shadowed)\nb = 2\nc = 3\ndebugEnv = <native fn debugEnv>\nf = <fn f>\n",
+        String::from_utf8(buffer).unwrap()
+    );
+}
+
+#[test]
+fn test_script_arguments() {
+    let mut buffer = Vec::new();
+    let input = "print argc;".to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+    assert_eq!("0\n", String::from_utf8(buffer).unwrap());
+
+    let mut buffer = Vec::new();
+    {
+        let mut interpreter = Interpreter::new(&mut buffer);
+        interpreter.set_args(&["one".to_owned(), "two".to_owned()]);
+        let mut s = Scanner::new("print argc; print arg0; print arg1;".to_string());
+        let tokens = s.scan_tokens().unwrap().to_vec();
+        let statements = Parser::new(tokens).parse().unwrap();
+        interpreter.interpret(&statements).unwrap();
+    }
+    assert_eq!("2\none\ntwo\n", String::from_utf8(buffer).unwrap());
+}
+
+#[test]
+fn test_interpret_continuing_runs_every_statement() {
+    let source = "print 1; print -nil; print 2; print undefined; print 3;";
+    let tokens = Scanner::new(source.to_owned())
+        .scan_tokens()
+        .unwrap()
+        .to_vec();
+    let statements = Parser::new(tokens).parse().unwrap();
+
+    let mut buffer = Vec::new();
+    let errors = Interpreter::new(&mut buffer).interpret_continuing(&statements);
+    assert_eq!(
+        errors,
+        vec![
+            runtime_error(0, 15, "Operand of unary '-' must be a number, got nil").unwrap_err(),
+            runtime_error(0, 36, "variable 'undefined' is not defined").unwrap_err(),
+        ]
+    );
+    assert_eq!("1\n2\n3\n", String::from_utf8(buffer).unwrap());
+
+    let mut buffer = Vec::new();
+    assert!(Interpreter::new(&mut buffer)
+        .interpret(&statements)
+        .is_err());
+    assert_eq!("1\n", String::from_utf8(buffer).unwrap());
+}
+
+#[test]
+fn test_runaway_recursion_is_an_error() {
+    on_main_thread_stack(runaway_recursion)
+}
+
+fn runaway_recursion() {
+    let mut buffer = Vec::new();
+    let input = "
+fun count(n) { if (n == 0) return 0; return 1 + count(n - 1); }
+print count(200);
+fun forever() { return 1 + forever(); }
+forever();"
+        .to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+
+    match result {
+        Err(RoxError::Runtime {
+            line: 3, message, ..
+        }) => {
+            assert_eq!(message, "max call depth exceeded (limit 1000)")
+        }
+        other => panic!("expected a runtime error, got {:?}", other),
+    }
+    assert_eq!("200\n", String::from_utf8(buffer).unwrap());
+}
+
+#[test]
+fn test_returning_a_call_of_itself_does_not_nest() {
+    let mut buffer = Vec::new();
+    let input = "
+fun count(n) { if (n == 0) return \"done\"; return count(n - 1); }
+print count(100000);
+fun sum(n) {
+  fun go(i, total) { if (i > n) return total; return go(i + 1, total + i); }
+  return go(1, 0);
+}
+print sum(100000);
+fun wrong(n) { return wrong(n, 1); }
+wrong(1);"
+        .to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+
+    assert_eq!(
+        called_from(
+            runtime_error(8, 27, "function 'wrong' expected 1 arguments but got 2"),
+            "wrong",
+            9
+        ),
+        result
+    );
+    assert_eq!("done\n5000050000\n", String::from_utf8(buffer).unwrap());
+}
+
+#[test]
+fn test_call_depth_limit() {
+    let mut buffer = Vec::new();
+    let source = "fun down(n) { if (n == 0) return 0; return 1 + down(n - 1); }
+print down(9);
+down(20);";
+    let tokens = Scanner::new(source.to_owned())
+        .scan_tokens()
+        .unwrap()
+        .to_vec();
+    let statements = Parser::new(tokens).parse().unwrap();
+    let result = Interpreter::new(&mut buffer)
+        .with_max_call_depth(10)
+        .interpret(&statements);
+
+    // the calls already made are all in the error
+    let mut expected = runtime_error(0, 51, "max call depth exceeded (limit 10)");
+    for _ in 0..9 {
+        expected = called_from(expected, "down", 0);
+    }
+    assert_eq!(called_from(expected, "down", 2), result);
+    assert_eq!("9\n", String::from_utf8(buffer).unwrap());
+}
+
+/// Runs `source` with tight limits, so a generated program that loops or
+/// recurses forever stops quickly. Only whether it panics matters, so its
+/// output and errors are thrown away.
+fn run_untrusted(source: String) {
+    let tokens = match Scanner::new(source).scan_tokens() {
+        Ok(tokens) => tokens.to_vec(),
+        Err(_) => return,
+    };
+    let mut statements = match Parser::new(tokens).parse() {
+        Ok(statements) => statements,
+        Err(_) => return,
+    };
+    if !resolve(&mut statements).errors.is_empty() {
+        return;
+    }
+    let _ = Interpreter::with_max_depth(io::sink(), 64)
+        .with_limits(10_000, 0)
+        .interpret(&statements);
+}
+
+/// Everything the scanner knows, for building programs that get past it
+/// more often than random text does.
+const TOKENS: &str = r#"
+    ( ) { } [ ] , . ; : ? - + * / % ! != = == > >= < <= ++ --
+    and or assert defined do else for fun if nil print return super this true false var while
+    a b f 0 1 2.5 "" "s" "é" "${a}" "x${f(b)}y" "\${"
+    len substr str num keys type format debugEnv version requireVersion "{}" "{:>4.1}"
+"#;
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(500))]
+
+    #[test]
+    fn test_arbitrary_text_never_panics(source in any::<String>()) {
+        run_untrusted(source);
+    }
+
+    #[test]
+    fn test_arbitrary_programs_never_panic(
+        tokens in prop::collection::vec(prop::sample::select(TOKENS.split_whitespace().collect::<Vec<_>>()), 0..40)
+    ) {
+        run_untrusted(tokens.join(" "));
+    }
+}
+
+#[test]
+fn test_function_expressions_can_be_passed_and_called_in_place() {
+    let mut buffer = Vec::new();
+    let input = r#"
+        fun apply(f, value) { return f(value); }
+        var twice = fun (x) { return x + x; };
+        print apply(twice, 4);
+        print apply(fun (s) { return s + "!"; }, "hi");
+        fun (x) { print x; }(42);
+
+        var offset = 10;
+        var shift = fun (x) { return x + offset; };
+        offset = 20;
+        print shift(1), twice;
+    "#
+    .to_string();
+    scan_parse_and_interpret(input, &mut buffer).unwrap();
+
+    assert_eq!(
+        "8\nhi!\n42\n21 <fn lambda>\n",
+        String::from_utf8(buffer).unwrap()
+    )
+}
+
+#[test]
+fn test_class_construction_checks_init_arity() {
+    let mut buffer = Vec::new();
+    let input = "class Point { init(x, y) {} }\nPoint(1);".to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+
+    assert_eq!(
+        runtime_error(1, 5, "class 'Point' expected 2 arguments but got 1"),
+        result
+    );
+
+    let mut buffer = Vec::new();
+    let input = "class Empty {}\nEmpty(1);".to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+
+    assert_eq!(
+        runtime_error(1, 5, "class 'Empty' expected 0 arguments but got 1"),
+        result
+    );
+}
+
+#[test]
+fn test_only_instances_have_properties() {
+    for (input, column, message) in [
+        (
+            "var a = 1;\nprint a.b;",
+            8,
+            "no properties on this value, can't read 'b' from number",
+        ),
+        (
+            "var a = \"s\";\na.b = 1;",
+            2,
+            "no properties on this value, can't set 'b' on string",
+        ),
+    ]
+    .iter()
+    {
+        let mut buffer = Vec::new();
+        let result = scan_parse_and_interpret(input.to_string(), &mut buffer);
+
+        assert_eq!(runtime_error(1, *column, *message), result);
+    }
+}
+
+#[test]
+fn test_super_method_must_exist() {
+    let mut buffer = Vec::new();
+    let input = "class A {}\nclass B < A { m() { return super.m(); } }\nB().m();".to_string();
+    let result = scan_parse_and_interpret(input, &mut buffer);
+
+    assert_eq!(
+        called_from(
+            runtime_error(1, 33, "superclass 'A' has no method 'm'"),
+            "m",
+            2
+        ),
+        result
+    );
+}
@@ -1,15 +1,24 @@
+use crate::number;
+use crate::span::Span;
+use crate::symbol::Symbol;
 use std::fmt::{self, Display, Formatter};
+use std::sync::Arc;
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TokenType {
     LeftParen,
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
+    MinusMinus,
     Plus,
+    PlusPlus,
     Colon,
     Semicolon,
     Slash,
@@ -22,17 +31,28 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
-    Identifer(String),
-    STRING(String),
-    Number(f32),
+    Identifer(Symbol),
+    STRING(Symbol),
+    /// The `${` of an interpolation in a string. The text before it is a
+    /// `STRING` of its own, as is the text after its `InterpolationEnd`.
+    InterpolationStart,
+    /// The `}` closing an interpolation.
+    InterpolationEnd,
+    Number(f64),
     // Keywords
     And,
+    Assert,
     Class,
+    Const,
+    Defined,
+    Do,
     Else,
+    Eprint,
     False,
     Fun,
     For,
     If,
+    Import,
     Nil,
     Or,
     Print,
@@ -47,9 +67,11 @@ pub enum TokenType {
 }
 
 impl TokenType {
-    pub fn get_identifier_value(&self) -> Option<String> {
+    /// The text of an `Identifer`. `None` for everything else, string
+    /// literals included, as those are values rather than names.
+    pub fn get_identifier_value(&self) -> Option<&Symbol> {
         match self {
-            TokenType::Identifer(val) => Some(val.to_string()),
+            TokenType::Identifer(val) => Some(val),
             _ => None,
         }
     }
@@ -62,10 +84,14 @@ impl Display for TokenType {
             TokenType::RightParen => write!(f, ")"),
             TokenType::LeftBrace => write!(f, "{{"),
             TokenType::RightBrace => write!(f, "}}"),
+            TokenType::LeftBracket => write!(f, "["),
+            TokenType::RightBracket => write!(f, "]"),
             TokenType::Comma => write!(f, ","),
             TokenType::Dot => write!(f, "."),
             TokenType::Minus => write!(f, "-"),
+            TokenType::MinusMinus => write!(f, "--"),
             TokenType::Plus => write!(f, "+"),
+            TokenType::PlusPlus => write!(f, "++"),
             TokenType::Semicolon => write!(f, ";"),
             TokenType::Slash => write!(f, "/"),
             TokenType::Star => write!(f, "*"),
@@ -79,14 +105,22 @@ impl Display for TokenType {
             TokenType::LessEqual => write!(f, "<="),
             TokenType::Identifer(s) => write!(f, "{}", s),
             TokenType::STRING(s) => write!(f, "{}", s),
-            TokenType::Number(n) => write!(f, "{}", n.to_string()),
+            TokenType::InterpolationStart => write!(f, "${{"),
+            TokenType::InterpolationEnd => write!(f, "}}"),
+            TokenType::Number(n) => write!(f, "{}", number::format(*n)),
             TokenType::And => write!(f, "and"),
+            TokenType::Assert => write!(f, "assert"),
             TokenType::Class => write!(f, "class"),
+            TokenType::Const => write!(f, "const"),
+            TokenType::Defined => write!(f, "defined"),
+            TokenType::Do => write!(f, "do"),
             TokenType::Else => write!(f, "else"),
+            TokenType::Eprint => write!(f, "eprint"),
             TokenType::False => write!(f, "false"),
             TokenType::Fun => write!(f, "fun"),
             TokenType::For => write!(f, "for"),
             TokenType::If => write!(f, "if"),
+            TokenType::Import => write!(f, "import"),
             TokenType::Nil => write!(f, "nil"),
             TokenType::Or => write!(f, "or"),
             TokenType::Print => write!(f, "print"),
@@ -103,20 +137,123 @@ impl Display for TokenType {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Token {
     pub tag: TokenType,
     pub line: u32,
+    /// Characters from the start of the line to the start of the token.
+    pub column: u32,
+    /// The byte range of the lexeme in the source. Both are 0 for tokens
+    /// that weren't scanned.
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The byte offsets follow from the line and column, so they're left out
+/// and a token built by hand equals the scanned one.
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        self.tag == other.tag && self.line == other.line && self.column == other.column
+    }
 }
 
 impl Token {
+    /// A token at the start of `line`, handy when the column doesn't matter.
+    #[cfg(test)]
     pub fn new(tag: TokenType, line: u32) -> Self {
-        Self { tag, line }
+        Self::with_column(tag, line, 0)
+    }
+
+    pub fn with_column(tag: TokenType, line: u32, column: u32) -> Self {
+        Self {
+            tag,
+            line,
+            column,
+            start: 0,
+            end: 0,
+        }
+    }
+
+    /// Where the token was written, as a span.
+    pub fn span(&self) -> Span {
+        Span {
+            line: self.line,
+            column: self.column,
+            start: self.start,
+            end: self.end,
+        }
+    }
+
+    /// The identifier this token spells, or `None` if it isn't one.
+    pub fn identifier_name(&self) -> Option<Name> {
+        self.tag.get_identifier_value().map(|symbol| Name {
+            symbol: Arc::clone(symbol),
+            line: self.line,
+            column: self.column,
+            binding: Binding::Dynamic,
+        })
+    }
+}
+
+/// An identifier in the syntax tree: what it says and where it was written.
+/// Variables and declarations hold one of these rather than a whole token,
+/// so nothing after the parser has to check that a token is an identifier.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Name {
+    pub symbol: Symbol,
+    pub line: u32,
+    pub column: u32,
+    /// Which variable a use of the name refers to, once the resolver has
+    /// worked it out. Always `Dynamic` in a declaration.
+    pub binding: Binding,
+}
+
+/// Where the variable a name refers to lives.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Binding {
+    /// Not resolved, so looked for by name from the innermost scope out.
+    Dynamic,
+    /// In the local scope this many out from the innermost one.
+    Local(usize),
+    /// In the global scope, whatever is declared in between by the time
+    /// it's used.
+    Global,
+}
+
+impl Display for Name {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.symbol)
     }
 }
 
 impl Display for Token {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "({}, {})", self.tag, self.line)
+        write!(f, "({}, {})", self.tag, self.line + 1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_identifier_name() {
+        let ident = Token::with_column(TokenType::Identifer("count".into()), 2, 6);
+        assert_eq!(
+            ident.identifier_name(),
+            Some(Name {
+                symbol: "count".into(),
+                line: 2,
+                column: 6,
+                binding: Binding::Dynamic,
+            })
+        );
+
+        let string = Token::with_column(TokenType::STRING("count".into()), 2, 6);
+        assert_eq!(string.identifier_name(), None);
+        assert_eq!(Token::new(TokenType::Var, 0).identifier_name(), None);
     }
 }
@@ -0,0 +1,100 @@
+//! Running a script without any streams to run it against, for hosts such
+//! as a page in a browser.
+
+use crate::error::{self, RoxError};
+use crate::{resolve, Interpreter, Parser, Scanner};
+
+/// Runs `source` as `run_source` does, and returns what it printed and
+/// every error found, each laid out as the command line shows it. Nothing
+/// runs if the source doesn't scan, parse or resolve. What `eprint` writes
+/// comes first among the errors, a line each, as a host is likely to show
+/// the two together.
+pub fn run_to_string(source: &str) -> (String, Vec<String>) {
+    let mut printed = Vec::new();
+    let mut eprinted = Vec::new();
+    let errors = run(source, &mut printed, &mut eprinted);
+
+    let mut reported: Vec<_> = String::from_utf8_lossy(&eprinted)
+        .lines()
+        .map(str::to_owned)
+        .collect();
+    reported.extend(errors.iter().map(|e| error::render(e, source)));
+    (String::from_utf8_lossy(&printed).into_owned(), reported)
+}
+
+fn run(source: &str, printed: &mut Vec<u8>, eprinted: &mut Vec<u8>) -> Vec<RoxError> {
+    let tokens = match Scanner::new(source.to_owned()).scan_tokens() {
+        Ok(tokens) => tokens.to_vec(),
+        Err(errors) => return errors,
+    };
+    let mut statements = match Parser::new(tokens).parse() {
+        Ok(statements) => statements,
+        Err(errors) => return errors,
+    };
+    let resolution = resolve(&mut statements);
+    if !resolution.errors.is_empty() {
+        return resolution.errors;
+    }
+
+    Interpreter::with_streams(printed, eprinted)
+        .interpret(&statements)
+        .err()
+        .into_iter()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::run_to_string;
+
+    #[test]
+    fn test_output_is_returned() {
+        let (output, errors) = run_to_string("var a = 1;\nprint a + 1;\nprint \"done\";");
+
+        assert_eq!(output, "2\ndone\n");
+        assert_eq!(errors, Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_every_scan_error_is_returned_and_nothing_runs() {
+        let (output, errors) = run_to_string("print 1;\nprint @;\nprint #;");
+
+        assert_eq!(output, "");
+        assert_eq!(
+            errors,
+            vec![
+                "[line 2, col 7] Error: Unexpected character: @\n2 | print @;\n  |       ^",
+                "[line 3, col 7] Error: Unexpected character: #\n3 | print #;\n  |       ^",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_output_before_a_runtime_error_is_kept() {
+        let (output, errors) = run_to_string("print 1;\neprint \"oops\";\nprint nil + 1;");
+
+        assert_eq!(output, "1\n");
+        assert_eq!(
+            errors,
+            vec![
+                "oops",
+                "[line 3, col 11] Error: Operands of '+' must be two numbers or include a \
+                 string, got nil and number\n3 | print nil + 1;\n  |           ^",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_errors_are_returned() {
+        let (output, errors) = run_to_string("print 1;\n{ var a = a; }");
+
+        assert_eq!(output, "");
+        assert_eq!(
+            errors,
+            vec![
+                "[line 2, col 11] Error at 'a': Cannot read local variable in its own \
+                 initializer\n2 | { var a = a; }\n  |           ^"
+            ]
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Remembers the modification time of the last run so a watch loop only
+/// re-runs a script after the file actually changed.
+pub struct Watcher<M> {
+    mtime: M,
+    last_run: Option<SystemTime>,
+    /// A new modification time seen on the last poll, which is waited on
+    /// until it stops changing.
+    pending: Option<SystemTime>,
+}
+
+impl<M: FnMut() -> io::Result<SystemTime>> Watcher<M> {
+    pub fn new(mtime: M) -> Self {
+        Self {
+            mtime,
+            last_run: None,
+            pending: None,
+        }
+    }
+
+    /// Whether the file should be run: on the first poll, and after a change
+    /// once the modification time is the same on two polls in a row, so an
+    /// editor writing a file in several goes only causes one run. A file
+    /// whose modification time can't be read (e.g. it is being replaced by
+    /// an editor) counts as unchanged.
+    pub fn changed(&mut self) -> bool {
+        let time = match (self.mtime)() {
+            Ok(time) => time,
+            Err(_) => return false,
+        };
+        if self.last_run == Some(time) {
+            self.pending = None;
+            false
+        } else if self.last_run.is_none() || self.pending == Some(time) {
+            self.last_run = Some(time);
+            self.pending = None;
+            true
+        } else {
+            self.pending = Some(time);
+            false
+        }
+    }
+
+    /// Calls `run` if the file changed since the last poll and returns
+    /// whether it did. A failing run is reported but never stops the watcher.
+    pub fn poll<E: Display>(&mut self, run: impl FnOnce() -> Result<(), E>) -> bool {
+        if !self.changed() {
+            return false;
+        }
+
+        if let Err(e) = run() {
+            eprintln!("{}", e);
+        }
+        true
+    }
+}
+
+/// Runs the file at `path` now and again every time it is modified, until the
+/// process is interrupted.
+pub fn watch_file(
+    path: &Path,
+    interval: Duration,
+    mut run: impl FnMut(&Path) -> io::Result<()>,
+) -> ! {
+    let mut watcher = Watcher::new(|| fs::metadata(path).and_then(|m| m.modified()));
+    let mut runs = 0;
+
+    loop {
+        let ran = watcher.poll(|| {
+            if runs > 0 {
+                println!("{}", separator(path, SystemTime::now()));
+            }
+            run(path)
+        });
+
+        if ran {
+            runs += 1;
+        }
+        thread::sleep(interval);
+    }
+}
+
+/// The line printed before each re-run, saying when it started, in UTC.
+fn separator(path: &Path, now: SystemTime) -> String {
+    let seconds = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() % (24 * 60 * 60);
+    format!(
+        "--- {} changed, re-running at {:02}:{:02}:{:02} UTC ---",
+        path.display(),
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn times(secs: &[u64]) -> impl FnMut() -> io::Result<SystemTime> {
+        let mut times = secs
+            .iter()
+            .map(|s| SystemTime::UNIX_EPOCH + Duration::from_secs(*s))
+            .collect::<Vec<_>>()
+            .into_iter();
+        move || Ok(times.next().expect("polled more often than expected"))
+    }
+
+    #[test]
+    fn test_reruns_on_change() {
+        let mut watcher = Watcher::new(times(&[1, 2, 2, 3, 3]));
+        let mut runs = 0;
+        let mut run = || -> Result<(), String> {
+            runs += 1;
+            Ok(())
+        };
+
+        // each change is run once it has been seen twice
+        let ran: Vec<_> = (0..5).map(|_| watcher.poll(&mut run)).collect();
+        assert_eq!(ran, [true, false, true, false, true]);
+        assert_eq!(runs, 3);
+    }
+
+    #[test]
+    fn test_rapid_writes_run_once() {
+        let mut watcher = Watcher::new(times(&[1, 2, 3, 4, 4, 4]));
+
+        let changed: Vec<_> = (0..6).map(|_| watcher.changed()).collect();
+        assert_eq!(changed, [true, false, false, false, true, false]);
+    }
+
+    #[test]
+    fn test_no_rerun_without_change() {
+        let mut watcher = Watcher::new(times(&[1, 1, 1, 2, 2]));
+        let mut runs = 0;
+        let mut run = || -> Result<(), String> {
+            runs += 1;
+            Ok(())
+        };
+
+        assert!(watcher.poll(&mut run));
+        assert!(!watcher.poll(&mut run));
+        assert!(!watcher.poll(&mut run));
+        assert!(!watcher.poll(&mut run));
+        assert!(watcher.poll(&mut run));
+        assert_eq!(runs, 2);
+    }
+
+    #[test]
+    fn test_unreadable_file_counts_as_unchanged() {
+        let mut results = vec![
+            Ok(SystemTime::UNIX_EPOCH),
+            Err(io::Error::new(io::ErrorKind::NotFound, "gone")),
+            Ok(SystemTime::UNIX_EPOCH),
+        ]
+        .into_iter();
+        let mut watcher = Watcher::new(move || results.next().unwrap());
+
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+        assert!(!watcher.changed());
+    }
+
+    #[test]
+    fn test_separator_shows_the_time() {
+        let now = UNIX_EPOCH + Duration::from_secs(3 * 24 * 60 * 60 + 13 * 3600 + 5 * 60 + 9);
+
+        assert_eq!(
+            separator(Path::new("game.lox"), now),
+            "--- game.lox changed, re-running at 13:05:09 UTC ---"
+        );
+    }
+
+    #[test]
+    fn test_survives_failing_run() {
+        let mut watcher = Watcher::new(times(&[1, 2, 2]));
+        let mut runs = 0;
+
+        assert!(watcher.poll(|| {
+            runs += 1;
+            Err("[line 1] Error: unexpected ';'")
+        }));
+        assert!(!watcher.changed());
+        assert!(watcher.poll(|| -> Result<(), String> {
+            runs += 1;
+            Ok(())
+        }));
+        assert_eq!(runs, 2);
+    }
+}
@@ -0,0 +1,67 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn print_ast(name: &str, source: &str) -> Output {
+    let path: PathBuf = env::temp_dir().join(format!("rox_ast_{}.lox", name));
+    fs::write(&path, source).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rox"))
+        .arg("--ast")
+        .arg(&path)
+        .output()
+        .unwrap();
+
+    fs::remove_file(&path).unwrap();
+    output
+}
+
+#[test]
+fn test_ast_is_printed_instead_of_run() {
+    let output = print_ast("ok", "var a = 1 + 2;\nprint a * 3;");
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "(var 'a' (+ 1 2))\n(print (* variable: 'a' 3))\n"
+    );
+}
+
+#[test]
+fn test_ast_parse_error_exits_65() {
+    let output = print_ast("parse", "print 1;\nvar = 2;");
+
+    assert_eq!(output.status.code(), Some(65));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_ast_subcommand_matches_the_flag() {
+    let path: PathBuf = env::temp_dir().join("rox_ast_subcommand.lox");
+    fs::write(&path, "print 1;").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rox"))
+        .arg("ast")
+        .arg(&path)
+        .output()
+        .unwrap();
+
+    fs::remove_file(&path).unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "(print 1)\n");
+}
+
+#[cfg(not(feature = "serde"))]
+#[test]
+fn test_json_needs_the_serde_feature() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rox"))
+        .args(["ast", "--json", "missing.lox"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(64));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("--features serde"));
+}
@@ -0,0 +1,86 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn check(name: &str, source: &str) -> Output {
+    let path: PathBuf = env::temp_dir().join(format!("rox_check_{}.lox", name));
+    fs::write(&path, source).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rox"))
+        .arg("check")
+        .arg(&path)
+        .output()
+        .unwrap();
+
+    fs::remove_file(&path).unwrap();
+    output
+}
+
+#[test]
+fn test_clean_file_is_not_run() {
+    let output = check("ok", "print 1;\n{ var a = 2; print a; }");
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_warnings_keep_exit_code_zero() {
+    let output = check("unused", "{\n  var unused = 1;\n}");
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "[line 2, col 7] Warning: variable 'unused' is declared but never read
+2 |   var unused = 1;
+  |       ^
+"
+    );
+}
+
+#[test]
+fn test_redeclaration_exits_65() {
+    let output = check("redeclared", "{ var a = 1; var a = 2; print a; }");
+
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "[line 1, col 18] Error at 'a': variable 'a' already declared in this scope
+1 | { var a = 1; var a = 2; print a; }
+  |                  ^
+"
+    );
+}
+
+#[test]
+fn test_parse_errors_are_all_reported_and_nothing_runs() {
+    let output = check("parse", "print \"ran\";\nvar = 1;\nprint (2;");
+
+    assert_eq!(output.status.code(), Some(65));
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "[line 2, col 5] Error at '=': expected an identifer after 'var' keyword
+2 | var = 1;
+  |     ^
+[line 3, col 9] Error at ';': expected ')' after expression
+3 | print (2;
+  |         ^
+"
+    );
+}
+
+#[test]
+fn test_check_needs_exactly_one_path() {
+    for args in [&["check"][..], &["check", "a.lox", "b.lox"]].iter() {
+        let status = Command::new(env!("CARGO_BIN_EXE_rox"))
+            .args(args.iter())
+            .output()
+            .unwrap()
+            .status;
+
+        assert_eq!(status.code(), Some(64), "rox {:?}", args);
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use rox::{Diagnostic, Interpreter, Parser, RoxError, Scanner, Value, Warning};
+
+#[test]
+fn test_run_source_captures_output() {
+    let mut out = Vec::new();
+    let source = r#"
+        fun greet(name) { return "hello " + name; }
+        for (var i = 0; i < 2; i = i + 1) { print i; }
+        print greet("rox");
+    "#;
+
+    rox::run_source(source, &mut out).unwrap();
+
+    assert_eq!("0\n1\nhello rox\n", String::from_utf8(out).unwrap());
+}
+
+#[test]
+fn test_run_source_reports_errors() {
+    let mut out = Vec::new();
+    let result = rox::run_source("print 1;\nprint nope;", &mut out);
+
+    match result {
+        Err(RoxError::Runtime { line, message, .. }) => {
+            assert_eq!(line, 1);
+            assert_eq!(message, "variable 'nope' is not defined");
+        }
+        other => panic!("expected a runtime error, got {:?}", other),
+    }
+    assert_eq!("1\n", String::from_utf8(out).unwrap());
+
+    assert!(matches!(
+        rox::run_source("print ;", Vec::new()),
+        Err(RoxError::Parse { .. })
+    ));
+}
+
+#[test]
+fn test_check_source_runs_nothing() {
+    assert_eq!(rox::check_source("print 1;\nfun f() { return 2; }"), vec![]);
+
+    let messages = |source| {
+        rox::check_source(source)
+            .iter()
+            .map(|d| (d.is_error(), d.to_string()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        messages("print 1;\nvar = 2;\nprint (2;"),
+        vec![
+            (
+                true,
+                "[line 2, col 5] Error at '=': expected an identifer after 'var' keyword"
+                    .to_owned()
+            ),
+            (
+                true,
+                "[line 3, col 9] Error at ';': expected ')' after expression".to_owned()
+            ),
+        ]
+    );
+
+    // warnings and errors come in the order they are in the source
+    let diagnostics = rox::check_source("{\n  var unused = 1;\n  var a = a;\n}");
+    assert_eq!(
+        diagnostics[0],
+        Diagnostic::Warning(Warning {
+            line: 1,
+            column: 6,
+            message: "variable 'unused' is declared but never read".to_owned(),
+        })
+    );
+    assert!(matches!(
+        &diagnostics[1],
+        Diagnostic::Error(RoxError::Resolve { line: 2, name, .. }) if name == "a"
+    ));
+}
+
+#[test]
+fn test_eval_expr_with_bindings() {
+    let mut bindings = HashMap::new();
+    bindings.insert("a".to_owned(), Value::Number(2.0));
+    bindings.insert("b".to_owned(), Value::Number(5.0));
+    bindings.insert("name".to_owned(), Value::Str("rox".to_owned()));
+
+    let eval = |source| rox::eval_expr(source, &bindings).map(|value| value.inspect());
+    assert_eq!(eval("(a + b) * 2"), Ok("14".to_owned()));
+    assert_eq!(
+        eval("a < b ? \"less\" : \"more\""),
+        Ok("\"less\"".to_owned())
+    );
+    assert_eq!(
+        eval("a * 2 >= b == (name == \"rox\")"),
+        Ok("false".to_owned())
+    );
+    assert_eq!(eval("\"hi \" + name"), Ok("\"hi rox\"".to_owned()));
+
+    match eval("a + c") {
+        Err(RoxError::Runtime { message, .. }) => {
+            assert_eq!(message, "variable 'c' is not defined")
+        }
+        other => panic!("expected a runtime error, got {:?}", other),
+    }
+    assert!(matches!(eval("a + b;"), Err(RoxError::Parse { .. })));
+}
+
+#[test]
+fn test_oversized_source_is_refused_by_every_entry_point() {
+    let limit = rox::ScannerOptions::default().max_source_size;
+    let source = " ".repeat(limit + 1);
+    let too_large = format!(
+        "[line 1, col 1] Error: input too large: source is {} bytes, the limit is {} bytes",
+        limit + 1,
+        limit
+    );
+
+    let error = rox::run_source(&source, io::sink()).unwrap_err();
+    assert_eq!(error.to_string(), too_large);
+    let diagnostics = rox::check_source(&source);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].to_string(), too_large);
+    let error = rox::eval_expr(&source, &HashMap::new()).unwrap_err();
+    assert_eq!(error.to_string(), too_large);
+}
+
+#[test]
+fn test_interpreter_keeps_state_between_inputs() {
+    let mut out = Vec::new();
+    {
+        let mut interpreter = Interpreter::new(&mut out);
+        for source in &["var a = 1;", "a = a + 1;", "print a;"] {
+            let mut scanner = Scanner::new(source.to_string());
+            let tokens = scanner.scan_tokens().unwrap().to_vec();
+            let statements = Parser::new(tokens).parse().unwrap();
+            interpreter.interpret(&statements).unwrap();
+        }
+    }
+
+    assert_eq!("2\n", String::from_utf8(out).unwrap());
+}
+
+#[test]
+fn test_output_and_errors_go_to_separate_streams() {
+    let mut out = Vec::new();
+    let mut err = Vec::new();
+    {
+        let mut interpreter = Interpreter::with_streams(&mut out, &mut err);
+        let source = "print \"out\";\neprint \"warning:\", 1 + 1;\nprint -nil;";
+        let tokens = Scanner::new(source.to_owned())
+            .scan_tokens()
+            .unwrap()
+            .to_vec();
+        let statements = Parser::new(tokens).parse().unwrap();
+
+        let error = interpreter.interpret(&statements).unwrap_err();
+        interpreter.report(&error, source).unwrap();
+    }
+
+    assert_eq!("out\n", String::from_utf8(out).unwrap());
+    assert_eq!(
+        "warning: 2\n\
+         [line 3, col 7] Error: Operand of unary '-' must be a number, got nil\n\
+         3 | print -nil;\n  |       ^\n",
+        String::from_utf8(err).unwrap()
+    );
+}
+
+/// Output that stays readable after the interpreter writing it has moved to
+/// another thread.
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_interpreters_run_on_other_threads() {
+    let workers: Vec<_> = (1..=2)
+        .map(|n| {
+            let out = SharedBuffer::default();
+            let mut interpreter = Interpreter::new(out.clone());
+            let source = format!(
+                "var total = 0; for (var i = 0; i < 1000; i = i + 1) {{ total = total + {}; }} print total;",
+                n
+            );
+            let tokens = Scanner::new(source).scan_tokens().unwrap().to_vec();
+            let statements = Parser::new(tokens).parse().unwrap();
+
+            let worker = thread::spawn(move || interpreter.interpret(&statements));
+            (worker, out)
+        })
+        .collect();
+
+    let printed: Vec<_> = workers
+        .into_iter()
+        .map(|(worker, out)| {
+            worker.join().unwrap().unwrap();
+            let bytes = out.0.lock().unwrap().clone();
+            String::from_utf8(bytes).unwrap()
+        })
+        .collect();
+    assert_eq!(printed, vec!["1000\n", "2000\n"]);
+}
+
+#[test]
+fn test_float_precision_applies_to_printed_numbers() {
+    let mut out = Vec::new();
+    {
+        let mut interpreter = Interpreter::new(&mut out);
+        let source = r#"print 1 / 3, 2, "1.5", [0.5]; print format("{}", 2.5);"#;
+        let tokens = Scanner::new(source.to_owned())
+            .scan_tokens()
+            .unwrap()
+            .to_vec();
+        let statements = Parser::new(tokens).parse().unwrap();
+
+        interpreter.set_float_precision(Some(2));
+        interpreter.interpret(&statements).unwrap();
+        interpreter.set_float_precision(None);
+        interpreter.interpret(&statements).unwrap();
+    }
+
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "0.33 2.00 1.5 [0.5]\n2.50\n0.3333333333333333 2 1.5 [0.5]\n2.5\n"
+    );
+}
+
+/// Runs `source`, resolved, in an interpreter in lenient mode or not, and
+/// returns how it went with what it printed and warned.
+fn run_in_mode(lenient: bool, source: &str) -> (Result<(), RoxError>, String, String) {
+    let mut out = Vec::new();
+    let mut err = Vec::new();
+    let result = {
+        let mut interpreter = Interpreter::with_streams(&mut out, &mut err);
+        interpreter.set_lenient(lenient);
+        let tokens = Scanner::new(source.to_owned())
+            .scan_tokens()
+            .unwrap()
+            .to_vec();
+        let mut statements = Parser::new(tokens).parse().unwrap();
+        assert!(rox::resolve(&mut statements).errors.is_empty());
+        interpreter.interpret(&statements)
+    };
+    (
+        result,
+        String::from_utf8(out).unwrap(),
+        String::from_utf8(err).unwrap(),
+    )
+}
+
+#[test]
+fn test_lenient_mode_reads_undefined_variables_as_nil() {
+    let source = "
+print defined missing;
+print missing;
+print missing ? \"set\" : \"unset\", missing == nil;
+missing = 1;
+print defined missing, missing;
+{ inner = 2; }
+fun f() { fromCall = 3; }
+f();
+print inner, fromCall;";
+
+    let (result, out, err) = run_in_mode(false, source);
+    match result {
+        Err(RoxError::Runtime { line, message, .. }) => {
+            assert_eq!(line, 2);
+            assert_eq!(message, "variable 'missing' is not defined");
+        }
+        other => panic!("expected a runtime error, got {:?}", other),
+    }
+    assert_eq!((out.as_str(), err.as_str()), ("false\n", ""));
+
+    let (result, out, err) = run_in_mode(true, source);
+    assert_eq!(result, Ok(()));
+    assert_eq!(out, "false\nnil\nunset true\ntrue 1\n2 3\n");
+    // once per name, however many times it's read
+    assert_eq!(
+        err,
+        "[line 3, col 7] Warning: variable 'missing' is not defined, so it reads as nil\n"
+    );
+}
+
+#[test]
+fn test_lenient_mode_is_per_interpreter() {
+    let source = "x = 1; print x;";
+    let (lenient, _, _) = run_in_mode(true, source);
+    let (strict, _, _) = run_in_mode(false, source);
+
+    assert_eq!(lenient, Ok(()));
+    assert!(matches!(strict, Err(RoxError::Runtime { .. })));
+
+    // declared variables and constants behave as they always do
+    let (result, out, _) = run_in_mode(true, "const c = 1; c = 2;");
+    assert!(
+        matches!(result, Err(RoxError::Runtime { message, .. }) if message.contains("constant"))
+    );
+    assert_eq!(out, "");
+}
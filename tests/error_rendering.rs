@@ -0,0 +1,79 @@
+use std::process::{Command, Output};
+
+fn run_fixture(name: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rox"))
+        .arg(format!(
+            "{}/tests/fixtures/{}.lox",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        ))
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_scan_errors_show_their_lines() {
+    let output = run_fixture("scan_errors");
+
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "[line 2, col 9] Error: Unexpected character: @
+2 | print 2 @ 3;
+  |         ^
+[line 3, col 7] Error: Unexpected character: #
+3 |   var # = 4;
+  |       ^
+"
+    );
+}
+
+#[test]
+fn test_parse_errors_show_their_lines() {
+    let output = run_fixture("parse_errors");
+
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "[line 1, col 5] Error at '=': expected an identifer after 'var' keyword
+1 | var = 1;
+  |     ^
+[line 2, col 9] Error at ';': expected ')' after expression
+2 | print (1;
+  |         ^
+"
+    );
+}
+
+#[test]
+fn test_runtime_error_shows_its_line() {
+    let output = run_fixture("runtime_error");
+
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "hi\n");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "[line 4, col 9] Error: Operand of unary '-' must be a number, got \"hi\"
+4 | \treturn -words;
+  | \t       ^
+    in function 'shout' called at line 6
+"
+    );
+}
+
+#[test]
+fn test_runtime_error_shows_the_calls_it_happened_in() {
+    let output = run_fixture("stack_trace");
+
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "[line 2, col 14] Error: Operands of '+' must be two numbers or include a string, got nil and number
+2 |   return nil + 1;
+  |              ^
+    in function 'inner' called at line 5
+    in function 'middle' called at line 8
+    in function 'outer' called at line 10
+"
+    );
+}
@@ -0,0 +1,122 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+fn run_script(name: &str, source: &str) -> Option<i32> {
+    let path: PathBuf = env::temp_dir().join(format!("rox_exit_codes_{}.lox", name));
+    fs::write(&path, source).unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_rox"))
+        .arg(&path)
+        .output()
+        .unwrap()
+        .status;
+
+    fs::remove_file(&path).unwrap();
+    status.code()
+}
+
+#[test]
+fn test_successful_script_exits_zero() {
+    assert_eq!(run_script("ok", "print 1;"), Some(0));
+}
+
+#[test]
+fn test_parse_error_exits_65() {
+    assert_eq!(run_script("parse", "print 1;\nvar = 2;"), Some(65));
+}
+
+#[test]
+fn test_resolve_error_exits_65_before_running() {
+    // the print would exit 70 if the script had been run
+    assert_eq!(
+        run_script("resolve", "print -nil;\n{ var a = a; }"),
+        Some(65)
+    );
+}
+
+#[test]
+fn test_runtime_error_exits_70() {
+    assert_eq!(run_script("runtime", "print 1;\nprint -nil;"), Some(70));
+}
+
+#[test]
+fn test_failed_assertion_exits_70() {
+    assert_eq!(run_script("assert_ok", "assert 1 + 1 == 2;"), Some(0));
+    assert_eq!(run_script("assert", "assert false;"), Some(70));
+}
+
+#[test]
+fn test_bad_flag_usage_exits_64() {
+    for args in [
+        &["--ast"][..],
+        &["--ast", "a.lox", "b.lox"],
+        &["--nope", "a.lox"],
+    ]
+    .iter()
+    {
+        let status = Command::new(env!("CARGO_BIN_EXE_rox"))
+            .args(args.iter())
+            .output()
+            .unwrap()
+            .status;
+
+        assert_eq!(status.code(), Some(64), "rox {:?}", args);
+    }
+}
+
+#[test]
+fn test_script_arguments() {
+    let path: PathBuf = env::temp_dir().join("rox_exit_codes_args.lox");
+    fs::write(&path, "print argc; print arg0 + arg1;").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rox"))
+        .arg(&path)
+        .args(["a", "b"])
+        .output()
+        .unwrap();
+
+    fs::remove_file(&path).unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "2\nab\n");
+}
+
+#[test]
+fn test_optimized_runs_still_fail_at_runtime() {
+    let path: PathBuf = env::temp_dir().join("rox_exit_codes_optimize.lox");
+    fs::write(&path, "print 2 * 3;\nprint 1 / 0;").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rox"))
+        .arg("--optimize")
+        .arg(&path)
+        .output()
+        .unwrap();
+
+    fs::remove_file(&path).unwrap();
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "6\n");
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("[line 2, col 9] Error: Divide by zero error"));
+}
+
+#[test]
+fn test_trace_goes_to_stderr() {
+    let path: PathBuf = env::temp_dir().join("rox_exit_codes_trace.lox");
+    fs::write(&path, "var a = 2;\nprint a;").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rox"))
+        .arg("--trace")
+        .arg(&path)
+        .output()
+        .unwrap();
+
+    fs::remove_file(&path).unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "2\n");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "var a = 2;\n  write a = 2\nprint a;\n  read a = 2\n"
+    );
+}
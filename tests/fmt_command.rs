@@ -0,0 +1,63 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn fmt(name: &str, source: &str, args: &[&str]) -> (Output, String) {
+    let path: PathBuf = env::temp_dir().join(format!("rox_fmt_{}.lox", name));
+    fs::write(&path, source).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rox"))
+        .arg("fmt")
+        .args(args)
+        .arg(&path)
+        .output()
+        .unwrap();
+
+    let after = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    (output, after)
+}
+
+#[test]
+fn test_formatted_source_is_printed() {
+    let source = "var a=1;if(a)print a;";
+    let (output, after) = fmt("print", source, &[]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "var a = 1;\nif (a)\n    print a;\n"
+    );
+    assert_eq!(after, source);
+}
+
+#[test]
+fn test_write_rewrites_the_file() {
+    let (output, after) = fmt("write", "fun f(){return 1+2;}", &["--write"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    assert_eq!(after, "fun f() {\n    return 1 + 2;\n}\n");
+}
+
+#[test]
+fn test_write_leaves_files_with_comments_alone() {
+    let source = "// the answer\nprint 42;";
+    let (output, after) = fmt("comments", source, &["--write"]);
+
+    assert_eq!(output.status.code(), Some(65));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("has comments, which formatting would remove"));
+    assert_eq!(after, source);
+}
+
+#[test]
+fn test_parse_error_exits_65() {
+    let (output, after) = fmt("parse", "print (1;", &["--write"]);
+
+    assert_eq!(output.status.code(), Some(65));
+    assert!(output.stdout.is_empty());
+    assert_eq!(after, "print (1;");
+}
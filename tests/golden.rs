@@ -0,0 +1,110 @@
+//! Runs every script in `tests/scripts` as a spec, checking what it printed
+//! against the expectations written in its comments:
+//!
+//! - `// expect: text` for each line the script prints, in order,
+//! - `// expect-error: text` for each error, as the error displays.
+//!
+//! Every script is also run with its constants folded, which must not change
+//! what it does.
+//!
+//! To add a test, drop a script in the directory. Running with
+//! `UPDATE_GOLDEN=1` rewrites the expectations to match what the scripts
+//! do now: existing ones are updated in order, ones that no longer happen
+//! are removed, and new ones are added at the end of the script.
+
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rox::spec::{expected, outcome, updated, Outcome};
+
+fn scripts() -> Vec<PathBuf> {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scripts");
+    let mut scripts: Vec<_> = fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension() == Some(OsStr::new("lox")))
+        .collect();
+    scripts.sort();
+    scripts
+}
+
+/// Expected and actual lines side by side, with the ones that differ marked.
+fn diff(expected: &[String], actual: &[String]) -> String {
+    let mut out = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        let (expected, actual) = (expected.get(i), actual.get(i));
+        let marker = if expected == actual { ' ' } else { '!' };
+        out += &format!(
+            "  {} expected {:?}\n  {}   actual {:?}\n",
+            marker, expected, marker, actual
+        );
+    }
+    out
+}
+
+#[test]
+fn test_golden_scripts() {
+    let update = matches!(env::var("UPDATE_GOLDEN").as_deref(), Ok("1"));
+    let scripts = scripts();
+    assert!(!scripts.is_empty(), "no scripts found in tests/scripts");
+
+    let mut failures = Vec::new();
+    for path in &scripts {
+        let source = fs::read_to_string(path).unwrap();
+        let actual = outcome(&source, path, false);
+        let expected = expected(&source);
+        if actual == expected {
+            continue;
+        }
+
+        if update {
+            fs::write(path, updated(&source, &actual)).unwrap();
+            continue;
+        }
+        let mut failure = format!("{}:\n", path.display());
+        if actual.output != expected.output {
+            failure += &format!(" output\n{}", diff(&expected.output, &actual.output));
+        }
+        if actual.errors != expected.errors {
+            failure += &format!(" errors\n{}", diff(&expected.errors, &actual.errors));
+        }
+        failures.push(failure);
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} scripts failed, rerun with UPDATE_GOLDEN=1 to accept their output\n\n{}",
+        failures.len(),
+        scripts.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn test_folding_constants_changes_nothing() {
+    for path in scripts() {
+        let source = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            outcome(&source, &path, true),
+            outcome(&source, &path, false),
+            "{}",
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn test_updating_keeps_code_and_order() {
+    let source = "print 1; // expect: 2\n// expect: gone\nprint 3;\n// expect-error: old";
+    let actual = Outcome {
+        output: vec!["1".to_owned()],
+        errors: vec!["new".to_owned(), "extra".to_owned()],
+    };
+
+    assert_eq!(
+        updated(source, &actual),
+        "print 1; // expect: 1\nprint 3;\n// expect-error: new\n// expect-error: extra\n"
+    );
+}
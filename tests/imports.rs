@@ -0,0 +1,64 @@
+use std::process::{Command, Output};
+
+fn run_fixture(name: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rox"))
+        .arg(format!(
+            "{}/tests/fixtures/imports/{}.lox",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        ))
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_each_file_is_imported_once() {
+    let output = run_fixture("main");
+
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "base runs once
+left sees hello
+right sees hello
+left, right and hello
+"
+    );
+}
+
+#[test]
+fn test_missing_import_is_reported_at_the_import() {
+    let output = run_fixture("missing");
+
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "before\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with("[line 2, col 1] Error: could not import 'nowhere.lox': "),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.ends_with("\n2 | import \"nowhere.lox\";\n  | ^\n"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_error_in_imported_file_shows_both_places() {
+    let output = run_fixture("broken_import");
+
+    // a file that doesn't parse is a static error, wherever it's imported
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "before\n");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "[line 2, col 1] Error at 'import': in imported file 'lib/broken.lox': \
+[line 2, col 5] Error at '=': expected an identifer after 'var' keyword
+2 | import \"lib/broken.lox\";
+  | ^
+"
+    );
+}
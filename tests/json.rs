@@ -0,0 +1,116 @@
+//! The syntax tree as JSON, with the `serde` feature: every fixture must
+//! come back from JSON as the program it was, and the format of a small
+//! one is pinned down so it doesn't change by accident.
+#![cfg(feature = "serde")]
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use rox::{Interpreter, Parser, Scanner, Stmt};
+
+fn parse(source: &str) -> Option<Vec<Stmt>> {
+    let tokens = Scanner::new(source.to_owned()).scan_tokens().ok()?.to_vec();
+    Parser::new(tokens).parse().ok()
+}
+
+#[test]
+fn test_every_script_round_trips() {
+    let mut round_tripped = 0;
+    for dir in [
+        "tests/scripts",
+        "tests/fixtures",
+        "tests/fixtures/imports",
+        "tests/fixtures/imports/lib",
+    ] {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(dir);
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension() != Some(OsStr::new("lox")) {
+                continue;
+            }
+            let statements = match parse(&fs::read_to_string(&path).unwrap()) {
+                Some(statements) => statements,
+                None => continue,
+            };
+
+            let json = serde_json::to_string(&statements).unwrap();
+            let read: Vec<Stmt> = serde_json::from_str(&json).unwrap();
+            assert_eq!(read, statements, "{}", path.display());
+            round_tripped += 1;
+        }
+    }
+    assert!(round_tripped > 20);
+}
+
+#[test]
+fn test_layout_only_changes_the_positions() {
+    let one_line = parse("fun f(a) { return a * 2; } print f(1);").unwrap();
+    let many_lines =
+        parse("fun f(a) {\n    return a\n        * 2;\n}\nprint f(\n    1\n);").unwrap();
+
+    let one_line_json = serde_json::to_string(&one_line).unwrap();
+    let many_lines_json = serde_json::to_string(&many_lines).unwrap();
+    assert_ne!(one_line_json, many_lines_json);
+
+    let read: Vec<Stmt> = serde_json::from_str(&many_lines_json).unwrap();
+    assert_eq!(read.len(), one_line.len());
+    assert!(read.iter().zip(&one_line).all(|(a, b)| a.structural_eq(b)));
+}
+
+#[test]
+fn test_numbers_round_trip_exactly() {
+    let source = "print 0.1, 123456789.123456789, 0.30000000000000004, 98765432109876543210.5;";
+    let statements = parse(source).unwrap();
+
+    let json = serde_json::to_string(&statements).unwrap();
+    let read: Vec<Stmt> = serde_json::from_str(&json).unwrap();
+    assert_eq!(read, statements);
+}
+
+#[test]
+fn test_json_format() {
+    let statements = parse("print -x, 1.5;").unwrap();
+
+    assert_eq!(
+        serde_json::to_string(&statements).unwrap(),
+        concat!(
+            r#"[{"kind":{"Print":{"line":0,"values":["#,
+            r#"{"kind":{"Unary":{"#,
+            r#""operator":{"tag":"Minus","line":0,"column":6,"start":6,"end":7},"#,
+            r#""operand":{"kind":{"Variable":"#,
+            r#"{"symbol":"x","line":0,"column":7,"binding":"Dynamic"}},"#,
+            r#""span":{"line":0,"column":7,"start":7,"end":8}}}},"#,
+            r#""span":{"line":0,"column":6,"start":6,"end":8}},"#,
+            r#"{"kind":{"Literal":{"Number":1.5}},"#,
+            r#""span":{"line":0,"column":10,"start":10,"end":13}}]}},"#,
+            r#""span":{"line":0,"column":0,"start":0,"end":14}}]"#,
+        )
+    );
+}
+
+#[test]
+fn test_a_program_built_elsewhere_runs() {
+    let statements = parse("var a = \"hi\";\nprint a;").unwrap();
+    let json = serde_json::to_string(&statements).unwrap();
+
+    let read: Vec<Stmt> = serde_json::from_str(&json).unwrap();
+    let mut out = Vec::new();
+    Interpreter::new(&mut out).interpret(&read).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "hi\n");
+}
+
+#[test]
+fn test_ast_json_prints_the_program() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/imports/lib/base.lox");
+    let output = Command::new(env!("CARGO_BIN_EXE_rox"))
+        .args(["ast", "--json"])
+        .arg(&path)
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    let printed: Vec<Stmt> = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(printed, parse(&fs::read_to_string(&path).unwrap()).unwrap());
+}
@@ -0,0 +1,101 @@
+//! The streaming pipeline, a `TokenStream` feeding `Parser::new_from_iter`,
+//! must give the same tokens, statements and errors as scanning everything
+//! up front and parsing the `Vec`, for every script and fixture there is.
+
+use std::cell::RefCell;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use rox::{Parser, RoxError, Scanner, Token};
+
+/// What a pipeline made of a source: the tokens, then the statements and
+/// parse errors, or the scan errors if there were any.
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    tokens: Vec<Token>,
+    parsed: Result<(String, Vec<RoxError>), Vec<RoxError>>,
+}
+
+fn batch(source: &str) -> Outcome {
+    let mut scanner = Scanner::new(source.to_owned());
+    let scanned = scanner.scan_tokens().map(<[Token]>::to_vec);
+    let tokens = scanner.tokens().to_vec();
+    let parsed = scanned.map(|tokens| {
+        let program = Parser::new(tokens).parse_program();
+        (format!("{:?}", program.statements), program.diagnostics)
+    });
+
+    Outcome { tokens, parsed }
+}
+
+fn streaming(source: &str) -> Outcome {
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let errors = Rc::new(RefCell::new(Vec::new()));
+    let (seen_by_parser, errors_from_scan) = (seen.clone(), errors.clone());
+    let stream = Scanner::new(source.to_owned())
+        .into_iter()
+        .filter_map(move |token| match token {
+            Ok(token) => {
+                seen_by_parser.borrow_mut().push(token.clone());
+                Some(token)
+            }
+            Err(e) => {
+                errors_from_scan.borrow_mut().push(e);
+                None
+            }
+        });
+    let program = Parser::new_from_iter(stream).parse_program();
+
+    let errors = errors.take();
+    Outcome {
+        tokens: seen.take(),
+        parsed: if errors.is_empty() {
+            Ok((format!("{:?}", program.statements), program.diagnostics))
+        } else {
+            Err(errors)
+        },
+    }
+}
+
+fn scripts(dir: &Path, found: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            scripts(&path, found);
+        } else if path.extension() == Some(OsStr::new("lox")) {
+            found.push(path);
+        }
+    }
+}
+
+#[test]
+fn test_streaming_matches_batch_for_every_script() {
+    let mut found = Vec::new();
+    for dir in ["tests/scripts", "tests/fixtures"] {
+        scripts(&Path::new(env!("CARGO_MANIFEST_DIR")).join(dir), &mut found);
+    }
+    assert!(found.len() > 20);
+
+    for path in found {
+        let source = fs::read_to_string(&path).unwrap();
+        assert_eq!(streaming(&source), batch(&source), "{}", path.display());
+    }
+}
+
+#[test]
+fn test_streaming_matches_batch_at_the_edges() {
+    for source in [
+        "",
+        "// nothing but a comment",
+        "print 1",
+        "var a = 1.foo; print .5; print a.b.c;",
+        "print \"x is ${x + 1}.\";",
+        "{ var a = (1 + ; } print a;",
+        "fun f( { print 1; }",
+        "print 1; @ \"open",
+    ] {
+        assert_eq!(streaming(source), batch(source), "{:?}", source);
+    }
+}
@@ -0,0 +1,105 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use serde_json::{json, Value};
+
+fn specs() -> String {
+    format!("{}/tests/fixtures/specs", env!("CARGO_MANIFEST_DIR"))
+}
+
+fn test(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rox"))
+        .arg("test")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_passing_spec_exits_0() {
+    let output = test(&[&format!("{}/passing.lox", specs())]);
+
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("PASS "), "{}", stdout);
+    assert!(stdout.contains("1 passed, 0 failed"), "{}", stdout);
+}
+
+#[test]
+fn test_json_report() {
+    let path: PathBuf = env::temp_dir().join("rox_test_report.json");
+    let output = test(&["--report", "json", path.to_str().unwrap(), &specs()]);
+    let report: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    // the report counts lines from 0, the listing as editors do
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("  line 2: expected output \"2\", got \"two\"\n"),
+        "{}",
+        stdout
+    );
+    let summary = &report["summary"];
+    assert_eq!(
+        (&summary["specs"], &summary["passed"], &summary["failed"]),
+        (&json!(2), &json!(1), &json!(1))
+    );
+    assert!(summary["duration_ms"].is_f64());
+
+    let specs = report["specs"].as_array().unwrap();
+    let failing = &specs[0];
+    assert!(failing["path"].as_str().unwrap().ends_with("failing.lox"));
+    assert_eq!(failing["status"], "fail");
+    assert!(failing["duration_ms"].is_f64());
+    assert_eq!(
+        failing["mismatches"],
+        json!([
+            {
+                "stream": "output",
+                "output_line": 1,
+                "source_line": 1,
+                "expected": "2",
+                "actual": "two"
+            },
+            {
+                "stream": "error",
+                "output_line": 0,
+                "source_line": 2,
+                "expected": "[line 1, col 1] Error: never happens",
+                "actual": null
+            }
+        ])
+    );
+
+    let passing = &specs[1];
+    assert!(passing["path"].as_str().unwrap().ends_with("passing.lox"));
+    assert_eq!(passing["status"], "pass");
+    assert_eq!(passing["mismatches"], json!([]));
+}
+
+#[test]
+fn test_unwritable_report_is_an_error() {
+    let path = env::temp_dir().join("rox_no_such_directory/report.json");
+    let output = test(&[
+        "--report",
+        "json",
+        path.to_str().unwrap(),
+        &format!("{}/passing.lox", specs()),
+    ]);
+
+    assert_eq!(output.status.code(), Some(74));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("could not write the report to "));
+}
+
+#[test]
+fn test_unknown_report_format_is_a_usage_error() {
+    let output = test(&["--report", "xml", "out.xml", &specs()]);
+
+    assert_eq!(output.status.code(), Some(64));
+    assert!(output.stdout.is_empty());
+}
@@ -0,0 +1,50 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+#[test]
+fn test_token_dump_of_fixture() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rox"))
+        .arg("--tokens")
+        .arg(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/tokens.lox"
+        ))
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "(var, 1)
+(a, 1)
+(=, 1)
+(1, 1)
+(;, 1)
+(print, 2)
+(a, 2)
+[line 2, col 9] Error: Unexpected character: @
+(hi, 2)
+(;, 2)
+(EOF, 2)
+10 tokens
+"
+    );
+}
+
+#[test]
+fn test_token_dump_from_stdin() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rox"))
+        .arg("--tokens")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"print 1;").unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "(print, 1)\n(1, 1)\n(;, 1)\n(EOF, 1)\n4 tokens\n"
+    );
+}